@@ -17,8 +17,17 @@ pub type Pan2 = knaster_graph::pan::Pan2<f32>;
 pub type WhiteNoise = knaster_graph::noise::WhiteNoise<f32>;
 pub type PinkNoise = knaster_graph::noise::PinkNoise<f32>;
 pub type BrownNoise = knaster_graph::noise::BrownNoise<f32>;
+pub type BlueNoise = knaster_graph::noise::BlueNoise<f32>;
+pub type VioletNoise = knaster_graph::noise::VioletNoise<f32>;
+pub type VelvetNoise = knaster_graph::noise::VelvetNoise<f32>;
+pub type Crackle = knaster_graph::noise::Crackle<f32>;
 
 pub type PolyBlep = knaster_graph::polyblep::PolyBlep<f32>;
+pub type PolyBlepSync = knaster_graph::polyblep::PolyBlepSync<f32>;
+pub type Unison = knaster_graph::unison::Unison<f32>;
+pub type AdditiveBank = knaster_graph::additive::AdditiveBank<f32>;
+pub type FmSynth<Operators> = knaster_graph::fm::FmSynth<f32, Operators>;
+pub type ModalResonator = knaster_graph::modal::ModalResonator<f32>;
 
 pub type SvfFilter = knaster_graph::svf::SvfFilter<f32>;
 
@@ -28,4 +37,7 @@ pub type OnePoleHpf = knaster_graph::onepole::OnePoleHpf<f32>;
 pub type AllpassDelay = knaster_graph::delay::AllpassDelay<f32>;
 pub type AllpassFeedbackDelay = knaster_graph::delay::AllpassFeedbackDelay<f32>;
 
+pub type PluckedString = knaster_graph::waveguide::PluckedString<f32>;
+pub type Waveguide = knaster_graph::waveguide::Waveguide<f32>;
+
 pub type DoneOnTrig = knaster_graph::util::DoneOnTrig<f32>;
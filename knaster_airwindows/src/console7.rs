@@ -0,0 +1,213 @@
+//! Console7
+//!
+//! ported from airwindows Console7Channel and Console7Buss plugins
+//! License: MIT
+// Original code: Copyright (c) 2016 airwindows, Airwindows uses the MIT license
+// Ported code: Copyright 2025 Erik Natanael Gustafsson
+//
+// Console7 is a console emulation for summing: put a [`Console7Channel`] on every channel,
+// sum the channels, and put a [`Console7Buss`] on the sum. The channel encodes the signal with
+// a sine based saturation and the buss decodes it with the inverse, so that the sum gets the
+// character of an analog console.
+
+use knaster_core::{Float, PFloat, impl_ugen};
+
+/// Gain factor for which a full scale sine gets neutral gain through a channel and a buss. This
+/// is the square root of the golden ratio.
+const CHANNEL_GAIN_SCALE: f64 = 1.272019649514069;
+const BUSS_GAIN_SCALE: f64 = 1.03;
+const GOLDEN_RATIO_INVERSE: f64 = 0.618_033_988_749_895;
+const GOLDEN_RATIO_INVERSE_COMPLEMENT: f64 = 0.381966011250105;
+
+/// Smooths gain changes, faster right after the gain was changed
+struct GainChase<F> {
+    input_gain: F,
+    gain_chase: F,
+    chase_speed: F,
+}
+impl<F: Float> GainChase<F> {
+    fn new(input_gain: F) -> Self {
+        let mut s = Self {
+            input_gain,
+            gain_chase: -F::ONE,
+            chase_speed: F::new(64.),
+        };
+        s.set(input_gain);
+        s
+    }
+    fn set(&mut self, input_gain: F) {
+        self.input_gain = input_gain;
+        if self.gain_chase != input_gain {
+            self.chase_speed *= F::new(2.0);
+        }
+        if self.chase_speed > F::new(2500.0) {
+            self.chase_speed = F::new(2500.0);
+        }
+        if self.gain_chase < F::ZERO {
+            self.gain_chase = input_gain;
+        }
+    }
+    /// Advance one sample and return the current gain
+    #[inline]
+    fn next(&mut self) -> F {
+        // Compensate the chase speed for recent fader activity
+        self.chase_speed *= F::new(0.9999);
+        self.chase_speed -= F::new(0.01);
+        if self.chase_speed < F::new(64.0) {
+            self.chase_speed = F::new(64.0);
+        }
+        self.gain_chase =
+            ((self.gain_chase * self.chase_speed) + self.input_gain) / (self.chase_speed + F::ONE);
+        self.gain_chase
+    }
+}
+
+/// Lowpass biquad at 20 kHz to tame ultrasonics before the saturation
+struct Ultrasonic<F> {
+    freq: F,
+    coefficients: [F; 5],
+    /// x1, x2, y1, y2 for each channel
+    state: [[F; 4]; 2],
+}
+impl<F: Float> Ultrasonic<F> {
+    fn new() -> Self {
+        Self {
+            freq: F::new(0.5),
+            coefficients: [F::ZERO; 5],
+            state: [[F::ZERO; 4]; 2],
+        }
+    }
+    fn init(&mut self, sample_rate: u32) {
+        self.freq = F::new(20000.0 / sample_rate as f64);
+        let reso = F::new(1.618_033_988_749_895);
+        let k = (F::PI * self.freq).tan();
+        let norm = F::ONE / (F::ONE + k / reso + k * k);
+        let a0 = k * k * norm;
+        self.coefficients = [
+            a0,
+            F::new(2.0) * a0,
+            a0,
+            F::new(2.0) * (k * k - F::ONE) * norm,
+            (F::ONE - k / reso + k * k) * norm,
+        ];
+        self.state = [[F::ZERO; 4]; 2];
+    }
+    #[inline]
+    fn process(&mut self, input: F, channel: usize) -> F {
+        if self.freq >= F::new(0.49999) {
+            return input;
+        }
+        let [a0, a1, a2, b1, b2] = self.coefficients;
+        let [x1, x2, y1, y2] = self.state[channel];
+        let output = a0 * input + a1 * x1 + a2 * x2 - b1 * y1 - b2 * y2;
+        self.state[channel] = [input, x1, output, y1];
+        output
+    }
+}
+
+/// Console7Channel, the channel strip part of Console7. Sum the outputs of several channels
+/// into a [`Console7Buss`].
+///
+/// `fader` sets the level, where 0.772 is unity gain for a full scale sine. Higher settings
+/// saturate more.
+pub struct Console7Channel<F: Float = f32> {
+    gain: GainChase<F>,
+    ultrasonic: Ultrasonic<F>,
+}
+#[impl_ugen]
+impl<F: Float> Console7Channel<F> {
+    #[allow(missing_docs)]
+    pub fn new(fader: F) -> Self {
+        Self {
+            gain: GainChase::new(fader * F::new(CHANNEL_GAIN_SCALE)),
+            ultrasonic: Ultrasonic::new(),
+        }
+    }
+    /// Channel level
+    #[param(default = 0.772, range = 0.0..=1.0)]
+    pub fn fader(&mut self, value: PFloat) {
+        self.gain.set(F::new(value * CHANNEL_GAIN_SCALE));
+    }
+    fn init(&mut self, sample_rate: u32, _block_size: usize) {
+        self.ultrasonic.init(sample_rate);
+    }
+    fn process(&mut self, input: [F; 2]) -> [F; 2] {
+        let gain = self.gain.next();
+        let mut output = [F::ZERO; 2];
+        for (channel, mut input_sample) in input.into_iter().enumerate() {
+            // Cut back extra hard before the saturation and amplify after, which makes faded
+            // settings slightly expanded
+            if gain != F::ONE {
+                input_sample *= gain.powi(3);
+            }
+            input_sample = self.ultrasonic.process(input_sample, channel);
+            let limit = F::new(1.097);
+            input_sample = input_sample.clamp(-limit, limit);
+            // A version of Spiral blended with regular Density by the golden ratio
+            let abs = input_sample.abs();
+            let spiral = (input_sample * abs).sin() / if abs == F::ZERO { F::ONE } else { abs };
+            input_sample = (spiral * F::new(GOLDEN_RATIO_INVERSE))
+                + (input_sample.sin() * F::new(GOLDEN_RATIO_INVERSE_COMPLEMENT));
+            if gain != F::ONE && gain > F::ZERO {
+                input_sample /= gain;
+            }
+            output[channel] = input_sample;
+        }
+        output
+    }
+}
+impl<F: Float> Default for Console7Channel<F> {
+    fn default() -> Self {
+        Self::new(F::new(0.772))
+    }
+}
+
+/// Console7Buss, the summing buss part of Console7, decoding the sum of several
+/// [`Console7Channel`]s.
+///
+/// `master` sets the level before decoding.
+pub struct Console7Buss<F: Float = f32> {
+    gain: GainChase<F>,
+    ultrasonic: Ultrasonic<F>,
+}
+#[impl_ugen]
+impl<F: Float> Console7Buss<F> {
+    #[allow(missing_docs)]
+    pub fn new(master: F) -> Self {
+        Self {
+            gain: GainChase::new(master * F::new(BUSS_GAIN_SCALE)),
+            ultrasonic: Ultrasonic::new(),
+        }
+    }
+    /// Master level
+    #[param(default = 0.98, range = 0.0..=1.0)]
+    pub fn master(&mut self, value: PFloat) {
+        self.gain.set(F::new(value * BUSS_GAIN_SCALE));
+    }
+    fn init(&mut self, sample_rate: u32, _block_size: usize) {
+        self.ultrasonic.init(sample_rate);
+    }
+    fn process(&mut self, input: [F; 2]) -> [F; 2] {
+        let gain = self.gain.next();
+        let mut output = [F::ZERO; 2];
+        for (channel, mut input_sample) in input.into_iter().enumerate() {
+            if gain != F::ONE {
+                input_sample *= gain;
+            }
+            input_sample = self.ultrasonic.process(input_sample, channel);
+            input_sample = input_sample.clamp(-F::ONE, F::ONE);
+            // The inverse of the channel saturation
+            let abs = input_sample.abs();
+            let spiral = (input_sample * abs).asin() / if abs == F::ZERO { F::ONE } else { abs };
+            input_sample = (spiral * F::new(GOLDEN_RATIO_INVERSE))
+                + (input_sample.asin() * F::new(GOLDEN_RATIO_INVERSE_COMPLEMENT));
+            output[channel] = input_sample;
+        }
+        output
+    }
+}
+impl<F: Float> Default for Console7Buss<F> {
+    fn default() -> Self {
+        Self::new(F::new(0.98))
+    }
+}
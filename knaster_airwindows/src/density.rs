@@ -0,0 +1,135 @@
+//! Density
+//!
+//! ported from airwindows Density plugin
+//! License: MIT
+// Original code: Copyright (c) 2016 airwindows, Airwindows uses the MIT license
+// Ported code: Copyright 2025 Erik Natanael Gustafsson
+
+use knaster_core::{Float, PFloat, impl_ugen};
+
+/// The slightly imprecise value of PI/2 used by the original
+#[allow(clippy::approx_constant)]
+const HALF_PI: f64 = 1.57079633;
+
+/// Density is a saturation and "anti-saturation" effect.
+///
+/// `density` is 0.2 for no effect, lower values make the sound sparser and higher values make it
+/// progressively more saturated. `highpass` cuts lows before the saturation.
+pub struct Density<F: Float = f32> {
+    density: F,
+    highpass: F,
+    output: F,
+    dry_wet: F,
+    overallscale: F,
+    // Values derived from the parameters
+    iir_amount: F,
+    density_curve: F,
+    out: F,
+    iir_sample: [F; 2],
+}
+#[impl_ugen]
+impl<F: Float> Density<F> {
+    #[allow(missing_docs)]
+    pub fn new() -> Self {
+        let mut s = Self {
+            density: F::new(0.2),
+            highpass: F::ZERO,
+            output: F::ONE,
+            dry_wet: F::ONE,
+            overallscale: F::ONE,
+            iir_amount: F::ZERO,
+            density_curve: F::ZERO,
+            out: F::ZERO,
+            iir_sample: [F::ZERO; 2],
+        };
+        s.update();
+        s
+    }
+    /// Amount of saturation, 0.2 is neutral
+    #[param(default = 0.2, range = 0.0..=1.0)]
+    pub fn density(&mut self, value: PFloat) {
+        self.density = F::new(value);
+        self.update();
+    }
+    /// Highpass before the saturation
+    #[param(default = 0.0, range = 0.0..=1.0)]
+    pub fn highpass(&mut self, value: PFloat) {
+        self.highpass = F::new(value);
+        self.update();
+    }
+    /// Output level
+    #[param(default = 1.0, range = 0.0..=1.0)]
+    pub fn output(&mut self, value: PFloat) {
+        self.output = F::new(value);
+    }
+    /// Dry/wet mix
+    #[param(default = 1.0, range = 0.0..=1.0)]
+    pub fn dry_wet(&mut self, value: PFloat) {
+        self.dry_wet = F::new(value);
+    }
+    fn init(&mut self, sample_rate: u32, _block_size: usize) {
+        self.overallscale = F::new(sample_rate as f64 / 44100.);
+        self.iir_sample = [F::ZERO; 2];
+        self.update();
+    }
+    fn update(&mut self) {
+        let density = (self.density * F::new(5.0)) - F::ONE;
+        self.iir_amount = self.highpass.powi(3) / self.overallscale;
+        let mut out = density.abs();
+        while out > F::ONE {
+            out -= F::ONE;
+        }
+        self.out = out;
+        self.density_curve = density * density.abs();
+    }
+    fn process(&mut self, input: [F; 2]) -> [F; 2] {
+        let half_pi = F::new(HALF_PI);
+        let mut output = [F::ZERO; 2];
+        for (channel, dry_sample) in input.into_iter().enumerate() {
+            let iir_sample = &mut self.iir_sample[channel];
+            *iir_sample =
+                (*iir_sample * (F::ONE - self.iir_amount)) + (dry_sample * self.iir_amount);
+            let mut input_sample = dry_sample - *iir_sample;
+
+            // Account for really high density settings
+            let mut count = self.density_curve;
+            while count > F::ONE {
+                let bridgerectifier = (input_sample.abs() * half_pi).min(half_pi).sin();
+                input_sample = if input_sample > F::ZERO {
+                    bridgerectifier
+                } else {
+                    -bridgerectifier
+                };
+                count -= F::ONE;
+            }
+            let mut bridgerectifier = (input_sample.abs() * half_pi).min(half_pi);
+            // Produce either a boosted or a starved version
+            bridgerectifier = if self.density_curve > F::ZERO {
+                bridgerectifier.sin()
+            } else {
+                F::ONE - bridgerectifier.cos()
+            };
+            // Blend according to the density control
+            input_sample = if input_sample > F::ZERO {
+                (input_sample * (F::ONE - self.out)) + (bridgerectifier * self.out)
+            } else {
+                (input_sample * (F::ONE - self.out)) - (bridgerectifier * self.out)
+            };
+
+            if self.output < F::ONE {
+                input_sample *= self.output;
+            }
+            if self.dry_wet < F::ONE {
+                input_sample =
+                    (dry_sample * (F::ONE - self.dry_wet)) + (input_sample * self.dry_wet);
+            }
+            output[channel] = input_sample;
+        }
+        output
+    }
+}
+impl<F: Float> Default for Density<F> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -49,6 +49,11 @@ impl<F: Float> UGen for Galactic<F> {
 
     type Parameters = U5;
 
+    fn reseed(&mut self, seed: u64) {
+        let mut rng = fastrand::Rng::with_seed(seed);
+        self.fpd_l = rng.u32(16386..u32::MAX);
+        self.fpd_r = rng.u32(16386..u32::MAX);
+    }
     fn init(&mut self, sample_rate: u32, _block_size: usize) {
         for (delay, time) in self.delays_left.iter_mut().zip(GALACTIC_DELAY_TIMES) {
             let time = ((time as f64 / 44100.) * sample_rate as f64) as usize;
@@ -1 +1,6 @@
+pub mod console7;
+pub mod density;
 pub mod galactic;
+pub mod pressure4;
+pub mod purestdrive;
+pub mod totape8;
@@ -0,0 +1,127 @@
+//! Pressure4
+//!
+//! ported from airwindows Pressure4 plugin
+//! License: MIT
+// Original code: Copyright (c) 2016 airwindows, Airwindows uses the MIT license
+// Ported code: Copyright 2025 Erik Natanael Gustafsson
+
+use knaster_core::{Float, PFloat, impl_ugen};
+
+/// Pressure4 is a vari-mu style stereo compressor. The compression is controlled by the louder
+/// of the two channels and applied equally to both.
+///
+/// - `pressure`: amount of compression, with makeup gain
+/// - `speed`: release speed
+/// - `mewiness`: the character of the gain reduction curve, from 0.0 to 1.0 where 0.5 is
+///   neutral
+/// - `output_gain`: output level
+pub struct Pressure4<F: Float = f32> {
+    pressure: F,
+    speed: F,
+    mewiness: F,
+    output_gain: F,
+    overallscale: F,
+    mu_speed: [F; 2],
+    mu_coefficient: [F; 2],
+    mu_vary: F,
+    flip: bool,
+}
+#[impl_ugen]
+impl<F: Float> Pressure4<F> {
+    #[allow(missing_docs)]
+    pub fn new() -> Self {
+        Self {
+            pressure: F::ZERO,
+            speed: F::new(0.2),
+            mewiness: F::ONE,
+            output_gain: F::ONE,
+            overallscale: F::ONE,
+            mu_speed: [F::new(10000.); 2],
+            mu_coefficient: [F::ONE; 2],
+            mu_vary: F::ONE,
+            flip: false,
+        }
+    }
+    /// Amount of compression
+    #[param(default = 0.0, range = 0.0..=1.0)]
+    pub fn pressure(&mut self, value: PFloat) {
+        self.pressure = F::new(value);
+    }
+    /// Release speed
+    #[param(default = 0.2, range = 0.0..=1.0)]
+    pub fn speed(&mut self, value: PFloat) {
+        self.speed = F::new(value);
+    }
+    /// Gain reduction curve
+    #[param(default = 1.0, range = 0.0..=1.0)]
+    pub fn mewiness(&mut self, value: PFloat) {
+        self.mewiness = F::new(value);
+    }
+    /// Output level
+    #[param(default = 1.0, range = 0.0..=1.0)]
+    pub fn output_gain(&mut self, value: PFloat) {
+        self.output_gain = F::new(value);
+    }
+    fn init(&mut self, sample_rate: u32, _block_size: usize) {
+        self.overallscale = F::new(sample_rate as f64 / 44100.);
+    }
+    fn process(&mut self, input: [F; 2]) -> [F; 2] {
+        let threshold = F::ONE - (self.pressure * F::new(0.95));
+        let mu_makeup_gain = F::ONE / threshold;
+        let release = (F::new(1.28) - self.speed).powi(5) * F::new(32768.0) / self.overallscale;
+        let fastest = release.sqrt();
+        let mut mewiness = (self.mewiness * F::new(2.0)) - F::ONE;
+        let positive_mu = mewiness >= F::ZERO;
+        mewiness = mewiness.abs();
+        let unmewiness = F::ONE - mewiness;
+
+        let mut input_sample = [input[0] * mu_makeup_gain, input[1] * mu_makeup_gain];
+        // Take the greater of either channel and apply the result to both
+        let input_sense = input_sample[0].abs().max(input_sample[1].abs());
+
+        // Alternate between two sets of coefficients
+        let i = if self.flip { 0 } else { 1 };
+        let mu_speed = &mut self.mu_speed[i];
+        let mu_coefficient = &mut self.mu_coefficient[i];
+        if input_sense > threshold {
+            self.mu_vary = threshold / input_sense;
+            let mu_attack = mu_speed.abs().sqrt();
+            *mu_coefficient *= mu_attack - F::ONE;
+            if self.mu_vary < threshold {
+                *mu_coefficient += threshold;
+            } else {
+                *mu_coefficient += self.mu_vary;
+            }
+            *mu_coefficient /= mu_attack;
+        } else {
+            let speed_squared = *mu_speed * *mu_speed;
+            *mu_coefficient *= speed_squared - F::ONE;
+            *mu_coefficient += F::ONE;
+            *mu_coefficient /= speed_squared;
+        }
+        let mut mu_new_speed = *mu_speed * (*mu_speed - F::ONE);
+        mu_new_speed = mu_new_speed + (input_sense * release).abs() + fastest;
+        *mu_speed = mu_new_speed / *mu_speed;
+
+        let mut coefficient = if positive_mu {
+            mu_coefficient.powi(2)
+        } else {
+            mu_coefficient.sqrt()
+        };
+        coefficient = (coefficient * mewiness) + (*mu_coefficient * unmewiness);
+        self.flip = !self.flip;
+
+        for sample in &mut input_sample {
+            *sample *= coefficient;
+            if self.output_gain != F::ONE {
+                *sample *= self.output_gain;
+            }
+        }
+        input_sample
+    }
+}
+impl<F: Float> Default for Pressure4<F> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
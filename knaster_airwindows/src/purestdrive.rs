@@ -0,0 +1,51 @@
+//! PurestDrive
+//!
+//! ported from airwindows PurestDrive plugin
+//! License: MIT
+// Original code: Copyright (c) 2016 airwindows, Airwindows uses the MIT license
+// Ported code: Copyright 2025 Erik Natanael Gustafsson
+
+use knaster_core::{Float, PFloat, impl_ugen};
+
+/// PurestDrive is a sine saturation which saturates less when the previous sample was low level
+/// or of the opposite polarity, letting highs through more cleanly.
+pub struct PurestDrive<F: Float = f32> {
+    drive: F,
+    previous_sample: [F; 2],
+}
+#[impl_ugen]
+impl<F: Float> PurestDrive<F> {
+    #[allow(missing_docs)]
+    pub fn new() -> Self {
+        Self {
+            drive: F::ZERO,
+            previous_sample: [F::ZERO; 2],
+        }
+    }
+    /// Amount of drive
+    #[param(default = 0.0, range = 0.0..=1.0)]
+    pub fn drive(&mut self, value: PFloat) {
+        self.drive = F::new(value);
+    }
+    fn init(&mut self, _sample_rate: u32, _block_size: usize) {
+        self.previous_sample = [F::ZERO; 2];
+    }
+    fn process(&mut self, input: [F; 2]) -> [F; 2] {
+        let mut output = [F::ZERO; 2];
+        for (channel, dry_sample) in input.into_iter().enumerate() {
+            let input_sample = dry_sample.sin();
+            // Saturate less if the previous sample was undistorted and low level, or of the
+            // inverse polarity
+            let apply =
+                ((self.previous_sample[channel] + input_sample).abs() / F::new(2.0)) * self.drive;
+            output[channel] = (dry_sample * (F::ONE - apply)) + (input_sample * apply);
+            self.previous_sample[channel] = dry_sample.sin();
+        }
+        output
+    }
+}
+impl<F: Float> Default for PurestDrive<F> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
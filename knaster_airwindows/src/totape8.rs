@@ -0,0 +1,511 @@
+//! ToTape8
+//!
+//! ported from airwindows ToTape8 plugin
+//! License: MIT
+// Original code: Copyright (c) 2016 airwindows, Airwindows uses the MIT license
+// Ported code: Copyright 2025 Erik Natanael Gustafsson
+
+use knaster_core::{Float, PFloat, impl_ugen};
+use knaster_core_dsp::noise::next_randomness_seed;
+
+const FLUTTER_BUFFER_SIZE: usize = 1002;
+/// Number of slew stages in the bias routine
+const BIAS_STAGES: usize = 9;
+const GOLDEN_RATIO: f64 = 1.618_033_988_749_895;
+/// The slightly imprecise value of PI/2 used by the original
+#[allow(clippy::approx_constant)]
+const HALF_PI: f64 = 1.57079633;
+
+/// Bandpass biquad with a transposed direct form II state per channel
+struct HeadBumpFilter<F> {
+    a0: F,
+    a2: F,
+    b1: F,
+    b2: F,
+    state: [[F; 2]; 2],
+}
+impl<F: Float> HeadBumpFilter<F> {
+    fn new() -> Self {
+        Self {
+            a0: F::ZERO,
+            a2: F::ZERO,
+            b1: F::ZERO,
+            b2: F::ZERO,
+            state: [[F::ZERO; 2]; 2],
+        }
+    }
+    fn set_freq(&mut self, freq: F) {
+        let reso = F::new(1.0 / GOLDEN_RATIO);
+        let k = (F::PI * freq).tan();
+        let norm = F::ONE / (F::ONE + k / reso + k * k);
+        self.a0 = k / reso * norm;
+        self.a2 = -self.a0;
+        self.b1 = F::new(2.0) * (k * k - F::ONE) * norm;
+        self.b2 = (F::ONE - k / reso + k * k) * norm;
+    }
+    #[inline]
+    fn process(&mut self, input: F, channel: usize) -> F {
+        let [s1, s2] = self.state[channel];
+        let output = (input * self.a0) + s1;
+        // a1 is 0 for a bandpass
+        self.state[channel] = [
+            -(output * self.b1) + s2,
+            (input * self.a2) - (output * self.b2),
+        ];
+        output
+    }
+}
+
+/// Per channel state
+struct Channel<F> {
+    iir_enc: F,
+    comp_enc: F,
+    avg_enc: F,
+    iir_dec: F,
+    comp_dec: F,
+    avg_dec: F,
+    iir_mid_roller: F,
+    iir_low_cutoff: F,
+    head_bump: F,
+    flutter_buffer: [F; FLUTTER_BUFFER_SIZE],
+    sweep: F,
+    next_max: F,
+    fpd: u32,
+    /// Previous sample of each slew stage of the bias routine
+    bias_slew: [F; BIAS_STAGES],
+    last_sample: F,
+    was_pos_clip: bool,
+    was_neg_clip: bool,
+    intermediate: [F; 16],
+}
+impl<F: Float> Channel<F> {
+    fn new(fpd: u32) -> Self {
+        Self {
+            iir_enc: F::ZERO,
+            comp_enc: F::ZERO,
+            avg_enc: F::ZERO,
+            iir_dec: F::ZERO,
+            comp_dec: F::ZERO,
+            avg_dec: F::ZERO,
+            iir_mid_roller: F::ZERO,
+            iir_low_cutoff: F::ZERO,
+            head_bump: F::ZERO,
+            flutter_buffer: [F::ZERO; FLUTTER_BUFFER_SIZE],
+            sweep: F::PI,
+            next_max: F::new(0.5),
+            fpd,
+            bias_slew: [F::ZERO; BIAS_STAGES],
+            last_sample: F::ZERO,
+            was_pos_clip: false,
+            was_neg_clip: false,
+            intermediate: [F::ZERO; 16],
+        }
+    }
+    /// The encode and decode halves of the Dubly noise reduction
+    #[inline]
+    fn dubly(
+        input_sample: F,
+        iir: &mut F,
+        comp: &mut F,
+        avg: &mut F,
+        freq: F,
+        gains: [F; 2],
+        amount: F,
+    ) -> F {
+        *iir = (*iir * (F::ONE - freq)) + (input_sample * freq);
+        let mut high_part = (input_sample - *iir) * gains[0];
+        high_part += *avg;
+        *avg = (input_sample - *iir) * gains[1];
+        high_part = high_part.clamp(-F::ONE, F::ONE);
+        let mut dubly = high_part.abs();
+        if dubly > F::ZERO {
+            let adjust = (F::ONE + (F::new(255.0) * dubly)).ln() / F::new(2.40823996531);
+            if adjust > F::ZERO {
+                dubly /= adjust;
+            }
+            *comp = (*comp * (F::ONE - freq)) + (dubly * freq);
+            input_sample + (high_part * *comp) * amount
+        } else {
+            input_sample
+        }
+    }
+    /// ClipOnly2, a clipper which softens the samples around the clipped ones. It delays the
+    /// signal by `spacing` samples.
+    #[inline]
+    fn clip_only(&mut self, input_sample: F, spacing: usize) -> F {
+        let mut input_sample = input_sample.clamp(F::new(-4.0), F::new(4.0));
+        if self.was_pos_clip {
+            // The current sample will be over
+            if input_sample < self.last_sample {
+                self.last_sample = F::new(0.7058208) + (input_sample * F::new(0.2609148));
+            } else {
+                self.last_sample = F::new(0.2491717) + (self.last_sample * F::new(0.7390851));
+            }
+        }
+        self.was_pos_clip = false;
+        if input_sample > F::new(0.9549925859) {
+            self.was_pos_clip = true;
+            input_sample = F::new(0.7058208) + (self.last_sample * F::new(0.2609148));
+        }
+        if self.was_neg_clip {
+            // The current sample will be -over
+            if input_sample > self.last_sample {
+                self.last_sample = F::new(-0.7058208) + (input_sample * F::new(0.2609148));
+            } else {
+                self.last_sample = F::new(-0.2491717) + (self.last_sample * F::new(0.7390851));
+            }
+        }
+        self.was_neg_clip = false;
+        if input_sample < F::new(-0.9549925859) {
+            self.was_neg_clip = true;
+            input_sample = F::new(-0.7058208) + (self.last_sample * F::new(0.2609148));
+        }
+        self.intermediate[spacing] = input_sample;
+        let output = self.last_sample;
+        for x in (1..=spacing).rev() {
+            self.intermediate[x - 1] = self.intermediate[x];
+        }
+        self.last_sample = self.intermediate[0];
+        output
+    }
+}
+
+/// ToTape8 is a tape emulation with noise reduction encoding, flutter, bias, head bump and
+/// clipping.
+///
+/// - `input`: input level, 0.5 is unity gain
+/// - `tilt`: the balance of the Dubly noise reduction encoding and decoding, which tilts the
+///   spectrum
+/// - `shape`: the frequency of the Dubly filters and the split between the low and high
+///   saturation
+/// - `flutter`: flutter depth
+/// - `flutter_speed`: flutter speed
+/// - `bias`: tape bias, 0.5 is neutral. Lower values give a sticky, underbiased sound and
+///   higher values a slew limited, overbiased sound.
+/// - `head_bump`: amount of head bump
+/// - `head_freq`: head bump frequency, from 25 Hz to 200 Hz
+/// - `output`: output level, 0.5 is unity gain
+/// - `dry_wet`: dry/wet mix
+pub struct ToTape8<F: Float = f32> {
+    input: F,
+    tilt: F,
+    shape: F,
+    flutter: F,
+    flutter_speed: F,
+    bias: F,
+    head_bump: F,
+    head_freq: F,
+    output: F,
+    dry_wet: F,
+    sample_rate: F,
+    overallscale: F,
+    spacing: usize,
+    flutter_count: usize,
+    // Values derived from the parameters
+    input_gain: F,
+    dubly_amount: F,
+    outly_amount: F,
+    iir_enc_freq: F,
+    iir_dec_freq: F,
+    iir_mid_freq: F,
+    flutter_depth: F,
+    flutter_frequency: F,
+    bias_amount: F,
+    under_bias: F,
+    bias_thresholds: [F; BIAS_STAGES],
+    head_bump_drive: F,
+    head_bump_mix: F,
+    iir_sub_freq: F,
+    output_gain: F,
+    head_bump_filters: [HeadBumpFilter<F>; 2],
+    channels: [Channel<F>; 2],
+}
+#[impl_ugen]
+impl<F: Float> ToTape8<F> {
+    #[allow(missing_docs)]
+    pub fn new() -> Self {
+        let mut rng = fastrand::Rng::with_seed(next_randomness_seed());
+        let mut s = Self {
+            input: F::new(0.5),
+            tilt: F::new(0.5),
+            shape: F::new(0.5),
+            flutter: F::new(0.5),
+            flutter_speed: F::new(0.5),
+            bias: F::new(0.5),
+            head_bump: F::new(0.5),
+            head_freq: F::new(0.5),
+            output: F::new(0.5),
+            dry_wet: F::ONE,
+            sample_rate: F::new(44100.),
+            overallscale: F::ONE,
+            spacing: 1,
+            flutter_count: 0,
+            input_gain: F::ONE,
+            dubly_amount: F::ZERO,
+            outly_amount: F::ZERO,
+            iir_enc_freq: F::ZERO,
+            iir_dec_freq: F::ZERO,
+            iir_mid_freq: F::ZERO,
+            flutter_depth: F::ZERO,
+            flutter_frequency: F::ZERO,
+            bias_amount: F::ZERO,
+            under_bias: F::ZERO,
+            bias_thresholds: [F::ZERO; BIAS_STAGES],
+            head_bump_drive: F::ZERO,
+            head_bump_mix: F::ZERO,
+            iir_sub_freq: F::ZERO,
+            output_gain: F::ONE,
+            head_bump_filters: [HeadBumpFilter::new(), HeadBumpFilter::new()],
+            channels: [
+                Channel::new(rng.u32(16386..u32::MAX)),
+                Channel::new(rng.u32(16386..u32::MAX)),
+            ],
+        };
+        s.update();
+        s
+    }
+    fn reseed(&mut self, seed: u64) {
+        let mut rng = fastrand::Rng::with_seed(seed);
+        for channel in &mut self.channels {
+            channel.fpd = rng.u32(16386..u32::MAX);
+        }
+    }
+    /// Input level
+    #[param(default = 0.5, range = 0.0..=1.0)]
+    pub fn input(&mut self, value: PFloat) {
+        self.input = F::new(value);
+        self.update();
+    }
+    /// Noise reduction encode/decode balance
+    #[param(default = 0.5, range = 0.0..=1.0)]
+    pub fn tilt(&mut self, value: PFloat) {
+        self.tilt = F::new(value);
+        self.update();
+    }
+    /// Filter frequencies
+    #[param(default = 0.5, range = 0.0..=1.0)]
+    pub fn shape(&mut self, value: PFloat) {
+        self.shape = F::new(value);
+        self.update();
+    }
+    /// Flutter depth
+    #[param(default = 0.5, range = 0.0..=1.0)]
+    pub fn flutter(&mut self, value: PFloat) {
+        self.flutter = F::new(value);
+        self.update();
+    }
+    /// Flutter speed
+    #[param(default = 0.5, range = 0.0..=1.0)]
+    pub fn flutter_speed(&mut self, value: PFloat) {
+        self.flutter_speed = F::new(value);
+        self.update();
+    }
+    /// Tape bias
+    #[param(default = 0.5, range = 0.0..=1.0)]
+    pub fn bias(&mut self, value: PFloat) {
+        self.bias = F::new(value);
+        self.update();
+    }
+    /// Head bump amount
+    #[param(default = 0.5, range = 0.0..=1.0)]
+    pub fn head_bump(&mut self, value: PFloat) {
+        self.head_bump = F::new(value);
+        self.update();
+    }
+    /// Head bump frequency
+    #[param(default = 0.5, range = 0.0..=1.0)]
+    pub fn head_freq(&mut self, value: PFloat) {
+        self.head_freq = F::new(value);
+        self.update();
+    }
+    /// Output level
+    #[param(default = 0.5, range = 0.0..=1.0)]
+    pub fn output(&mut self, value: PFloat) {
+        self.output = F::new(value);
+        self.update();
+    }
+    /// Dry/wet mix
+    #[param(default = 1.0, range = 0.0..=1.0)]
+    pub fn dry_wet(&mut self, value: PFloat) {
+        self.dry_wet = F::new(value);
+    }
+    fn init(&mut self, sample_rate: u32, _block_size: usize) {
+        self.sample_rate = F::new(sample_rate as f64);
+        self.overallscale = F::new(sample_rate as f64 / 44100.);
+        self.spacing = (sample_rate as usize / 44100).clamp(1, 16);
+        let fpd = [self.channels[0].fpd, self.channels[1].fpd];
+        self.channels = fpd.map(Channel::new);
+        self.head_bump_filters = [HeadBumpFilter::new(), HeadBumpFilter::new()];
+        self.flutter_count = 0;
+        self.update();
+    }
+    fn update(&mut self) {
+        let overallscale = self.overallscale;
+        self.input_gain = (self.input * F::new(2.0)).powi(2);
+        self.dubly_amount = self.tilt * F::new(2.0);
+        self.outly_amount = ((F::ONE - self.tilt) * F::new(-2.0)).max(-F::ONE);
+        self.iir_enc_freq = (F::ONE - self.shape) / overallscale;
+        self.iir_dec_freq = self.shape / overallscale;
+        self.iir_mid_freq = ((self.shape * F::new(0.618)) + F::new(0.382)) / overallscale;
+        self.flutter_depth = (self.flutter.powi(6) * overallscale * F::new(50.)).min(F::new(498.0));
+        self.flutter_frequency = (F::new(0.02) * self.flutter_speed.powi(3)) / overallscale;
+
+        let bias = (self.bias * F::new(2.0)) - F::ONE;
+        self.bias_amount = bias;
+        self.under_bias = (bias.powi(4) * F::new(0.25)) / overallscale;
+        let mut over_bias = (F::ONE - bias).powi(3) / overallscale;
+        if bias > F::ZERO {
+            self.under_bias = F::ZERO;
+        }
+        if bias < F::ZERO {
+            over_bias = F::ONE / overallscale;
+        }
+        // The last stage has the lowest threshold
+        for threshold in self.bias_thresholds.iter_mut().rev() {
+            *threshold = over_bias;
+            over_bias *= F::new(GOLDEN_RATIO);
+        }
+
+        self.head_bump_drive = (self.head_bump * F::new(0.1)) / overallscale;
+        self.head_bump_mix = self.head_bump * F::new(0.5);
+        let sub_curve = (self.head_bump * F::PI).sin();
+        self.iir_sub_freq = (sub_curve * F::new(0.008)) / overallscale;
+        let head_freq =
+            (((self.head_freq * self.head_freq) * F::new(175.0)) + F::new(25.0)) / self.sample_rate;
+        self.head_bump_filters[0].set_freq(head_freq);
+        self.head_bump_filters[1].set_freq(head_freq * F::new(0.9375));
+        self.output_gain = self.output * F::new(2.0);
+    }
+    fn process(&mut self, input: [F; 2]) -> [F; 2] {
+        let half_pi = F::new(HALF_PI);
+        let mut samples = input.map(|x| x * self.input_gain);
+
+        for (channel, sample) in self.channels.iter_mut().zip(&mut samples) {
+            *sample = Channel::dubly(
+                *sample,
+                &mut channel.iir_enc,
+                &mut channel.comp_enc,
+                &mut channel.avg_enc,
+                self.iir_enc_freq,
+                [F::new(2.848), F::new(1.152)],
+                self.dubly_amount,
+            );
+        }
+
+        if self.flutter_depth > F::ZERO {
+            if self.flutter_count > 999 {
+                self.flutter_count = 999;
+            }
+            for (i, sample) in samples.iter_mut().enumerate() {
+                // The left channel is updated before the right channel reads it
+                let other = &self.channels[1 - i];
+                let other = (other.sweep + other.next_max).sin();
+                let channel = &mut self.channels[i];
+                channel.flutter_buffer[self.flutter_count] = *sample;
+                let offset = self.flutter_depth + (self.flutter_depth * channel.sweep.sin());
+                channel.sweep += channel.next_max * self.flutter_frequency;
+                if channel.sweep > F::TAU {
+                    channel.sweep -= F::TAU;
+                    // Pick the next flutter speed of two random candidates, whichever is
+                    // closer to the other channel
+                    let flut_a = F::new(0.24 + (channel.fpd as f64 / u32::MAX as f64 * 0.74));
+                    channel.fpd ^= channel.fpd << 13;
+                    channel.fpd ^= channel.fpd >> 17;
+                    channel.fpd ^= channel.fpd << 5;
+                    let flut_b = F::new(0.24 + (channel.fpd as f64 / u32::MAX as f64 * 0.74));
+                    channel.next_max = if (flut_a - other).abs() < (flut_b - other).abs() {
+                        flut_a
+                    } else {
+                        flut_b
+                    };
+                }
+                let whole = offset.floor();
+                let fraction = offset - whole;
+                let count = self.flutter_count + whole.to_usize().unwrap_or(0);
+                let wrap = |index: usize| if index > 999 { index - 1000 } else { index };
+                *sample = (channel.flutter_buffer[wrap(count)] * (F::ONE - fraction))
+                    + (channel.flutter_buffer[wrap(count + 1)] * fraction);
+            }
+            self.flutter_count = self.flutter_count.checked_sub(1).unwrap_or(999);
+        }
+
+        if self.bias_amount.abs() > F::new(0.001) {
+            for (channel, sample) in self.channels.iter_mut().zip(&mut samples) {
+                for (slew, &threshold) in channel.bias_slew.iter_mut().zip(&self.bias_thresholds) {
+                    if self.under_bias > F::ZERO {
+                        let stuck = (*sample - (*slew / F::new(0.975))).abs() / self.under_bias;
+                        if stuck < F::ONE {
+                            *sample =
+                                (*sample * stuck) + ((*slew / F::new(0.975)) * (F::ONE - stuck));
+                        }
+                    }
+                    if (*sample - *slew) > threshold {
+                        *sample = *slew + threshold;
+                    }
+                    if -(*sample - *slew) > threshold {
+                        *sample = *slew - threshold;
+                    }
+                    *slew = *sample * F::new(0.975);
+                }
+            }
+        }
+
+        // The basic ToTape algorithm
+        for (channel, sample) in self.channels.iter_mut().zip(&mut samples) {
+            channel.iir_mid_roller = (channel.iir_mid_roller * (F::ONE - self.iir_mid_freq))
+                + (*sample * self.iir_mid_freq);
+            let mut highs = *sample - channel.iir_mid_roller;
+            let mut lows = channel.iir_mid_roller;
+            if self.iir_sub_freq > F::ZERO {
+                channel.iir_low_cutoff = (channel.iir_low_cutoff * (F::ONE - self.iir_sub_freq))
+                    + (lows * self.iir_sub_freq);
+                lows -= channel.iir_low_cutoff;
+            }
+            lows = lows.clamp(-half_pi, half_pi).sin();
+            let mut thinned_high = F::ONE - (highs.abs() * half_pi).min(half_pi).cos();
+            if highs < F::ZERO {
+                thinned_high = -thinned_high;
+            }
+            highs -= thinned_high;
+            highs = highs.clamp(-half_pi, half_pi).sin();
+            *sample = lows + highs;
+        }
+
+        if self.head_bump_mix > F::ZERO {
+            let cube_coefficient = F::new(0.0618) / self.overallscale.sqrt();
+            for (i, (channel, sample)) in self.channels.iter_mut().zip(&mut samples).enumerate() {
+                channel.head_bump += *sample * self.head_bump_drive;
+                channel.head_bump -= channel.head_bump.powi(3) * cube_coefficient;
+                let bump = self.head_bump_filters[0].process(channel.head_bump, i);
+                let bump = self.head_bump_filters[1].process(bump, i);
+                *sample += bump * self.head_bump_mix;
+            }
+        }
+
+        for ((channel, sample), dry_sample) in self.channels.iter_mut().zip(&mut samples).zip(input)
+        {
+            *sample = Channel::dubly(
+                *sample,
+                &mut channel.iir_dec,
+                &mut channel.comp_dec,
+                &mut channel.avg_dec,
+                self.iir_dec_freq,
+                [F::new(2.628), F::new(1.372)],
+                self.outly_amount,
+            );
+            if self.output_gain != F::ONE {
+                *sample *= self.output_gain;
+            }
+            *sample = channel.clip_only(*sample, self.spacing);
+            if self.dry_wet < F::ONE {
+                *sample = (*sample * self.dry_wet) + (dry_sample * (F::ONE - self.dry_wet));
+            }
+        }
+        samples
+    }
+}
+impl<F: Float> Default for ToTape8<F> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,163 @@
+//! Regression tests against golden data rendered by the upstream airwindows plugins.
+//!
+//! `golden/regenerate.sh` fetches the plugin sources at the commit pinned in
+//! `golden/AIRWINDOWS_COMMIT`, builds them against `golden/render.cpp` and writes `golden/data.rs`,
+//! whose header records the commit. Until a commit is pinned, `data.rs` holds placeholder data
+//! rendered by a C++ transcription of the plugins, which cannot catch an error made in both the
+//! transcription and the ports. The parameters are exact in `f32` because the plugins' VST
+//! `setParameter()` takes a `float`.
+
+#[path = "golden/data.rs"]
+mod data;
+
+use knaster_airwindows::{
+    console7::{Console7Buss, Console7Channel},
+    density::Density,
+    pressure4::Pressure4,
+    purestdrive::PurestDrive,
+    totape8::ToTape8,
+};
+use knaster_core::{AudioCtx, UGen, UGenFlags, log::ArLogSender, typenum::U2};
+
+const SAMPLE_RATE: u32 = 44100;
+const FRAMES: usize = 4096;
+const STRIDE: usize = 128;
+
+/// The same test signal as in `render.cpp`
+fn test_signal(i: usize) -> [f64; 2] {
+    let t = i as f64 / SAMPLE_RATE as f64;
+    let burst = if i % 1024 < 512 { 1.0 } else { 0.2 };
+    [
+        0.7 * (std::f64::consts::TAU * 220.0 * t).sin()
+            + 0.3 * burst * (std::f64::consts::TAU * 3520.0 * t + 0.5).sin(),
+        0.6 * (std::f64::consts::TAU * 330.0 * t + 1.0).sin()
+            + 0.4 * burst * (std::f64::consts::TAU * 5120.0 * t).sin(),
+    ]
+}
+
+/// Sets the parameters, runs the test signal scaled by `input_gain` through `ugen` and compares
+/// every `STRIDE` output frame to `golden`
+fn check<U: UGen<Sample = f64, Inputs = U2, Outputs = U2>>(
+    mut ugen: U,
+    parameters: &[(&'static str, f64)],
+    input_gain: f64,
+    golden: &[[f64; FRAMES / STRIDE]; 2],
+) {
+    let mut ctx = AudioCtx::new(SAMPLE_RATE, 64, ArLogSender::non_rt());
+    let mut flags = UGenFlags::new();
+    ugen.init(SAMPLE_RATE, 64);
+    for &(name, value) in parameters {
+        ugen.param(&mut ctx, name, value).unwrap();
+    }
+    for i in 0..FRAMES {
+        let [l, r] = test_signal(i);
+        let out = ugen.process(
+            &mut ctx,
+            &mut flags,
+            [l * input_gain, r * input_gain].into(),
+        );
+        if i % STRIDE == 0 {
+            for channel in 0..2 {
+                let expected = golden[channel][i / STRIDE];
+                assert!(
+                    (out[channel] - expected).abs() < 1e-9,
+                    "frame {i}, channel {channel}: {} != {expected}",
+                    out[channel]
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn density() {
+    check(
+        Density::new(),
+        &[
+            ("density", 0.6875),
+            ("highpass", 0.3125),
+            ("output", 0.90625),
+            ("dry_wet", 0.8125),
+        ],
+        1.0,
+        &data::DENSITY,
+    );
+}
+
+#[test]
+fn purest_drive() {
+    check(
+        PurestDrive::new(),
+        &[("drive", 0.8125)],
+        1.0,
+        &data::PURESTDRIVE,
+    );
+}
+
+#[test]
+fn console7() {
+    check(Console7Channel::new(1.0), &[], 1.5, &data::CONSOLE7CHANNEL);
+    check(Console7Buss::new(0.90625), &[], 1.0, &data::CONSOLE7BUSS);
+}
+
+#[test]
+fn pressure4() {
+    check(
+        Pressure4::new(),
+        &[
+            ("pressure", 0.6875),
+            ("speed", 0.40625),
+            ("mewiness", 0.3125),
+            ("output_gain", 0.90625),
+        ],
+        1.0,
+        &data::PRESSURE4,
+    );
+}
+
+#[test]
+fn to_tape8() {
+    // The flutter is randomised, so it is disabled here
+    check(
+        ToTape8::new(),
+        &[
+            ("input", 0.6875),
+            ("tilt", 0.59375),
+            ("shape", 0.40625),
+            ("flutter", 0.0),
+            ("bias", 0.3125),
+            ("head_bump", 0.59375),
+            ("head_freq", 0.40625),
+            ("output", 0.59375),
+            ("dry_wet", 0.90625),
+        ],
+        1.0,
+        &data::TOTAPE8_UNDER_BIAS,
+    );
+    check(
+        ToTape8::new(),
+        &[
+            ("flutter", 0.0),
+            ("bias", 0.8125),
+            ("head_bump", 0.3125),
+            ("head_freq", 0.6875),
+        ],
+        1.0,
+        &data::TOTAPE8_OVER_BIAS,
+    );
+}
+
+#[test]
+fn to_tape8_flutter_is_stable() {
+    let mut ctx = AudioCtx::new(48000, 64, ArLogSender::non_rt());
+    let mut flags = UGenFlags::new();
+    let mut tape = ToTape8::<f32>::new();
+    tape.init(48000, 64);
+    tape.param(&mut ctx, "flutter", 1.0).unwrap();
+    tape.param(&mut ctx, "flutter_speed", 1.0).unwrap();
+    for i in 0..48000 {
+        let [l, r] = test_signal(i);
+        let out = tape.process(&mut ctx, &mut flags, [l as f32, r as f32].into());
+        assert!(out[0].is_finite() && out[1].is_finite() && out[0].abs() < 2.0);
+    }
+}
@@ -0,0 +1,495 @@
+//! Placeholder golden data rendered by the C++ transcription of the plugins in the git history of
+//! `render.cpp`, not by the upstream plugins. No upstream commit is pinned yet; replace it by
+//! running `regenerate.sh --pin`. Do not edit.
+#![allow(clippy::excessive_precision)]
+
+pub const DENSITY: [[f64; 32]; 2] = [
+    [
+        7.16660771870531899e-01,
+        -7.77600839739895910e-01,
+        8.58621246315470987e-01,
+        -8.39136652242261971e-01,
+        -7.83859454655347010e-01,
+        8.68804172866134872e-01,
+        -7.17411228335839479e-01,
+        -7.17815355306020031e-01,
+        7.62195500505793544e-01,
+        -8.56153580307718709e-01,
+        8.77907372565831512e-01,
+        7.68634996885707245e-01,
+        -8.58481127217000095e-01,
+        8.41784183236309214e-01,
+        6.91808367163575633e-01,
+        -7.88459635341738352e-01,
+        8.47860275783992590e-01,
+        -8.95837266914378483e-01,
+        -7.37711221058009015e-01,
+        8.86683089593873541e-01,
+        -8.60134211202352561e-01,
+        -6.37146576671516640e-01,
+        7.66209350967730352e-01,
+        -8.52805387920779645e-01,
+        9.08303932087205279e-01,
+        7.04393715385207453e-01,
+        -8.69940635435241250e-01,
+        8.43021481806270012e-01,
+        4.28725917962512915e-01,
+        -7.42618005838022954e-01,
+        8.39952095341270022e-01,
+        -8.72646229063060663e-01,
+    ],
+    [
+        8.30993565518033606e-01,
+        7.40543342339894384e-01,
+        2.96411506158693994e-01,
+        6.77644261063032105e-01,
+        7.33467973855623012e-01,
+        7.05102429076393866e-01,
+        4.34523629672695033e-01,
+        -7.38126920617262039e-01,
+        -8.86775666882294589e-01,
+        -9.21879018805556116e-01,
+        -8.95445285351252118e-01,
+        -8.27468937677146132e-01,
+        -8.15406188114270458e-01,
+        -7.94550517247235022e-01,
+        -7.78667835188268431e-01,
+        -7.62871617708920091e-01,
+        -7.99528682846505356e-01,
+        -7.51990859351206464e-01,
+        2.56505490587649709e-01,
+        8.83358922618998288e-01,
+        8.54008584866722709e-01,
+        8.54516232685970101e-01,
+        8.42259788918828134e-01,
+        8.26355067107088281e-01,
+        7.38453355428460245e-01,
+        7.96066232936147311e-01,
+        8.30742229442049673e-01,
+        8.25569338364082084e-01,
+        7.29081858868527388e-01,
+        4.74770535248045578e-01,
+        -7.25348076300440292e-01,
+        -8.37871924225889964e-01,
+    ],
+];
+pub const PURESTDRIVE: [[f64; 32]; 2] = [
+    [
+        1.43798817000826640e-01,
+        -2.47420279888692252e-01,
+        6.38929180085868409e-01,
+        -6.29797656576697396e-01,
+        -2.52763522027734555e-01,
+        6.76821936723174478e-01,
+        -5.56428195297299255e-01,
+        9.74797310234839787e-02,
+        1.64357828409536083e-01,
+        -6.19501989625760863e-01,
+        7.21721448507767316e-01,
+        1.72150839483659723e-01,
+        -6.27697605683394055e-01,
+        6.09037236317653718e-01,
+        -2.19105074308286268e-01,
+        -2.77287489639697626e-01,
+        5.78740639499478959e-01,
+        -7.90689344265601113e-01,
+        -7.65043099054129715e-03,
+        7.55664456274150020e-01,
+        -6.43320104800315873e-01,
+        3.30886879698198966e-01,
+        1.59336440480878583e-01,
+        -6.02420606393982649e-01,
+        8.45540948220117272e-01,
+        -1.56408602158762178e-01,
+        -6.82948792873787625e-01,
+        5.63092581783602997e-01,
+        -4.28556337447982494e-01,
+        -3.39162857483634797e-02,
+        5.40690689684512060e-01,
+        -6.95058225622190995e-01,
+    ],
+    [
+        5.00721025613485748e-01,
+        9.53789684738711452e-02,
+        -1.21881424282247935e-01,
+        -7.56406154697702587e-02,
+        -7.98735456057410136e-03,
+        -1.16108758537870949e-01,
+        -2.64328615927446164e-01,
+        -4.34801973864711866e-01,
+        -7.58050067492736512e-01,
+        -8.88940620540061066e-01,
+        -7.96994965718255344e-01,
+        -4.80718510551330747e-01,
+        -4.19262833147115543e-01,
+        -3.09282961835476600e-01,
+        -2.25479461405822201e-01,
+        -1.41593175140570571e-01,
+        -3.36269743946926103e-01,
+        -8.35466304817607114e-02,
+        3.64896041802370230e-01,
+        7.37331750495671123e-01,
+        6.06473477175441977e-01,
+        6.11485008840567001e-01,
+        5.52580549952441213e-01,
+        4.73364292062967595e-01,
+        1.42659463465316272e-01,
+        3.16761662509830566e-01,
+        4.94589629916565521e-01,
+        4.70300872579090812e-01,
+        -3.42513038821086954e-02,
+        -2.52852035442648226e-01,
+        -4.37733043133283373e-01,
+        -5.43108348082770864e-01,
+    ],
+];
+pub const CONSOLE7CHANNEL: [[f64; 32]; 2] = [
+    [
+        3.09790908488706107e-01,
+        -5.53861069737051182e-01,
+        6.80555435886559756e-01,
+        -6.80555435886559423e-01,
+        -5.91197612824950536e-01,
+        6.80555435886559534e-01,
+        -6.80555435886559423e-01,
+        2.40046521406028335e-01,
+        4.36003032779821831e-01,
+        -6.80555435886559201e-01,
+        6.80555435886559201e-01,
+        4.20239762218707236e-01,
+        -6.80555435886564974e-01,
+        6.80555435886565308e-01,
+        -5.09775015121101349e-01,
+        -6.05229470338034203e-01,
+        6.80555435886565863e-01,
+        -6.80555435886565863e-01,
+        -3.30986130311802101e-02,
+        6.80555435886566196e-01,
+        -6.80555435886566196e-01,
+        6.69652676226820565e-01,
+        3.74618963125914528e-01,
+        -6.80555435886566640e-01,
+        6.80555435886566751e-01,
+        -3.58388616364076762e-01,
+        -6.80555435886567195e-01,
+        6.80555435886567195e-01,
+        -6.80555435886567195e-01,
+        -7.75019983554545294e-02,
+        6.80555435886567195e-01,
+        -6.80555435886567195e-01,
+    ],
+    [
+        6.80555435886564419e-01,
+        2.05034957553978742e-01,
+        -2.91249396795932203e-01,
+        -1.57699566152389559e-01,
+        3.73893479456306327e-02,
+        -2.79434152915661538e-01,
+        -5.91490827827666599e-01,
+        -6.80555435886559423e-01,
+        -6.80555435886559978e-01,
+        -6.80555435886559201e-01,
+        -6.80555435886559201e-01,
+        -6.80555435886563531e-01,
+        -6.80555435886564974e-01,
+        -6.48611008109798770e-01,
+        -5.21162138818969445e-01,
+        -3.41077212662524498e-01,
+        -6.41842288194523602e-01,
+        -1.77942915811357349e-01,
+        6.80555435886566085e-01,
+        6.80555435886566196e-01,
+        6.80555435886566196e-01,
+        6.80555435886566418e-01,
+        6.80555435886566640e-01,
+        6.80555435886566640e-01,
+        4.03472068797832650e-01,
+        6.67506044563668688e-01,
+        6.80555435886567195e-01,
+        6.80555435886567195e-01,
+        -6.54504243496747129e-02,
+        -5.75255206204364655e-01,
+        -6.80555435886567195e-01,
+        -6.80555435886567195e-01,
+    ],
+];
+pub const CONSOLE7BUSS: [[f64; 32]; 2] = [
+    [
+        1.20789059828607021e-01,
+        -2.29537341053790478e-01,
+        6.57738091660415636e-01,
+        -6.34944239741451977e-01,
+        -2.50570669198413531e-01,
+        6.95575947160502084e-01,
+        -5.46733425105724202e-01,
+        9.30157180419484142e-02,
+        1.73500961505961199e-01,
+        -6.32327306340412232e-01,
+        7.63369753226417869e-01,
+        1.66652618464868174e-01,
+        -6.55517334092871495e-01,
+        6.08414791375571751e-01,
+        -2.07269588541053373e-01,
+        -2.59282440161718919e-01,
+        5.83306819125883558e-01,
+        -8.83998039706767935e-01,
+        -1.27312129053173059e-02,
+        8.10637004260773564e-01,
+        -6.27640685065884663e-01,
+        3.14430111771986653e-01,
+        1.47327493375692786e-01,
+        -6.02715590203567353e-01,
+        9.47956816523050749e-01,
+        -1.40597152956454030e-01,
+        -7.01447522690993419e-01,
+        5.46719719875701449e-01,
+        -3.95052566343566669e-01,
+        -2.98275180157788755e-02,
+        5.30411208125248335e-01,
+        -7.24363330871006239e-01,
+    ],
+    [
+        4.30308032591605383e-01,
+        7.92717849756745252e-02,
+        -1.13342398078831036e-01,
+        -6.08318620871689114e-02,
+        1.43821144176082757e-02,
+        -1.08623306513216708e-01,
+        -2.50747223771532191e-01,
+        -4.20272406451607905e-01,
+        -7.90791689024251454e-01,
+        -1.13564796466671281e+00,
+        -8.63503581205532722e-01,
+        -4.48138476351001569e-01,
+        -3.63366251515770622e-01,
+        -2.91607290940843922e-01,
+        -2.12825427983898974e-01,
+        -1.33486827250152068e-01,
+        -2.85738042539209092e-01,
+        -6.87010618286542640e-02,
+        3.61354187774107827e-01,
+        8.03941684190621286e-01,
+        6.41547434979316877e-01,
+        6.09230049203304036e-01,
+        5.39597570063029330e-01,
+        4.54977501253370387e-01,
+        1.59471531654440973e-01,
+        3.11577401621802863e-01,
+        4.89406888217030711e-01,
+        4.50943048286193970e-01,
+        -2.51843242563928524e-02,
+        -2.41267358531666060e-01,
+        -4.21878850092501534e-01,
+        -5.31814080965229197e-01,
+    ],
+];
+pub const PRESSURE4: [[f64; 32]; 2] = [
+    [
+        3.73771153937291323e-01,
+        -4.93292770615997744e-01,
+        1.08052252155189565e+00,
+        -9.33378377873151921e-01,
+        -3.38682841756000885e-01,
+        8.86496502326078462e-01,
+        -6.99430474528380919e-01,
+        1.16410775229641994e-01,
+        1.97417564477237295e-01,
+        -7.76339851469947106e-01,
+        9.09342579792554506e-01,
+        2.06782542603550223e-01,
+        -7.88758511221199332e-01,
+        7.44763267112431349e-01,
+        -2.62599740065951215e-01,
+        -3.35736325029687699e-01,
+        7.04479065849291430e-01,
+        -1.00087216944003887e+00,
+        -9.11477765506477251e-03,
+        9.69503957689259344e-01,
+        -7.88797883983780257e-01,
+        3.90631963257467463e-01,
+        1.89530732895011517e-01,
+        -7.42562135289052239e-01,
+        1.07821044702395419e+00,
+        -1.84264521100483691e-01,
+        -8.70268691817453366e-01,
+        6.96960544019991635e-01,
+        -5.22658205321972158e-01,
+        -4.03272830185266370e-02,
+        6.57032737383936416e-01,
+        -8.67539920798597541e-01,
+    ],
+    [
+        1.31206018733203589e+00,
+        1.89798742741228765e-01,
+        -1.98020091091480555e-01,
+        -1.08372548442536853e-01,
+        -1.06711960013536073e-02,
+        -1.45716493031767397e-01,
+        -3.25455399483826013e-01,
+        -5.25421372298077172e-01,
+        -9.62866632446115700e-01,
+        -1.19565824351283889e+00,
+        -1.01369260210166590e+00,
+        -5.83355637999245613e-01,
+        -5.10625941659291205e-01,
+        -3.68157411602420603e-01,
+        -2.70261963387381510e-01,
+        -1.71039066400792000e-01,
+        -3.98694844420587224e-01,
+        -9.82749381503643243e-02,
+        4.38607195455649357e-01,
+        9.48071697181208539e-01,
+        7.46469105750023676e-01,
+        7.40328188978293222e-01,
+        6.71716605646668241e-01,
+        5.73950865360169371e-01,
+        1.67832660164045439e-01,
+        3.75253901442988924e-01,
+        6.14954684215865854e-01,
+        5.76284960305744720e-01,
+        -4.13628924639345627e-02,
+        -3.01393038195602092e-01,
+        -5.26500518748681312e-01,
+        -6.60070901409594235e-01,
+    ],
+];
+pub const TOTAPE8_UNDER_BIAS: [[f64; 32]; 2] = [
+    [
+        1.34838432732432081e-02,
+        -6.31654467811179177e-01,
+        9.10184976224927555e-01,
+        -7.21227436395150523e-01,
+        -8.49477910715603524e-01,
+        9.31699888763603323e-01,
+        -8.28060679151994661e-01,
+        1.07968147816744475e-02,
+        8.12468583177789028e-01,
+        -9.19270888246211171e-01,
+        8.79031092170412642e-01,
+        7.56113472067067027e-01,
+        -9.25146755085710870e-01,
+        9.24357691422833860e-01,
+        -2.49228584736839620e-01,
+        -6.98251984349628319e-01,
+        9.21298378431542386e-01,
+        -9.42080749519765837e-01,
+        -5.35684962507503526e-01,
+        9.36848124708489327e-01,
+        -7.35052341496051143e-01,
+        4.73316605866187456e-01,
+        5.18935018811247484e-01,
+        -9.23701109336411141e-01,
+        9.51450124548439025e-01,
+        2.73895206171992922e-01,
+        -9.22881699077797157e-01,
+        7.97130344300800986e-01,
+        -5.32382445069164767e-01,
+        -2.90638354753297468e-01,
+        9.17274021296772823e-01,
+        -9.33832141480057998e-01,
+    ],
+    [
+        4.73327428954441742e-02,
+        7.24703606888730534e-02,
+        1.93096747742224517e-01,
+        5.19855219682925362e-01,
+        5.41685785945618892e-01,
+        -1.73942164585329323e-01,
+        -5.07958000029467005e-01,
+        -8.13727657377771973e-01,
+        -9.40625290567526307e-01,
+        -9.56351557242464456e-01,
+        -8.59156381531102298e-01,
+        -5.74590233373353021e-01,
+        -2.42661208275344181e-01,
+        -7.42633834696992068e-01,
+        -6.32970157718984239e-01,
+        -4.03164758703829784e-01,
+        -1.10399208421311665e-01,
+        3.18694437762136018e-01,
+        8.46608644805935784e-01,
+        9.37143236075945962e-01,
+        8.89915500899483680e-01,
+        9.23856962687890637e-01,
+        9.17944621928711291e-01,
+        8.93045956414499131e-01,
+        8.50630900117412048e-01,
+        8.86825525265045544e-01,
+        8.86218583401169768e-01,
+        7.53951804235990508e-01,
+        -3.17228603159863898e-01,
+        -4.65374805463184715e-01,
+        -7.47876604532052736e-01,
+        -8.75231572198746810e-01,
+    ],
+];
+pub const TOTAPE8_OVER_BIAS: [[f64; 32]; 2] = [
+    [
+        0.00000000000000000e+00,
+        -3.82637800947872808e-01,
+        5.66038342199275069e-01,
+        -2.93718534594462843e-01,
+        -2.37061308378451324e-01,
+        6.12430668249874355e-01,
+        -4.35997061026225319e-01,
+        2.03525287094408792e-02,
+        4.00832013612035298e-01,
+        -5.94461432217457730e-01,
+        3.55746726382762724e-01,
+        1.46810216267140886e-01,
+        -4.15561033184861983e-01,
+        4.90315810657291518e-01,
+        -1.41521119721951610e-01,
+        -3.17966123617339336e-01,
+        5.99238280267601731e-01,
+        -4.55560257337040975e-01,
+        -7.77165563285589084e-02,
+        3.50860472181058980e-01,
+        -3.84235025863160773e-01,
+        2.23499077092990772e-01,
+        2.09109680941460957e-01,
+        -5.79234896507573871e-01,
+        5.47132662184139140e-01,
+        -2.92131082181654872e-02,
+        -2.95966265313204080e-01,
+        3.96223208391621173e-01,
+        -4.20016320404519028e-01,
+        -1.27292659624299087e-01,
+        5.34654097407262552e-01,
+        -5.96953126075818852e-01,
+    ],
+    [
+        0.00000000000000000e+00,
+        2.39446695087851935e-01,
+        1.88705103858282985e-01,
+        3.90433489825364813e-02,
+        -1.14663568389215681e-01,
+        -1.21811048635860439e-01,
+        -2.78546570845090380e-01,
+        -4.25866455954801548e-01,
+        -5.43962883562400190e-01,
+        -4.12156083518043226e-01,
+        -3.81259177599113042e-01,
+        -3.40906583081422465e-01,
+        -3.43038211588296282e-01,
+        -3.70490001952569592e-01,
+        -3.17781696687017001e-01,
+        -1.64148947583362570e-01,
+        4.58643036238558580e-03,
+        2.97567990191440090e-01,
+        3.96933789222489453e-01,
+        4.00428642510585908e-01,
+        3.95146780706475964e-01,
+        5.19084921697625545e-01,
+        4.95704904922852463e-01,
+        4.65492660659734325e-01,
+        4.53558390020906754e-01,
+        2.51885615141137886e-01,
+        1.04599604780119163e-01,
+        -8.76004701285447857e-02,
+        -2.20107007242341363e-01,
+        -2.41685558796718658e-01,
+        -3.83510984650586972e-01,
+        -4.42335337912806104e-01,
+    ],
+];
@@ -0,0 +1,31 @@
+// Exposes one upstream airwindows plugin to `render.cpp`. `regenerate.sh` compiles this file once
+// per plugin with -DPLUGIN=<name>, since the headers of different plugins declare the same global
+// names and can't be included in the same file.
+
+#include <cmath>
+#include <vector>
+
+#define STRINGIFY(x) #x
+#define HEADER(name) STRINGIFY(name.h)
+#include HEADER(PLUGIN)
+
+#define CONCAT2(a, b) a##b
+#define CONCAT(a, b) CONCAT2(a, b)
+
+// Sets the parameters which are not NaN, in the order of the plugin's parameters, and processes
+// `left` and `right` in place as a single block.
+extern "C" void CONCAT(render_, PLUGIN)(const float *parameters, int num_parameters, double *left,
+                                        double *right, int frames) {
+	PLUGIN plugin(nullptr);
+	for (int i = 0; i < num_parameters; i++) {
+		if (!std::isnan(parameters[i])) plugin.setParameter(i, parameters[i]);
+	}
+	std::vector<double> out_left(frames), out_right(frames);
+	double *inputs[2] = {left, right};
+	double *outputs[2] = {out_left.data(), out_right.data()};
+	plugin.processDoubleReplacing(inputs, outputs, frames);
+	for (int i = 0; i < frames; i++) {
+		left[i] = out_left[i];
+		right[i] = out_right[i];
+	}
+}
@@ -0,0 +1,53 @@
+#!/bin/sh
+# Renders data.rs with the upstream airwindows plugins at the commit pinned in AIRWINDOWS_COMMIT,
+# and records that commit in the header of data.rs and in the crate README.
+#
+#   ./regenerate.sh          render with the pinned commit
+#   ./regenerate.sh --pin    pin the current upstream master first, then render
+#
+# Needs git, a C++ compiler (CXX, default c++) and network access. Commit AIRWINDOWS_COMMIT,
+# data.rs and README.md together.
+set -eu
+cd "$(dirname "$0")"
+
+REPO=https://github.com/airwindows/airwindows.git
+PLUGINS="Density PurestDrive Console7Channel Console7Buss Pressure4 ToTape8"
+CXX=${CXX:-c++}
+# No fused multiply-adds, which the Rust ports don't use either
+CXXFLAGS="-O0 -ffp-contract=off"
+
+if [ "${1:-}" = "--pin" ]; then
+	git ls-remote "$REPO" refs/heads/master | cut -f1 > AIRWINDOWS_COMMIT
+fi
+if [ ! -s AIRWINDOWS_COMMIT ]; then
+	echo "No upstream commit is pinned yet, run $0 --pin" >&2
+	exit 1
+fi
+COMMIT=$(cat AIRWINDOWS_COMMIT)
+
+WORK=$(mktemp -d)
+trap 'rm -rf "$WORK"' EXIT
+git init -q "$WORK/airwindows"
+git -C "$WORK/airwindows" fetch -q --depth 1 "$REPO" "$COMMIT"
+git -C "$WORK/airwindows" checkout -q FETCH_HEAD
+if [ "$(git -C "$WORK/airwindows" rev-parse HEAD)" != "$COMMIT" ]; then
+	echo "Fetched the wrong commit" >&2
+	exit 1
+fi
+
+OBJECTS=""
+for plugin in $PLUGINS; do
+	src="$WORK/airwindows/plugins/LinuxVST/src/$plugin"
+	for file in "$src/$plugin.cpp" "$src/${plugin}Proc.cpp" plugin.cpp; do
+		object="$WORK/$plugin-$(basename "$file" .cpp).o"
+		# Every plugin defines createEffectInstance, so each gets its own name
+		$CXX $CXXFLAGS -c -Ivst -I"$src" -DPLUGIN="$plugin" \
+			-DcreateEffectInstance="createEffectInstance_$plugin" "$file" -o "$object"
+		OBJECTS="$OBJECTS $object"
+	done
+done
+$CXX $CXXFLAGS -DAIRWINDOWS_COMMIT="\"$COMMIT\"" render.cpp $OBJECTS -o "$WORK/render"
+"$WORK/render" > data.rs
+sed -i "s|^Upstream commit of the golden data: .*|Upstream commit of the golden data: \`$COMMIT\`|" \
+	../../README.md
+echo "Rendered data.rs with airwindows $COMMIT"
@@ -0,0 +1,85 @@
+// Renders the golden data in `data.rs` used by `../golden.rs` with the upstream airwindows
+// plugins. Build and run it with `regenerate.sh`, which fetches the plugin sources at the commit
+// pinned in `AIRWINDOWS_COMMIT` and links this file with one `plugin.cpp` per plugin.
+//
+// Each plugin processes the whole test signal through its own processDoubleReplacing() as a
+// single block at 44100 Hz. The plugins dither their output and replace near silent samples with
+// tiny noise, both many orders of magnitude below the tolerance of the tests. Parameters are set
+// through setParameter(), which takes a float, so all parameter values are exact in f32 to match
+// the f64 parameters of the ports.
+//
+// Original code: Copyright (c) 2016 airwindows, Airwindows uses the MIT license
+
+#include <cmath>
+#include <cstdio>
+#include <vector>
+
+#ifndef AIRWINDOWS_COMMIT
+#error "Build with regenerate.sh, which defines AIRWINDOWS_COMMIT"
+#endif
+
+static const double SAMPLE_RATE = 44100.0;
+static const int FRAMES = 4096;
+static const int STRIDE = 128;
+// Leaves a parameter at the plugin's default
+static const float DEFAULT = NAN;
+
+typedef void (*RenderFn)(const float *parameters, int num_parameters, double *left, double *right,
+                         int frames);
+
+extern "C" {
+void render_Density(const float *, int, double *, double *, int);
+void render_PurestDrive(const float *, int, double *, double *, int);
+void render_Console7Channel(const float *, int, double *, double *, int);
+void render_Console7Buss(const float *, int, double *, double *, int);
+void render_Pressure4(const float *, int, double *, double *, int);
+void render_ToTape8(const float *, int, double *, double *, int);
+}
+
+static void test_signal(std::vector<double> &l, std::vector<double> &r, double gain) {
+	l.resize(FRAMES);
+	r.resize(FRAMES);
+	for (int i = 0; i < FRAMES; i++) {
+		double t = i / SAMPLE_RATE;
+		double burst = (i % 1024) < 512 ? 1.0 : 0.2;
+		l[i] = 0.7 * sin(2.0 * M_PI * 220.0 * t) + 0.3 * burst * sin(2.0 * M_PI * 3520.0 * t + 0.5);
+		r[i] = 0.6 * sin(2.0 * M_PI * 330.0 * t + 1.0) + 0.4 * burst * sin(2.0 * M_PI * 5120.0 * t);
+		l[i] *= gain;
+		r[i] *= gain;
+	}
+}
+
+// Renders the test signal scaled by `gain` through a plugin and prints every STRIDE frame
+static void render(const char *name, RenderFn fn, std::vector<float> parameters, double gain) {
+	std::vector<double> l, r;
+	test_signal(l, r, gain);
+	fn(parameters.data(), (int)parameters.size(), l.data(), r.data(), FRAMES);
+	printf("pub const %s: [[f64; %d]; 2] = [\n", name, FRAMES / STRIDE);
+	const std::vector<double> *channels[2] = {&l, &r};
+	for (int c = 0; c < 2; c++) {
+		printf("    [\n");
+		for (int i = 0; i < FRAMES; i += STRIDE) printf("        %.17e,\n", (*channels[c])[i]);
+		printf("    ],\n");
+	}
+	printf("];\n");
+}
+
+int main() {
+	printf("//! Golden data rendered by `render.cpp` with the upstream airwindows plugins at commit\n"
+	       "//! `%s`, see `regenerate.sh`. Do not edit.\n"
+	       "#![allow(clippy::excessive_precision)]\n\n",
+	       AIRWINDOWS_COMMIT);
+	render("DENSITY", render_Density, {0.6875f, 0.3125f, 0.90625f, 0.8125f}, 1.0);
+	render("PURESTDRIVE", render_PurestDrive, {0.8125f}, 1.0);
+	render("CONSOLE7CHANNEL", render_Console7Channel, {1.0f}, 1.5);
+	render("CONSOLE7BUSS", render_Console7Buss, {0.90625f}, 1.0);
+	render("PRESSURE4", render_Pressure4, {0.6875f, 0.40625f, 0.3125f, 0.90625f}, 1.0);
+	// The flutter is randomised, so it is disabled
+	render("TOTAPE8_UNDER_BIAS", render_ToTape8,
+	       {0.6875f, 0.59375f, 0.40625f, 0.0f, DEFAULT, 0.3125f, 0.59375f, 0.40625f, 0.59375f,
+	        0.90625f},
+	       1.0);
+	render("TOTAPE8_OVER_BIAS", render_ToTape8,
+	       {0.5f, 0.5f, 0.5f, 0.0f, DEFAULT, 0.8125f, 0.3125f, 0.6875f, 0.5f, 1.0f}, 1.0);
+	return 0;
+}
@@ -0,0 +1,107 @@
+// Minimal stand-in for the VST 2.4 SDK headers with what the airwindows LinuxVST plugins use, so
+// that `render.cpp` can build and run them offline. This is not a VST host: the host callback is
+// never called and the sample rate is fixed at 44100 Hz.
+
+#ifndef __audioeffectx__
+#define __audioeffectx__
+#define __audioeffect__
+
+#include <cmath>
+#include <cstdint>
+#include <cstdio>
+#include <cstdlib>
+#include <cstring>
+
+typedef int32_t VstInt32;
+typedef intptr_t VstIntPtr;
+typedef VstIntPtr (*audioMasterCallback)(void *, VstInt32, VstInt32, VstIntPtr, void *, float);
+
+enum {
+	kVstMaxProgNameLen = 24,
+	kVstMaxParamStrLen = 8,
+	kVstMaxVendorStrLen = 64,
+	kVstMaxProductStrLen = 64,
+	kVstMaxEffectNameLen = 32,
+	kVstMaxLabelLen = 64,
+	kVstMaxShortLabelLen = 8,
+};
+
+enum VstPlugCategory {
+	kPlugCategUnknown = 0,
+	kPlugCategEffect,
+	kPlugCategSynth,
+	kPlugCategAnalysis,
+	kPlugCategMastering,
+	kPlugCategSpacializer,
+	kPlugCategRoomFx,
+	kPlugSurroundFx,
+	kPlugCategRestoration,
+	kPlugCategOfflineProcess,
+	kPlugCategShell,
+	kPlugCategGenerator,
+	kPlugCategMaxCount,
+};
+
+inline char *vst_strncpy(char *dst, const char *src, size_t maxLen) {
+	char *result = strncpy(dst, src, maxLen);
+	dst[maxLen] = 0;
+	return result;
+}
+
+inline char *vst_strncat(char *dst, const char *src, size_t maxLen) {
+	char *result = strncat(dst, src, maxLen);
+	dst[maxLen] = 0;
+	return result;
+}
+
+class AudioEffect {
+public:
+	AudioEffect(audioMasterCallback, VstInt32, VstInt32) {}
+	virtual ~AudioEffect() {}
+
+	void setNumInputs(VstInt32) {}
+	void setNumOutputs(VstInt32) {}
+	void setUniqueID(VstInt32) {}
+	void setInitialDelay(VstInt32) {}
+	void canProcessReplacing(bool = true) {}
+	void canDoubleReplacing(bool = true) {}
+	void programsAreChunks(bool = true) {}
+	void noTail(bool = true) {}
+	void isSynth(bool = true) {}
+	bool updateDisplay() { return false; }
+	float getSampleRate() { return sampleRate; }
+	virtual void setSampleRate(float rate) { sampleRate = rate; }
+
+	void float2string(float value, char *text, VstInt32 maxLen) {
+		char buffer[64];
+		snprintf(buffer, sizeof(buffer), "%.3f", value);
+		vst_strncpy(text, buffer, maxLen);
+	}
+	void int2string(VstInt32 value, char *text, VstInt32 maxLen) {
+		char buffer[64];
+		snprintf(buffer, sizeof(buffer), "%d", value);
+		vst_strncpy(text, buffer, maxLen);
+	}
+	void dB2string(float value, char *text, VstInt32 maxLen) {
+		if (value <= 0) vst_strncpy(text, "-oo", maxLen);
+		else float2string(20.0f * log10f(value), text, maxLen);
+	}
+	void Hz2string(float samples, char *text, VstInt32 maxLen) {
+		float2string(samples ? getSampleRate() / samples : 0, text, maxLen);
+	}
+	void ms2string(float samples, char *text, VstInt32 maxLen) {
+		float2string(samples * 1000.0f / getSampleRate(), text, maxLen);
+	}
+
+protected:
+	float sampleRate = 44100.0f;
+	VstInt32 curProgram = 0;
+};
+
+class AudioEffectX : public AudioEffect {
+public:
+	AudioEffectX(audioMasterCallback audioMaster, VstInt32 numPrograms, VstInt32 numParams)
+		: AudioEffect(audioMaster, numPrograms, numParams) {}
+};
+
+#endif
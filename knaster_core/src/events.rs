@@ -0,0 +1,100 @@
+//! Events sent from the audio thread to the main thread.
+//!
+//! [`UGen`](crate::UGen)s can send small [`UGenEvent`]s through the [`AudioCtx`](crate::AudioCtx)
+//! without allocating, e.g. when a trigger fires or an analysis value is ready. The events are
+//! received on the main thread from an [`EventReceiver`]. Create a channel using
+//! [`event_channel`] and attach the sender to the `AudioCtx`, or use the receiver provided by
+//! `knaster_graph`.
+
+use knaster_primitives::Seconds;
+
+/// The maximum number of values in a [`UGenEvent`]
+pub const MAX_EVENT_VALUES: usize = 4;
+
+/// Identifies the node which sent an event. Set by the graph running the node; `knaster_graph`
+/// converts it into a `NodeId`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Default)]
+pub struct EventSource {
+    /// Identifies the graph the node is in
+    pub graph: u64,
+    /// Identifies the node within the graph
+    pub node: u64,
+}
+
+/// An event sent from a [`UGen`](crate::UGen) on the audio thread.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct UGenEvent {
+    /// The node which sent the event
+    pub source: EventSource,
+    /// A user chosen id for telling events apart
+    pub id: u32,
+    values: [f64; MAX_EVENT_VALUES],
+    num_values: u8,
+    /// The time the event was sent, in frames since the audio processing started
+    pub frame: u64,
+}
+impl UGenEvent {
+    /// Create a new event. Values after [`MAX_EVENT_VALUES`] are dropped.
+    pub fn new(source: EventSource, id: u32, values: &[f64], frame: u64) -> Self {
+        let num_values = values.len().min(MAX_EVENT_VALUES);
+        let mut event_values = [0.0; MAX_EVENT_VALUES];
+        event_values[..num_values].copy_from_slice(&values[..num_values]);
+        Self {
+            source,
+            id,
+            values: event_values,
+            num_values: num_values as u8,
+            frame,
+        }
+    }
+    /// The values sent with the event
+    pub fn values(&self) -> &[f64] {
+        &self.values[..self.num_values as usize]
+    }
+    /// The time the event was sent, given the sample rate of the audio processing
+    pub fn time(&self, sample_rate: u32) -> Seconds {
+        Seconds::from_samples(self.frame, sample_rate as u64)
+    }
+}
+
+/// Sends [`UGenEvent`]s from the audio thread. Sending never blocks or allocates; if the
+/// channel is full the event is dropped.
+pub enum EventSender {
+    /// Sends via a ring buffer to an [`EventReceiver`]
+    RingBuffer(rtrb::Producer<UGenEvent>),
+    /// There is no receiver. Events are dropped.
+    None,
+}
+impl EventSender {
+    /// Send an event. Returns false if the event could not be sent.
+    pub fn send(&mut self, event: UGenEvent) -> bool {
+        match self {
+            EventSender::RingBuffer(sender) => sender.push(event).is_ok(),
+            EventSender::None => false,
+        }
+    }
+}
+
+/// Receives [`UGenEvent`]s sent from the audio thread.
+pub struct EventReceiver {
+    receiver: rtrb::Consumer<UGenEvent>,
+}
+impl EventReceiver {
+    /// Receive the next event, if there is one
+    pub fn try_recv(&mut self) -> Option<UGenEvent> {
+        self.receiver.pop().ok()
+    }
+    /// Call `event_handler` for every event received since the last call
+    pub fn recv(&mut self, mut event_handler: impl FnMut(UGenEvent)) {
+        while let Ok(event) = self.receiver.pop() {
+            event_handler(event);
+        }
+    }
+}
+
+/// Create a channel for [`UGenEvent`]s holding up to `capacity` events which haven't been
+/// received yet.
+pub fn event_channel(capacity: usize) -> (EventSender, EventReceiver) {
+    let (tx, rx) = rtrb::RingBuffer::new(capacity);
+    (EventSender::RingBuffer(tx), EventReceiver { receiver: rx })
+}
@@ -33,6 +33,7 @@ mod core {
     // pub use std::*;
 }
 
+pub mod events;
 pub mod log;
 mod parameters;
 mod ugen;
@@ -1,3 +1,4 @@
+use crate::events::{EventSender, EventSource, UGenEvent};
 use crate::log::ArLogSender;
 use crate::numeric_array::NumericArray;
 use crate::{Param, ParameterError, ParameterHint, ParameterType, ParameterValue, rt_log};
@@ -9,6 +10,8 @@ pub struct AudioCtx {
     sample_rate: u32,
     block_size: usize,
     logger: ArLogSender,
+    events: EventSender,
+    event_source: EventSource,
     /// Metadata about the current context of block processing.
     pub block: BlockMetadata,
 }
@@ -19,9 +22,39 @@ impl AudioCtx {
             sample_rate,
             block_size,
             logger,
+            events: EventSender::None,
+            event_source: EventSource::default(),
             block: BlockMetadata::new(block_size),
         }
     }
+    /// Set the sender used by [`Self::send_event`]. Without a sender, events are dropped.
+    pub fn set_event_sender(&mut self, events: EventSender) {
+        self.events = events;
+    }
+    /// The source of the events sent through this [`AudioCtx`], i.e. the node currently
+    /// being processed
+    pub fn event_source(&self) -> EventSource {
+        self.event_source
+    }
+    /// Set the source of the events sent through this [`AudioCtx`]. Called by the graph
+    /// before processing each node.
+    pub fn set_event_source(&mut self, source: EventSource) {
+        self.event_source = source;
+    }
+    /// Send an event to the main thread. `frame_in_block` is the frame within the current block
+    /// the event happened at, used for the event timestamp. At most
+    /// [`MAX_EVENT_VALUES`](crate::events::MAX_EVENT_VALUES) values are sent.
+    ///
+    /// Returns false if the event could not be sent, e.g. because the channel is full.
+    pub fn send_event(&mut self, id: u32, values: &[f64], frame_in_block: usize) -> bool {
+        let event = UGenEvent::new(
+            self.event_source,
+            id,
+            values,
+            self.frame_clock() + frame_in_block as u64,
+        );
+        self.events.send(event)
+    }
     /// Get the block size
     pub fn block_size(&self) -> usize {
         self.block_size
@@ -244,6 +277,11 @@ pub trait UGen {
     /// is safe to allocate here.
     #[allow(unused)]
     fn init(&mut self, sample_rate: u32, block_size: usize) {}
+    /// Replace the seed of any randomness the UGen produces. A graph with a master seed calls
+    /// this with a seed derived from it when the UGen is pushed, so that its randomness is
+    /// reproducible. Wrappers forward the seed to the UGen they wrap.
+    #[allow(unused)]
+    fn reseed(&mut self, seed: u64) {}
     /// Process a single frame and return it
     fn process(
         &mut self,
@@ -1,7 +1,11 @@
 //! Contains DSP related code in knaster_core which is neither implementations
 //! of [`Gen`] nor wrappers_graph.
 
+pub mod atomic;
 #[cfg(any(feature = "alloc", feature = "std"))]
 pub mod buffer;
+pub mod response;
+#[cfg(any(feature = "alloc", feature = "std"))]
+pub mod shared_table;
 pub mod wavetable;
 pub mod xorrng;
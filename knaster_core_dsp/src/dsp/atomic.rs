@@ -0,0 +1,34 @@
+//! Atomic floats for sharing values between the audio thread and other threads without locking
+
+use crate::core::sync::atomic::{AtomicU32, Ordering};
+
+/// An `f32` stored as bits in an [`AtomicU32`].
+///
+/// All operations use relaxed ordering, which is enough for values that stand on their own, such
+/// as levels published by a meter or parameters read by the audio thread. Use a separate flag
+/// with release/acquire ordering to signal that a group of values has changed.
+#[derive(Debug, Default)]
+pub struct AtomicF32(AtomicU32);
+impl AtomicF32 {
+    #[allow(missing_docs)]
+    pub fn new(value: f32) -> Self {
+        Self(AtomicU32::new(value.to_bits()))
+    }
+    #[allow(missing_docs)]
+    pub fn load(&self) -> f32 {
+        f32::from_bits(self.0.load(Ordering::Relaxed))
+    }
+    #[allow(missing_docs)]
+    pub fn store(&self, value: f32) {
+        self.0.store(value.to_bits(), Ordering::Relaxed);
+    }
+    /// Store `value` and return the previous value
+    pub fn swap(&self, value: f32) -> f32 {
+        f32::from_bits(self.0.swap(value.to_bits(), Ordering::Relaxed))
+    }
+    /// Raise the value to `value` if it is higher. Only valid for non-negative values, for which
+    /// the bit patterns are ordered like the values.
+    pub fn fetch_max(&self, value: f32) {
+        self.0.fetch_max(value.to_bits(), Ordering::Relaxed);
+    }
+}
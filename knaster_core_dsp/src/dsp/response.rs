@@ -0,0 +1,153 @@
+//! Frequency response of filters
+//!
+//! [`FrequencyResponse`] is implemented by filters whose response can be computed from their
+//! parameters. Since the response only depends on the parameter values, it can be computed on a
+//! separate instance of the filter outside of the audio thread, e.g. to draw EQ curves in a GUI:
+//!
+//! ```
+//! use knaster_core_dsp::{dsp::response::FrequencyResponse, svf::{SvfFilter, SvfFilterType}};
+//! let filter = SvfFilter::<f32>::new(SvfFilterType::Low, 1000., 0.707, 0.0);
+//! let gain_db = filter.frequency_response(1000., 48000.).magnitude_db();
+//! assert!((gain_db + 3.0).abs() < 0.1);
+//! ```
+
+use core::ops::{Add, Div, Mul, Neg, Sub};
+use knaster_core::Float;
+
+/// A complex number, used for frequency responses
+#[derive(Copy, Clone, Debug, PartialEq, Default)]
+pub struct Complex<T> {
+    /// Real part
+    pub re: T,
+    /// Imaginary part
+    pub im: T,
+}
+impl<T: Float> Complex<T> {
+    #[allow(missing_docs)]
+    pub fn new(re: T, im: T) -> Self {
+        Self { re, im }
+    }
+    /// A complex number with only a real part
+    pub fn real(re: T) -> Self {
+        Self { re, im: T::ZERO }
+    }
+    /// A complex number with only an imaginary part
+    pub fn imaginary(im: T) -> Self {
+        Self { re: T::ZERO, im }
+    }
+    /// A complex number from magnitude and phase in radians
+    pub fn from_polar(magnitude: T, phase: T) -> Self {
+        Self {
+            re: magnitude * phase.cos(),
+            im: magnitude * phase.sin(),
+        }
+    }
+    /// The absolute value, i.e. the linear gain of a frequency response
+    pub fn magnitude(self) -> T {
+        self.re.hypot(self.im)
+    }
+    /// The magnitude in decibels
+    pub fn magnitude_db(self) -> T {
+        T::new(20.) * self.magnitude().log10()
+    }
+    /// The argument in radians between -PI and PI, i.e. the phase shift of a frequency response
+    pub fn phase(self) -> T {
+        self.im.atan2(self.re)
+    }
+    /// The complex conjugate
+    pub fn conj(self) -> Self {
+        Self {
+            re: self.re,
+            im: -self.im,
+        }
+    }
+}
+impl<T: Float> Add for Complex<T> {
+    type Output = Self;
+    fn add(self, rhs: Self) -> Self {
+        Self::new(self.re + rhs.re, self.im + rhs.im)
+    }
+}
+impl<T: Float> Sub for Complex<T> {
+    type Output = Self;
+    fn sub(self, rhs: Self) -> Self {
+        Self::new(self.re - rhs.re, self.im - rhs.im)
+    }
+}
+impl<T: Float> Mul for Complex<T> {
+    type Output = Self;
+    fn mul(self, rhs: Self) -> Self {
+        Self::new(
+            self.re * rhs.re - self.im * rhs.im,
+            self.re * rhs.im + self.im * rhs.re,
+        )
+    }
+}
+impl<T: Float> Mul<T> for Complex<T> {
+    type Output = Self;
+    fn mul(self, rhs: T) -> Self {
+        Self::new(self.re * rhs, self.im * rhs)
+    }
+}
+impl<T: Float> Div for Complex<T> {
+    type Output = Self;
+    fn div(self, rhs: Self) -> Self {
+        let denominator = rhs.re * rhs.re + rhs.im * rhs.im;
+        Self::new(
+            (self.re * rhs.re + self.im * rhs.im) / denominator,
+            (self.im * rhs.re - self.re * rhs.im) / denominator,
+        )
+    }
+}
+impl<T: Float> Add<T> for Complex<T> {
+    type Output = Self;
+    fn add(self, rhs: T) -> Self {
+        Self::new(self.re + rhs, self.im)
+    }
+}
+impl<T: Float> Neg for Complex<T> {
+    type Output = Self;
+    fn neg(self) -> Self {
+        Self::new(-self.re, -self.im)
+    }
+}
+
+/// Filters whose frequency response can be computed from their current parameters
+pub trait FrequencyResponse {
+    /// The complex frequency response at `freq` Hz, at the sample rate `sample_rate`.
+    ///
+    /// Nonlinear filters return the response of the filter linearised around 0, i.e. for small
+    /// input signals.
+    fn frequency_response(&self, freq: f64, sample_rate: f64) -> Complex<f64>;
+    /// The linear gain at `freq` Hz
+    fn magnitude_response(&self, freq: f64, sample_rate: f64) -> f64 {
+        self.frequency_response(freq, sample_rate).magnitude()
+    }
+    /// The phase shift in radians at `freq` Hz
+    fn phase_response(&self, freq: f64, sample_rate: f64) -> f64 {
+        self.frequency_response(freq, sample_rate).phase()
+    }
+}
+
+/// Returns the Laplace variable `s` of an analog prototype filter normalised to `cutoff_freq`,
+/// for a digital filter made using the bilinear transform with the cutoff prewarped.
+///
+/// The response of the digital filter at `freq` is the response of the analog prototype at
+/// `bilinear_s(freq, cutoff_freq, sample_rate)`.
+pub fn bilinear_s(freq: f64, cutoff_freq: f64, sample_rate: f64) -> Complex<f64> {
+    prewarped_s(freq, prewarp(cutoff_freq, sample_rate), sample_rate)
+}
+/// Like [`bilinear_s`], but taking the prewarped cutoff `g = tan(PI * cutoff_freq / sample_rate)`
+/// directly, for filters which modify `g`.
+pub fn prewarped_s(freq: f64, g: f64, sample_rate: f64) -> Complex<f64> {
+    Complex::imaginary(prewarp(freq, sample_rate) / g)
+}
+/// `tan(PI * freq / sample_rate)`
+fn prewarp<T: Float>(freq: T, sample_rate: T) -> T {
+    (T::PI * freq / sample_rate).tan()
+}
+/// Returns `z^-1` at `freq` for evaluating the response of a digital filter from its transfer
+/// function
+pub fn z_inverse(freq: f64, sample_rate: f64) -> Complex<f64> {
+    Complex::from_polar(1.0, -f64::TAU * freq / sample_rate)
+}
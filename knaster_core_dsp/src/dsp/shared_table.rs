@@ -0,0 +1,93 @@
+//! Tables of entries that a UGen reads on the audio thread while any other thread changes them,
+//! without locking or allocating, and padding of per-entry state for vectorized loops.
+
+use crate::core::marker::PhantomData;
+use crate::core::sync::{
+    Arc,
+    atomic::{AtomicBool, Ordering},
+};
+use crate::dsp::atomic::AtomicF32;
+use std::prelude::v1::*;
+
+/// Number of entries that are processed together in a vectorized loop
+pub(crate) const LANES: usize = 8;
+
+/// The length of per-entry state for `len` entries, padded to a multiple of [`LANES`]. The
+/// padding entries must be left silent.
+pub(crate) fn padded_len(len: usize) -> usize {
+    len.div_ceil(LANES) * LANES
+}
+
+struct Table<const N: usize> {
+    entries: Vec<[AtomicF32; N]>,
+    changed: AtomicBool,
+}
+
+/// A fixed number of entries of type `T`, each stored as `N` atomic floats and shared by all
+/// clones of the table.
+///
+/// Each value of an entry is set atomically on its own, so a reader can see a mix of an old and a
+/// new entry until the next change is flagged. The owning UGen picks up the changes at the start
+/// of its next block.
+pub struct SharedTable<T, const N: usize> {
+    table: Arc<Table<N>>,
+    _entry: PhantomData<fn() -> T>,
+}
+impl<T, const N: usize> Clone for SharedTable<T, N> {
+    fn clone(&self) -> Self {
+        Self {
+            table: self.table.clone(),
+            _entry: PhantomData,
+        }
+    }
+}
+impl<T, const N: usize> SharedTable<T, N>
+where
+    T: Copy + From<[f32; N]>,
+    [f32; N]: From<T>,
+{
+    /// A table with one entry per item in `entries`
+    pub(crate) fn new(entries: impl IntoIterator<Item = T>) -> Self {
+        let entries = entries
+            .into_iter()
+            .map(|entry| <[f32; N]>::from(entry).map(AtomicF32::new))
+            .collect();
+        Self {
+            table: Arc::new(Table {
+                entries,
+                changed: AtomicBool::new(false),
+            }),
+            _entry: PhantomData,
+        }
+    }
+    /// The number of entries
+    pub fn len(&self) -> usize {
+        self.table.entries.len()
+    }
+    #[allow(missing_docs)]
+    pub fn is_empty(&self) -> bool {
+        self.table.entries.is_empty()
+    }
+    /// Set the entry at `index`
+    ///
+    /// # Panics
+    /// Panics if `index` is out of bounds
+    pub fn set(&self, index: usize, entry: T) {
+        let values = <[f32; N]>::from(entry);
+        for (atomic, value) in self.table.entries[index].iter().zip(values) {
+            atomic.store(value);
+        }
+        self.table.changed.store(true, Ordering::Release);
+    }
+    /// The entry at `index`
+    ///
+    /// # Panics
+    /// Panics if `index` is out of bounds
+    pub fn get(&self, index: usize) -> T {
+        T::from(self.table.entries[index].each_ref().map(AtomicF32::load))
+    }
+    /// Returns true once after any entry has been set
+    pub(crate) fn take_changed(&self) -> bool {
+        self.table.changed.swap(false, Ordering::Acquire)
+    }
+}
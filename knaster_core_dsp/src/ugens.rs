@@ -1,17 +1,47 @@
+#[macro_use]
+mod param_names;
+
+#[cfg(any(feature = "std", feature = "alloc"))]
+pub mod additive;
+#[cfg(any(feature = "std", feature = "alloc"))]
+pub mod analysis;
 #[cfg(any(feature = "std", feature = "alloc"))]
 pub mod buffer;
 #[cfg(any(feature = "std", feature = "alloc"))]
+pub mod cascade;
+#[cfg(any(feature = "std", feature = "alloc"))]
 pub mod closure;
 #[cfg(any(feature = "std", feature = "alloc"))]
 pub mod delay;
+#[cfg(any(feature = "std", feature = "alloc"))]
+pub mod meter;
+#[cfg(any(feature = "std", feature = "alloc"))]
+pub mod modal;
+#[cfg(any(feature = "std", feature = "alloc"))]
+pub mod modfx;
+#[cfg(any(feature = "std", feature = "alloc"))]
+pub mod pitch;
+#[cfg(any(feature = "std", feature = "alloc"))]
+pub mod reverb;
+#[cfg(any(feature = "std", feature = "alloc"))]
+pub mod waveguide;
 
 pub mod dynamics;
 pub mod envelopes;
+pub mod eq;
+pub mod fm;
 pub mod math;
 pub mod noise;
 pub mod onepole;
 pub mod osc;
 pub mod pan;
 pub mod polyblep;
+pub mod sequencer;
+pub mod stochastic;
 pub mod svf;
+pub mod tempo;
+pub mod trigger;
+pub mod unison;
 pub mod util;
+pub mod va_filter;
+pub mod waveshaper;
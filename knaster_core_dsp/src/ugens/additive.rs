@@ -0,0 +1,300 @@
+//! # Additive synthesis
+//!
+//! [`AdditiveBank`] is a bank of sine partials, each with its own frequency ratio, amplitude and
+//! phase. The spectrum is reshaped while it plays by setting partials through an
+//! [`AdditiveHandle`], e.g. to detune single partials or to morph between timbres.
+//!
+//! ```
+//! use knaster_core_dsp::additive::{AdditiveBank, Partial};
+//! let bank = AdditiveBank::<f32>::new(64, 110.0);
+//! let handle = bank.handle();
+//! // Push `bank` to a graph, then from any thread:
+//! handle.set(1, Partial { ratio: 2.01, amplitude: 0.3, phase: 0.0 });
+//! ```
+
+use crate::dsp::shared_table::{LANES, SharedTable, padded_len};
+use knaster_core::{Float, PFloat, impl_ugen};
+use std::prelude::v1::*;
+
+/// How often the oscillators are renormalized in samples
+const RENORMALIZE_INTERVAL: u32 = 64;
+
+/// One sine partial of an [`AdditiveBank`]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Partial {
+    /// Frequency as a ratio of the fundamental frequency of the bank
+    pub ratio: f32,
+    /// Linear amplitude
+    pub amplitude: f32,
+    /// Phase in the range 0-1
+    pub phase: f32,
+}
+impl Default for Partial {
+    fn default() -> Self {
+        Self {
+            ratio: 1.0,
+            amplitude: 0.0,
+            phase: 0.0,
+        }
+    }
+}
+
+impl From<Partial> for [f32; 3] {
+    fn from(partial: Partial) -> Self {
+        [partial.ratio, partial.amplitude, partial.phase]
+    }
+}
+impl From<[f32; 3]> for Partial {
+    fn from([ratio, amplitude, phase]: [f32; 3]) -> Self {
+        Self {
+            ratio,
+            amplitude,
+            phase,
+        }
+    }
+}
+
+/// The partials of an [`AdditiveBank`], from [`AdditiveBank::handle`]. Entry `i` is partial `i`.
+/// A new phase shifts the running partial instead of restarting it.
+pub type AdditiveHandle = SharedTable<Partial, 3>;
+
+/// Additive oscillator bank with a fixed number of sine partials.
+///
+/// The partials start as a harmonic series where partial `i` has ratio `i + 1` and amplitude
+/// `1 / (i + 1)`. Change them at runtime through the handle from [`AdditiveBank::handle`].
+/// Partials at or above the Nyquist frequency are silenced automatically and come back when
+/// `freq` is lowered again. The output is the plain sum of all partials.
+///
+/// Every partial is a recursive oscillator which is rotated by a complex multiplication per
+/// sample, so no sines are computed in the audio loop. All partials are processed in the same
+/// vectorized loop, which keeps hundreds of partials per voice cheap.
+pub struct AdditiveBank<F: Copy = f32> {
+    table: AdditiveHandle,
+    freq: F,
+    sample_duration: F,
+    ratios: Vec<F>,
+    amplitudes: Vec<F>,
+    phases: Vec<F>,
+    gains: Vec<F>,
+    /// Real part of the oscillator state
+    re: Vec<F>,
+    /// Imaginary part of the oscillator state, which is the sine output
+    im: Vec<F>,
+    cos: Vec<F>,
+    sin: Vec<F>,
+    samples_until_renormalize: u32,
+}
+#[impl_ugen]
+impl<F: Float> AdditiveBank<F> {
+    #[allow(missing_docs)]
+    pub fn new(num_partials: usize, freq: F) -> Self {
+        let num_partials = num_partials.max(1);
+        let table = AdditiveHandle::new((0..num_partials).map(|i| Partial {
+            ratio: (i + 1) as f32,
+            amplitude: 1.0 / (i + 1) as f32,
+            phase: 0.0,
+        }));
+        // Padding partials have zero gain and stay silent
+        let len = padded_len(num_partials);
+        let mut s = Self {
+            table,
+            freq,
+            sample_duration: F::ZERO,
+            ratios: vec![F::ZERO; len],
+            amplitudes: vec![F::ZERO; len],
+            phases: vec![F::ZERO; len],
+            gains: vec![F::ZERO; len],
+            re: vec![F::ONE; len],
+            im: vec![F::ZERO; len],
+            cos: vec![F::ONE; len],
+            sin: vec![F::ZERO; len],
+            samples_until_renormalize: RENORMALIZE_INTERVAL,
+        };
+        s.load_table();
+        s.reset_phases();
+        s
+    }
+    /// A handle for changing the partials of this bank from another thread
+    pub fn handle(&self) -> AdditiveHandle {
+        self.table.clone()
+    }
+    fn init(&mut self, sample_rate: u32, _block_size: usize) {
+        self.sample_duration = F::ONE / F::from(sample_rate).unwrap();
+        self.update_coefficients();
+    }
+    /// The fundamental frequency that the ratios of the partials are relative to
+    #[param(kind = Frequency, default = 110.0)]
+    pub fn freq(&mut self, freq: PFloat) {
+        self.freq = F::new(freq);
+        self.update_coefficients();
+    }
+    /// Restart all partials from their phases
+    #[param]
+    pub fn t_reset(&mut self) {
+        self.reset_phases();
+    }
+    fn reset_phases(&mut self) {
+        for ((re, im), &phase) in self.re.iter_mut().zip(&mut self.im).zip(&self.phases) {
+            *re = (phase * F::TAU).cos();
+            *im = (phase * F::TAU).sin();
+        }
+    }
+    /// Copy the shared table to the local arrays. A changed phase rotates the running oscillator
+    /// by the difference, so the partial keeps running without a reset.
+    fn load_table(&mut self) {
+        for i in 0..self.table.len() {
+            let partial = self.table.get(i);
+            self.ratios[i] = F::new(partial.ratio);
+            self.amplitudes[i] = F::new(partial.amplitude);
+            let phase = F::new(partial.phase);
+            if phase != self.phases[i] {
+                let angle = (phase - self.phases[i]) * F::TAU;
+                let (sin, cos) = (angle.sin(), angle.cos());
+                let re = self.re[i] * cos - self.im[i] * sin;
+                self.im[i] = self.re[i] * sin + self.im[i] * cos;
+                self.re[i] = re;
+                self.phases[i] = phase;
+            }
+        }
+        self.update_coefficients();
+    }
+    fn update_coefficients(&mut self) {
+        let step = self.freq * self.sample_duration;
+        let nyquist = F::new(0.5);
+        for i in 0..self.table.len() {
+            let partial_step = self.ratios[i] * step;
+            let angle = partial_step * F::TAU;
+            self.cos[i] = angle.cos();
+            self.sin[i] = angle.sin();
+            self.gains[i] = if partial_step.abs() < nyquist {
+                self.amplitudes[i]
+            } else {
+                F::ZERO
+            };
+        }
+    }
+    fn apply_changes(&mut self) {
+        if self.table.take_changed() {
+            self.load_table();
+        }
+    }
+    /// Correct the slow drift of the amplitude of the recursive oscillators
+    fn renormalize(&mut self) {
+        let three_halves = F::new(1.5);
+        let half = F::new(0.5);
+        for (re, im) in self.re.iter_mut().zip(&mut self.im) {
+            let k = three_halves - half * (*re * *re + *im * *im);
+            *re *= k;
+            *im *= k;
+        }
+    }
+    #[inline(always)]
+    fn next_sample(&mut self) -> F {
+        let mut sums = [F::ZERO; LANES];
+        for ((((re, im), cos), sin), gains) in self
+            .re
+            .chunks_exact_mut(LANES)
+            .zip(self.im.chunks_exact_mut(LANES))
+            .zip(self.cos.chunks_exact(LANES))
+            .zip(self.sin.chunks_exact(LANES))
+            .zip(self.gains.chunks_exact(LANES))
+        {
+            for (((((sum, re), im), &cos), &sin), &gain) in
+                sums.iter_mut().zip(re).zip(im).zip(cos).zip(sin).zip(gains)
+            {
+                *sum += gain * *im;
+                let next_re = *re * cos - *im * sin;
+                *im = *re * sin + *im * cos;
+                *re = next_re;
+            }
+        }
+        self.samples_until_renormalize -= 1;
+        if self.samples_until_renormalize == 0 {
+            self.samples_until_renormalize = RENORMALIZE_INTERVAL;
+            self.renormalize();
+        }
+        sums.iter().fold(F::ZERO, |acc, &sum| acc + sum)
+    }
+    #[allow(missing_docs)]
+    pub fn process(&mut self) -> [F; 1] {
+        self.apply_changes();
+        [self.next_sample()]
+    }
+    #[allow(missing_docs)]
+    pub fn process_block(&mut self, output: [&mut [F]; 1]) {
+        self.apply_changes();
+        for out in output[0].iter_mut() {
+            *out = self.next_sample();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use knaster_core::{AudioCtx, UGen, UGenFlags, log::ArLogSender};
+
+    const SR: u32 = 48000;
+
+    fn expected(partials: &[(f64, f64, f64)], freq: f64, frame: usize) -> f64 {
+        let t = frame as f64 / SR as f64;
+        partials
+            .iter()
+            .map(|&(ratio, amp, phase)| {
+                amp * (std::f64::consts::TAU * (ratio * freq * t + phase)).sin()
+            })
+            .sum()
+    }
+
+    #[test]
+    fn sum_of_sines() {
+        let mut ctx = AudioCtx::new(SR, 64, ArLogSender::non_rt());
+        let mut flags = UGenFlags::new();
+        let mut bank = AdditiveBank::<f64>::new(20, 100.);
+        bank.init(SR, 64);
+        let partials: Vec<_> = (0..20)
+            .map(|i| ((i + 1) as f64, 1. / (i + 1) as f64, 0.))
+            .collect();
+        for frame in 0..SR as usize {
+            let out = UGen::process(&mut bank, &mut ctx, &mut flags, [].into())[0];
+            assert!((out - expected(&partials, 100., frame)).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn handle_updates_and_nyquist() {
+        let mut ctx = AudioCtx::new(SR, 64, ArLogSender::non_rt());
+        let mut flags = UGenFlags::new();
+        let mut bank = AdditiveBank::<f64>::new(2, 1000.);
+        let handle = bank.handle();
+        assert_eq!(handle.len(), 2);
+        bank.init(SR, 64);
+        let first = Partial {
+            ratio: 1.5,
+            amplitude: 0.5,
+            phase: 0.25,
+        };
+        // Above Nyquist at 1000 Hz
+        let second = Partial {
+            ratio: 30.0,
+            amplitude: 1.0,
+            phase: 0.0,
+        };
+        handle.set(0, first);
+        handle.set(1, second);
+        assert_eq!(handle.get(1), second);
+        for frame in 0..1000 {
+            let out = UGen::process(&mut bank, &mut ctx, &mut flags, [].into())[0];
+            assert!((out - expected(&[(1.5, 0.5, 0.25)], 1000., frame)).abs() < 1e-6);
+        }
+        // Lowering the frequency brings the partial back
+        bank.param(&mut ctx, "freq", 500.).unwrap();
+        bank.param(&mut ctx, "t_reset", knaster_core::PTrigger)
+            .unwrap();
+        for frame in 0..1000 {
+            let out = UGen::process(&mut bank, &mut ctx, &mut flags, [].into())[0];
+            let partials = [(1.5, 0.5, 0.25), (30.0, 1.0, 0.0)];
+            assert!((out - expected(&partials, 500., frame)).abs() < 1e-6);
+        }
+    }
+}
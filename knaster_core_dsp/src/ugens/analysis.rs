@@ -0,0 +1,517 @@
+//! # Analysis
+//!
+//! [`UGen`]s which analyse their input and output control signals, e.g. for driving parameters
+//! from a live signal using `connect_to_parameter` in a graph:
+//! - [`EnvelopeFollower`]: peak or RMS amplitude with attack and release
+//! - [`PitchTracker`]: monophonic pitch tracking using the YIN algorithm
+//! - [`OnsetDetector`]: energy based onset detection with a trigger output
+
+#[allow(unused)]
+use knaster_core::UGen;
+use knaster_core::{
+    Float, KnasterIntegerParameter, PFloat, PInteger, impl_ugen,
+    num_derive::{FromPrimitive, ToPrimitive},
+    num_traits,
+};
+use std::prelude::v1::*;
+
+/// Lowest `min_freq` of a [`PitchTracker`], which determines the size of its buffers
+pub const PITCH_TRACKER_LOWEST_FREQ: f64 = 40.0;
+
+/// Coefficient for a one pole smoother with time constant `time` in seconds
+fn smoothing_coefficient<F: Float>(time: PFloat, sample_rate: u32) -> F {
+    if time <= 0.0 {
+        F::ZERO
+    } else {
+        F::new((-1.0 / (time * sample_rate as f64)).exp())
+    }
+}
+
+/// What amplitude measure an [`EnvelopeFollower`] follows
+#[derive(
+    Clone, Copy, Debug, FromPrimitive, ToPrimitive, PartialEq, Default, KnasterIntegerParameter,
+)]
+#[num_traits = "num_traits"]
+#[repr(u8)]
+pub enum FollowerMode {
+    /// The absolute value of the input
+    #[default]
+    Peak = 0,
+    /// The root mean square of the input, where the attack and release smooth the mean
+    Rms,
+}
+
+/// Envelope follower outputting the amplitude of the input.
+///
+/// - `attack`: time constant in seconds when the amplitude rises
+/// - `release`: time constant in seconds when the amplitude falls
+/// - `mode`: [`FollowerMode`]
+pub struct EnvelopeFollower<F: Float = f32> {
+    attack: PFloat,
+    release: PFloat,
+    attack_coeff: F,
+    release_coeff: F,
+    mode: FollowerMode,
+    /// The amplitude, or the mean square in [`FollowerMode::Rms`]
+    state: F,
+    sample_rate: u32,
+}
+#[impl_ugen]
+impl<F: Float> EnvelopeFollower<F> {
+    #[allow(missing_docs)]
+    pub fn new(attack: PFloat, release: PFloat) -> Self {
+        let mut s = Self {
+            attack,
+            release,
+            attack_coeff: F::ZERO,
+            release_coeff: F::ZERO,
+            mode: FollowerMode::Peak,
+            state: F::ZERO,
+            sample_rate: 48000,
+        };
+        s.update_coefficients();
+        s
+    }
+    /// Attack time in seconds
+    #[param(kind = Seconds, default = 0.01, range = 0.0..=5.0)]
+    pub fn attack(&mut self, attack: PFloat) {
+        self.attack = attack;
+        self.update_coefficients();
+    }
+    /// Release time in seconds
+    #[param(kind = Seconds, default = 0.1, range = 0.0..=5.0)]
+    pub fn release(&mut self, release: PFloat) {
+        self.release = release;
+        self.update_coefficients();
+    }
+    /// Peak or RMS
+    #[param(from = FollowerMode)]
+    pub fn mode(&mut self, mode: PInteger) {
+        self.mode = FollowerMode::from(mode);
+    }
+    fn update_coefficients(&mut self) {
+        self.attack_coeff = smoothing_coefficient(self.attack, self.sample_rate);
+        self.release_coeff = smoothing_coefficient(self.release, self.sample_rate);
+    }
+    fn init(&mut self, sample_rate: u32, _block_size: usize) {
+        self.sample_rate = sample_rate;
+        self.state = F::ZERO;
+        self.update_coefficients();
+    }
+    fn process(&mut self, input: [F; 1]) -> [F; 1] {
+        let x = match self.mode {
+            FollowerMode::Peak => input[0].abs(),
+            FollowerMode::Rms => input[0] * input[0],
+        };
+        let coeff = if x > self.state {
+            self.attack_coeff
+        } else {
+            self.release_coeff
+        };
+        self.state = x + (self.state - x) * coeff;
+        match self.mode {
+            FollowerMode::Peak => [self.state],
+            FollowerMode::Rms => [self.state.sqrt()],
+        }
+    }
+}
+
+/// Monophonic pitch tracker using the YIN algorithm.
+///
+/// Outputs the detected frequency in Hz and a confidence between 0 and 1. The frequency keeps its
+/// last value when no pitch is detected, e.g. during silence, so use the confidence to decide
+/// whether it is valid.
+///
+/// - `min_freq`: the lowest detectable frequency, which also sets the analysis window and the
+///   latency. Clamped to at least [`PITCH_TRACKER_LOWEST_FREQ`].
+/// - `max_freq`: the highest detectable frequency
+/// - `threshold`: how aperiodic a signal can be and still be detected as pitched. Lower values
+///   give fewer octave errors but more missed detections.
+///
+/// An analysis starts every half window and its work is spread evenly over the frames until the
+/// next one, so the cost is the same in every block. The result is output at the end of the half
+/// window.
+pub struct PitchTracker<F: Float = f32> {
+    /// Ring buffer with the latest input
+    input: Vec<F>,
+    write_pos: usize,
+    /// The input of the running analysis in order, oldest first
+    frame: Vec<F>,
+    /// Cumulative mean normalised difference function
+    difference: Vec<F>,
+    frames_until_analysis: usize,
+    /// The next lag of the difference function to compute, or 0 when the analysis is finished
+    tau: usize,
+    tau_min: usize,
+    tau_max: usize,
+    /// The number of lags computed per frame so that the analysis finishes within its hop
+    taus_per_frame: usize,
+    running_sum: F,
+    min_freq: PFloat,
+    max_freq: PFloat,
+    threshold: F,
+    freq: F,
+    confidence: F,
+    sample_rate: u32,
+}
+#[impl_ugen]
+impl<F: Float> PitchTracker<F> {
+    #[allow(missing_docs)]
+    pub fn new() -> Self {
+        Self {
+            input: Vec::new(),
+            write_pos: 0,
+            frame: Vec::new(),
+            difference: Vec::new(),
+            frames_until_analysis: 0,
+            tau: 0,
+            tau_min: 0,
+            tau_max: 0,
+            taus_per_frame: 0,
+            running_sum: F::ZERO,
+            min_freq: 60.0,
+            max_freq: 1500.0,
+            threshold: F::new(0.15),
+            freq: F::ZERO,
+            confidence: F::ZERO,
+            sample_rate: 48000,
+        }
+    }
+    /// Lowest detectable frequency
+    #[param(kind = Frequency, default = 60.0, range = 40.0..=1000.0, logarithmic = true)]
+    pub fn min_freq(&mut self, min_freq: PFloat) {
+        self.min_freq = min_freq.max(PITCH_TRACKER_LOWEST_FREQ);
+    }
+    /// Highest detectable frequency
+    #[param(kind = Frequency, default = 1500.0, range = 100.0..=8000.0, logarithmic = true)]
+    pub fn max_freq(&mut self, max_freq: PFloat) {
+        self.max_freq = max_freq;
+    }
+    /// Aperiodicity threshold for detection
+    #[param(default = 0.15, range = 0.01..=0.5)]
+    pub fn threshold(&mut self, threshold: PFloat) {
+        self.threshold = F::new(threshold);
+    }
+    fn init(&mut self, sample_rate: u32, _block_size: usize) {
+        self.sample_rate = sample_rate;
+        let max_period = (sample_rate as f64 / PITCH_TRACKER_LOWEST_FREQ).ceil() as usize;
+        self.input = vec![F::ZERO; max_period * 2];
+        self.frame = vec![F::ZERO; max_period * 2];
+        self.difference = vec![F::ZERO; max_period + 1];
+        self.write_pos = 0;
+        self.frames_until_analysis = 0;
+        self.tau = 0;
+        self.freq = F::ZERO;
+        self.confidence = F::ZERO;
+    }
+    fn process(&mut self, input: [F; 1]) -> [F; 2] {
+        if self.input.is_empty() {
+            return [F::ZERO; 2];
+        }
+        self.input[self.write_pos] = input[0];
+        self.write_pos = (self.write_pos + 1) % self.input.len();
+        if self.frames_until_analysis == 0 {
+            self.frames_until_analysis = self.start_analysis();
+        }
+        self.continue_analysis();
+        self.frames_until_analysis -= 1;
+        [self.freq, self.confidence]
+    }
+    /// Start a YIN analysis of the latest input and return the number of frames until the next
+    /// analysis
+    fn start_analysis(&mut self) -> usize {
+        let sample_rate = self.sample_rate as f64;
+        let max_period = self.difference.len() - 1;
+        let tau_max = ((sample_rate / self.min_freq) as usize).clamp(4, max_period);
+        let window = tau_max;
+        let len = self.input.len();
+        for (i, x) in self.frame[..window * 2].iter_mut().enumerate() {
+            *x = self.input[(self.write_pos + len - window * 2 + i) % len];
+        }
+        let hop = (window / 2).max(1);
+        self.tau_max = tau_max;
+        self.tau_min = ((sample_rate / self.max_freq) as usize).clamp(2, tau_max - 2);
+        self.taus_per_frame = tau_max.div_ceil(hop);
+        self.tau = 1;
+        self.running_sum = F::ZERO;
+        self.difference[0] = F::ONE;
+        hop
+    }
+    /// Compute the next lags of the cumulative mean normalised difference function, and the
+    /// result once all lags are done
+    fn continue_analysis(&mut self) {
+        if self.tau == 0 {
+            return;
+        }
+        let window = self.tau_max;
+        let frame = &self.frame[..window * 2];
+        let end = (self.tau + self.taus_per_frame).min(self.tau_max + 1);
+        for tau in self.tau..end {
+            let mut sum = F::ZERO;
+            for j in 0..window {
+                let delta = frame[j] - frame[j + tau];
+                sum += delta * delta;
+            }
+            self.running_sum += sum;
+            self.difference[tau] = if self.running_sum > F::ZERO {
+                sum * F::from_usize(tau) / self.running_sum
+            } else {
+                F::ONE
+            };
+        }
+        self.tau = end;
+        if self.tau > self.tau_max {
+            self.tau = 0;
+            self.finish_analysis();
+        }
+    }
+    /// Find the period in the finished difference function
+    fn finish_analysis(&mut self) {
+        let sample_rate = self.sample_rate as f64;
+        let (tau_min, tau_max) = (self.tau_min, self.tau_max);
+        let d = &self.difference[..=tau_max];
+        // The first dip below the threshold, followed to its local minimum
+        let mut best = None;
+        let mut tau = tau_min;
+        while tau < tau_max {
+            if d[tau] < self.threshold {
+                while tau + 1 < tau_max && d[tau + 1] < d[tau] {
+                    tau += 1;
+                }
+                best = Some(tau);
+                break;
+            }
+            tau += 1;
+        }
+        match best {
+            Some(tau) => {
+                // Parabolic interpolation of the minimum
+                let (a, b, c) = (d[tau - 1], d[tau], d[tau + 1]);
+                let denominator = a + c - b * F::new(2.0);
+                let offset = if denominator.abs() > F::new(1e-12) {
+                    (a - c) / (denominator * F::new(2.0))
+                } else {
+                    F::ZERO
+                };
+                self.freq = F::new(sample_rate) / (F::from_usize(tau) + offset);
+                self.confidence = (F::ONE - b).clamp(F::ZERO, F::ONE);
+            }
+            None => {
+                let min = d[tau_min..tau_max]
+                    .iter()
+                    .fold(F::ONE, |min, &x| if x < min { x } else { min });
+                self.confidence = (F::ONE - min).clamp(F::ZERO, F::ONE) * self.threshold;
+            }
+        }
+    }
+}
+impl<F: Float> Default for PitchTracker<F> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Onset detector comparing a short term energy to a long term energy.
+///
+/// Outputs a single sample trigger of 1.0 when the short term energy rises `threshold` dB above
+/// the long term energy. The detector rearms once the ratio has fallen below half the threshold
+/// and at least `min_interval` seconds have passed.
+///
+/// - `threshold`: the rise in dB needed for an onset
+/// - `floor`: short term energies below this level in dB are ignored
+/// - `min_interval`: the shortest time between onsets in seconds
+pub struct OnsetDetector<F: Float = f32> {
+    fast: F,
+    slow: F,
+    fast_coeff: F,
+    slow_coeff: F,
+    threshold_ratio: F,
+    rearm_ratio: F,
+    floor: F,
+    min_interval: PFloat,
+    min_interval_frames: usize,
+    frames_since_onset: usize,
+    armed: bool,
+    sample_rate: u32,
+}
+#[impl_ugen]
+impl<F: Float> OnsetDetector<F> {
+    /// Time constant of the short term energy in seconds
+    const FAST_TIME: PFloat = 0.002;
+    /// Time constant of the long term energy in seconds
+    const SLOW_TIME: PFloat = 0.1;
+    #[allow(missing_docs)]
+    pub fn new() -> Self {
+        let mut s = Self {
+            fast: F::ZERO,
+            slow: F::ZERO,
+            fast_coeff: F::ZERO,
+            slow_coeff: F::ZERO,
+            threshold_ratio: F::ONE,
+            rearm_ratio: F::ONE,
+            floor: F::ZERO,
+            min_interval: 0.05,
+            min_interval_frames: 0,
+            frames_since_onset: 0,
+            armed: true,
+            sample_rate: 48000,
+        };
+        s.threshold(9.0);
+        s.floor(-50.0);
+        s.update_coefficients();
+        s
+    }
+    /// Rise in dB needed for an onset
+    #[param(default = 9.0, range = 1.0..=30.0)]
+    pub fn threshold(&mut self, threshold: PFloat) {
+        // The energies are squared amplitudes
+        self.threshold_ratio = F::new(10.0f64.powf(threshold / 10.0));
+        self.rearm_ratio = F::new(10.0f64.powf(threshold / 20.0));
+    }
+    /// Level in dB below which onsets are ignored
+    #[param(default = -50.0, range = -90.0..=0.0)]
+    pub fn floor(&mut self, floor: PFloat) {
+        self.floor = F::new(10.0f64.powf(floor / 10.0));
+    }
+    /// Shortest time between onsets in seconds
+    #[param(kind = Seconds, default = 0.05, range = 0.0..=2.0)]
+    pub fn min_interval(&mut self, min_interval: PFloat) {
+        self.min_interval = min_interval;
+        self.update_coefficients();
+    }
+    fn update_coefficients(&mut self) {
+        self.fast_coeff = smoothing_coefficient(Self::FAST_TIME, self.sample_rate);
+        self.slow_coeff = smoothing_coefficient(Self::SLOW_TIME, self.sample_rate);
+        self.min_interval_frames = (self.min_interval * self.sample_rate as f64) as usize;
+    }
+    fn init(&mut self, sample_rate: u32, _block_size: usize) {
+        self.sample_rate = sample_rate;
+        self.fast = F::ZERO;
+        self.slow = F::ZERO;
+        self.frames_since_onset = usize::MAX;
+        self.armed = true;
+        self.update_coefficients();
+    }
+    fn process(&mut self, input: [F; 1]) -> [F; 1] {
+        let energy = input[0] * input[0];
+        self.fast = energy + (self.fast - energy) * self.fast_coeff;
+        self.slow = energy + (self.slow - energy) * self.slow_coeff;
+        self.frames_since_onset = self.frames_since_onset.saturating_add(1);
+        let reference = self.slow.max(F::new(1e-12));
+        if !self.armed && self.fast < reference * self.rearm_ratio {
+            self.armed = true;
+        }
+        if self.armed
+            && self.fast > self.floor
+            && self.fast > reference * self.threshold_ratio
+            && self.frames_since_onset >= self.min_interval_frames
+        {
+            self.armed = false;
+            self.frames_since_onset = 0;
+            [F::ONE]
+        } else {
+            [F::ZERO]
+        }
+    }
+}
+impl<F: Float> Default for OnsetDetector<F> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use knaster_core::{AudioCtx, UGenFlags, log::ArLogSender};
+
+    const SR: u32 = 48000;
+
+    #[test]
+    fn envelope_follower_rms_of_sine() {
+        let mut ctx = AudioCtx::new(SR, 64, ArLogSender::non_rt());
+        let mut flags = UGenFlags::new();
+        // Equal attack and release give the true RMS
+        let mut follower = EnvelopeFollower::<f64>::new(0.1, 0.1);
+        follower.init(SR, 64);
+        follower.param(&mut ctx, "mode", FollowerMode::Rms).unwrap();
+        let mut out = 0.0;
+        for i in 0..SR as usize {
+            let x = (std::f64::consts::TAU * 100.0 * i as f64 / SR as f64).sin();
+            out = UGen::process(&mut follower, &mut ctx, &mut flags, [x].into())[0];
+        }
+        assert!(
+            (out - std::f64::consts::FRAC_1_SQRT_2).abs() < 0.05,
+            "{out}"
+        );
+    }
+
+    #[test]
+    fn pitch_tracker_detects_sawtooth() {
+        let mut ctx = AudioCtx::new(SR, 64, ArLogSender::non_rt());
+        let mut flags = UGenFlags::new();
+        for freq in [82.4, 220.0, 659.3] {
+            let mut tracker = PitchTracker::<f32>::new();
+            tracker.init(SR, 64);
+            let mut out = [0.0; 2];
+            for i in 0..SR as usize / 4 {
+                let phase = (freq * i as f64 / SR as f64).fract();
+                let x = (phase * 2.0 - 1.0) as f32;
+                let o = UGen::process(&mut tracker, &mut ctx, &mut flags, [x].into());
+                out = [o[0], o[1]];
+            }
+            assert!(
+                (out[0] as f64 / freq - 1.0).abs() < 0.005,
+                "{} Hz, expected {freq}",
+                out[0]
+            );
+            assert!(out[1] > 0.9, "confidence {}", out[1]);
+        }
+    }
+
+    #[test]
+    fn pitch_tracker_spreads_analysis_over_hop() {
+        let mut ctx = AudioCtx::new(SR, 64, ArLogSender::non_rt());
+        let mut flags = UGenFlags::new();
+        let mut tracker = PitchTracker::<f32>::new();
+        tracker.init(SR, 64);
+        for i in 0..SR as usize / 10 {
+            let tau = tracker.tau;
+            let x = (i as f32 * 0.05).sin();
+            UGen::process(&mut tracker, &mut ctx, &mut flags, [x].into());
+            if tau != 0 && tracker.tau != 0 {
+                assert!(tracker.tau - tau <= tracker.taus_per_frame);
+            }
+            if tracker.frames_until_analysis == 0 {
+                assert_eq!(
+                    tracker.tau, 0,
+                    "analysis not finished at the end of its hop"
+                );
+            }
+        }
+        // Only a few lags of the difference function are computed per frame
+        assert!(tracker.taus_per_frame <= 3);
+    }
+
+    #[test]
+    fn onset_detector_triggers_once_per_burst() {
+        let mut ctx = AudioCtx::new(SR, 64, ArLogSender::non_rt());
+        let mut flags = UGenFlags::new();
+        let mut detector = OnsetDetector::<f32>::new();
+        detector.init(SR, 64);
+        let mut onsets = Vec::new();
+        for i in 0..SR as usize {
+            // A decaying burst every 0.25 s over low level noise
+            let t = (i % (SR as usize / 4)) as f32 / SR as f32;
+            let noise = ((i * 7919 % 1000) as f32 / 500.0 - 1.0) * 0.001;
+            let x = (std::f32::consts::TAU * 440.0 * t).sin() * (-t * 20.0).exp() + noise;
+            if UGen::process(&mut detector, &mut ctx, &mut flags, [x].into())[0] > 0.0 {
+                onsets.push(i);
+            }
+        }
+        assert_eq!(onsets.len(), 4, "{onsets:?}");
+        for (onset, expected) in onsets.iter().zip((0..4).map(|n| n * SR as usize / 4)) {
+            assert!(onset - expected < 100, "{onsets:?}");
+        }
+    }
+}
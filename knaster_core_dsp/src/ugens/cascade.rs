@@ -0,0 +1,517 @@
+//! # Filter cascades
+//!
+//! Higher order filters made from cascaded [`SvfFilter`] sections, and crossovers for splitting
+//! a signal into frequency bands.
+//!
+//! - [`SvfCascade`]: generic cascade of second order SVF sections
+//! - [`ButterworthFilter`]: Butterworth lowpass/highpass of any even order
+//! - [`LinkwitzRileyFilter`]: 2-band Linkwitz-Riley crossover of any even order
+//! - [`Crossover`]: N-band Linkwitz-Riley crossover with one output channel per band
+//!
+//! The outputs of the crossovers sum back to a flat magnitude response.
+use crate::dsp::response::{Complex, FrequencyResponse};
+use crate::svf::{SvfFilter, SvfFilterType};
+use crate::ugens::param_names::collect_param_names;
+use knaster_core::{
+    AudioCtx, Float, Frame, PFloat, ParameterHint, ParameterValue, Size, UGen, UGenFlags,
+    impl_ugen,
+    numeric_array::NumericArray,
+    typenum::{B1, Sub1, U1},
+};
+use std::prelude::v1::*;
+
+/// The maximum number of bands supported by [`Crossover`]
+pub const MAX_CROSSOVER_BANDS: usize = 16;
+const CROSSOVER_PARAM_NAMES: [[&str; 1]; MAX_CROSSOVER_BANDS - 1] = indexed_param_names!(
+    ["crossover"];
+    0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14
+);
+
+/// Returns the Q values of the second order sections of a Butterworth filter of order `order`.
+///
+/// # Panics
+/// Panics if `order` is not even and larger than 0.
+pub fn butterworth_qs<F: Float>(order: usize) -> Vec<F> {
+    assert!(
+        order > 0 && order.is_multiple_of(2),
+        "Butterworth order must be even"
+    );
+    (0..order / 2)
+        .map(|k| butterworth_q(order, k))
+        .collect::<Vec<_>>()
+}
+/// The Q of the `k`th complex pole pair of a Butterworth filter of order `order`
+fn butterworth_q<F: Float>(order: usize, k: usize) -> F {
+    // Angle of the pole from the negative real axis. Odd orders have a real pole at angle 0.
+    let angle = F::PI * F::new((2 * k + 1 + order % 2) as f32) / F::new((2 * order) as f32);
+    F::ONE / (F::new(2.) * angle.cos())
+}
+
+/// Returns the Q values of the second order sections of a Linkwitz-Riley filter of order
+/// `order`.
+///
+/// A Linkwitz-Riley filter is two cascaded Butterworth filters of half the order. The real pole
+/// of odd order Butterworth filters appears twice, which is a second order section with Q = 0.5.
+///
+/// # Panics
+/// Panics if `order` is not even and larger than 0.
+pub fn linkwitz_riley_qs<F: Float>(order: usize) -> Vec<F> {
+    assert!(
+        order > 0 && order.is_multiple_of(2),
+        "Linkwitz-Riley order must be even"
+    );
+    let butterworth_order = order / 2;
+    let mut qs = Vec::with_capacity(butterworth_order);
+    if butterworth_order % 2 == 1 {
+        qs.push(F::new(0.5));
+    }
+    for k in 0..butterworth_order / 2 {
+        let q = butterworth_q(butterworth_order, k);
+        qs.push(q);
+        qs.push(q);
+    }
+    qs
+}
+
+/// A series of [`SvfFilter`] sections of the same type and cutoff frequency, but with
+/// individual Q values.
+///
+/// This is a building block for higher order filters, not a UGen itself.
+#[derive(Clone, Debug)]
+pub struct SvfCascade<F: Float> {
+    ty: SvfFilterType,
+    sections: Vec<SvfFilter<F>>,
+    qs: Vec<F>,
+    cutoff_freq: F,
+}
+impl<F: Float> SvfCascade<F> {
+    /// Create a new cascade with one section per value in `qs`
+    pub fn new(ty: SvfFilterType, qs: &[F], cutoff_freq: F) -> Self {
+        Self {
+            ty,
+            sections: qs
+                .iter()
+                .map(|&q| SvfFilter::new(ty, cutoff_freq, q, F::ZERO))
+                .collect(),
+            qs: qs.to_vec(),
+            cutoff_freq,
+        }
+    }
+    /// Returns the current cutoff frequency
+    pub fn cutoff_freq(&self) -> F {
+        self.cutoff_freq
+    }
+    /// Set the cutoff frequency of all sections and recalculate coefficients
+    pub fn set_cutoff_freq(&mut self, cutoff_freq: F, sample_rate: F) {
+        self.cutoff_freq = cutoff_freq;
+        for (section, &q) in self.sections.iter_mut().zip(&self.qs) {
+            section.set_coeffs(cutoff_freq, q, F::ZERO, sample_rate);
+        }
+    }
+    /// Clear the internal state of all sections
+    pub fn reset(&mut self) {
+        for section in &mut self.sections {
+            section.reset();
+        }
+    }
+    /// Process one sample through all the sections
+    #[inline]
+    pub fn process_sample(&mut self, input: F) -> F {
+        let mut x = input;
+        for section in &mut self.sections {
+            x = section.process_sample(x);
+        }
+        x
+    }
+}
+
+impl<F: Float> FrequencyResponse for SvfCascade<F> {
+    fn frequency_response(&self, freq: f64, sample_rate: f64) -> Complex<f64> {
+        let cutoff_freq = self.cutoff_freq.to_f64();
+        self.qs.iter().fold(Complex::real(1.0), |response, &q| {
+            let section = SvfFilter::new(self.ty, cutoff_freq, q.to_f64(), 0.0);
+            response * section.frequency_response(freq, sample_rate)
+        })
+    }
+}
+
+/// Butterworth lowpass or highpass filter of any even order, made from cascaded SVF sections.
+///
+/// The order is set at construction and cannot be changed without allocating.
+#[derive(Clone, Debug)]
+pub struct ButterworthFilter<F: Float = f32> {
+    cascade: SvfCascade<F>,
+    sample_rate: F,
+}
+#[impl_ugen]
+impl<F: Float> ButterworthFilter<F> {
+    /// New Butterworth lowpass filter with a slope of `order * 6` dB/octave
+    ///
+    /// # Panics
+    /// Panics if `order` is not even and larger than 0.
+    pub fn lowpass(order: usize, cutoff_freq: F) -> Self {
+        Self {
+            cascade: SvfCascade::new(SvfFilterType::Low, &butterworth_qs(order), cutoff_freq),
+            sample_rate: F::new(48000.),
+        }
+    }
+    /// New Butterworth highpass filter with a slope of `order * 6` dB/octave
+    ///
+    /// # Panics
+    /// Panics if `order` is not even and larger than 0.
+    pub fn highpass(order: usize, cutoff_freq: F) -> Self {
+        Self {
+            cascade: SvfCascade::new(SvfFilterType::High, &butterworth_qs(order), cutoff_freq),
+            sample_rate: F::new(48000.),
+        }
+    }
+    /// Cutoff frequency in Hz
+    #[param(kind = Frequency, default = 1000.0)]
+    pub fn cutoff_freq(&mut self, cutoff_freq: PFloat, ctx: &AudioCtx) {
+        self.sample_rate = F::new(ctx.sample_rate() as f32);
+        self.cascade
+            .set_cutoff_freq(F::new(cutoff_freq), self.sample_rate);
+    }
+    fn init(&mut self, sample_rate: u32, _block_size: usize) {
+        self.sample_rate = F::new(sample_rate as f32);
+        self.cascade.reset();
+        self.cascade
+            .set_cutoff_freq(self.cascade.cutoff_freq(), self.sample_rate);
+    }
+    fn process(&mut self, input: [F; 1]) -> [F; 1] {
+        [self.cascade.process_sample(input[0])]
+    }
+}
+
+impl<F: Float> FrequencyResponse for ButterworthFilter<F> {
+    fn frequency_response(&self, freq: f64, sample_rate: f64) -> Complex<f64> {
+        self.cascade.frequency_response(freq, sample_rate)
+    }
+}
+
+/// A Linkwitz-Riley lowpass/highpass pair which is the building block of [`LinkwitzRileyFilter`]
+/// and [`Crossover`].
+///
+/// The highpass output is inverted for orders 2, 6, 10 etc. so that the outputs always sum to
+/// an allpass response.
+#[derive(Clone, Debug)]
+pub struct LinkwitzRileySplit<F: Float> {
+    lowpass: SvfCascade<F>,
+    highpass: SvfCascade<F>,
+    highpass_sign: F,
+}
+impl<F: Float> LinkwitzRileySplit<F> {
+    /// # Panics
+    /// Panics if `order` is not even and larger than 0.
+    pub fn new(order: usize, cutoff_freq: F) -> Self {
+        let qs = linkwitz_riley_qs(order);
+        let highpass_sign = if (order / 2) % 2 == 1 {
+            -F::ONE
+        } else {
+            F::ONE
+        };
+        Self {
+            lowpass: SvfCascade::new(SvfFilterType::Low, &qs, cutoff_freq),
+            highpass: SvfCascade::new(SvfFilterType::High, &qs, cutoff_freq),
+            highpass_sign,
+        }
+    }
+    /// Returns the current crossover frequency
+    pub fn cutoff_freq(&self) -> F {
+        self.lowpass.cutoff_freq()
+    }
+    /// Set the crossover frequency and recalculate coefficients
+    pub fn set_cutoff_freq(&mut self, cutoff_freq: F, sample_rate: F) {
+        self.lowpass.set_cutoff_freq(cutoff_freq, sample_rate);
+        self.highpass.set_cutoff_freq(cutoff_freq, sample_rate);
+    }
+    /// Clear the internal state
+    pub fn reset(&mut self) {
+        self.lowpass.reset();
+        self.highpass.reset();
+    }
+    /// Split one sample into `[low, high]`
+    #[inline]
+    pub fn process_sample(&mut self, input: F) -> [F; 2] {
+        [
+            self.lowpass.process_sample(input),
+            self.highpass.process_sample(input) * self.highpass_sign,
+        ]
+    }
+    /// The frequency responses of the `[low, high]` outputs
+    pub fn band_responses(&self, freq: f64, sample_rate: f64) -> [Complex<f64>; 2] {
+        [
+            self.lowpass.frequency_response(freq, sample_rate),
+            self.highpass.frequency_response(freq, sample_rate) * self.highpass_sign.to_f64(),
+        ]
+    }
+    /// Process one sample and return the sum of the two bands. This is an allpass filter with
+    /// the same phase response as the split, used to phase align other bands.
+    #[inline]
+    pub fn process_allpass(&mut self, input: F) -> F {
+        let [low, high] = self.process_sample(input);
+        low + high
+    }
+}
+
+/// 2-band Linkwitz-Riley crossover of any even order. Outputs `[low, high]`.
+///
+/// An order of 4 (24 dB/octave) is the most common choice.
+#[derive(Clone, Debug)]
+pub struct LinkwitzRileyFilter<F: Float = f32> {
+    split: LinkwitzRileySplit<F>,
+    sample_rate: F,
+}
+#[impl_ugen]
+impl<F: Float> LinkwitzRileyFilter<F> {
+    /// # Panics
+    /// Panics if `order` is not even and larger than 0.
+    pub fn new(order: usize, cutoff_freq: F) -> Self {
+        Self {
+            split: LinkwitzRileySplit::new(order, cutoff_freq),
+            sample_rate: F::new(48000.),
+        }
+    }
+    /// Crossover frequency in Hz
+    #[param(kind = Frequency, default = 1000.0)]
+    pub fn cutoff_freq(&mut self, cutoff_freq: PFloat, ctx: &AudioCtx) {
+        self.sample_rate = F::new(ctx.sample_rate() as f32);
+        self.split
+            .set_cutoff_freq(F::new(cutoff_freq), self.sample_rate);
+    }
+    fn init(&mut self, sample_rate: u32, _block_size: usize) {
+        self.sample_rate = F::new(sample_rate as f32);
+        self.split.reset();
+        self.split
+            .set_cutoff_freq(self.split.cutoff_freq(), self.sample_rate);
+    }
+    fn process(&mut self, input: [F; 1]) -> [F; 2] {
+        self.split.process_sample(input[0])
+    }
+}
+
+impl<F: Float> LinkwitzRileyFilter<F> {
+    /// The frequency response of output `band`, 0 for low and 1 for high
+    pub fn band_response(&self, band: usize, freq: f64, sample_rate: f64) -> Complex<f64> {
+        self.split.band_responses(freq, sample_rate)[band]
+    }
+}
+
+/// N-band Linkwitz-Riley crossover, outputting each band on its own channel from low to high.
+///
+/// `Bands` is the number of bands, between 2 and [`MAX_CROSSOVER_BANDS`]. The parameters
+/// `crossover0`, `crossover1` etc. set the `Bands - 1` crossover frequencies, which should be
+/// in increasing order.
+///
+/// The bands are split off one at a time from the bottom. Lower bands are passed through
+/// allpass filters matching the crossovers above them so that all bands are phase aligned and
+/// sum back to a flat magnitude response.
+pub struct Crossover<F: Float, Bands: Size> {
+    splits: Vec<LinkwitzRileySplit<F>>,
+    /// Allpass compensation for each band, one for each crossover above the band's own
+    compensation: Vec<Vec<LinkwitzRileySplit<F>>>,
+    sample_rate: F,
+    _bands: core::marker::PhantomData<Bands>,
+}
+impl<F: Float, Bands: Size> Crossover<F, Bands> {
+    /// Create a new crossover of Linkwitz-Riley order `order` with crossover frequencies
+    /// `crossover_freqs`.
+    ///
+    /// # Panics
+    /// Panics if `order` is not even, if `Bands` is not between 2 and [`MAX_CROSSOVER_BANDS`],
+    /// or if the number of crossover frequencies is not `Bands - 1`.
+    pub fn new(order: usize, crossover_freqs: &[F]) -> Self {
+        let bands = Bands::USIZE;
+        assert!(
+            (2..=MAX_CROSSOVER_BANDS).contains(&bands),
+            "Crossover supports between 2 and {MAX_CROSSOVER_BANDS} bands"
+        );
+        assert_eq!(
+            crossover_freqs.len(),
+            bands - 1,
+            "Crossover needs one fewer crossover frequency than bands"
+        );
+        let splits = crossover_freqs
+            .iter()
+            .map(|&freq| LinkwitzRileySplit::new(order, freq))
+            .collect();
+        let compensation = (0..bands)
+            .map(|band| {
+                crossover_freqs
+                    .iter()
+                    .skip(band + 1)
+                    .map(|&freq| LinkwitzRileySplit::new(order, freq))
+                    .collect()
+            })
+            .collect();
+        Self {
+            splits,
+            compensation,
+            sample_rate: F::new(48000.),
+            _bands: core::marker::PhantomData,
+        }
+    }
+    /// The frequency response of output `band`, counting from the lowest band
+    pub fn band_response(&self, band: usize, freq: f64, sample_rate: f64) -> Complex<f64> {
+        let mut response = Complex::real(1.0);
+        // Highpassed by all the crossovers below
+        for split in &self.splits[..band] {
+            response = response * split.band_responses(freq, sample_rate)[1];
+        }
+        if let Some(split) = self.splits.get(band) {
+            response = response * split.band_responses(freq, sample_rate)[0];
+        }
+        for allpass in &self.compensation[band] {
+            let [low, high] = allpass.band_responses(freq, sample_rate);
+            response = response * (low + high);
+        }
+        response
+    }
+    /// Set crossover frequency `index` in Hz
+    pub fn set_crossover_freq(&mut self, index: usize, freq: F) {
+        self.splits[index].set_cutoff_freq(freq, self.sample_rate);
+        // Band `band` has compensation for the crossovers `band + 1..`
+        for (band, compensation) in self.compensation.iter_mut().enumerate().take(index) {
+            compensation[index - band - 1].set_cutoff_freq(freq, self.sample_rate);
+        }
+    }
+}
+impl<F: Float, Bands: Size> UGen for Crossover<F, Bands>
+where
+    Bands: core::ops::Sub<B1> + Send,
+    Sub1<Bands>: Size,
+{
+    type Sample = F;
+    type Inputs = U1;
+    type Outputs = Bands;
+    type Parameters = Sub1<Bands>;
+
+    fn init(&mut self, sample_rate: u32, _block_size: usize) {
+        self.sample_rate = F::new(sample_rate as f32);
+        for split in self
+            .splits
+            .iter_mut()
+            .chain(self.compensation.iter_mut().flatten())
+        {
+            split.reset();
+            split.set_cutoff_freq(split.cutoff_freq(), self.sample_rate);
+        }
+    }
+
+    fn process(
+        &mut self,
+        _ctx: &mut AudioCtx,
+        _flags: &mut UGenFlags,
+        input: Frame<Self::Sample, Self::Inputs>,
+    ) -> Frame<Self::Sample, Self::Outputs> {
+        let mut out: Frame<F, Bands> = NumericArray::default();
+        let mut rest = input[0];
+        for (band, split) in self.splits.iter_mut().enumerate() {
+            let [low, high] = split.process_sample(rest);
+            let mut low = low;
+            for allpass in &mut self.compensation[band] {
+                low = allpass.process_allpass(low);
+            }
+            out[band] = low;
+            rest = high;
+        }
+        out[Bands::USIZE - 1] = rest;
+        out
+    }
+
+    fn param_descriptions() -> NumericArray<&'static str, Self::Parameters> {
+        collect_param_names(CROSSOVER_PARAM_NAMES.as_flattened().iter().copied())
+    }
+
+    fn param_hints() -> NumericArray<ParameterHint, Self::Parameters> {
+        let mut hints: NumericArray<ParameterHint, Self::Parameters> = NumericArray::default();
+        for hint in hints.iter_mut() {
+            *hint = ParameterHint::new_float(|h| {
+                h.kind(knaster_core::FloatParameterKind::Frequency)
+                    .nyquist()
+                    .logarithmic(true)
+            });
+        }
+        hints
+    }
+
+    fn param_apply(&mut self, _ctx: &mut AudioCtx, index: usize, value: ParameterValue) {
+        let Some(freq) = value.float() else {
+            return;
+        };
+        if index < Bands::USIZE - 1 {
+            self.set_crossover_freq(index, F::new(freq));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use knaster_core::{log::ArLogSender, typenum::U4};
+
+    const SR: u32 = 48000;
+
+    /// Measures the amplitude of a sine at `freq` from its RMS after the filter has settled
+    fn sine_gain(mut filter: impl FnMut(f64) -> f64, freq: f64) -> f64 {
+        let sr = SR as f64;
+        let mut sum = 0.0;
+        for i in 0..SR as usize {
+            let x = (core::f64::consts::TAU * freq * i as f64 / sr).sin();
+            let y = filter(x);
+            if i >= SR as usize / 2 {
+                sum += y * y;
+            }
+        }
+        (sum / (SR / 2) as f64).sqrt() * core::f64::consts::SQRT_2
+    }
+
+    #[test]
+    fn butterworth_is_3db_down_at_cutoff() {
+        for order in [2, 4, 8] {
+            let mut lp = ButterworthFilter::<f64>::lowpass(order, 1000.);
+            lp.init(SR, 64);
+            let gain = sine_gain(|x| lp.cascade.process_sample(x), 1000.);
+            assert!(
+                (gain - core::f64::consts::FRAC_1_SQRT_2).abs() < 0.01,
+                "order {order}: {gain}"
+            );
+        }
+    }
+
+    #[test]
+    fn crossover_band_responses_sum_flat() {
+        let crossover = Crossover::<f64, U4>::new(6, &[200., 1000., 5000.]);
+        for freq in [20., 200., 700., 1000., 5000., 15000.] {
+            let sum = (0..4).fold(Complex::real(0.0), |sum, band| {
+                sum + crossover.band_response(band, freq, SR as f64)
+            });
+            assert!((sum.magnitude() - 1.0).abs() < 1e-9, "{freq} Hz: {sum:?}");
+        }
+    }
+
+    #[test]
+    fn crossover_sums_flat() {
+        let mut ctx = AudioCtx::new(SR, 64, ArLogSender::non_rt());
+        let mut flags = UGenFlags::new();
+        for order in [2, 4, 6, 8] {
+            let mut crossover = Crossover::<f64, U4>::new(order, &[200., 1000., 5000.]);
+            crossover.init(SR, 64);
+            for freq in [50., 200., 600., 1000., 3000., 5000., 12000.] {
+                let gain = sine_gain(
+                    |x| {
+                        crossover
+                            .process(&mut ctx, &mut flags, [x].into())
+                            .iter()
+                            .sum()
+                    },
+                    freq,
+                );
+                assert!(
+                    (gain - 1.0).abs() < 0.01,
+                    "order {order}, {freq} Hz: {gain}"
+                );
+            }
+        }
+    }
+}
@@ -61,14 +61,14 @@ impl<F: Float> AllpassInterpolator<F> {
     pub fn new() -> Self {
         Self {
             coeff: F::ONE,
-            prev_input: F::ONE,
-            prev_output: F::ONE,
+            prev_input: F::ZERO,
+            prev_output: F::ZERO,
         }
     }
     /// Reset any state to 0
     pub fn clear(&mut self) {
-        self.prev_input = F::ONE;
-        self.prev_output = F::ONE;
+        self.prev_input = F::ZERO;
+        self.prev_output = F::ZERO;
     }
     /// Set the fractional number of frames in the delay time that we want to interpolate over
     pub fn set_delta(&mut self, delta: F) {
@@ -268,6 +268,73 @@ impl<F: Float> AllpassFeedbackDelay<F> {
         delayed_sig - self.feedback * delay_write
     }
 }
+/// Delay line for modulated delay times, read with cubic interpolation at any fractional delay.
+///
+/// Unlike [`AllpassInterpolator`], cubic interpolation doesn't have any internal state, so the
+/// delay time can be swept smoothly without artefacts. Write a sample first, then read.
+#[derive(Clone, Debug)]
+pub struct InterpolatingDelay<F: Copy = f32> {
+    buffer: Vec<F>,
+    /// The buffer length is a power of two so that wrapping is a mask
+    mask: usize,
+    /// The position of the next write
+    write_frame: usize,
+}
+impl<F: Float> InterpolatingDelay<F> {
+    /// Create a new delay line which can delay by at least `max_delay_frames` frames. Allocates.
+    pub fn new(max_delay_frames: usize) -> Self {
+        // Room for the interpolation points on either side
+        let len = (max_delay_frames + 4).next_power_of_two();
+        Self {
+            buffer: vec![F::ZERO; len],
+            mask: len - 1,
+            write_frame: 0,
+        }
+    }
+    /// The longest delay in frames that can be read
+    pub fn max_delay_frames(&self) -> usize {
+        self.buffer.len() - 3
+    }
+    /// Set all samples to 0
+    pub fn clear(&mut self) {
+        self.buffer.fill(F::ZERO);
+    }
+    /// Write a new sample and advance
+    #[inline]
+    pub fn write_and_advance(&mut self, input: F) {
+        self.buffer[self.write_frame] = input;
+        self.write_frame = (self.write_frame + 1) & self.mask;
+    }
+    /// Read the sample written `delay_frames` frames ago, where 0 is the last written sample.
+    /// The delay is clamped to between 1 and [`Self::max_delay_frames`] so that all
+    /// interpolation points are available.
+    #[inline]
+    pub fn read(&self, delay_frames: F) -> F {
+        let delay_frames = delay_frames.clamp(F::ONE, F::from_usize(self.max_delay_frames()));
+        let whole = delay_frames.floor();
+        let t = delay_frames - whole;
+        let whole = whole.to_usize().unwrap_or(1);
+        // The last written sample is at write_frame - 1
+        let newest = self.write_frame + self.mask;
+        let at = |offset: usize| self.buffer[(newest - offset) & self.mask];
+        let y_m1 = at(whole - 1);
+        let y0 = at(whole);
+        let y1 = at(whole + 1);
+        let y2 = at(whole + 2);
+        hermite_interpolate(y_m1, y0, y1, y2, t)
+    }
+}
+
+/// 4-point cubic Hermite interpolation between `y0` and `y1`, at `t` between 0 and 1
+#[inline]
+pub fn hermite_interpolate<F: Float>(y_m1: F, y0: F, y1: F, y2: F, t: F) -> F {
+    let half = F::new(0.5);
+    let c1 = half * (y1 - y_m1);
+    let c2 = y_m1 - F::new(2.5) * y0 + F::new(2.) * y1 - half * y2;
+    let c3 = half * (y2 - y_m1) + F::new(1.5) * (y0 - y1);
+    ((c3 * t + c2) * t + c1) * t + y0
+}
+
 /// A sample delay with a static number of samples of delay
 ///
 /// # Examples
@@ -414,3 +481,20 @@ impl<F: Float> StaticSampleDelay<F> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn allpass_interpolator_starts_and_clears_to_zero() {
+        let mut allpass = AllpassInterpolator::<f64>::new();
+        allpass.set_delta(0.3);
+        assert!((0..10).all(|_| allpass.process_sample(0.) == 0.));
+        for i in 0..10 {
+            allpass.process_sample(i as f64);
+        }
+        allpass.clear();
+        assert!((0..10).all(|_| allpass.process_sample(0.) == 0.));
+    }
+}
@@ -0,0 +1,360 @@
+//! # Parametric EQ
+//!
+//! [`ParametricEq`] is a multi-band EQ made from [`SvfFilter`](crate::svf::SvfFilter) style
+//! bands in series, with all bands in a single node.
+
+use crate::dsp::response::{Complex, FrequencyResponse};
+use crate::svf::{SvfCoefficients, SvfFilter, SvfFilterType, SvfState};
+use crate::ugens::param_names::collect_param_names;
+use knaster_core::{
+    AudioCtx, Float, FloatParameterKind, Frame, PFloat, PInteger, ParameterHint, ParameterValue,
+    Size, UGen, UGenFlags,
+    numeric_array::NumericArray,
+    typenum::{Prod, U5},
+};
+
+/// The maximum number of bands supported by [`ParametricEq`]
+pub const MAX_EQ_BANDS: usize = 16;
+/// The number of parameters for each band
+const PARAMETERS_PER_BAND: usize = 5;
+/// Time in seconds over which coefficient changes are interpolated
+const COEFFICIENT_SMOOTHING_TIME: f64 = 0.02;
+
+const BAND_PARAM_NAMES: [[&str; PARAMETERS_PER_BAND]; MAX_EQ_BANDS] = indexed_param_names!(
+    "band" ["_type", "_freq", "_q", "_gain", "_enable"];
+    0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15
+);
+
+/// Settings of one band of a [`ParametricEq`]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct EqBandSettings {
+    /// Filter type
+    pub ty: SvfFilterType,
+    /// Cutoff or center frequency in Hz
+    pub freq: PFloat,
+    #[allow(missing_docs)]
+    pub q: PFloat,
+    /// Gain in dB. Only used by Bell, LowShelf and HighShelf.
+    pub gain_db: PFloat,
+    /// If false, the band is bypassed
+    pub enabled: bool,
+}
+impl Default for EqBandSettings {
+    fn default() -> Self {
+        Self {
+            ty: SvfFilterType::Bell,
+            freq: 1000.,
+            q: 0.707,
+            gain_db: 0.,
+            enabled: true,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+struct EqBand<F: Float, Channels: Size> {
+    settings: EqBandSettings,
+    current: SvfCoefficients<F>,
+    target: SvfCoefficients<F>,
+    step: SvfCoefficients<F>,
+    /// Number of samples left until `current` reaches `target`
+    remaining: usize,
+    a: [F; 3],
+    states: NumericArray<SvfState<F>, Channels>,
+}
+impl<F: Float, Channels: Size> EqBand<F, Channels> {
+    fn target_coefficients(&self, sample_rate: F) -> SvfCoefficients<F> {
+        let EqBandSettings {
+            ty,
+            freq,
+            q,
+            gain_db,
+            enabled,
+        } = self.settings;
+        let coefficients =
+            SvfCoefficients::new(ty, F::new(freq), F::new(q), F::new(gain_db), sample_rate);
+        if enabled {
+            coefficients
+        } else {
+            coefficients.bypassed()
+        }
+    }
+    /// Jump directly to the current settings
+    fn reset(&mut self, sample_rate: F) {
+        self.target = self.target_coefficients(sample_rate);
+        self.current = self.target;
+        self.remaining = 0;
+        self.a = self.current.a();
+        for state in self.states.iter_mut() {
+            state.reset();
+        }
+    }
+    /// Start interpolating towards the current settings
+    fn update(&mut self, sample_rate: F, smoothing_samples: usize) {
+        self.target = self.target_coefficients(sample_rate);
+        if smoothing_samples == 0 {
+            self.current = self.target;
+            self.a = self.current.a();
+            self.remaining = 0;
+            return;
+        }
+        let steps = F::from_usize(smoothing_samples);
+        let step = |current: F, target: F| (target - current) / steps;
+        self.step = SvfCoefficients {
+            g: step(self.current.g, self.target.g),
+            k: step(self.current.k, self.target.k),
+            m0: step(self.current.m0, self.target.m0),
+            m1: step(self.current.m1, self.target.m1),
+            m2: step(self.current.m2, self.target.m2),
+        };
+        self.remaining = smoothing_samples;
+    }
+    #[inline]
+    fn advance_coefficients(&mut self) {
+        if self.remaining == 0 {
+            return;
+        }
+        self.remaining -= 1;
+        if self.remaining == 0 {
+            self.current = self.target;
+        } else {
+            let SvfCoefficients { g, k, m0, m1, m2 } = self.step;
+            self.current.g += g;
+            self.current.k += k;
+            self.current.m0 += m0;
+            self.current.m1 += m1;
+            self.current.m2 += m2;
+        }
+        self.a = self.current.a();
+    }
+}
+
+/// Parametric EQ with `Bands` bands processing `Channels` channels with the same settings.
+///
+/// Each band has the parameters `band{n}_type`, `band{n}_freq`, `band{n}_q`, `band{n}_gain`
+/// and `band{n}_enable`, e.g. `band0_freq`. The type is an [`SvfFilterType`]. Coefficient
+/// changes are interpolated to avoid clicks.
+///
+/// `Bands` can be at most [`MAX_EQ_BANDS`].
+///
+/// ```
+/// use knaster_core_dsp::{eq::ParametricEq, svf::SvfFilterType};
+/// use knaster_core::typenum::{U2, U6};
+/// let mut eq = ParametricEq::<f32, U6, U2>::new();
+/// eq.set_band(0, SvfFilterType::High, 40., 0.707, 0.);
+/// eq.set_band(1, SvfFilterType::LowShelf, 120., 0.707, 3.);
+/// eq.set_band(5, SvfFilterType::HighShelf, 8000., 0.707, -2.);
+/// ```
+pub struct ParametricEq<F: Float, Bands: Size, Channels: Size> {
+    bands: NumericArray<EqBand<F, Channels>, Bands>,
+    sample_rate: F,
+    smoothing_samples: usize,
+}
+impl<F: Float, Bands: Size, Channels: Size> ParametricEq<F, Bands, Channels> {
+    /// New EQ where all bands are 0 dB bell filters at 1000 Hz
+    ///
+    /// # Panics
+    /// Panics if `Bands` is larger than [`MAX_EQ_BANDS`]
+    pub fn new() -> Self {
+        assert!(
+            Bands::USIZE <= MAX_EQ_BANDS,
+            "ParametricEq supports at most {MAX_EQ_BANDS} bands"
+        );
+        Self {
+            bands: NumericArray::default(),
+            sample_rate: F::new(48000.),
+            smoothing_samples: 0,
+        }
+    }
+    /// Set the settings of a band. Changes made before the EQ is initialised are applied
+    /// immediately, later changes are smoothed.
+    pub fn set_band(
+        &mut self,
+        band: usize,
+        ty: SvfFilterType,
+        freq: PFloat,
+        q: PFloat,
+        gain_db: PFloat,
+    ) {
+        let settings = &mut self.bands[band].settings;
+        settings.ty = ty;
+        settings.freq = freq;
+        settings.q = q;
+        settings.gain_db = gain_db;
+        self.update_band(band);
+    }
+    /// Enable or bypass a band
+    pub fn set_band_enabled(&mut self, band: usize, enabled: bool) {
+        self.bands[band].settings.enabled = enabled;
+        self.update_band(band);
+    }
+    /// The current settings of a band
+    pub fn band(&self, band: usize) -> EqBandSettings {
+        self.bands[band].settings
+    }
+    fn update_band(&mut self, band: usize) {
+        self.bands[band].update(self.sample_rate, self.smoothing_samples);
+    }
+}
+impl<F: Float, Bands: Size, Channels: Size> Default for ParametricEq<F, Bands, Channels> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<F: Float, Bands: Size, Channels: Size> UGen for ParametricEq<F, Bands, Channels>
+where
+    Bands: core::ops::Mul<U5> + Send,
+    Prod<Bands, U5>: Size,
+{
+    type Sample = F;
+    type Inputs = Channels;
+    type Outputs = Channels;
+    type Parameters = Prod<Bands, U5>;
+
+    fn init(&mut self, sample_rate: u32, _block_size: usize) {
+        self.sample_rate = F::new(sample_rate as f32);
+        self.smoothing_samples = (COEFFICIENT_SMOOTHING_TIME * sample_rate as f64) as usize;
+        for band in self.bands.iter_mut() {
+            band.reset(self.sample_rate);
+        }
+    }
+
+    fn process(
+        &mut self,
+        _ctx: &mut AudioCtx,
+        _flags: &mut UGenFlags,
+        input: Frame<Self::Sample, Self::Inputs>,
+    ) -> Frame<Self::Sample, Self::Outputs> {
+        let mut out = input;
+        for band in self.bands.iter_mut() {
+            band.advance_coefficients();
+            for (sample, state) in out.iter_mut().zip(band.states.iter_mut()) {
+                *sample = state.process_sample(band.a, &band.current, *sample);
+            }
+        }
+        out
+    }
+
+    fn param_descriptions() -> NumericArray<&'static str, Self::Parameters> {
+        collect_param_names(BAND_PARAM_NAMES.as_flattened().iter().copied())
+    }
+
+    fn param_hints() -> NumericArray<ParameterHint, Self::Parameters> {
+        let mut hints: NumericArray<ParameterHint, Self::Parameters> = NumericArray::default();
+        let defaults = EqBandSettings::default();
+        for band_hints in hints.chunks_mut(PARAMETERS_PER_BAND) {
+            let mut ty = ParameterHint::from_pinteger_enum::<SvfFilterType>();
+            if let ParameterHint::Integer(hint) = &mut ty {
+                hint.default = Some(PInteger(defaults.ty as usize));
+            }
+            band_hints[0] = ty;
+            band_hints[1] = ParameterHint::new_float(|h| {
+                h.kind(FloatParameterKind::Frequency)
+                    .minmax(20., 20000.)
+                    .logarithmic(true)
+                    .default(defaults.freq)
+            });
+            band_hints[2] = ParameterHint::new_float(|h| {
+                h.kind(FloatParameterKind::Q)
+                    .minmax(0.1, 20.)
+                    .logarithmic(true)
+                    .default(defaults.q)
+            });
+            band_hints[3] =
+                ParameterHint::new_float(|h| h.minmax(-24., 24.).default(defaults.gain_db));
+            band_hints[4] = ParameterHint::boolean();
+        }
+        hints
+    }
+
+    fn param_apply(&mut self, _ctx: &mut AudioCtx, index: usize, value: ParameterValue) {
+        let band = index / PARAMETERS_PER_BAND;
+        if band >= Bands::USIZE {
+            return;
+        }
+        let settings = &mut self.bands[band].settings;
+        match (index % PARAMETERS_PER_BAND, value) {
+            (0, ParameterValue::Integer(ty)) => settings.ty = SvfFilterType::from(ty),
+            (1, ParameterValue::Float(freq)) => settings.freq = freq,
+            (2, ParameterValue::Float(q)) => settings.q = q,
+            (3, ParameterValue::Float(gain_db)) => settings.gain_db = gain_db,
+            (4, ParameterValue::Bool(enabled)) => settings.enabled = enabled,
+            _ => return,
+        }
+        self.update_band(band);
+    }
+}
+
+impl<F: Float, Bands: Size, Channels: Size> FrequencyResponse for ParametricEq<F, Bands, Channels> {
+    fn frequency_response(&self, freq: f64, sample_rate: f64) -> Complex<f64> {
+        self.bands.iter().filter(|band| band.settings.enabled).fold(
+            Complex::real(1.0),
+            |response, band| {
+                let EqBandSettings {
+                    ty,
+                    freq: f,
+                    q,
+                    gain_db,
+                    ..
+                } = band.settings;
+                response * SvfFilter::new(ty, f, q, gain_db).frequency_response(freq, sample_rate)
+            },
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use knaster_core::{
+        log::ArLogSender,
+        typenum::{U2, U3},
+    };
+
+    #[test]
+    fn eq_param_names() {
+        let names = ParametricEq::<f32, U3, U2>::param_descriptions();
+        assert_eq!(names.len(), 15);
+        assert_eq!(names[0], "band0_type");
+        assert_eq!(names[6], "band1_freq");
+        assert_eq!(names[14], "band2_enable");
+    }
+
+    #[test]
+    fn eq_matches_frequency_response() {
+        const SR: u32 = 48000;
+        let mut ctx = AudioCtx::new(SR, 64, ArLogSender::non_rt());
+        let mut flags = UGenFlags::new();
+        let mut eq = ParametricEq::<f64, U3, U2>::new();
+        eq.set_band(0, SvfFilterType::LowShelf, 200., 0.707, 6.);
+        eq.set_band(1, SvfFilterType::Bell, 1000., 2., -9.);
+        eq.set_band(2, SvfFilterType::Low, 8000., 0.707, 0.);
+        eq.init(SR, 64);
+        // Changing the parameter is smoothed, but settles well within the test time
+        eq.param(&mut ctx, "band2_enable", false).unwrap();
+        for freq in [100., 1000., 10000.] {
+            let mut sum = [0.0; 2];
+            for i in 0..SR as usize {
+                let x = (core::f64::consts::TAU * freq * i as f64 / SR as f64).sin();
+                let out = eq.process(&mut ctx, &mut flags, [x, x * 0.5].into());
+                if i >= SR as usize / 2 {
+                    sum[0] += out[0] * out[0];
+                    sum[1] += out[1] * out[1];
+                }
+            }
+            let expected = eq.magnitude_response(freq, SR as f64);
+            for (channel, amp) in [(0, 1.0), (1, 0.5)] {
+                let measured =
+                    (sum[channel] / (SR / 2) as f64).sqrt() * core::f64::consts::SQRT_2 / amp;
+                assert!(
+                    (measured - expected).abs() < 0.01 * expected,
+                    "{freq} Hz: measured {measured}, expected {expected}"
+                );
+            }
+        }
+        // The bell is centered at its frequency
+        let bell = SvfFilter::<f64>::new(SvfFilterType::Bell, 1000., 2., -9.);
+        assert!((bell.frequency_response(1000., SR as f64).magnitude_db() + 9.).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,653 @@
+//! # FM synthesis
+//!
+//! [`FmSynth`]: a phase modulation synthesizer with up to [`MAX_FM_OPERATORS`] operators, each
+//! with its own envelope. The operators are connected by an [`FmAlgorithm`] or a free modulation
+//! matrix.
+//!
+//! All operators are computed sample by sample within the UGen, so modulation has no block
+//! latency and operator feedback is delayed by a single sample.
+
+use crate::ugens::param_names::collect_param_names;
+use knaster_core::{
+    AudioCtx, Float, FloatParameterKind, Frame, KnasterIntegerParameter, PFloat, PInteger,
+    ParameterHint, ParameterValue, Size, UGen, UGenFlags,
+    num_derive::{FromPrimitive, ToPrimitive},
+    num_traits,
+    numeric_array::NumericArray,
+    typenum::{Prod, Sum, U1, U3, U8},
+};
+
+/// The maximum number of operators of an [`FmSynth`]
+pub const MAX_FM_OPERATORS: usize = 8;
+/// The modulation index in radians of a modulator at full level
+pub const MAX_MODULATION_INDEX: f64 = 4.0 * core::f64::consts::PI;
+/// The feedback index in radians of an operator at full feedback
+const MAX_FEEDBACK_INDEX: f64 = core::f64::consts::PI;
+/// Envelope level in the release stage below which the operator is silent
+const SILENCE_THRESHOLD: f64 = 1e-4;
+
+/// How the operators of an [`FmSynth`] modulate each other.
+///
+/// Operators are numbered from 0. Apart from [`FmAlgorithm::Matrix`], operator 0 is always a
+/// carrier and modulators always have higher numbers than the operators they modulate.
+#[derive(
+    Clone, Copy, Debug, FromPrimitive, ToPrimitive, PartialEq, Default, KnasterIntegerParameter,
+)]
+#[num_traits = "num_traits"]
+#[repr(u8)]
+pub enum FmAlgorithm {
+    /// Every operator modulates the one below it, with operator 0 as the only carrier
+    #[default]
+    Stack = 0,
+    /// Odd operators modulate the even operator below them, and even operators are carriers
+    Pairs,
+    /// All other operators modulate operator 0, which is the only carrier
+    ManyToOne,
+    /// The last operator modulates all other operators, which are carriers
+    OneToMany,
+    /// All operators are carriers, for additive synthesis
+    Parallel,
+    /// The `mod{source}_{target}` and `output{n}` parameters decide the routing
+    Matrix,
+}
+
+/// The number of parameters for each operator of an [`FmSynth`]
+const PARAMETERS_PER_OPERATOR: usize = 8;
+/// The number of parameters before the operator parameters of an [`FmSynth`]
+const GLOBAL_PARAMETERS: usize = 3;
+const GLOBAL_PARAM_NAMES: [&str; GLOBAL_PARAMETERS] = ["freq", "algorithm", "t_reset"];
+const OPERATOR_PARAM_NAMES: [[&str; PARAMETERS_PER_OPERATOR]; MAX_FM_OPERATORS] = indexed_param_names!(
+    ["ratio", "level", "feedback", "attack", "decay", "sustain", "release", "output"];
+    0, 1, 2, 3, 4, 5, 6, 7
+);
+const MATRIX_PARAM_NAMES: [[&str; MAX_FM_OPERATORS]; MAX_FM_OPERATORS] = indexed_param_names!(
+    "mod" ["_0", "_1", "_2", "_3", "_4", "_5", "_6", "_7"];
+    0, 1, 2, 3, 4, 5, 6, 7
+);
+
+/// The settings of one operator of an [`FmSynth`]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FmOperator {
+    /// Frequency as a ratio of the frequency of the [`FmSynth`]
+    pub ratio: PFloat,
+    /// Amplitude of a carrier, or modulation depth of a modulator, between 0 and 1
+    pub level: PFloat,
+    /// Self-modulation of the operator between 0 and 1
+    pub feedback: PFloat,
+    /// Envelope attack time in seconds
+    pub attack: PFloat,
+    /// Envelope decay time in seconds
+    pub decay: PFloat,
+    /// Envelope sustain level between 0 and 1
+    pub sustain: PFloat,
+    /// Envelope release time in seconds
+    pub release: PFloat,
+    /// Output level of the operator with [`FmAlgorithm::Matrix`]
+    pub output: PFloat,
+}
+impl Default for FmOperator {
+    fn default() -> Self {
+        Self {
+            ratio: 1.,
+            level: 0.,
+            feedback: 0.,
+            attack: 0.005,
+            decay: 0.5,
+            sustain: 1.,
+            release: 0.2,
+            output: 0.,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum EnvelopeStage {
+    Attack,
+    /// Decaying towards the sustain level, which is held until release
+    Decay,
+    Release,
+    Idle,
+}
+
+/// Operator envelope with a linear attack and exponential decay and release. The decay and
+/// release times are the times to fall by 60 dB.
+#[derive(Clone, Copy, Debug)]
+struct OperatorEnvelope<F> {
+    stage: EnvelopeStage,
+    level: F,
+    attack_step: F,
+    decay_coeff: F,
+    sustain: F,
+    release_coeff: F,
+}
+impl<F: Float> OperatorEnvelope<F> {
+    fn new() -> Self {
+        Self {
+            stage: EnvelopeStage::Idle,
+            level: F::ZERO,
+            attack_step: F::ONE,
+            decay_coeff: F::ZERO,
+            sustain: F::ONE,
+            release_coeff: F::ZERO,
+        }
+    }
+    fn set(&mut self, operator: &FmOperator, sample_rate: u32) {
+        let sample_rate = sample_rate as PFloat;
+        let coeff = |seconds: PFloat| {
+            let frames = seconds * sample_rate;
+            if frames > 0. {
+                F::new(num_traits::Float::powf(0.001_f64, 1. / frames))
+            } else {
+                F::ZERO
+            }
+        };
+        let attack_frames = operator.attack * sample_rate;
+        self.attack_step = if attack_frames > 1. {
+            F::new(1. / attack_frames)
+        } else {
+            F::ONE
+        };
+        self.decay_coeff = coeff(operator.decay);
+        self.sustain = F::new(operator.sustain);
+        self.release_coeff = coeff(operator.release);
+    }
+    #[inline]
+    fn next_sample(&mut self) -> F {
+        match self.stage {
+            EnvelopeStage::Attack => {
+                self.level += self.attack_step;
+                if self.level >= F::ONE {
+                    self.level = F::ONE;
+                    self.stage = EnvelopeStage::Decay;
+                }
+            }
+            EnvelopeStage::Decay => {
+                self.level = self.sustain + (self.level - self.sustain) * self.decay_coeff;
+            }
+            EnvelopeStage::Release => {
+                self.level *= self.release_coeff;
+                if self.level < F::new(SILENCE_THRESHOLD) {
+                    self.level = F::ZERO;
+                    self.stage = EnvelopeStage::Idle;
+                }
+            }
+            EnvelopeStage::Idle => (),
+        }
+        self.level
+    }
+}
+
+/// Phase modulation synthesizer with `Operators` sine operators in the style of classic FM
+/// synthesizers.
+///
+/// The input is a gate: the envelopes of all operators start when it goes above 0 and are
+/// released when it goes back to 0 or below. The output is the sum of the carriers.
+///
+/// Each operator plays `freq * ratio{n}` and has an envelope with `attack{n}`, `decay{n}`,
+/// `sustain{n}` and `release{n}`. `level{n}` is the amplitude of a carrier, or the modulation
+/// depth of a modulator, where 1 is a modulation index of [`MAX_MODULATION_INDEX`] radians.
+/// `feedback{n}` lets the operator modulate itself by the average of its last two samples, which
+/// tames the noise at high feedback, with a feedback index of up to π radians.
+///
+/// `algorithm` selects the routing between the operators, see [`FmAlgorithm`]. With
+/// [`FmAlgorithm::Matrix`], `mod{source}_{target}` sets how much operator `source` modulates
+/// operator `target`, and `output{n}` how much of operator `n` is in the output. The operators
+/// are computed from the highest number to the lowest, so modulation from a higher operator to a
+/// lower one has no delay, and modulation in the other direction is delayed by one sample.
+///
+/// By default only operator 0 has a level, so the output is a sine wave with any algorithm.
+/// `t_reset` restarts the phases of all operators.
+///
+/// The parameters are `freq`, `algorithm` and `t_reset`, then `ratio{n}`, `level{n}`,
+/// `feedback{n}`, `attack{n}`, `decay{n}`, `sustain{n}`, `release{n}` and `output{n}` for each
+/// operator, then `mod{source}_{target}` for every pair of operators.
+///
+/// `Operators` can be at most [`MAX_FM_OPERATORS`].
+///
+/// ```
+/// use knaster_core_dsp::fm::{FmAlgorithm, FmSynth};
+/// use knaster_core::typenum::U2;
+/// // A bell: a modulator at a non-integer ratio with a fast decay
+/// let mut fm = FmSynth::<f32, U2>::new(FmAlgorithm::Stack);
+/// let mut modulator = fm.operator(1);
+/// modulator.ratio = 3.5;
+/// modulator.level = 0.3;
+/// modulator.decay = 1.0;
+/// modulator.sustain = 0.0;
+/// fm.set_operator(1, modulator);
+/// ```
+pub struct FmSynth<F: Copy, Operators: Size> {
+    algorithm: FmAlgorithm,
+    freq: PFloat,
+    sample_rate: u32,
+    operators: [FmOperator; MAX_FM_OPERATORS],
+    /// `matrix[source][target]` as set by the parameters
+    matrix: [[PFloat; MAX_FM_OPERATORS]; MAX_FM_OPERATORS],
+    /// The modulation in radians per unit of output, from the algorithm or the matrix
+    modulation: [[F; MAX_FM_OPERATORS]; MAX_FM_OPERATORS],
+    output_gains: [F; MAX_FM_OPERATORS],
+    levels: [F; MAX_FM_OPERATORS],
+    feedback: [F; MAX_FM_OPERATORS],
+    envelopes: [OperatorEnvelope<F>; MAX_FM_OPERATORS],
+    phases: [F; MAX_FM_OPERATORS],
+    steps: [F; MAX_FM_OPERATORS],
+    /// The latest output of each operator
+    outputs: [F; MAX_FM_OPERATORS],
+    /// The output of each operator one sample before `outputs`
+    previous_outputs: [F; MAX_FM_OPERATORS],
+    gate: bool,
+    _operators: core::marker::PhantomData<Operators>,
+}
+impl<F: Float, Operators: Size> FmSynth<F, Operators> {
+    /// New [`FmSynth`] where only operator 0 has a level
+    ///
+    /// # Panics
+    /// Panics if `Operators` is larger than [`MAX_FM_OPERATORS`]
+    pub fn new(algorithm: FmAlgorithm) -> Self {
+        assert!(
+            Operators::USIZE <= MAX_FM_OPERATORS,
+            "FmSynth supports at most {MAX_FM_OPERATORS} operators"
+        );
+        let mut operators = [FmOperator::default(); MAX_FM_OPERATORS];
+        operators[0].level = 1.;
+        operators[0].output = 1.;
+        let mut s = Self {
+            algorithm,
+            freq: 220.,
+            sample_rate: 0,
+            operators,
+            matrix: [[0.; MAX_FM_OPERATORS]; MAX_FM_OPERATORS],
+            modulation: [[F::ZERO; MAX_FM_OPERATORS]; MAX_FM_OPERATORS],
+            output_gains: [F::ZERO; MAX_FM_OPERATORS],
+            levels: [F::ZERO; MAX_FM_OPERATORS],
+            feedback: [F::ZERO; MAX_FM_OPERATORS],
+            envelopes: [OperatorEnvelope::new(); MAX_FM_OPERATORS],
+            phases: [F::ZERO; MAX_FM_OPERATORS],
+            steps: [F::ZERO; MAX_FM_OPERATORS],
+            outputs: [F::ZERO; MAX_FM_OPERATORS],
+            previous_outputs: [F::ZERO; MAX_FM_OPERATORS],
+            gate: false,
+            _operators: core::marker::PhantomData,
+        };
+        for operator in 0..Operators::USIZE {
+            s.update_operator(operator);
+        }
+        s.update_routing();
+        s
+    }
+    /// The current settings of an operator
+    pub fn operator(&self, operator: usize) -> FmOperator {
+        self.operators[operator]
+    }
+    /// Set all settings of an operator
+    ///
+    /// # Panics
+    /// Panics if `operator` is out of bounds
+    pub fn set_operator(&mut self, operator: usize, settings: FmOperator) {
+        assert!(operator < Operators::USIZE, "operator out of bounds");
+        self.operators[operator] = settings;
+        self.update_operator(operator);
+        self.update_routing();
+    }
+    /// Set how much `source` modulates `target` with [`FmAlgorithm::Matrix`], where 1 is a
+    /// modulation index of [`MAX_MODULATION_INDEX`] radians at full level
+    pub fn set_modulation(&mut self, source: usize, target: usize, amount: PFloat) {
+        self.matrix[source][target] = amount;
+        self.update_routing();
+    }
+    /// Set the routing between the operators
+    pub fn set_algorithm(&mut self, algorithm: FmAlgorithm) {
+        self.algorithm = algorithm;
+        self.update_routing();
+    }
+    /// Restart the phases of all operators
+    pub fn reset(&mut self) {
+        self.phases = [F::ZERO; MAX_FM_OPERATORS];
+        self.outputs = [F::ZERO; MAX_FM_OPERATORS];
+        self.previous_outputs = [F::ZERO; MAX_FM_OPERATORS];
+    }
+    fn update_operator(&mut self, operator: usize) {
+        let settings = &self.operators[operator];
+        self.levels[operator] = F::new(settings.level);
+        self.feedback[operator] = F::new(settings.feedback * MAX_FEEDBACK_INDEX * 0.5);
+        self.steps[operator] = if self.sample_rate > 0 {
+            F::new(self.freq * settings.ratio / self.sample_rate as PFloat)
+        } else {
+            F::ZERO
+        };
+        self.envelopes[operator].set(settings, self.sample_rate);
+    }
+    fn update_routing(&mut self) {
+        let n = Operators::USIZE;
+        let mut modulation = [[0.; MAX_FM_OPERATORS]; MAX_FM_OPERATORS];
+        let mut carriers = [false; MAX_FM_OPERATORS];
+        match self.algorithm {
+            FmAlgorithm::Stack => {
+                carriers[0] = true;
+                for target in 0..n.saturating_sub(1) {
+                    modulation[target + 1][target] = 1.;
+                }
+            }
+            FmAlgorithm::Pairs => {
+                for target in (0..n).step_by(2) {
+                    carriers[target] = true;
+                    if target + 1 < n {
+                        modulation[target + 1][target] = 1.;
+                    }
+                }
+            }
+            FmAlgorithm::ManyToOne => {
+                carriers[0] = true;
+                for row in modulation.iter_mut().take(n).skip(1) {
+                    row[0] = 1.;
+                }
+            }
+            FmAlgorithm::OneToMany => {
+                if n == 1 {
+                    carriers[0] = true;
+                }
+                for target in 0..n.saturating_sub(1) {
+                    carriers[target] = true;
+                    modulation[n - 1][target] = 1.;
+                }
+            }
+            FmAlgorithm::Parallel => carriers[..n].fill(true),
+            FmAlgorithm::Matrix => modulation = self.matrix,
+        }
+        let num_carriers = carriers.iter().filter(|&&c| c).count().max(1) as PFloat;
+        for operator in 0..MAX_FM_OPERATORS {
+            for (scaled, &amount) in self.modulation[operator]
+                .iter_mut()
+                .zip(&modulation[operator])
+            {
+                *scaled = F::new(amount * MAX_MODULATION_INDEX);
+            }
+            self.output_gains[operator] = if self.algorithm == FmAlgorithm::Matrix {
+                F::new(self.operators[operator].output)
+            } else if carriers[operator] {
+                F::new(1. / num_carriers)
+            } else {
+                F::ZERO
+            };
+        }
+    }
+    fn set_gate(&mut self, gate: bool) {
+        if gate == self.gate {
+            return;
+        }
+        self.gate = gate;
+        for envelope in &mut self.envelopes[..Operators::USIZE] {
+            envelope.stage = if gate {
+                EnvelopeStage::Attack
+            } else if envelope.stage == EnvelopeStage::Idle {
+                EnvelopeStage::Idle
+            } else {
+                EnvelopeStage::Release
+            };
+        }
+    }
+    #[inline]
+    fn next_sample(&mut self) -> F {
+        let n = Operators::USIZE;
+        let mut out = F::ZERO;
+        for operator in (0..n).rev() {
+            // Higher operators have already been computed for this sample
+            let mut modulation = self.feedback[operator]
+                * (self.outputs[operator] + self.previous_outputs[operator]);
+            for (source, row) in self.modulation[..n].iter().enumerate() {
+                modulation += row[operator] * self.outputs[source];
+            }
+            let envelope = self.envelopes[operator].next_sample();
+            let sample = (self.phases[operator] * F::TAU + modulation).sin()
+                * envelope
+                * self.levels[operator];
+            self.previous_outputs[operator] = self.outputs[operator];
+            self.outputs[operator] = sample;
+            out += sample * self.output_gains[operator];
+            let phase = self.phases[operator] + self.steps[operator];
+            self.phases[operator] = phase - phase.floor();
+        }
+        out
+    }
+}
+
+impl<F: Float, Operators: Size> UGen for FmSynth<F, Operators>
+where
+    Operators: core::ops::Mul<U8> + core::ops::Mul<Operators> + Send,
+    Prod<Operators, U8>: core::ops::Add<Prod<Operators, Operators>>,
+    Sum<Prod<Operators, U8>, Prod<Operators, Operators>>: core::ops::Add<U3>,
+    Sum<Sum<Prod<Operators, U8>, Prod<Operators, Operators>>, U3>: Size,
+{
+    type Sample = F;
+    type Inputs = U1;
+    type Outputs = U1;
+    type Parameters = Sum<Sum<Prod<Operators, U8>, Prod<Operators, Operators>>, U3>;
+
+    fn init(&mut self, sample_rate: u32, _block_size: usize) {
+        self.sample_rate = sample_rate;
+        for operator in 0..Operators::USIZE {
+            self.update_operator(operator);
+        }
+    }
+
+    fn process(
+        &mut self,
+        _ctx: &mut AudioCtx,
+        _flags: &mut UGenFlags,
+        input: Frame<Self::Sample, Self::Inputs>,
+    ) -> Frame<Self::Sample, Self::Outputs> {
+        self.set_gate(input[0] > F::ZERO);
+        [self.next_sample()].into()
+    }
+
+    fn param_descriptions() -> NumericArray<&'static str, Self::Parameters> {
+        let n = Operators::USIZE;
+        let matrix_names = MATRIX_PARAM_NAMES[..n].iter().flat_map(|row| &row[..n]);
+        collect_param_names(
+            GLOBAL_PARAM_NAMES
+                .into_iter()
+                .chain(OPERATOR_PARAM_NAMES[..n].as_flattened().iter().copied())
+                .chain(matrix_names.copied()),
+        )
+    }
+
+    fn param_hints() -> NumericArray<ParameterHint, Self::Parameters> {
+        let n = Operators::USIZE;
+        let mut hints: NumericArray<ParameterHint, Self::Parameters> = NumericArray::default();
+        let defaults = FmOperator::default();
+        hints[0] = ParameterHint::new_float(|h| {
+            h.kind(FloatParameterKind::Frequency)
+                .minmax(0., 20000.)
+                .default(220.)
+        });
+        hints[1] = ParameterHint::from_pinteger_enum::<FmAlgorithm>();
+        hints[2] = ParameterHint::Trigger;
+        let operator_hints =
+            &mut hints[GLOBAL_PARAMETERS..GLOBAL_PARAMETERS + n * PARAMETERS_PER_OPERATOR];
+        for (operator, op_hints) in operator_hints
+            .chunks_mut(PARAMETERS_PER_OPERATOR)
+            .enumerate()
+        {
+            let first = if operator == 0 { 1. } else { 0. };
+            op_hints[0] = ParameterHint::new_float(|h| h.minmax(0., 32.).default(defaults.ratio));
+            op_hints[1] = ParameterHint::new_float(|h| h.minmax(0., 1.).default(first));
+            op_hints[2] = ParameterHint::new_float(|h| h.minmax(0., 1.).default(defaults.feedback));
+            for (hint, default) in op_hints[3..5]
+                .iter_mut()
+                .zip([defaults.attack, defaults.decay])
+            {
+                *hint = ParameterHint::new_float(|h| {
+                    h.kind(FloatParameterKind::Seconds)
+                        .minmax(0., 30.)
+                        .default(default)
+                });
+            }
+            op_hints[5] = ParameterHint::new_float(|h| h.minmax(0., 1.).default(defaults.sustain));
+            op_hints[6] = ParameterHint::new_float(|h| {
+                h.kind(FloatParameterKind::Seconds)
+                    .minmax(0., 30.)
+                    .default(defaults.release)
+            });
+            op_hints[7] = ParameterHint::new_float(|h| h.minmax(0., 1.).default(first));
+        }
+        for hint in &mut hints[GLOBAL_PARAMETERS + n * PARAMETERS_PER_OPERATOR..] {
+            *hint = ParameterHint::new_float(|h| h.minmax(0., 1.).default(0.));
+        }
+        hints
+    }
+
+    fn param_apply(&mut self, _ctx: &mut AudioCtx, index: usize, value: ParameterValue) {
+        let n = Operators::USIZE;
+        let matrix_start = GLOBAL_PARAMETERS + n * PARAMETERS_PER_OPERATOR;
+        match (index, value) {
+            (0, ParameterValue::Float(freq)) => {
+                self.freq = freq;
+                for operator in 0..n {
+                    self.update_operator(operator);
+                }
+            }
+            (1, ParameterValue::Integer(algorithm)) => {
+                self.set_algorithm(FmAlgorithm::from(algorithm))
+            }
+            (2, ParameterValue::Trigger) => self.reset(),
+            (index, ParameterValue::Float(value)) if index >= matrix_start => {
+                let index = index - matrix_start;
+                if index < n * n {
+                    self.set_modulation(index / n, index % n, value);
+                }
+            }
+            (index, ParameterValue::Float(value)) if index >= GLOBAL_PARAMETERS => {
+                let operator = (index - GLOBAL_PARAMETERS) / PARAMETERS_PER_OPERATOR;
+                let settings = &mut self.operators[operator];
+                match (index - GLOBAL_PARAMETERS) % PARAMETERS_PER_OPERATOR {
+                    0 => settings.ratio = value,
+                    1 => settings.level = value,
+                    2 => settings.feedback = value,
+                    3 => settings.attack = value.max(0.),
+                    4 => settings.decay = value.max(0.),
+                    5 => settings.sustain = value.clamp(0., 1.),
+                    6 => settings.release = value.max(0.),
+                    _ => settings.output = value,
+                }
+                self.update_operator(operator);
+                self.update_routing();
+            }
+            _ => (),
+        }
+    }
+}
+
+#[cfg(all(test, any(feature = "std", feature = "alloc")))]
+mod tests {
+    use super::*;
+    use knaster_core::{
+        log::ArLogSender,
+        typenum::{U2, U4},
+    };
+    use std::f64::consts::TAU;
+    use std::prelude::v1::*;
+
+    const SR: u32 = 48000;
+
+    fn run<Operators: Size>(
+        fm: &mut FmSynth<f64, Operators>,
+        ctx: &mut AudioCtx,
+        frames: usize,
+        gate: f64,
+    ) -> Vec<f64>
+    where
+        FmSynth<f64, Operators>: UGen<Sample = f64, Inputs = U1, Outputs = U1>,
+    {
+        let mut flags = UGenFlags::new();
+        (0..frames)
+            .map(|_| UGen::process(fm, ctx, &mut flags, [gate].into())[0])
+            .collect()
+    }
+
+    #[test]
+    fn stack_matches_phase_modulation() {
+        let mut ctx = AudioCtx::new(SR, 64, ArLogSender::non_rt());
+        let mut fm = FmSynth::<f64, U2>::new(FmAlgorithm::Stack);
+        fm.init(SR, 64);
+        fm.param(&mut ctx, "freq", 300.).unwrap();
+        for attack in ["attack0", "attack1"] {
+            fm.param(&mut ctx, attack, 0.).unwrap();
+        }
+        fm.param(&mut ctx, "ratio1", 2.).unwrap();
+        fm.param(&mut ctx, "level1", 0.25).unwrap();
+        fm.param(&mut ctx, "feedback1", 0.5).unwrap();
+        let output = run(&mut fm, &mut ctx, 2000, 1.);
+        // Reference implementation with feedback on the modulator
+        let (mut m1, mut m2) = (0., 0.);
+        for (i, &out) in output.iter().enumerate() {
+            let t = i as f64 / SR as f64;
+            let feedback = 0.5 * MAX_FEEDBACK_INDEX * 0.5 * (m1 + m2);
+            let modulator = 0.25 * (TAU * 600. * t + feedback).sin();
+            (m2, m1) = (m1, modulator);
+            let carrier = (TAU * 300. * t + modulator * MAX_MODULATION_INDEX).sin();
+            assert!((out - carrier).abs() < 1e-6, "{i}: {out} != {carrier}");
+        }
+        // Without modulation, Parallel is a plain sine
+        fm.param(&mut ctx, "algorithm", FmAlgorithm::Parallel)
+            .unwrap();
+        fm.param(&mut ctx, "level1", 0.).unwrap();
+        fm.param(&mut ctx, "t_reset", knaster_core::PTrigger)
+            .unwrap();
+        let output = run(&mut fm, &mut ctx, 100, 1.);
+        for (i, &out) in output.iter().enumerate() {
+            let expected = (TAU * 300. * i as f64 / SR as f64).sin() * 0.5;
+            assert!((out - expected).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn matrix_and_envelopes() {
+        let mut ctx = AudioCtx::new(SR, 64, ArLogSender::non_rt());
+        let mut fm = FmSynth::<f64, U4>::new(FmAlgorithm::Matrix);
+        fm.init(SR, 64);
+        let mut carrier = fm.operator(0);
+        carrier.attack = 0.01;
+        carrier.decay = 0.1;
+        carrier.sustain = 0.5;
+        carrier.release = 0.05;
+        fm.set_operator(0, carrier);
+        assert!(run(&mut fm, &mut ctx, 100, 0.).iter().all(|&x| x == 0.));
+        // The attack reaches full level after 10 ms
+        run(&mut fm, &mut ctx, 480, 1.);
+        assert!((fm.envelopes[0].level - 1.).abs() < 1e-9);
+        // The decay falls by 60 dB towards the sustain level in 100 ms
+        run(&mut fm, &mut ctx, 4800, 1.);
+        assert!((fm.envelopes[0].level - 0.5005).abs() < 1e-6);
+        // The release falls by 60 dB in 50 ms and then cuts off below the silence threshold
+        run(&mut fm, &mut ctx, 2400, 0.);
+        assert!(fm.envelopes[0].level < 0.001);
+        run(&mut fm, &mut ctx, 2400, 0.);
+        let tail = run(&mut fm, &mut ctx, 2400, 0.);
+        assert!(tail.iter().all(|&x| x == 0.));
+        assert_eq!(fm.envelopes[0].stage, EnvelopeStage::Idle);
+
+        // Modulation from a lower operator to a higher one is delayed by a sample
+        let mut modulator = fm.operator(0);
+        modulator.attack = 0.;
+        modulator.sustain = 1.;
+        fm.set_operator(0, modulator);
+        let mut carrier = modulator;
+        carrier.level = 1.;
+        carrier.output = 1.;
+        fm.set_operator(3, carrier);
+        modulator.output = 0.;
+        modulator.level = 0.1;
+        fm.set_operator(0, modulator);
+        fm.param(&mut ctx, "mod0_3", 1.).unwrap();
+        fm.param(&mut ctx, "t_reset", knaster_core::PTrigger)
+            .unwrap();
+        let output = run(&mut fm, &mut ctx, 500, 1.);
+        let mut previous = 0.;
+        for (i, &out) in output.iter().enumerate() {
+            let phase = TAU * 220. * i as f64 / SR as f64;
+            let expected = (phase + previous * MAX_MODULATION_INDEX).sin();
+            previous = 0.1 * phase.sin();
+            assert!((out - expected).abs() < 1e-9, "{i}: {out} != {expected}");
+        }
+    }
+}
@@ -0,0 +1,567 @@
+//! # Metering
+//!
+//! [`Meter`] measures the level of its input and publishes it to a [`MeterHandle`] which can be
+//! read from any thread, e.g. to draw meters in a GUI. The levels are stored in atomics, so
+//! neither the audio thread nor the reader ever blocks.
+//!
+//! ```
+//! use knaster_core_dsp::meter::Meter;
+//! use knaster_core::typenum::U2;
+//! let (meter, handle) = Meter::<f32, U2>::new();
+//! // Push `meter` to a graph and connect a signal to it, then on the GUI thread:
+//! let peak = handle.take_peak(0);
+//! let loudness = handle.momentary_lufs();
+//! ```
+
+use crate::core::{
+    marker::PhantomData,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+};
+use crate::dsp::atomic::AtomicF32;
+use knaster_core::{AudioCtx, Float, Size, UGenFlags, impl_ugen, typenum::U0};
+use std::prelude::v1::*;
+
+/// How often the levels are published in seconds
+const PUBLISH_INTERVAL: f64 = 0.01;
+/// Time constant of the RMS level in seconds
+const RMS_TIME: f64 = 0.3;
+/// Length of the blocks that loudness is computed from in seconds
+const LOUDNESS_STEP: f64 = 0.1;
+/// Number of steps in the momentary loudness window of 400 ms
+const MOMENTARY_STEPS: usize = 4;
+/// Number of steps in the short-term loudness window of 3 s
+const SHORT_TERM_STEPS: usize = 30;
+/// Absolute gate for integrated loudness in LUFS
+const ABSOLUTE_GATE: f64 = -70.0;
+/// Relative gate for integrated loudness in LU below the absolutely gated loudness
+const RELATIVE_GATE: f64 = -10.0;
+/// Highest loudness in the integrated loudness histogram in LUFS
+const HISTOGRAM_MAX: f64 = 10.0;
+/// Width of the bins of the integrated loudness histogram in LU
+const HISTOGRAM_BIN_WIDTH: f64 = 0.1;
+const HISTOGRAM_BINS: usize = ((HISTOGRAM_MAX - ABSOLUTE_GATE) / HISTOGRAM_BIN_WIDTH) as usize;
+/// Oversampling factor for true peak
+const TRUE_PEAK_OVERSAMPLING: usize = 4;
+/// Number of filter taps per oversampled phase for true peak
+const TRUE_PEAK_TAPS: usize = 12;
+
+fn loudness_from_energy(energy: f64) -> f64 {
+    if energy > 0.0 {
+        -0.691 + 10.0 * energy.log10()
+    } else {
+        f64::NEG_INFINITY
+    }
+}
+
+struct ChannelLevels {
+    peak: AtomicF32,
+    true_peak: AtomicF32,
+    rms: AtomicF32,
+}
+
+/// The levels shared between a [`Meter`] and its [`MeterHandle`]s
+struct MeterLevels {
+    channels: Vec<ChannelLevels>,
+    momentary: AtomicF32,
+    short_term: AtomicF32,
+    integrated: AtomicF32,
+    reset: AtomicBool,
+}
+
+/// Reads the levels measured by a [`Meter`] from any thread. Reading never blocks.
+///
+/// Peak levels are the highest level since they were last taken with [`MeterHandle::take_peak`]
+/// or [`MeterHandle::take_true_peak`], so no peaks are missed between reads. Levels are linear
+/// amplitudes and loudness is in LUFS, with negative infinity for silence. All levels are
+/// updated every 10 ms.
+#[derive(Clone)]
+pub struct MeterHandle {
+    levels: Arc<MeterLevels>,
+}
+impl MeterHandle {
+    /// The number of channels of the [`Meter`]
+    pub fn channels(&self) -> usize {
+        self.levels.channels.len()
+    }
+    /// The highest absolute sample value since the peak was last taken
+    ///
+    /// # Panics
+    /// Panics if `channel` is out of bounds
+    pub fn peak(&self, channel: usize) -> f32 {
+        self.levels.channels[channel].peak.load()
+    }
+    /// The highest absolute sample value since the peak was last taken, resetting it to 0
+    ///
+    /// # Panics
+    /// Panics if `channel` is out of bounds
+    pub fn take_peak(&self, channel: usize) -> f32 {
+        self.levels.channels[channel].peak.swap(0.0)
+    }
+    /// The highest true peak since the true peak was last taken. Always 0 unless true peak
+    /// metering is enabled with the `true_peak` parameter.
+    ///
+    /// # Panics
+    /// Panics if `channel` is out of bounds
+    pub fn true_peak(&self, channel: usize) -> f32 {
+        self.levels.channels[channel].true_peak.load()
+    }
+    /// The highest true peak since the true peak was last taken, resetting it to 0
+    ///
+    /// # Panics
+    /// Panics if `channel` is out of bounds
+    pub fn take_true_peak(&self, channel: usize) -> f32 {
+        self.levels.channels[channel].true_peak.swap(0.0)
+    }
+    /// The RMS level over roughly the last 300 ms
+    ///
+    /// # Panics
+    /// Panics if `channel` is out of bounds
+    pub fn rms(&self, channel: usize) -> f32 {
+        self.levels.channels[channel].rms.load()
+    }
+    /// Momentary loudness over the last 400 ms
+    pub fn momentary_lufs(&self) -> f32 {
+        self.levels.momentary.load()
+    }
+    /// Short-term loudness over the last 3 s
+    pub fn short_term_lufs(&self) -> f32 {
+        self.levels.short_term.load()
+    }
+    /// Gated integrated loudness since the meter started or was reset
+    pub fn integrated_lufs(&self) -> f32 {
+        self.levels.integrated.load()
+    }
+    /// Reset the integrated loudness. Takes effect on the next update of the levels.
+    pub fn reset(&self) {
+        self.levels.reset.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Biquad filter with the a0 coefficient normalised to 1
+#[derive(Clone, Copy, Debug, Default)]
+struct Biquad<F> {
+    b: [F; 3],
+    a: [F; 2],
+    x: [F; 2],
+    y: [F; 2],
+}
+impl<F: Float> Biquad<F> {
+    fn new(b: [f64; 3], a: [f64; 2]) -> Self {
+        Self {
+            b: b.map(F::new),
+            a: a.map(F::new),
+            x: [F::ZERO; 2],
+            y: [F::ZERO; 2],
+        }
+    }
+    #[inline]
+    fn process(&mut self, x: F) -> F {
+        let y = self.b[0] * x + self.b[1] * self.x[0] + self.b[2] * self.x[1]
+            - self.a[0] * self.y[0]
+            - self.a[1] * self.y[1];
+        self.x = [x, self.x[0]];
+        self.y = [y, self.y[0]];
+        y
+    }
+}
+
+/// The K-weighting filters of ITU-R BS.1770: a high shelf followed by a highpass. The
+/// coefficients are computed for any sample rate, matching the coefficients given in the
+/// standard at 48 kHz.
+fn k_weighting<F: Float>(sample_rate: u32) -> [Biquad<F>; 2] {
+    let sample_rate = sample_rate as f64;
+    let shelf = {
+        let f0 = 1681.974450955533;
+        let gain = 3.999843853973347;
+        let q = 0.7071752369554196;
+        let k = (core::f64::consts::PI * f0 / sample_rate).tan();
+        let vh = 10.0f64.powf(gain / 20.0);
+        let vb = vh.powf(0.4996667741545416);
+        let a0 = 1.0 + k / q + k * k;
+        Biquad::new(
+            [
+                (vh + vb * k / q + k * k) / a0,
+                2.0 * (k * k - vh) / a0,
+                (vh - vb * k / q + k * k) / a0,
+            ],
+            [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+        )
+    };
+    let highpass = {
+        let f0 = 38.13547087602444;
+        let q = 0.5003270373238773;
+        let k = (core::f64::consts::PI * f0 / sample_rate).tan();
+        let a0 = 1.0 + k / q + k * k;
+        Biquad::new(
+            [1.0, -2.0, 1.0],
+            [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+        )
+    };
+    [shelf, highpass]
+}
+
+/// Polyphase interpolation filter for true peak, a Blackman windowed sinc with each phase
+/// normalised to unity gain at DC
+fn true_peak_filter<F: Float>() -> [[F; TRUE_PEAK_TAPS]; TRUE_PEAK_OVERSAMPLING] {
+    let length = TRUE_PEAK_TAPS * TRUE_PEAK_OVERSAMPLING;
+    let center = (length - 1) as f64 / 2.0;
+    let mut phases = [[F::ZERO; TRUE_PEAK_TAPS]; TRUE_PEAK_OVERSAMPLING];
+    for (phase, taps) in phases.iter_mut().enumerate() {
+        let mut coefficients = [0.0; TRUE_PEAK_TAPS];
+        for (tap, c) in coefficients.iter_mut().enumerate() {
+            let n = (phase + tap * TRUE_PEAK_OVERSAMPLING) as f64;
+            let x = (n - center) / TRUE_PEAK_OVERSAMPLING as f64;
+            let sinc = if x == 0.0 {
+                1.0
+            } else {
+                (core::f64::consts::PI * x).sin() / (core::f64::consts::PI * x)
+            };
+            let w = core::f64::consts::TAU * (n + 0.5) / length as f64;
+            let window = 0.42 - 0.5 * w.cos() + 0.08 * (2.0 * w).cos();
+            *c = sinc * window;
+        }
+        let sum: f64 = coefficients.iter().sum();
+        for (t, c) in taps.iter_mut().zip(coefficients) {
+            *t = F::new(c / sum);
+        }
+    }
+    phases
+}
+
+/// Measurement state of one channel
+struct ChannelState<F> {
+    peak: F,
+    true_peak: F,
+    mean_square: F,
+    k_weighting: [Biquad<F>; 2],
+    /// The latest input for true peak interpolation, newest first
+    history: [F; TRUE_PEAK_TAPS],
+}
+impl<F: Float> ChannelState<F> {
+    fn new(sample_rate: u32) -> Self {
+        Self {
+            peak: F::ZERO,
+            true_peak: F::ZERO,
+            mean_square: F::ZERO,
+            k_weighting: k_weighting(sample_rate),
+            history: [F::ZERO; TRUE_PEAK_TAPS],
+        }
+    }
+}
+
+/// Level meter for `Channels` channels, publishing its measurements to [`MeterHandle`]s.
+///
+/// Measures peak and RMS levels per channel, and optionally:
+/// - `lufs`: loudness according to ITU-R BS.1770 / EBU R 128; momentary, short-term and gated
+///   integrated. All channels are weighted equally, which is correct for mono and stereo.
+/// - `true_peak`: the peak level of the signal between samples, estimated with 4x oversampling
+///
+/// `t_reset` resets the integrated loudness, like [`MeterHandle::reset`].
+///
+/// [`Meter::new`] returns the meter together with its handle, since the meter can't be reached
+/// once it has been pushed to a graph. Clone the handle to read the levels from several places.
+pub struct Meter<F: Float, Channels: Size> {
+    levels: Arc<MeterLevels>,
+    channels: Vec<ChannelState<F>>,
+    lufs: bool,
+    true_peak: bool,
+    rms_coeff: F,
+    true_peak_filter: [[F; TRUE_PEAK_TAPS]; TRUE_PEAK_OVERSAMPLING],
+    publish_interval: usize,
+    frames_until_publish: usize,
+    /// Sum of the K-weighted energy of the current loudness step
+    step_energy: f64,
+    step_frames: usize,
+    step_length: usize,
+    /// Mean energy of the latest loudness steps, as a ring buffer
+    steps: [f64; SHORT_TERM_STEPS],
+    step_index: usize,
+    steps_filled: usize,
+    /// Gating blocks above the absolute gate, as (count, energy sum) per loudness bin
+    histogram: Vec<(u64, f64)>,
+    _channels: PhantomData<Channels>,
+}
+#[impl_ugen]
+impl<F: Float, Channels: Size> Meter<F, Channels> {
+    type Inputs = Channels;
+    type Outputs = U0;
+
+    /// A new meter and the handle for reading its levels from another thread
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> (Self, MeterHandle) {
+        let levels = MeterLevels {
+            channels: (0..Channels::USIZE)
+                .map(|_| ChannelLevels {
+                    peak: AtomicF32::new(0.0),
+                    true_peak: AtomicF32::new(0.0),
+                    rms: AtomicF32::new(0.0),
+                })
+                .collect(),
+            momentary: AtomicF32::new(f32::NEG_INFINITY),
+            short_term: AtomicF32::new(f32::NEG_INFINITY),
+            integrated: AtomicF32::new(f32::NEG_INFINITY),
+            reset: AtomicBool::new(false),
+        };
+        let mut meter = Self {
+            levels: Arc::new(levels),
+            channels: Vec::new(),
+            lufs: false,
+            true_peak: false,
+            rms_coeff: F::ZERO,
+            true_peak_filter: true_peak_filter(),
+            publish_interval: 1,
+            frames_until_publish: 0,
+            step_energy: 0.0,
+            step_frames: 0,
+            step_length: 1,
+            steps: [0.0; SHORT_TERM_STEPS],
+            step_index: 0,
+            steps_filled: 0,
+            histogram: vec![(0, 0.0); HISTOGRAM_BINS],
+            _channels: PhantomData,
+        };
+        meter.init(48000, 64);
+        let handle = MeterHandle {
+            levels: meter.levels.clone(),
+        };
+        (meter, handle)
+    }
+    /// Enable loudness metering
+    #[param(default = false)]
+    pub fn lufs(&mut self, lufs: bool) {
+        self.lufs = lufs;
+    }
+    /// Enable true peak metering
+    #[param(default = false)]
+    pub fn true_peak(&mut self, true_peak: bool) {
+        self.true_peak = true_peak;
+    }
+    /// Reset the integrated loudness
+    #[param]
+    pub fn t_reset(&mut self) {
+        self.reset_loudness();
+    }
+    fn reset_loudness(&mut self) {
+        self.step_energy = 0.0;
+        self.step_frames = 0;
+        self.steps_filled = 0;
+        self.histogram.fill((0, 0.0));
+        self.levels.momentary.store(f32::NEG_INFINITY);
+        self.levels.short_term.store(f32::NEG_INFINITY);
+        self.levels.integrated.store(f32::NEG_INFINITY);
+    }
+    fn init(&mut self, sample_rate: u32, _block_size: usize) {
+        let sr = sample_rate as f64;
+        self.channels = (0..Channels::USIZE)
+            .map(|_| ChannelState::new(sample_rate))
+            .collect();
+        self.rms_coeff = F::new((-1.0 / (RMS_TIME * sr)).exp());
+        self.publish_interval = ((PUBLISH_INTERVAL * sr) as usize).max(1);
+        self.frames_until_publish = self.publish_interval;
+        self.step_length = ((LOUDNESS_STEP * sr) as usize).max(1);
+        self.reset_loudness();
+    }
+    fn process(
+        &mut self,
+        _ctx: &mut AudioCtx,
+        _flags: &mut UGenFlags,
+        input: knaster_core::Frame<Self::Sample, Self::Inputs>,
+    ) -> knaster_core::Frame<Self::Sample, Self::Outputs> {
+        let mut energy = 0.0;
+        for (channel, &x) in self.channels.iter_mut().zip(input.iter()) {
+            let abs = x.abs();
+            if abs > channel.peak {
+                channel.peak = abs;
+            }
+            let square = x * x;
+            channel.mean_square = square + (channel.mean_square - square) * self.rms_coeff;
+            if self.lufs {
+                let [shelf, highpass] = &mut channel.k_weighting;
+                let weighted = highpass.process(shelf.process(x));
+                energy += (weighted * weighted).to_f64();
+            }
+            if self.true_peak {
+                channel.history.copy_within(0..TRUE_PEAK_TAPS - 1, 1);
+                channel.history[0] = x;
+                for taps in &self.true_peak_filter {
+                    let mut y = F::ZERO;
+                    for (&t, &h) in taps.iter().zip(channel.history.iter()) {
+                        y += t * h;
+                    }
+                    let y = y.abs();
+                    if y > channel.true_peak {
+                        channel.true_peak = y;
+                    }
+                }
+            }
+        }
+        if self.lufs {
+            self.step_energy += energy;
+            self.step_frames += 1;
+            if self.step_frames >= self.step_length {
+                self.finish_loudness_step();
+            }
+        }
+        self.frames_until_publish -= 1;
+        if self.frames_until_publish == 0 {
+            self.frames_until_publish = self.publish_interval;
+            self.publish();
+        }
+        Default::default()
+    }
+    /// Store the mean energy of the finished step and update the loudness measurements
+    fn finish_loudness_step(&mut self) {
+        self.steps[self.step_index] = self.step_energy / self.step_frames as f64;
+        self.step_index = (self.step_index + 1) % SHORT_TERM_STEPS;
+        self.steps_filled = (self.steps_filled + 1).min(SHORT_TERM_STEPS);
+        self.step_energy = 0.0;
+        self.step_frames = 0;
+        let mean_of_latest = |n: usize| {
+            let n = n.min(self.steps_filled);
+            let sum: f64 = (1..=n)
+                .map(|i| self.steps[(self.step_index + SHORT_TERM_STEPS - i) % SHORT_TERM_STEPS])
+                .sum();
+            sum / n as f64
+        };
+        let momentary = mean_of_latest(MOMENTARY_STEPS);
+        let short_term = mean_of_latest(SHORT_TERM_STEPS);
+        self.levels
+            .momentary
+            .store(loudness_from_energy(momentary) as f32);
+        self.levels
+            .short_term
+            .store(loudness_from_energy(short_term) as f32);
+        // Every momentary window is a gating block, overlapping the previous one by 75%
+        if self.steps_filled >= MOMENTARY_STEPS {
+            let loudness = loudness_from_energy(momentary);
+            if loudness > ABSOLUTE_GATE {
+                let bin = (((loudness - ABSOLUTE_GATE) / HISTOGRAM_BIN_WIDTH) as usize)
+                    .min(HISTOGRAM_BINS - 1);
+                self.histogram[bin].0 += 1;
+                self.histogram[bin].1 += momentary;
+            }
+            self.levels
+                .integrated
+                .store(self.integrated_loudness() as f32);
+        }
+    }
+    fn integrated_loudness(&self) -> f64 {
+        let (count, energy) = self
+            .histogram
+            .iter()
+            .fold((0, 0.0), |(c, e), &(count, energy)| (c + count, e + energy));
+        if count == 0 {
+            return f64::NEG_INFINITY;
+        }
+        let relative_gate = loudness_from_energy(energy / count as f64) + RELATIVE_GATE;
+        let first_bin = ((relative_gate - ABSOLUTE_GATE) / HISTOGRAM_BIN_WIDTH).max(0.0) as usize;
+        let (count, energy) = self.histogram[first_bin.min(HISTOGRAM_BINS)..]
+            .iter()
+            .fold((0, 0.0), |(c, e), &(count, energy)| (c + count, e + energy));
+        if count == 0 {
+            return f64::NEG_INFINITY;
+        }
+        loudness_from_energy(energy / count as f64)
+    }
+    fn publish(&mut self) {
+        if self.levels.reset.swap(false, Ordering::Relaxed) {
+            self.reset_loudness();
+        }
+        for (channel, levels) in self.channels.iter_mut().zip(self.levels.channels.iter()) {
+            levels.peak.fetch_max(channel.peak.to_f64() as f32);
+            levels
+                .true_peak
+                .fetch_max(channel.true_peak.to_f64() as f32);
+            levels.rms.store(channel.mean_square.sqrt().to_f64() as f32);
+            channel.peak = F::ZERO;
+            channel.true_peak = F::ZERO;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use knaster_core::{UGen, log::ArLogSender, typenum::U2};
+
+    const SR: u32 = 48000;
+
+    fn run(meter: &mut Meter<f64, U2>, seconds: f64, signal: impl Fn(usize) -> [f64; 2]) {
+        let mut ctx = AudioCtx::new(SR, 64, ArLogSender::non_rt());
+        let mut flags = UGenFlags::new();
+        for i in 0..(seconds * SR as f64) as usize {
+            UGen::process(meter, &mut ctx, &mut flags, signal(i).into());
+        }
+    }
+
+    #[test]
+    fn peak_rms_and_loudness_of_sine() {
+        let mut ctx = AudioCtx::new(SR, 64, ArLogSender::non_rt());
+        let (mut meter, handle) = Meter::<f64, U2>::new();
+        meter.init(SR, 64);
+        meter.param(&mut ctx, "lufs", true).unwrap();
+        // A 997 Hz sine at -20 dBFS in both channels has a loudness of -20 LUFS
+        let amplitude = 0.1;
+        run(&mut meter, 4.0, |i| {
+            let x = amplitude * (std::f64::consts::TAU * 997.0 * i as f64 / SR as f64).sin();
+            [x, x]
+        });
+        for channel in 0..2 {
+            assert!((handle.take_peak(channel) - 0.1).abs() < 1e-3);
+            assert_eq!(handle.peak(channel), 0.0);
+            assert!((handle.rms(channel) - 0.1 / 2.0f32.sqrt()).abs() < 1e-3);
+        }
+        for loudness in [
+            handle.momentary_lufs(),
+            handle.short_term_lufs(),
+            handle.integrated_lufs(),
+        ] {
+            assert!((loudness + 20.0).abs() < 0.05, "{loudness}");
+        }
+        handle.reset();
+        run(&mut meter, 0.02, |_| [0.0; 2]);
+        assert_eq!(handle.integrated_lufs(), f32::NEG_INFINITY);
+    }
+
+    #[test]
+    fn integrated_loudness_is_gated() {
+        let mut ctx = AudioCtx::new(SR, 64, ArLogSender::non_rt());
+        let (mut meter, handle) = Meter::<f64, U2>::new();
+        meter.init(SR, 64);
+        meter.param(&mut ctx, "lufs", true).unwrap();
+        let sine = |amplitude: f64| {
+            move |i: usize| {
+                let x = amplitude * (std::f64::consts::TAU * 997.0 * i as f64 / SR as f64).sin();
+                [x, x]
+            }
+        };
+        // Silence and the quiet part fall below the absolute and relative gates respectively.
+        // Without gating the result would be about -23 LUFS, and the blocks overlapping the end
+        // of the loud part lower it slightly.
+        run(&mut meter, 5.0, sine(0.1));
+        run(&mut meter, 5.0, |_| [0.0; 2]);
+        run(&mut meter, 5.0, sine(0.01));
+        let integrated = handle.integrated_lufs();
+        assert!((integrated + 20.0).abs() < 0.2, "{integrated}");
+    }
+
+    #[test]
+    fn true_peak_between_samples() {
+        let mut ctx = AudioCtx::new(SR, 64, ArLogSender::non_rt());
+        let (mut meter, handle) = Meter::<f64, U2>::new();
+        meter.init(SR, 64);
+        meter.param(&mut ctx, "true_peak", true).unwrap();
+        // A sine at a quarter of the sample rate sampled 45 degrees off its peaks
+        run(&mut meter, 0.1, |i| {
+            let x = (std::f64::consts::FRAC_PI_2 * i as f64 + std::f64::consts::FRAC_PI_4).sin();
+            [x, 0.0]
+        });
+        assert!((handle.peak(0) - std::f32::consts::FRAC_1_SQRT_2).abs() < 1e-3);
+        let true_peak = handle.true_peak(0);
+        assert!((true_peak - 1.0).abs() < 0.03, "{true_peak}");
+        assert_eq!(handle.true_peak(1), 0.0);
+    }
+}
@@ -0,0 +1,383 @@
+//! # Modal synthesis
+//!
+//! [`ModalResonator`] is a bank of tuned resonators, one per vibrating mode of an object, which
+//! rings when it is excited by its input. It is the core of struck and plucked object sounds such
+//! as bells, bars and drums.
+//!
+//! The object is chosen with a [`ModalPreset`], and single modes can be retuned through a
+//! [`ModalHandle`] to model objects that no preset covers. Modes keep ringing through changes,
+//! so the object can be morphed while it sounds.
+//!
+//! ```
+//! use knaster_core_dsp::modal::{ModalMode, ModalPreset, ModalResonator};
+//! let resonator = ModalResonator::<f32>::new(ModalPreset::Bell, 16);
+//! let handle = resonator.handle();
+//! // Push `resonator` to a graph and excite it with e.g. a short noise burst, then from any
+//! // thread:
+//! handle.set_preset(ModalPreset::Bar);
+//! handle.set(0, ModalMode { ratio: 1.0, decay: 4.0, gain: 0.5 });
+//! ```
+
+use crate::dsp::shared_table::{LANES, SharedTable, padded_len};
+use knaster_core::{
+    Float, KnasterIntegerParameter, PFloat, PInteger, impl_ugen,
+    num_derive::{FromPrimitive, ToPrimitive},
+    num_traits,
+};
+use std::prelude::v1::*;
+
+/// The shortest decay time of a mode in seconds
+const MIN_DECAY: PFloat = 0.001;
+
+/// One mode of a [`ModalResonator`]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ModalMode {
+    /// Frequency as a ratio of the frequency of the resonator
+    pub ratio: f32,
+    /// Time in seconds for the mode to fall by 60 dB
+    pub decay: f32,
+    /// Amplitude of the mode when the resonator is excited by an impulse of 1
+    pub gain: f32,
+}
+impl From<ModalMode> for [f32; 3] {
+    fn from(mode: ModalMode) -> Self {
+        [mode.ratio, mode.decay, mode.gain]
+    }
+}
+impl From<[f32; 3]> for ModalMode {
+    fn from([ratio, decay, gain]: [f32; 3]) -> Self {
+        Self { ratio, decay, gain }
+    }
+}
+impl Default for ModalMode {
+    fn default() -> Self {
+        Self {
+            ratio: 1.0,
+            decay: 1.0,
+            gain: 0.0,
+        }
+    }
+}
+
+/// Frequency ratios of a free-free bar
+const BAR_RATIOS: [f32; 8] = [1.0, 2.756, 5.404, 8.933, 13.345, 18.638, 24.812, 31.870];
+/// Approximate frequency ratios of a thin rectangular plate
+const PLATE_RATIOS: [f32; 16] = [
+    1.0, 1.651, 2.049, 2.432, 2.628, 3.027, 3.418, 3.751, 4.107, 4.525, 4.732, 5.137, 5.508, 5.851,
+    6.167, 6.574,
+];
+/// Frequency ratios of a church bell, from the hum note an octave below the prime
+const BELL_RATIOS: [f32; 12] = [
+    0.5, 1.0, 1.183, 1.506, 2.0, 2.514, 2.662, 3.011, 4.166, 5.433, 6.796, 8.215,
+];
+/// Frequency ratios of an ideal circular membrane
+const MEMBRANE_RATIOS: [f32; 16] = [
+    1.0, 1.594, 2.136, 2.296, 2.653, 2.918, 3.156, 3.501, 3.600, 3.652, 4.060, 4.154, 4.601, 4.832,
+    4.903, 5.131,
+];
+
+/// Mode tables for a [`ModalResonator`], approximating the modes of common objects
+#[derive(
+    Clone, Copy, Debug, FromPrimitive, ToPrimitive, PartialEq, Default, KnasterIntegerParameter,
+)]
+#[num_traits = "num_traits"]
+#[repr(u8)]
+pub enum ModalPreset {
+    /// A bar like in a marimba or glockenspiel, with widely spaced modes
+    Bar = 0,
+    /// A thin metal plate with dense, slowly decaying modes
+    Plate,
+    /// A church bell with a long ringing hum an octave below the pitch
+    #[default]
+    Bell,
+    /// A drum membrane with quickly decaying, inharmonic modes
+    Membrane,
+}
+impl ModalPreset {
+    /// The modes of the preset
+    pub fn modes(self) -> impl Iterator<Item = ModalMode> {
+        // The decay time of the first mode and how quickly the decay falls with the ratio
+        let (ratios, decay, decay_rolloff): (&'static [f32], f32, f32) = match self {
+            ModalPreset::Bar => (&BAR_RATIOS, 2.0, 0.7),
+            ModalPreset::Plate => (&PLATE_RATIOS, 4.0, 0.5),
+            ModalPreset::Bell => (&BELL_RATIOS, 8.0, 0.6),
+            ModalPreset::Membrane => (&MEMBRANE_RATIOS, 0.6, 0.8),
+        };
+        ratios.iter().enumerate().map(move |(i, &ratio)| ModalMode {
+            ratio,
+            decay: decay * num_traits::Float::powf(ratio / ratios[0], -decay_rolloff),
+            gain: 1.0 / num_traits::Float::sqrt((i + 1) as f32),
+        })
+    }
+}
+
+/// The modes of a [`ModalResonator`], from [`ModalResonator::handle`]. Modes whose settings are
+/// unchanged keep ringing undisturbed.
+pub type ModalHandle = SharedTable<ModalMode, 3>;
+impl ModalHandle {
+    /// Load the modes of a preset. Modes beyond the modes of the preset are silenced, and modes
+    /// of the preset that don't fit are left out.
+    pub fn set_preset(&self, preset: ModalPreset) {
+        let mut modes = preset.modes();
+        for index in 0..self.len() {
+            self.set(index, modes.next().unwrap_or_default());
+        }
+    }
+}
+
+/// Modal resonator: a bank of tuned two-pole resonators which are excited by the input.
+///
+/// Each mode rings at `freq * ratio` and decays by 60 dB in its decay time. An impulse of 1 in
+/// the input makes each mode start at its gain. Load modes from a [`ModalPreset`] with the
+/// `preset` parameter, or change them through the handle from [`ModalResonator::handle`].
+///
+/// `damping` shortens the decays, more for higher modes, by multiplying the decay time of each
+/// mode by `(1 - damping)` to the power of its ratio. `brightness` tilts the gains of the modes
+/// from falling by 6 dB per doubling of the ratio at 0 to rising by 6 dB at 1, with no change at
+/// 0.5. Modes at or above the Nyquist frequency are silenced automatically.
+///
+/// Every mode costs one two-pole filter per sample, and all of them run in a single loop, which
+/// is much cheaper than a separate filter node per mode.
+pub struct ModalResonator<F: Copy = f32> {
+    table: ModalHandle,
+    freq: PFloat,
+    damping: PFloat,
+    brightness: PFloat,
+    sample_rate: u32,
+    ratios: Vec<PFloat>,
+    decays: Vec<PFloat>,
+    gains: Vec<PFloat>,
+    /// Input gain of each resonator
+    b0: Vec<F>,
+    a1: Vec<F>,
+    a2: Vec<F>,
+    y1: Vec<F>,
+    y2: Vec<F>,
+}
+#[impl_ugen]
+impl<F: Float> ModalResonator<F> {
+    #[allow(missing_docs)]
+    pub fn new(preset: ModalPreset, num_modes: usize) -> Self {
+        let num_modes = num_modes.max(1);
+        let table = ModalHandle::new((0..num_modes).map(|_| ModalMode::default()));
+        table.set_preset(preset);
+        // Padding modes have zero coefficients and stay silent
+        let len = padded_len(num_modes);
+        let mut s = Self {
+            table,
+            freq: 440.,
+            damping: 0.,
+            brightness: 0.5,
+            sample_rate: 0,
+            ratios: vec![0.; num_modes],
+            decays: vec![0.; num_modes],
+            gains: vec![0.; num_modes],
+            b0: vec![F::ZERO; len],
+            a1: vec![F::ZERO; len],
+            a2: vec![F::ZERO; len],
+            y1: vec![F::ZERO; len],
+            y2: vec![F::ZERO; len],
+        };
+        s.apply_changes();
+        s
+    }
+    /// A handle for changing the modes of this resonator from another thread
+    pub fn handle(&self) -> ModalHandle {
+        self.table.clone()
+    }
+    fn init(&mut self, sample_rate: u32, _block_size: usize) {
+        self.sample_rate = sample_rate;
+        self.update_coefficients();
+    }
+    /// The frequency that the ratios of the modes are relative to
+    #[param(kind = Frequency, default = 440.0)]
+    pub fn freq(&mut self, freq: PFloat) {
+        self.freq = freq;
+        self.update_coefficients();
+    }
+    /// Shortens the decays, more for higher modes
+    #[param(default = 0.0, range = 0.0..=1.0)]
+    pub fn damping(&mut self, damping: PFloat) {
+        self.damping = damping.clamp(0., 1.);
+        self.update_coefficients();
+    }
+    /// Tilts the gains of the modes towards lower or higher modes
+    #[param(default = 0.5, range = 0.0..=1.0)]
+    pub fn brightness(&mut self, brightness: PFloat) {
+        self.brightness = brightness.clamp(0., 1.);
+        self.update_coefficients();
+    }
+    /// Load the modes of a preset
+    #[param(from = ModalPreset)]
+    pub fn preset(&mut self, preset: PInteger) {
+        self.table.set_preset(ModalPreset::from(preset));
+        self.apply_changes();
+    }
+    /// Silence all modes
+    #[param]
+    pub fn t_clear(&mut self) {
+        self.y1.fill(F::ZERO);
+        self.y2.fill(F::ZERO);
+    }
+    fn apply_changes(&mut self) {
+        if self.table.take_changed() {
+            for i in 0..self.ratios.len() {
+                let mode = self.table.get(i);
+                self.ratios[i] = mode.ratio as PFloat;
+                self.decays[i] = mode.decay as PFloat;
+                self.gains[i] = mode.gain as PFloat;
+            }
+            self.update_coefficients();
+        }
+    }
+    fn update_coefficients(&mut self) {
+        if self.sample_rate == 0 {
+            return;
+        }
+        let sample_rate = self.sample_rate as PFloat;
+        let tilt = 2. * self.brightness - 1.;
+        for i in 0..self.ratios.len() {
+            let ratio = self.ratios[i].abs();
+            let w = core::f64::consts::TAU * ratio * self.freq / sample_rate;
+            if w <= 0. || w >= core::f64::consts::PI {
+                self.b0[i] = F::ZERO;
+                self.a1[i] = F::ZERO;
+                self.a2[i] = F::ZERO;
+                continue;
+            }
+            let decay = self.decays[i] * num_traits::Float::powf(1. - self.damping, ratio);
+            let frames = decay.max(MIN_DECAY) * sample_rate;
+            let r = num_traits::Float::powf(0.001, 1. / frames);
+            let gain = self.gains[i] * num_traits::Float::powf(ratio, tilt);
+            // Scaled so that an impulse of 1 starts a sine with an amplitude of `gain`
+            self.b0[i] = F::new(gain * num_traits::Float::sin(w));
+            self.a1[i] = F::new(2. * r * num_traits::Float::cos(w));
+            self.a2[i] = F::new(r * r);
+        }
+    }
+    #[inline(always)]
+    fn next_sample(&mut self, input: F) -> F {
+        let mut sums = [F::ZERO; LANES];
+        for ((((y1, y2), b0), a1), a2) in self
+            .y1
+            .chunks_exact_mut(LANES)
+            .zip(self.y2.chunks_exact_mut(LANES))
+            .zip(self.b0.chunks_exact(LANES))
+            .zip(self.a1.chunks_exact(LANES))
+            .zip(self.a2.chunks_exact(LANES))
+        {
+            for (((((sum, y1), y2), &b0), &a1), &a2) in
+                sums.iter_mut().zip(y1).zip(y2).zip(b0).zip(a1).zip(a2)
+            {
+                let y = b0 * input + a1 * *y1 - a2 * *y2;
+                *y2 = *y1;
+                *y1 = y;
+                *sum += y;
+            }
+        }
+        sums.iter().fold(F::ZERO, |acc, &sum| acc + sum)
+    }
+    #[allow(missing_docs)]
+    pub fn process(&mut self, input: [F; 1]) -> [F; 1] {
+        self.apply_changes();
+        [self.next_sample(input[0])]
+    }
+    #[allow(missing_docs)]
+    pub fn process_block(&mut self, input: [&[F]; 1], output: [&mut [F]; 1]) {
+        self.apply_changes();
+        for (&input, out) in input[0].iter().zip(output[0].iter_mut()) {
+            *out = self.next_sample(input);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use knaster_core::{AudioCtx, UGen, UGenFlags, log::ArLogSender};
+    use std::f64::consts::TAU;
+
+    const SR: u32 = 48000;
+
+    fn run(resonator: &mut ModalResonator<f64>, ctx: &mut AudioCtx, frames: usize) -> Vec<f64> {
+        let mut flags = UGenFlags::new();
+        (0..frames)
+            .map(|i| {
+                let impulse = if i == 0 { 1. } else { 0. };
+                UGen::process(resonator, ctx, &mut flags, [impulse].into())[0]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn impulse_response_of_a_mode() {
+        let mut ctx = AudioCtx::new(SR, 64, ArLogSender::non_rt());
+        let mut resonator = ModalResonator::<f64>::new(ModalPreset::Bar, 2);
+        let handle = resonator.handle();
+        assert_eq!(handle.len(), 2);
+        assert_eq!(handle.get(1).ratio, BAR_RATIOS[1]);
+        resonator.init(SR, 64);
+        resonator.param(&mut ctx, "freq", 1000.).unwrap();
+        let mode = ModalMode {
+            ratio: 1.5,
+            decay: 0.5,
+            gain: 0.25,
+        };
+        handle.set(0, mode);
+        // Above Nyquist
+        handle.set(
+            1,
+            ModalMode {
+                ratio: 30.,
+                decay: 1.,
+                gain: 1.,
+            },
+        );
+        let output = run(&mut resonator, &mut ctx, SR as usize);
+        let r = 0.001f64.powf(1. / (0.5 * SR as f64));
+        let w = TAU * 1500. / SR as f64;
+        for (i, &out) in output.iter().enumerate() {
+            // The brightness tilt is neutral by default
+            let expected = 0.25 * r.powi(i as i32) * (w * (i + 1) as f64).sin();
+            assert!((out - expected).abs() < 1e-9, "{i}: {out} != {expected}");
+        }
+    }
+
+    #[test]
+    fn presets_damping_and_brightness() {
+        let mut ctx = AudioCtx::new(SR, 64, ArLogSender::non_rt());
+        let energy = |s: &[f64]| s.iter().map(|x| x * x).sum::<f64>();
+        let mut resonator = ModalResonator::<f64>::new(ModalPreset::Membrane, 4);
+        resonator.init(SR, 64);
+        resonator.param(&mut ctx, "freq", 200.).unwrap();
+        let membrane = run(&mut resonator, &mut ctx, SR as usize);
+        assert!(membrane[..100].iter().any(|x| x.abs() > 0.01));
+        // Damping makes it decay faster
+        resonator
+            .param(&mut ctx, "t_clear", knaster_core::PTrigger)
+            .unwrap();
+        resonator.param(&mut ctx, "damping", 0.5).unwrap();
+        let damped = run(&mut resonator, &mut ctx, SR as usize);
+        assert!(energy(&damped[SR as usize / 2..]) < energy(&membrane[SR as usize / 2..]) * 0.01);
+        // Brightness raises the higher modes
+        resonator
+            .param(&mut ctx, "t_clear", knaster_core::PTrigger)
+            .unwrap();
+        resonator.param(&mut ctx, "brightness", 1.).unwrap();
+        let bright = run(&mut resonator, &mut ctx, SR as usize);
+        assert!(energy(&bright) > energy(&damped));
+        resonator
+            .param(&mut ctx, "preset", ModalPreset::Bar)
+            .unwrap();
+        assert_eq!(resonator.handle().get(3).ratio, BAR_RATIOS[3]);
+        // Loading a preset with fewer modes than the table silences the rest
+        let mut resonator = ModalResonator::<f64>::new(ModalPreset::Bar, 12);
+        assert_eq!(resonator.handle().get(8).gain, 0.);
+        resonator.init(SR, 64);
+        assert!(
+            run(&mut resonator, &mut ctx, 1000)
+                .iter()
+                .all(|x| x.is_finite())
+        );
+    }
+}
@@ -0,0 +1,485 @@
+//! # Modulation effects
+//!
+//! Stereo modulated delay effects with internal LFOs:
+//! - [`Chorus`]: multi-voice chorus
+//! - [`Flanger`]: flanger with optional through-zero flanging
+//! - [`Phaser`]: phaser with any number of allpass stages
+//!
+//! All effects have the inputs `[left, right, modulation]` and the outputs `[left, right]`. The
+//! `modulation` input is added to the internal LFO and should be between -1.0 and 1.0. It can
+//! be left unconnected. Set `rate` to 0.0 to stop the internal LFO.
+//!
+//! Modulated delays are read with cubic interpolation and the modulation is computed every
+//! sample, so sweeps are smooth.
+
+use crate::delay::InterpolatingDelay;
+use knaster_core::{Float, PFloat, impl_ugen};
+use std::prelude::v1::*;
+
+/// LFO phase offset between the left and right channels, as a fraction of a cycle
+const STEREO_PHASE_OFFSET: f64 = 0.25;
+
+/// Maximum modulation depth of the [`Chorus`] in seconds
+const CHORUS_MAX_DEPTH: f64 = 0.01;
+/// Maximum base delay of the [`Chorus`] in seconds
+const CHORUS_MAX_DELAY: f64 = 0.05;
+/// Maximum modulation depth of the [`Flanger`] in seconds
+const FLANGER_MAX_DEPTH: f64 = 0.005;
+/// Maximum base delay of the [`Flanger`] in seconds
+const FLANGER_MAX_DELAY: f64 = 0.01;
+/// Maximum sweep of the [`Phaser`] in octaves in each direction from the center frequency
+const PHASER_MAX_OCTAVES: f64 = 3.0;
+
+/// Sine LFO as a phase accumulator between 0 and 1
+#[derive(Clone, Copy, Debug)]
+struct Lfo<F> {
+    phase: F,
+}
+impl<F: Float> Lfo<F> {
+    fn new() -> Self {
+        Self { phase: F::ZERO }
+    }
+    #[inline]
+    fn advance(&mut self, rate: F, sample_rate: F) {
+        self.phase += rate / sample_rate;
+        self.phase = self.phase - self.phase.floor();
+    }
+    /// Sine at the current phase plus `offset` cycles
+    #[inline]
+    fn value(&self, offset: F) -> F {
+        ((self.phase + offset) * F::TAU).sin()
+    }
+}
+
+/// Combine an LFO value with external modulation, keeping it in range
+#[inline]
+fn modulate<F: Float>(lfo: F, external: F) -> F {
+    (lfo + external).clamp(-F::ONE, F::ONE)
+}
+
+/// Multi-voice stereo chorus.
+///
+/// Each voice reads a delayed copy of the input modulated by the LFO at an evenly spread phase.
+/// The left and right channels have their LFOs offset by a quarter cycle.
+///
+/// - `rate`: LFO frequency in Hz
+/// - `depth`: modulation depth from 0.0 to 1.0, where 1.0 is 10 ms
+/// - `delay`: the base delay time in seconds
+/// - `feedback`: amount of the wet signal fed back into the delay
+/// - `mix`: dry/wet mix, 0.0 is only dry and 1.0 is only wet
+pub struct Chorus<F: Float = f32> {
+    voices: usize,
+    rate: F,
+    depth: F,
+    delay: F,
+    feedback: F,
+    mix: F,
+    sample_rate: F,
+    lfo: Lfo<F>,
+    delays: [InterpolatingDelay<F>; 2],
+    last_wet: [F; 2],
+}
+#[impl_ugen]
+impl<F: Float> Chorus<F> {
+    /// New chorus with `voices` voices per channel
+    ///
+    /// # Panics
+    /// Panics if `voices` is 0
+    pub fn new(voices: usize) -> Self {
+        assert!(voices > 0, "Chorus needs at least one voice");
+        Self {
+            voices,
+            rate: F::new(0.5),
+            depth: F::new(0.3),
+            delay: F::new(0.015),
+            feedback: F::ZERO,
+            mix: F::new(0.5),
+            sample_rate: F::new(48000.),
+            lfo: Lfo::new(),
+            delays: [InterpolatingDelay::new(0), InterpolatingDelay::new(0)],
+            last_wet: [F::ZERO; 2],
+        }
+    }
+    /// LFO frequency in Hz
+    #[param(kind = Frequency, default = 0.5, range = 0.0..=10.0)]
+    pub fn rate(&mut self, rate: PFloat) {
+        self.rate = F::new(rate);
+    }
+    /// Modulation depth, where 1.0 is 10 ms
+    #[param(default = 0.3, range = 0.0..=1.0)]
+    pub fn depth(&mut self, depth: PFloat) {
+        self.depth = F::new(depth.clamp(0.0, 1.0));
+    }
+    /// Base delay time in seconds
+    #[param(kind = Seconds, default = 0.015, range = 0.001..=0.05)]
+    pub fn delay(&mut self, delay: PFloat) {
+        self.delay = F::new(delay.clamp(0.0, CHORUS_MAX_DELAY));
+    }
+    /// Feedback amount
+    #[param(default = 0.0, range = -0.95..=0.95)]
+    pub fn feedback(&mut self, feedback: PFloat) {
+        self.feedback = F::new(feedback);
+    }
+    /// Dry/wet mix
+    #[param(default = 0.5, range = 0.0..=1.0)]
+    pub fn mix(&mut self, mix: PFloat) {
+        self.mix = F::new(mix);
+    }
+    fn init(&mut self, sample_rate: u32, _block_size: usize) {
+        self.sample_rate = F::new(sample_rate as f32);
+        let max_frames = ((CHORUS_MAX_DELAY + CHORUS_MAX_DEPTH) * sample_rate as f64) as usize;
+        self.delays = [
+            InterpolatingDelay::new(max_frames),
+            InterpolatingDelay::new(max_frames),
+        ];
+        self.last_wet = [F::ZERO; 2];
+    }
+    fn process(&mut self, input: [F; 3]) -> [F; 2] {
+        let [left, right, external] = input;
+        let voice_gain = F::ONE / F::from_usize(self.voices);
+        let voice_phase = voice_gain;
+        let depth = self.depth * F::new(CHORUS_MAX_DEPTH) * self.sample_rate;
+        let delay = self.delay * self.sample_rate;
+        let mut out = [F::ZERO; 2];
+        for (channel, dry) in [left, right].into_iter().enumerate() {
+            let delay_line = &mut self.delays[channel];
+            delay_line.write_and_advance(dry + self.last_wet[channel] * self.feedback);
+            let channel_phase = F::new(STEREO_PHASE_OFFSET) * F::from_usize(channel);
+            let mut wet = F::ZERO;
+            for voice in 0..self.voices {
+                let offset = channel_phase + voice_phase * F::from_usize(voice);
+                let modulation = modulate(self.lfo.value(offset), external);
+                let delay_frames = delay + depth * F::new(0.5) * (modulation + F::ONE);
+                wet += delay_line.read(delay_frames);
+            }
+            wet *= voice_gain;
+            self.last_wet[channel] = wet;
+            out[channel] = dry + (wet - dry) * self.mix;
+        }
+        self.lfo.advance(self.rate, self.sample_rate);
+        out
+    }
+}
+
+/// Stereo flanger.
+///
+/// - `rate`: LFO frequency in Hz
+/// - `depth`: modulation depth from 0.0 to 1.0, where 1.0 is 5 ms
+/// - `delay`: the shortest delay time in seconds
+/// - `feedback`: amount of the wet signal fed back into the delay. Negative values emphasise
+///   odd harmonics.
+/// - `mix`: dry/wet mix, 0.5 gives the deepest notches
+/// - `through_zero`: if true, the dry signal is delayed to the center of the sweep so that the
+///   wet signal passes through zero delay relative to the dry signal, like tape flanging.
+pub struct Flanger<F: Float = f32> {
+    rate: F,
+    depth: F,
+    delay: F,
+    feedback: F,
+    mix: F,
+    through_zero: bool,
+    sample_rate: F,
+    lfo: Lfo<F>,
+    delays: [InterpolatingDelay<F>; 2],
+    /// The input without feedback, for the delayed dry signal of through-zero flanging
+    dry_delays: [InterpolatingDelay<F>; 2],
+    last_wet: [F; 2],
+}
+#[impl_ugen]
+impl<F: Float> Flanger<F> {
+    #[allow(missing_docs)]
+    pub fn new() -> Self {
+        Self {
+            rate: F::new(0.2),
+            depth: F::new(0.5),
+            delay: F::new(0.0005),
+            feedback: F::new(0.5),
+            mix: F::new(0.5),
+            through_zero: false,
+            sample_rate: F::new(48000.),
+            lfo: Lfo::new(),
+            delays: [InterpolatingDelay::new(0), InterpolatingDelay::new(0)],
+            dry_delays: [InterpolatingDelay::new(0), InterpolatingDelay::new(0)],
+            last_wet: [F::ZERO; 2],
+        }
+    }
+    /// LFO frequency in Hz
+    #[param(kind = Frequency, default = 0.2, range = 0.0..=10.0)]
+    pub fn rate(&mut self, rate: PFloat) {
+        self.rate = F::new(rate);
+    }
+    /// Modulation depth, where 1.0 is 5 ms
+    #[param(default = 0.5, range = 0.0..=1.0)]
+    pub fn depth(&mut self, depth: PFloat) {
+        self.depth = F::new(depth.clamp(0.0, 1.0));
+    }
+    /// Shortest delay time in seconds
+    #[param(kind = Seconds, default = 0.0005, range = 0.0..=0.01)]
+    pub fn delay(&mut self, delay: PFloat) {
+        self.delay = F::new(delay.clamp(0.0, FLANGER_MAX_DELAY));
+    }
+    /// Feedback amount
+    #[param(default = 0.5, range = -0.98..=0.98)]
+    pub fn feedback(&mut self, feedback: PFloat) {
+        self.feedback = F::new(feedback);
+    }
+    /// Dry/wet mix
+    #[param(default = 0.5, range = 0.0..=1.0)]
+    pub fn mix(&mut self, mix: PFloat) {
+        self.mix = F::new(mix);
+    }
+    /// Enable through-zero flanging
+    #[param(default = false)]
+    pub fn through_zero(&mut self, through_zero: bool) {
+        self.through_zero = through_zero;
+    }
+    fn init(&mut self, sample_rate: u32, _block_size: usize) {
+        self.sample_rate = F::new(sample_rate as f32);
+        let max_frames =
+            ((FLANGER_MAX_DELAY + 2. * FLANGER_MAX_DEPTH) * sample_rate as f64) as usize;
+        self.delays = [
+            InterpolatingDelay::new(max_frames),
+            InterpolatingDelay::new(max_frames),
+        ];
+        let max_dry_frames =
+            ((FLANGER_MAX_DELAY + FLANGER_MAX_DEPTH) * sample_rate as f64) as usize;
+        self.dry_delays = [
+            InterpolatingDelay::new(max_dry_frames + 1),
+            InterpolatingDelay::new(max_dry_frames + 1),
+        ];
+        self.last_wet = [F::ZERO; 2];
+    }
+    fn process(&mut self, input: [F; 3]) -> [F; 2] {
+        let [left, right, external] = input;
+        let depth = self.depth * F::new(FLANGER_MAX_DEPTH) * self.sample_rate;
+        let delay = self.delay * self.sample_rate;
+        let mut out = [F::ZERO; 2];
+        for (channel, input) in [left, right].into_iter().enumerate() {
+            let delay_line = &mut self.delays[channel];
+            delay_line.write_and_advance(input + self.last_wet[channel] * self.feedback);
+            let dry_delay_line = &mut self.dry_delays[channel];
+            dry_delay_line.write_and_advance(input);
+            let channel_phase = F::new(STEREO_PHASE_OFFSET) * F::from_usize(channel);
+            let modulation = modulate(self.lfo.value(channel_phase), external);
+            let (dry, wet) = if self.through_zero {
+                // The wet signal sweeps around the delayed dry signal
+                let center = delay + depth;
+                let dry = dry_delay_line.read(center);
+                (dry, delay_line.read(center + depth * modulation))
+            } else {
+                let delay_frames = delay + depth * F::new(0.5) * (modulation + F::ONE);
+                (input, delay_line.read(delay_frames))
+            };
+            self.last_wet[channel] = wet;
+            out[channel] = dry + (wet - dry) * self.mix;
+        }
+        self.lfo.advance(self.rate, self.sample_rate);
+        out
+    }
+}
+impl<F: Float> Default for Flanger<F> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// First order allpass filter
+#[derive(Clone, Copy, Debug, Default)]
+struct FirstOrderAllpass<F> {
+    state: F,
+}
+impl<F: Float> FirstOrderAllpass<F> {
+    /// Process one sample where `a` is `(tan(PI * freq / sample_rate) - 1) / (tan(...) + 1)`
+    #[inline]
+    fn process(&mut self, input: F, a: F) -> F {
+        let output = a * input + self.state;
+        self.state = input - a * output;
+        output
+    }
+}
+
+/// Stereo phaser made from a series of first order allpass filters.
+///
+/// - `rate`: LFO frequency in Hz
+/// - `depth`: modulation depth from 0.0 to 1.0, where 1.0 sweeps 3 octaves in each direction
+/// - `freq`: the center frequency of the sweep
+/// - `feedback`: amount of the output fed back into the allpass chain
+/// - `mix`: dry/wet mix, 0.5 gives the deepest notches
+pub struct Phaser<F: Float = f32> {
+    rate: F,
+    depth: F,
+    freq: F,
+    feedback: F,
+    mix: F,
+    sample_rate: F,
+    lfo: Lfo<F>,
+    stages: [Vec<FirstOrderAllpass<F>>; 2],
+    last_wet: [F; 2],
+}
+#[impl_ugen]
+impl<F: Float> Phaser<F> {
+    /// New phaser with `stages` allpass stages per channel. Each pair of stages gives one notch.
+    ///
+    /// # Panics
+    /// Panics if `stages` is 0
+    pub fn new(stages: usize) -> Self {
+        assert!(stages > 0, "Phaser needs at least one stage");
+        Self {
+            rate: F::new(0.3),
+            depth: F::new(0.5),
+            freq: F::new(800.),
+            feedback: F::ZERO,
+            mix: F::new(0.5),
+            sample_rate: F::new(48000.),
+            lfo: Lfo::new(),
+            stages: [
+                vec![FirstOrderAllpass::default(); stages],
+                vec![FirstOrderAllpass::default(); stages],
+            ],
+            last_wet: [F::ZERO; 2],
+        }
+    }
+    /// LFO frequency in Hz
+    #[param(kind = Frequency, default = 0.3, range = 0.0..=10.0)]
+    pub fn rate(&mut self, rate: PFloat) {
+        self.rate = F::new(rate);
+    }
+    /// Modulation depth, where 1.0 is 3 octaves in each direction
+    #[param(default = 0.5, range = 0.0..=1.0)]
+    pub fn depth(&mut self, depth: PFloat) {
+        self.depth = F::new(depth.clamp(0.0, 1.0));
+    }
+    /// Center frequency of the sweep in Hz
+    #[param(kind = Frequency, default = 800.0, range = 50.0..=5000.0, logarithmic = true)]
+    pub fn freq(&mut self, freq: PFloat) {
+        self.freq = F::new(freq);
+    }
+    /// Feedback amount
+    #[param(default = 0.0, range = -0.95..=0.95)]
+    pub fn feedback(&mut self, feedback: PFloat) {
+        self.feedback = F::new(feedback);
+    }
+    /// Dry/wet mix
+    #[param(default = 0.5, range = 0.0..=1.0)]
+    pub fn mix(&mut self, mix: PFloat) {
+        self.mix = F::new(mix);
+    }
+    fn init(&mut self, sample_rate: u32, _block_size: usize) {
+        self.sample_rate = F::new(sample_rate as f32);
+        for stage in self.stages.iter_mut().flatten() {
+            *stage = FirstOrderAllpass::default();
+        }
+        self.last_wet = [F::ZERO; 2];
+    }
+    fn process(&mut self, input: [F; 3]) -> [F; 2] {
+        let [left, right, external] = input;
+        let octaves = self.depth * F::new(PHASER_MAX_OCTAVES);
+        let max_freq = self.sample_rate * F::new(0.45);
+        let mut out = [F::ZERO; 2];
+        for (channel, dry) in [left, right].into_iter().enumerate() {
+            let channel_phase = F::new(STEREO_PHASE_OFFSET) * F::from_usize(channel);
+            let modulation = modulate(self.lfo.value(channel_phase), external);
+            let freq =
+                (self.freq * F::new(2.).powf(octaves * modulation)).clamp(F::new(10.), max_freq);
+            let t = (F::PI * freq / self.sample_rate).tan();
+            let a = (t - F::ONE) / (t + F::ONE);
+            let mut wet = dry + self.last_wet[channel] * self.feedback;
+            for stage in &mut self.stages[channel] {
+                wet = stage.process(wet, a);
+            }
+            self.last_wet[channel] = wet;
+            out[channel] = dry + (wet - dry) * self.mix;
+        }
+        self.lfo.advance(self.rate, self.sample_rate);
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use knaster_core::{
+        AudioCtx, UGen, UGenFlags,
+        log::ArLogSender,
+        typenum::{U2, U3},
+    };
+
+    const SR: u32 = 48000;
+
+    /// Runs a sine at `freq` through `ugen` for one second and returns the peak output over the
+    /// second half
+    fn sine_peak<U: UGen<Sample = f32, Inputs = U3, Outputs = U2>>(ugen: &mut U, freq: f32) -> f32 {
+        let mut ctx = AudioCtx::new(SR, 64, ArLogSender::non_rt());
+        let mut flags = UGenFlags::new();
+        ugen.init(SR, 64);
+        let mut peak = 0.0f32;
+        for i in 0..SR as usize {
+            let x = (core::f32::consts::TAU * freq * i as f32 / SR as f32).sin();
+            let out = ugen.process(&mut ctx, &mut flags, [x, x, 0.0].into());
+            assert!(out[0].is_finite() && out[1].is_finite());
+            if i > SR as usize / 2 {
+                peak = peak.max(out[0].abs()).max(out[1].abs());
+            }
+        }
+        peak
+    }
+
+    #[test]
+    fn phaser_notch_at_center_frequency() {
+        let mut ctx = AudioCtx::new(SR, 64, ArLogSender::non_rt());
+        let mut phaser = Phaser::<f32>::new(2);
+        phaser.param(&mut ctx, "depth", 0.0).unwrap();
+        phaser.param(&mut ctx, "freq", 800.0).unwrap();
+        // Two allpass stages shift the phase by 180 degrees at the center frequency
+        assert!(sine_peak(&mut phaser, 800.) < 0.01);
+        assert!(sine_peak(&mut phaser, 50.) > 0.9);
+        phaser.param(&mut ctx, "mix", 0.0).unwrap();
+        assert!((sine_peak(&mut phaser, 800.) - 1.0).abs() < 0.001);
+    }
+
+    #[test]
+    fn feedback_is_stable() {
+        let mut ctx = AudioCtx::new(SR, 64, ArLogSender::non_rt());
+        let mut chorus = Chorus::<f32>::new(3);
+        chorus.param(&mut ctx, "feedback", 0.95).unwrap();
+        chorus.param(&mut ctx, "rate", 5.0).unwrap();
+        chorus.param(&mut ctx, "depth", 1.0).unwrap();
+        assert!(sine_peak(&mut chorus, 440.) < 20.);
+        let mut flanger = Flanger::<f32>::new();
+        flanger.param(&mut ctx, "feedback", -0.98).unwrap();
+        flanger.param(&mut ctx, "rate", 5.0).unwrap();
+        flanger.param(&mut ctx, "through_zero", true).unwrap();
+        assert!(sine_peak(&mut flanger, 440.) < 100.);
+        let mut phaser = Phaser::<f32>::new(8);
+        phaser.param(&mut ctx, "feedback", 0.95).unwrap();
+        phaser.param(&mut ctx, "rate", 5.0).unwrap();
+        phaser.param(&mut ctx, "depth", 1.0).unwrap();
+        assert!(sine_peak(&mut phaser, 440.) < 50.);
+    }
+
+    #[test]
+    fn through_zero_dry_signal_has_no_feedback() {
+        let mut ctx = AudioCtx::new(SR, 64, ArLogSender::non_rt());
+        let mut flags = UGenFlags::new();
+        let mut flanger = Flanger::<f64>::new();
+        flanger.init(SR, 64);
+        flanger.param(&mut ctx, "through_zero", true).unwrap();
+        flanger.param(&mut ctx, "feedback", 0.9).unwrap();
+        flanger.param(&mut ctx, "mix", 0.0).unwrap();
+        flanger.param(&mut ctx, "delay", 0.0005).unwrap();
+        flanger.param(&mut ctx, "depth", 0.5).unwrap();
+        // 0.5 ms + 0.5 * 5 ms
+        let center = 144;
+        let input: Vec<f64> = (0..2000).map(|i| (i as f64 * 0.37).sin()).collect();
+        for (i, &x) in input.iter().enumerate() {
+            let out = UGen::process(&mut flanger, &mut ctx, &mut flags, [x, x, 0.0].into());
+            let expected = if i >= center { input[i - center] } else { 0.0 };
+            assert!(
+                (out[0] - expected).abs() < 1e-9,
+                "{i}: {} != {expected}",
+                out[0]
+            );
+            assert!((out[1] - expected).abs() < 1e-9);
+        }
+    }
+}
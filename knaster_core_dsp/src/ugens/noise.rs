@@ -1,44 +1,177 @@
 //! # Noise
 //!
 //! Contains UGens producing noise and random numbers with different distributions and interpolations.
+//!
+//! UGens producing randomness are reseeded when they are pushed to a graph which has a master
+//! seed, see `AudioProcessorOptions::seed` in `knaster_graph`. Each node gets the next seed from
+//! the [`RandomnessSeeds`] of its graph hierarchy, so pushing the same nodes in the same order
+//! gives the same randomness every time, e.g. for reproducible renders and tests.
+//!
+//! UGens created outside such a graph are seeded from [`next_randomness_seed`] when they are
+//! created, a process-global sequence which can be restarted with [`set_randomness_seed`]. A seed
+//! given explicitly using the `seed` method of a UGen always takes precedence.
 
 use crate::core::marker::PhantomData;
-use crate::core::sync::atomic::AtomicU64;
+use crate::core::mem::size_of;
+use crate::core::ops::{Deref, DerefMut};
+use crate::core::sync::atomic::{AtomicU64, Ordering};
 use knaster_core::Float;
+#[allow(unused)]
+use knaster_core::UGen;
 use knaster_core::{AudioCtx, PFloat, UGenFlags, impl_ugen};
 
 /// Used to seed random number generating Gens to create a deterministic result as long as all Gens are created in the same order from start.
 static NEXT_SEED: AtomicU64 = AtomicU64::new(0);
+/// Mixed into every seed from [`next_randomness_seed`]
+static MASTER_SEED: AtomicU64 = AtomicU64::new(0);
 
-/// Request the next randomness seed. Use this if you are implementing your own [`UGen`] producing
-/// randomness. If all [`UGen`]s use deterministic algorithms and are seeded using this function,
-/// a graph constructed in the same order will have deterministic randomness.
+/// Request the next randomness seed from the global sequence. Use this if you are implementing
+/// your own [`UGen`] producing randomness, and replace the seed in [`UGen::reseed`]. If all
+/// [`UGen`]s use deterministic algorithms and are seeded using this function, a graph
+/// constructed in the same order will have deterministic randomness.
 /// ```
 /// # use crate::knaster_core_dsp::noise::next_randomness_seed;
 /// let rng = fastrand::Rng::with_seed(next_randomness_seed());
 /// ```
 pub fn next_randomness_seed() -> u64 {
-    NEXT_SEED.fetch_add(1, crate::core::sync::atomic::Ordering::SeqCst)
+    let n = NEXT_SEED.fetch_add(1, Ordering::SeqCst);
+    derive_seed(MASTER_SEED.load(Ordering::SeqCst), n)
+}
+
+/// Set the master seed and restart the sequence of seeds returned by [`next_randomness_seed`].
+/// After this, [`UGen`]s created in the same order get the same seeds every time, and different
+/// master seeds give different sequences of seeds.
+///
+/// The seeds are global for the process, so prefer giving the graph a master seed, which only
+/// affects the nodes pushed to it. This is the fallback for UGens used outside a graph. Create
+/// all randomness producing [`UGen`]s from one thread after setting the seed to get a
+/// reproducible result.
+pub fn set_randomness_seed(seed: u64) {
+    MASTER_SEED.store(seed, Ordering::SeqCst);
+    NEXT_SEED.store(0, Ordering::SeqCst);
+}
+
+/// A sequence of randomness seeds derived from a master seed, like the global sequence of
+/// [`next_randomness_seed`] but owned by e.g. a graph hierarchy. The same master seed always
+/// gives the same sequence.
+#[derive(Debug)]
+pub struct RandomnessSeeds {
+    master: u64,
+    next: AtomicU64,
+}
+impl RandomnessSeeds {
+    /// A new sequence of seeds derived from `master`
+    pub fn new(master: u64) -> Self {
+        Self {
+            master,
+            next: AtomicU64::new(0),
+        }
+    }
+    /// The master seed of the sequence
+    pub fn master(&self) -> u64 {
+        self.master
+    }
+    /// The next seed in the sequence
+    pub fn next_seed(&self) -> u64 {
+        derive_seed(self.master, self.next.fetch_add(1, Ordering::SeqCst))
+    }
+}
+
+/// The `n`th seed of the sequence for `master`
+fn derive_seed(master: u64, n: u64) -> u64 {
+    splitmix64(master.wrapping_add(splitmix64(n)))
+}
+
+/// Scrambles the bits of `x` so that consecutive numbers give unrelated seeds
+fn splitmix64(x: u64) -> u64 {
+    let mut z = x.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+/// The random number generator of a UGen producing randomness, starting from a seed from
+/// [`next_randomness_seed`]. A seed set with [`SeededRng::set_seed`], i.e. through the `seed`
+/// method of the UGen, takes precedence over seeds from [`UGen::reseed`].
+#[derive(Clone, Debug)]
+pub(crate) struct SeededRng {
+    rng: fastrand::Rng,
+    explicit: bool,
+}
+impl SeededRng {
+    pub(crate) fn new() -> Self {
+        Self {
+            rng: fastrand::Rng::with_seed(next_randomness_seed()),
+            explicit: false,
+        }
+    }
+    /// Set the seed explicitly
+    pub(crate) fn set_seed(&mut self, seed: u64) {
+        self.rng = fastrand::Rng::with_seed(seed);
+        self.explicit = true;
+    }
+    /// Set the seed unless it was set explicitly. Returns true if the seed was changed.
+    pub(crate) fn reseed(&mut self, seed: u64) -> bool {
+        if !self.explicit {
+            self.rng = fastrand::Rng::with_seed(seed);
+        }
+        !self.explicit
+    }
+}
+impl Deref for SeededRng {
+    type Target = fastrand::Rng;
+    fn deref(&self) -> &Self::Target {
+        &self.rng
+    }
+}
+impl DerefMut for SeededRng {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.rng
+    }
+}
+
+/// A uniformly distributed random number in the range 0..1, generated with the precision of `F`
+#[inline]
+pub(crate) fn random_unipolar<F: Float>(rng: &mut fastrand::Rng) -> F {
+    // The size is known at compile time so only one branch remains
+    if size_of::<F>() == size_of::<f64>() {
+        F::new(rng.f64())
+    } else {
+        F::new(rng.f32())
+    }
+}
+/// A uniformly distributed random number in the range -1..1, generated with the precision of `F`
+#[inline]
+pub(crate) fn random_bipolar<F: Float>(rng: &mut fastrand::Rng) -> F {
+    random_unipolar::<F>(rng) * F::new(2.0) - F::ONE
 }
 
 /// White noise (fastrand RNG, based on wyrand)
 #[derive(Clone, Debug)]
 pub struct WhiteNoise<F: Copy = f32> {
-    rng: fastrand::Rng,
+    rng: SeededRng,
     _marker: PhantomData<F>,
 }
 #[impl_ugen]
 impl<F: Float> WhiteNoise<F> {
     #[allow(missing_docs)]
     pub fn new() -> Self {
-        let rng = fastrand::Rng::with_seed(next_randomness_seed());
+        let rng = SeededRng::new();
         Self {
             rng,
             _marker: PhantomData,
         }
     }
+    /// Use `seed` instead of a seed from the graph or [`next_randomness_seed`]
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.rng.set_seed(seed);
+        self
+    }
+    fn reseed(&mut self, seed: u64) {
+        self.rng.reseed(seed);
+    }
     fn process(&mut self) -> [F; 1] {
-        [F::new(self.rng.f32() * 2.0 - 1.0)]
+        [random_bipolar(&mut self.rng)]
     }
 }
 
@@ -60,13 +193,13 @@ pub struct PinkNoise<F: Copy = f32> {
     counter: u32,
     mask: u32,
     pink: F,
-    rng: fastrand::Rng,
+    rng: SeededRng,
 }
 #[impl_ugen]
 impl<F: Float> PinkNoise<F> {
     #[allow(missing_docs)]
     pub fn new() -> Self {
-        let rng = fastrand::Rng::with_seed(next_randomness_seed());
+        let rng = SeededRng::new();
         Self {
             white_noises: [F::ZERO; PINK_NOISE_OCTAVES as usize],
             always_on_white_noise: F::ZERO,
@@ -76,6 +209,14 @@ impl<F: Float> PinkNoise<F> {
             rng,
         }
     }
+    /// Use `seed` instead of a seed from the graph or [`next_randomness_seed`]
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.rng.set_seed(seed);
+        self
+    }
+    fn reseed(&mut self, seed: u64) {
+        self.rng.reseed(seed);
+    }
     fn noise_index(&self) -> u32 {
         assert!(self.counter > 0);
         assert!(self.counter <= self.mask);
@@ -91,18 +232,17 @@ impl<F: Float> PinkNoise<F> {
         self.counter += 1;
     }
 
-    // TODO: Generate f64 random number when F is f64
     #[allow(missing_docs)]
     pub fn process(&mut self) -> [F; 1] {
         let index = self.noise_index() as usize;
         assert!(index < PINK_NOISE_OCTAVES as usize);
 
         self.pink -= self.white_noises[index];
-        self.white_noises[index] = F::new(self.rng.f32() * 2.0 - 1.0);
+        self.white_noises[index] = random_bipolar(&mut self.rng);
         self.pink += self.white_noises[index];
 
         self.pink -= self.always_on_white_noise;
-        self.always_on_white_noise = F::new(self.rng.f32() * 2.0 - 1.0);
+        self.always_on_white_noise = random_bipolar(&mut self.rng);
         self.pink += self.always_on_white_noise;
 
         self.increment_counter();
@@ -123,7 +263,7 @@ impl<F: Float> Default for PinkNoise<F> {
 /// This implementation uses a simple integration of white noise samples with a small step size,
 /// and clamps the output to prevent it from exceeding the [-1.0, 1.0] range.
 pub struct BrownNoise<F: Copy = f32> {
-    rng: fastrand::Rng,
+    rng: SeededRng,
     last_output: F,
 }
 
@@ -131,16 +271,24 @@ pub struct BrownNoise<F: Copy = f32> {
 impl<F: Float> BrownNoise<F> {
     #[allow(missing_docs)]
     pub fn new() -> Self {
-        let rng = fastrand::Rng::with_seed(next_randomness_seed());
+        let rng = SeededRng::new();
         Self {
             rng,
             last_output: F::ZERO,
         }
     }
+    /// Use `seed` instead of a seed from the graph or [`next_randomness_seed`]
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.rng.set_seed(seed);
+        self
+    }
+    fn reseed(&mut self, seed: u64) {
+        self.rng.reseed(seed);
+    }
 
     /// Produce one frame of brown noise
     pub fn process(&mut self) -> [F; 1] {
-        let white = F::new(self.rng.f32() * 2.0 - 1.0);
+        let white = random_bipolar::<F>(&mut self.rng);
         // Adjust the coefficient to control the step size
         self.last_output += white * F::new(0.1);
         // Clamp to [-1.0, 1.0] to prevent output from exceeding the range
@@ -157,7 +305,7 @@ impl<F: Float> Default for BrownNoise<F> {
 
 /// Random numbers 0..1 with linear interpolation with new values at some frequency. Freq is sampled at control rate only.
 pub struct RandomLin<F: Copy = f32> {
-    rng: fastrand::Rng,
+    rng: SeededRng,
     current_value: F,
     current_change_width: F,
     // when phase reaches 1 we choose a new value
@@ -170,9 +318,9 @@ pub struct RandomLin<F: Copy = f32> {
 impl<F: Float> RandomLin<F> {
     /// Create a new RandomLin, seeding it from the global atomic seed.
     pub fn new(freq: F) -> Self {
-        let mut rng = fastrand::Rng::with_seed(next_randomness_seed() * 94 + 53);
+        let mut rng = SeededRng::new();
         Self {
-            current_value: F::new(rng.f32()),
+            current_value: random_unipolar(&mut rng),
             phase: F::ZERO,
             rng,
             freq_to_phase_inc: F::ZERO,
@@ -181,10 +329,22 @@ impl<F: Float> RandomLin<F> {
         }
     }
 
+    /// Use `seed` instead of a seed from the graph or [`next_randomness_seed`]
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.rng.set_seed(seed);
+        self.current_value = random_unipolar(&mut self.rng);
+        self
+    }
+    fn reseed(&mut self, seed: u64) {
+        if self.rng.reseed(seed) {
+            self.current_value = random_unipolar(&mut self.rng);
+        }
+    }
+
     #[inline]
     fn new_value(&mut self) {
         let old_target = self.current_value + self.current_change_width;
-        let new = F::new(self.rng.f32());
+        let new = random_unipolar::<F>(&mut self.rng);
         self.current_value = old_target;
         self.current_change_width = new - old_target;
         self.phase = F::new(0.0);
@@ -215,3 +375,261 @@ impl<F: Float> RandomLin<F> {
         }
     }
 }
+
+/// Blue noise, rising 3 dB per octave
+///
+/// Computed by differentiating [`PinkNoise`]. Cannot surpass +-1.0.
+pub struct BlueNoise<F: Copy = f32> {
+    pink: PinkNoise<F>,
+    last_pink: F,
+}
+#[impl_ugen]
+impl<F: Float> BlueNoise<F> {
+    #[allow(missing_docs)]
+    pub fn new() -> Self {
+        Self {
+            pink: PinkNoise::new(),
+            last_pink: F::ZERO,
+        }
+    }
+    /// Use `seed` instead of a seed from the graph or [`next_randomness_seed`]
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.pink = self.pink.seed(seed);
+        self
+    }
+    fn reseed(&mut self, seed: u64) {
+        self.pink.reseed(seed);
+    }
+    #[allow(missing_docs)]
+    pub fn process(&mut self) -> [F; 1] {
+        let pink = self.pink.process()[0];
+        // Only one octave and the always on white noise change every sample in the pink
+        // noise, so the difference is at most 4 / (PINK_NOISE_OCTAVES + 1)
+        let scale = F::from(PINK_NOISE_OCTAVES + 1).unwrap() / F::new(4.0);
+        let blue = (pink - self.last_pink) * scale;
+        self.last_pink = pink;
+        [blue]
+    }
+}
+impl<F: Float> Default for BlueNoise<F> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Violet noise, rising 6 dB per octave
+///
+/// Computed by differentiating white noise. Cannot surpass +-1.0.
+pub struct VioletNoise<F: Copy = f32> {
+    rng: SeededRng,
+    last_white: F,
+}
+#[impl_ugen]
+impl<F: Float> VioletNoise<F> {
+    #[allow(missing_docs)]
+    pub fn new() -> Self {
+        Self {
+            rng: SeededRng::new(),
+            last_white: F::ZERO,
+        }
+    }
+    /// Use `seed` instead of a seed from the graph or [`next_randomness_seed`]
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.rng.set_seed(seed);
+        self
+    }
+    fn reseed(&mut self, seed: u64) {
+        self.rng.reseed(seed);
+    }
+    #[allow(missing_docs)]
+    pub fn process(&mut self) -> [F; 1] {
+        let white = random_bipolar::<F>(&mut self.rng);
+        let violet = (white - self.last_white) * F::new(0.5);
+        self.last_white = white;
+        [violet]
+    }
+}
+impl<F: Float> Default for VioletNoise<F> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Velvet noise: sparse impulses of +1.0 or -1.0 with random timing
+///
+/// The time is divided into periods of equal length with exactly one impulse at a random
+/// position in each period. Perceptually smooth at densities of a couple of thousand impulses
+/// per second, and cheap to convolve with, e.g. for decorrelation and artificial reverb.
+pub struct VelvetNoise<F: Copy = f32> {
+    rng: SeededRng,
+    density: F,
+    sample_rate: F,
+    /// The length of a period in samples
+    period: F,
+    /// The position in the current period in samples
+    position: F,
+    /// The position of the impulse in the current period in samples
+    impulse_position: F,
+    impulse_sign: F,
+}
+#[impl_ugen]
+impl<F: Float> VelvetNoise<F> {
+    /// Create a new [`VelvetNoise`] with `density` impulses per second
+    pub fn new(density: F) -> Self {
+        Self {
+            rng: SeededRng::new(),
+            density,
+            sample_rate: F::ZERO,
+            period: F::ONE,
+            position: F::ZERO,
+            impulse_position: F::ZERO,
+            impulse_sign: F::ONE,
+        }
+    }
+    /// Use `seed` instead of a seed from the graph or [`next_randomness_seed`]
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.rng.set_seed(seed);
+        self
+    }
+    fn reseed(&mut self, seed: u64) {
+        self.rng.reseed(seed);
+    }
+    fn init(&mut self, sample_rate: u32, _block_size: usize) {
+        self.sample_rate = F::from(sample_rate).unwrap();
+        self.update_period();
+        self.position = F::ZERO;
+        self.new_impulse();
+    }
+    /// The number of impulses per second
+    #[param(kind = Frequency, default = 2000.0, range = 1.0..=20000.0, logarithmic = true)]
+    pub fn density(&mut self, density: PFloat) {
+        self.density = F::new(density);
+        self.update_period();
+    }
+    fn update_period(&mut self) {
+        if self.sample_rate > F::ZERO {
+            // At least one sample per period
+            self.period = (self.sample_rate / self.density.max(F::new(1e-3))).max(F::ONE);
+        }
+    }
+    fn new_impulse(&mut self) {
+        self.impulse_position = random_unipolar::<F>(&mut self.rng) * self.period;
+        self.impulse_sign = if self.rng.bool() { F::ONE } else { -F::ONE };
+    }
+    #[allow(missing_docs)]
+    pub fn process(&mut self) -> [F; 1] {
+        let next_position = self.position + F::ONE;
+        let out = if self.impulse_position >= self.position && self.impulse_position < next_position
+        {
+            self.impulse_sign
+        } else {
+            F::ZERO
+        };
+        self.position = next_position;
+        if self.position >= self.period {
+            self.position -= self.period;
+            self.new_impulse();
+            // The new impulse may fall within the part of the sample belonging to the new period
+            if self.impulse_position < self.position {
+                self.impulse_position = self.position;
+            }
+        }
+        [out]
+    }
+}
+
+/// Chaotic crackling noise, generated by the equation `y[n] = |y[n-1] * chaos - y[n-2] - 0.05|`
+///
+/// Deterministic; the same `chaos` gives the same output every time. Outputs positive values.
+pub struct Crackle<F: Copy = f32> {
+    chaos: F,
+    y1: F,
+    y2: F,
+}
+#[impl_ugen]
+impl<F: Float> Crackle<F> {
+    /// Create a new [`Crackle`]. See [`Crackle::chaos`] for the `chaos` parameter.
+    pub fn new(chaos: F) -> Self {
+        Self {
+            chaos,
+            y1: F::new(0.3),
+            y2: F::ZERO,
+        }
+    }
+    /// Higher values give more chaotic noise. Values around 1.0 give a low buzz and values close
+    /// to 2.0 give a bright, noisy crackle.
+    #[param(default = 1.5, range = 1.0..=2.0)]
+    pub fn chaos(&mut self, chaos: PFloat) {
+        self.chaos = F::new(chaos);
+    }
+    #[allow(missing_docs)]
+    pub fn process(&mut self) -> [F; 1] {
+        let y0 = (self.y1 * self.chaos - self.y2 - F::new(0.05)).abs();
+        self.y2 = self.y1;
+        self.y1 = y0;
+        [y0]
+    }
+}
+
+#[cfg(all(test, any(feature = "std", feature = "alloc")))]
+mod tests {
+    use super::*;
+    use std::prelude::v1::*;
+
+    fn render<U: UGen<Inputs = knaster_core::typenum::U0, Outputs = knaster_core::typenum::U1>>(
+        ugen: &mut U,
+        frames: usize,
+    ) -> Vec<U::Sample> {
+        let mut ctx = AudioCtx::new(48000, 64, knaster_core::log::ArLogSender::non_rt());
+        let mut flags = UGenFlags::new();
+        ugen.init(48000, 64);
+        (0..frames)
+            .map(|_| ugen.process(&mut ctx, &mut flags, Default::default())[0])
+            .collect()
+    }
+
+    #[test]
+    fn seeded_noise_is_reproducible() {
+        let a = render(&mut WhiteNoise::<f64>::new().seed(7), 1000);
+        let b = render(&mut WhiteNoise::<f64>::new().seed(7), 1000);
+        let c = render(&mut WhiteNoise::<f64>::new().seed(8), 1000);
+        assert_eq!(a, b);
+        assert_ne!(a, c);
+        assert_eq!(
+            render(&mut BlueNoise::<f32>::new().seed(3), 1000),
+            render(&mut BlueNoise::<f32>::new().seed(3), 1000)
+        );
+        assert_eq!(
+            render(&mut VelvetNoise::<f32>::new(1000.).seed(3), 1000),
+            render(&mut VelvetNoise::<f32>::new(1000.).seed(3), 1000)
+        );
+    }
+
+    #[test]
+    fn noise_is_within_range() {
+        let noises = [
+            render(&mut BlueNoise::<f32>::new(), 48000),
+            render(&mut VioletNoise::<f32>::new(), 48000),
+            render(&mut VelvetNoise::<f32>::new(2000.), 48000),
+        ];
+        for noise in noises {
+            assert!(noise.iter().all(|x| x.abs() <= 1.0));
+            assert!(noise.iter().any(|x| x.abs() > 0.1));
+        }
+    }
+
+    #[test]
+    fn velvet_noise_density() {
+        for density in [100., 2000., 30000.] {
+            let noise = render(&mut VelvetNoise::<f64>::new(density), 48000);
+            let impulses = noise.iter().filter(|x| **x != 0.0).count();
+            assert!(noise.iter().all(|x| [-1.0, 0.0, 1.0].contains(x)));
+            // There is one impulse per period, and the density is capped at one impulse per sample
+            let expected = density.min(48000.);
+            assert!(
+                (impulses as f64 - expected).abs() <= 1.0,
+                "{density}: {impulses} impulses"
+            );
+        }
+    }
+}
@@ -3,6 +3,8 @@
 
 use knaster_core::{AudioCtx, Float, PFloat, impl_ugen};
 
+use crate::dsp::response::{Complex, FrequencyResponse, z_inverse};
+
 // To use it as a DC blocker:
 //
 // `OnePole *dcBlockerLp = new OnePole(10.0 / sampleRate);`
@@ -39,11 +41,24 @@ impl<T: Float> OnePole<T> {
         // if freq > sample_rate * T::from_f32(0.5).unwrap() {
         //     println!("OnePole freq out of bounds: {freq}");
         // }
-        let f: T = freq / sample_rate;
-        let b_tmp: T = (T::new(-2.0_f64) * T::PI * f).exp();
-        self.b1 = b_tmp;
+        self.b1 = Self::lowpass_b1(freq, sample_rate);
         self.a0 = T::new(1.0_f64) - self.b1;
     }
+    /// The feedback coefficient of a lowpass OnePole with cutoff `freq`
+    #[inline]
+    fn lowpass_b1(freq: T, sample_rate: T) -> T {
+        let f: T = freq / sample_rate;
+        (T::new(-2.0_f64) * T::PI * f).exp()
+    }
+    /// The frequency response at `freq` of a lowpass OnePole with cutoff `cutoff_freq`
+    pub fn lowpass_response(cutoff_freq: f64, freq: f64, sample_rate: f64) -> Complex<f64> {
+        let b1 = OnePole::<f64>::lowpass_b1(cutoff_freq, sample_rate);
+        Complex::real(1.0 - b1) / (-(z_inverse(freq, sample_rate) * b1) + 1.0)
+    }
+    /// The frequency response at `freq` of a highpass OnePole with cutoff `cutoff_freq`
+    pub fn highpass_response(cutoff_freq: f64, freq: f64, sample_rate: f64) -> Complex<f64> {
+        -Self::lowpass_response(cutoff_freq, freq, sample_rate) + 1.0
+    }
     // TODO: Not verified to set the frequency correctly. In fact, I suspect it doesn't
     /// Calculate coefficients for a highpass OnePole
     #[inline]
@@ -111,21 +126,20 @@ impl<T: Float> Default for OnePole<T> {
 pub struct OnePoleLpf<F: Float> {
     /// The interval one pole filter implementation
     pub op: OnePole<F>,
+    cutoff_freq: F,
 }
 #[impl_ugen]
 impl<F: Float> OnePoleLpf<F> {
     #[allow(missing_docs)]
     pub fn new(cutoff_freq: F) -> Self {
-        let mut op = OnePole::new();
-        op.b1 = cutoff_freq;
-        Self { op }
+        Self {
+            op: OnePole::new(),
+            cutoff_freq,
+        }
     }
     fn init(&mut self, sample_rate: u32, _block_size: usize) {
-        // Only assume b1 is frequency if a0 is set to its standard value
-        if self.op.a0 == F::ONE {
-            let freq = self.op.b1;
-            self.op.set_freq_lowpass(freq, F::new(sample_rate as f32));
-        }
+        self.op
+            .set_freq_lowpass(self.cutoff_freq, F::new(sample_rate as f32));
     }
 
     fn process(&mut self, input: [F; 1]) -> [F; 1] {
@@ -134,8 +148,9 @@ impl<F: Float> OnePoleLpf<F> {
 
     #[param(kind = Frequency)]
     fn cutoff_freq(&mut self, ctx: &AudioCtx, freq: PFloat) {
+        self.cutoff_freq = F::new(freq);
         self.op
-            .set_freq_lowpass(F::new(freq), F::from(ctx.sample_rate()).unwrap())
+            .set_freq_lowpass(self.cutoff_freq, F::from(ctx.sample_rate()).unwrap())
     }
 }
 
@@ -144,6 +159,7 @@ impl<F: Float> OnePoleLpf<F> {
 pub struct OnePoleHpf<F: Float> {
     /// The interval one pole filter implementation
     pub op: OnePole<F>,
+    cutoff_freq: F,
 }
 
 impl<F: Float> Default for OnePoleHpf<F> {
@@ -155,15 +171,15 @@ impl<F: Float> Default for OnePoleHpf<F> {
 impl<F: Float> OnePoleHpf<F> {
     #[allow(missing_docs)]
     pub fn new() -> Self {
-        Self { op: OnePole::new() }
+        Self {
+            op: OnePole::new(),
+            cutoff_freq: F::ZERO,
+        }
     }
 
     fn init(&mut self, sample_rate: u32, _block_size: usize) {
-        // Only assume b1 is frequency if a0 is set to its standard value
-        if self.op.a0 == F::ONE {
-            let freq = self.op.b1;
-            self.op.set_freq_highpass(freq, F::new(sample_rate as f32));
-        }
+        self.op
+            .set_freq_highpass(self.cutoff_freq, F::new(sample_rate as f32));
     }
     fn process(&mut self, input: [F; 1]) -> [F; 1] {
         [self.op.process_hp(input[0])]
@@ -171,7 +187,19 @@ impl<F: Float> OnePoleHpf<F> {
 
     #[param(kind = Frequency)]
     fn cutoff_freq(&mut self, ctx: &AudioCtx, freq: PFloat) {
+        self.cutoff_freq = F::new(freq);
         self.op
-            .set_freq_highpass(F::new(freq), F::from(ctx.sample_rate()).unwrap())
+            .set_freq_highpass(self.cutoff_freq, F::from(ctx.sample_rate()).unwrap())
+    }
+}
+
+impl<F: Float> FrequencyResponse for OnePoleLpf<F> {
+    fn frequency_response(&self, freq: f64, sample_rate: f64) -> Complex<f64> {
+        OnePole::<F>::lowpass_response(self.cutoff_freq.to_f64(), freq, sample_rate)
+    }
+}
+impl<F: Float> FrequencyResponse for OnePoleHpf<F> {
+    fn frequency_response(&self, freq: f64, sample_rate: f64) -> Complex<f64> {
+        OnePole::<F>::highpass_response(self.cutoff_freq.to_f64(), freq, sample_rate)
     }
 }
@@ -0,0 +1,42 @@
+//! Parameter names for UGens which implement [`UGen`] manually because they have a variable
+//! number of indexed items, such as the bands of an EQ, each with the same parameters.
+
+#[allow(unused)]
+use knaster_core::UGen;
+use knaster_core::{Size, numeric_array::NumericArray};
+
+/// The names of the parameters repeated for each of a number of indexed items, as an array with
+/// the names of each item in its own row. The index is appended to each name, or inserted between
+/// a common prefix and each suffix:
+///
+/// - `indexed_param_names!(["value", "weight"]; 0, 1)` gives
+///   `[["value0", "weight0"], ["value1", "weight1"]]`
+/// - `indexed_param_names!("band" ["_freq", "_q"]; 0, 1)` gives
+///   `[["band0_freq", "band0_q"], ["band1_freq", "band1_q"]]`
+macro_rules! indexed_param_names {
+    (@item $index:literal $prefix:literal [$($suffix:literal),*]) => {
+        [$(concat!($prefix, $index, $suffix)),*]
+    };
+    (@item $index:literal [$($name:literal),*]) => {
+        [$(concat!($name, $index)),*]
+    };
+    ($prefix:literal $suffixes:tt; $($index:literal),*) => {
+        [$(indexed_param_names!(@item $index $prefix $suffixes)),*]
+    };
+    ($names:tt; $($index:literal),*) => {
+        [$(indexed_param_names!(@item $index $names)),*]
+    };
+}
+
+/// Collect the names of all parameters of a UGen in order, e.g. some fixed names followed by the
+/// flattened rows of `indexed_param_names!`, into the array returned by
+/// [`UGen::param_descriptions`]. Names beyond the number of parameters are ignored.
+pub(crate) fn collect_param_names<N: Size>(
+    names: impl IntoIterator<Item = &'static str>,
+) -> NumericArray<&'static str, N> {
+    let mut descriptions = NumericArray::default();
+    for (description, name) in descriptions.iter_mut().zip(names) {
+        *description = name;
+    }
+    descriptions
+}
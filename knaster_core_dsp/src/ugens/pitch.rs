@@ -0,0 +1,418 @@
+//! # Pitch shifting
+//!
+//! Time domain pitch shifting with overlapping delay line grains:
+//! - [`PitchShifter`]: a single pitch shifted voice
+//! - [`Harmonizer`]: several pitch shifted voices at different intervals
+//!
+//! The input is written to a delay line which is read by two taps half a window apart. The
+//! delay of each tap sweeps across the window at a speed which gives the desired pitch, and the
+//! taps are crossfaded with Hann windows so that each tap is silent when it jumps back. Longer
+//! windows give a smoother sound for sustained tones while shorter windows give less latency
+//! and smearing of transients.
+//!
+//! The wet signal is delayed by half a window, see `latency_frames`.
+
+use crate::delay::InterpolatingDelay;
+use crate::ugens::param_names::collect_param_names;
+use knaster_core::{
+    AudioCtx, Float, FloatParameterKind, Frame, PFloat, ParameterHint, ParameterValue, Size, UGen,
+    UGenFlags, impl_ugen,
+    numeric_array::NumericArray,
+    typenum::{Prod, Sum, U2, U3},
+};
+use std::prelude::v1::*;
+
+/// The longest window in seconds
+pub const MAX_PITCH_WINDOW: f64 = 0.2;
+/// The shortest window in seconds
+const MIN_PITCH_WINDOW: f64 = 0.005;
+/// The maximum number of voices supported by [`Harmonizer`]
+pub const MAX_HARMONIZER_VOICES: usize = 8;
+/// The number of parameters for each voice of a [`Harmonizer`]
+const PARAMETERS_PER_VOICE: usize = 3;
+
+/// Pitch ratio from an interval in semitones and cents
+fn interval_ratio<F: Float>(semitones: PFloat, cents: PFloat) -> F {
+    F::new(2.0f64.powf((semitones + cents / 100.) / 12.))
+}
+
+/// The window in frames, the delay line and the latency shared by all voices
+struct PitchDelay<F: Copy> {
+    delay: InterpolatingDelay<F>,
+    window: PFloat,
+    window_frames: F,
+    sample_rate: u32,
+}
+impl<F: Float> PitchDelay<F> {
+    fn new(window: PFloat) -> Self {
+        let mut s = Self {
+            delay: InterpolatingDelay::new(0),
+            window: 0.,
+            window_frames: F::ONE,
+            sample_rate: 48000,
+        };
+        s.set_window(window);
+        s
+    }
+    fn init(&mut self, sample_rate: u32) {
+        self.sample_rate = sample_rate;
+        self.delay = InterpolatingDelay::new((MAX_PITCH_WINDOW * sample_rate as f64) as usize + 1);
+        self.set_window(self.window);
+    }
+    fn set_window(&mut self, window: PFloat) {
+        self.window = window.clamp(MIN_PITCH_WINDOW, MAX_PITCH_WINDOW);
+        self.window_frames = F::new(self.window * self.sample_rate as f64);
+    }
+    fn latency_frames(&self) -> usize {
+        (self.window_frames.to_f64() / 2.).round() as usize
+    }
+}
+
+/// One pitch shifted voice reading from a [`PitchDelay`]
+#[derive(Clone, Copy, Debug)]
+struct PitchVoice<F> {
+    /// Phase of the first tap between 0 and 1
+    phase: F,
+    ratio: F,
+}
+impl<F: Float> PitchVoice<F> {
+    fn new() -> Self {
+        Self {
+            phase: F::ZERO,
+            ratio: F::ONE,
+        }
+    }
+    #[inline]
+    fn process(&mut self, delay: &PitchDelay<F>) -> F {
+        let half = F::new(0.5);
+        let mut output = F::ZERO;
+        for tap_phase in [self.phase, self.phase + half] {
+            let tap_phase = tap_phase - tap_phase.floor();
+            let gain = (F::PI * tap_phase).sin();
+            output += delay.delay.read(tap_phase * delay.window_frames) * gain * gain;
+        }
+        // The delay changes by 1 - ratio frames every frame, giving a read speed of `ratio`
+        self.phase += (F::ONE - self.ratio) / delay.window_frames;
+        self.phase = self.phase - self.phase.floor();
+        output
+    }
+}
+
+/// Pitch shifter with a single voice.
+///
+/// - `semitones` and `cents`: the pitch shift interval
+/// - `window`: the window size in seconds
+/// - `mix`: dry/wet mix. The dry signal is not delayed.
+pub struct PitchShifter<F: Float = f32> {
+    delay: PitchDelay<F>,
+    voice: PitchVoice<F>,
+    semitones: PFloat,
+    cents: PFloat,
+    mix: F,
+}
+#[impl_ugen]
+impl<F: Float> PitchShifter<F> {
+    #[allow(missing_docs)]
+    pub fn new(semitones: PFloat) -> Self {
+        let mut s = Self {
+            delay: PitchDelay::new(0.05),
+            voice: PitchVoice::new(),
+            semitones,
+            cents: 0.,
+            mix: F::ONE,
+        };
+        s.update_ratio();
+        s
+    }
+    /// The latency of the wet signal in frames at the current sample rate and window size
+    pub fn latency_frames(&self) -> usize {
+        self.delay.latency_frames()
+    }
+    /// Pitch shift in semitones
+    #[param(default = 0.0, range = -24.0..=24.0)]
+    pub fn semitones(&mut self, semitones: PFloat) {
+        self.semitones = semitones;
+        self.update_ratio();
+    }
+    /// Fine pitch shift in cents
+    #[param(default = 0.0, range = -100.0..=100.0)]
+    pub fn cents(&mut self, cents: PFloat) {
+        self.cents = cents;
+        self.update_ratio();
+    }
+    /// Window size in seconds
+    #[param(kind = Seconds, default = 0.05, range = 0.005..=0.2)]
+    pub fn window(&mut self, window: PFloat) {
+        self.delay.set_window(window);
+    }
+    /// Dry/wet mix
+    #[param(default = 1.0, range = 0.0..=1.0)]
+    pub fn mix(&mut self, mix: PFloat) {
+        self.mix = F::new(mix);
+    }
+    fn update_ratio(&mut self) {
+        self.voice.ratio = interval_ratio(self.semitones, self.cents);
+    }
+    fn init(&mut self, sample_rate: u32, _block_size: usize) {
+        self.delay.init(sample_rate);
+        self.voice.phase = F::ZERO;
+    }
+    fn process(&mut self, input: [F; 1]) -> [F; 1] {
+        self.delay.delay.write_and_advance(input[0]);
+        let wet = self.voice.process(&self.delay);
+        [input[0] + (wet - input[0]) * self.mix]
+    }
+}
+
+const VOICE_PARAM_NAMES: [[&str; PARAMETERS_PER_VOICE]; MAX_HARMONIZER_VOICES] = indexed_param_names!(
+    "voice" ["_semitones", "_cents", "_gain"];
+    0, 1, 2, 3, 4, 5, 6, 7
+);
+
+/// Settings of one voice of a [`Harmonizer`]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct HarmonizerVoiceSettings {
+    /// Interval in semitones
+    pub semitones: PFloat,
+    /// Fine tuning of the interval in cents
+    pub cents: PFloat,
+    /// Linear gain of the voice
+    pub gain: PFloat,
+}
+impl Default for HarmonizerVoiceSettings {
+    fn default() -> Self {
+        Self {
+            semitones: 0.,
+            cents: 0.,
+            gain: 0.5,
+        }
+    }
+}
+
+/// Harmonizer with `Voices` pitch shifted voices mixed with the dry signal. All voices share
+/// one delay line and window size.
+///
+/// The parameters are `window` and `mix`, which work like for [`PitchShifter`], followed by
+/// `voice{n}_semitones`, `voice{n}_cents` and `voice{n}_gain` for each voice, e.g.
+/// `voice0_semitones`.
+///
+/// `Voices` can be at most [`MAX_HARMONIZER_VOICES`].
+///
+/// ```
+/// use knaster_core_dsp::pitch::Harmonizer;
+/// use knaster_core::typenum::U2;
+/// // A major triad
+/// let mut harmonizer = Harmonizer::<f32, U2>::new();
+/// harmonizer.set_voice(0, 4., 0., 0.5);
+/// harmonizer.set_voice(1, 7., 0., 0.5);
+/// ```
+pub struct Harmonizer<F: Float, Voices: Size> {
+    delay: PitchDelay<F>,
+    voices: NumericArray<PitchVoice<F>, Voices>,
+    settings: NumericArray<HarmonizerVoiceSettings, Voices>,
+    gains: NumericArray<F, Voices>,
+    mix: F,
+}
+impl<F: Float, Voices: Size> Harmonizer<F, Voices> {
+    /// New harmonizer where all voices are unshifted at gain 0.5
+    ///
+    /// # Panics
+    /// Panics if `Voices` is larger than [`MAX_HARMONIZER_VOICES`]
+    pub fn new() -> Self {
+        assert!(
+            Voices::USIZE <= MAX_HARMONIZER_VOICES,
+            "Harmonizer supports at most {MAX_HARMONIZER_VOICES} voices"
+        );
+        let mut s = Self {
+            delay: PitchDelay::new(0.05),
+            voices: NumericArray::from_iter((0..Voices::USIZE).map(|_| PitchVoice::new())),
+            settings: NumericArray::default(),
+            gains: NumericArray::default(),
+            mix: F::new(0.5),
+        };
+        for voice in 0..Voices::USIZE {
+            s.update_voice(voice);
+        }
+        s
+    }
+    /// Set the interval and gain of a voice
+    pub fn set_voice(&mut self, voice: usize, semitones: PFloat, cents: PFloat, gain: PFloat) {
+        self.settings[voice] = HarmonizerVoiceSettings {
+            semitones,
+            cents,
+            gain,
+        };
+        self.update_voice(voice);
+    }
+    /// The current settings of a voice
+    pub fn voice(&self, voice: usize) -> HarmonizerVoiceSettings {
+        self.settings[voice]
+    }
+    /// The latency of the wet signal in frames at the current sample rate and window size
+    pub fn latency_frames(&self) -> usize {
+        self.delay.latency_frames()
+    }
+    fn update_voice(&mut self, voice: usize) {
+        let settings = self.settings[voice];
+        self.voices[voice].ratio = interval_ratio(settings.semitones, settings.cents);
+        self.gains[voice] = F::new(settings.gain);
+    }
+}
+impl<F: Float, Voices: Size> Default for Harmonizer<F, Voices> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<F: Float, Voices: Size> UGen for Harmonizer<F, Voices>
+where
+    Voices: core::ops::Mul<U3> + Send,
+    Prod<Voices, U3>: core::ops::Add<U2>,
+    Sum<Prod<Voices, U3>, U2>: Size,
+{
+    type Sample = F;
+    type Inputs = knaster_core::typenum::U1;
+    type Outputs = knaster_core::typenum::U1;
+    type Parameters = Sum<Prod<Voices, U3>, U2>;
+
+    fn init(&mut self, sample_rate: u32, _block_size: usize) {
+        self.delay.init(sample_rate);
+        for voice in self.voices.iter_mut() {
+            voice.phase = F::ZERO;
+        }
+    }
+
+    fn process(
+        &mut self,
+        _ctx: &mut AudioCtx,
+        _flags: &mut UGenFlags,
+        input: Frame<Self::Sample, Self::Inputs>,
+    ) -> Frame<Self::Sample, Self::Outputs> {
+        let dry = input[0];
+        self.delay.delay.write_and_advance(dry);
+        let mut wet = F::ZERO;
+        for (voice, &gain) in self.voices.iter_mut().zip(self.gains.iter()) {
+            wet += voice.process(&self.delay) * gain;
+        }
+        [dry + (wet - dry) * self.mix].into()
+    }
+
+    fn param_descriptions() -> NumericArray<&'static str, Self::Parameters> {
+        collect_param_names(
+            ["window", "mix"]
+                .into_iter()
+                .chain(VOICE_PARAM_NAMES.as_flattened().iter().copied()),
+        )
+    }
+
+    fn param_hints() -> NumericArray<ParameterHint, Self::Parameters> {
+        let mut hints: NumericArray<ParameterHint, Self::Parameters> = NumericArray::default();
+        let defaults = HarmonizerVoiceSettings::default();
+        hints[0] = ParameterHint::new_float(|h| {
+            h.kind(FloatParameterKind::Seconds)
+                .minmax(MIN_PITCH_WINDOW, MAX_PITCH_WINDOW)
+                .default(0.05)
+        });
+        hints[1] = ParameterHint::new_float(|h| h.minmax(0., 1.).default(0.5));
+        for voice_hints in hints[2..].chunks_mut(PARAMETERS_PER_VOICE) {
+            voice_hints[0] =
+                ParameterHint::new_float(|h| h.minmax(-24., 24.).default(defaults.semitones));
+            voice_hints[1] =
+                ParameterHint::new_float(|h| h.minmax(-100., 100.).default(defaults.cents));
+            voice_hints[2] = ParameterHint::new_float(|h| h.minmax(0., 1.).default(defaults.gain));
+        }
+        hints
+    }
+
+    fn param_apply(&mut self, _ctx: &mut AudioCtx, index: usize, value: ParameterValue) {
+        let ParameterValue::Float(value) = value else {
+            return;
+        };
+        match index {
+            0 => self.delay.set_window(value),
+            1 => self.mix = F::new(value),
+            _ => {
+                let voice = (index - 2) / PARAMETERS_PER_VOICE;
+                if voice >= Voices::USIZE {
+                    return;
+                }
+                let settings = &mut self.settings[voice];
+                match (index - 2) % PARAMETERS_PER_VOICE {
+                    0 => settings.semitones = value,
+                    1 => settings.cents = value,
+                    _ => settings.gain = value,
+                }
+                self.update_voice(voice);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use knaster_core::log::ArLogSender;
+
+    /// Magnitude of the frequency `freq` in `signal`
+    fn magnitude_at(signal: &[f32], freq: f32, sample_rate: f32) -> f32 {
+        let (mut re, mut im) = (0.0, 0.0);
+        for (i, x) in signal.iter().enumerate() {
+            let phase = core::f32::consts::TAU * freq * i as f32 / sample_rate;
+            re += x * phase.cos();
+            im += x * phase.sin();
+        }
+        (re * re + im * im).sqrt() / signal.len() as f32
+    }
+
+    #[test]
+    fn shifts_pitch() {
+        const SR: u32 = 48000;
+        let mut ctx = AudioCtx::new(SR, 64, ArLogSender::non_rt());
+        let mut flags = UGenFlags::new();
+        for (semitones, cents) in [(12., 0.), (-12., 0.), (7., 0.), (0., 50.)] {
+            let mut shifter = PitchShifter::<f32>::new(0.);
+            shifter.init(SR, 64);
+            shifter.param(&mut ctx, "semitones", semitones).unwrap();
+            shifter.param(&mut ctx, "cents", cents).unwrap();
+            // The taps are half a window (25 ms) apart, so a whole number of periods per half
+            // window keeps them in phase
+            let freq = 200.0;
+            let output: Vec<f32> = (0..SR as usize)
+                .map(|i| {
+                    let x = (core::f32::consts::TAU * freq * i as f32 / SR as f32).sin();
+                    UGen::process(&mut shifter, &mut ctx, &mut flags, [x].into())[0]
+                })
+                .collect();
+            let expected = freq * 2.0f32.powf((semitones + cents / 100.) as f32 / 12.);
+            let output = &output[SR as usize / 2..];
+            let shifted = magnitude_at(output, expected, SR as f32);
+            let original = magnitude_at(output, freq, SR as f32);
+            assert!(
+                shifted > 0.3 && shifted > original * 5.0,
+                "{semitones} semitones {cents} cents: {shifted} at {expected} Hz, {original} at {freq} Hz"
+            );
+        }
+    }
+
+    #[test]
+    fn unshifted_output_is_delayed_by_latency() {
+        const SR: u32 = 48000;
+        let mut ctx = AudioCtx::new(SR, 64, ArLogSender::non_rt());
+        let mut flags = UGenFlags::new();
+        let mut harmonizer = Harmonizer::<f64, knaster_core::typenum::U1>::new();
+        harmonizer.set_voice(0, 0., 0., 1.0);
+        harmonizer.init(SR, 64);
+        harmonizer.param(&mut ctx, "window", 0.02).unwrap();
+        harmonizer.param(&mut ctx, "mix", 1.0).unwrap();
+        assert_eq!(harmonizer.latency_frames(), 480);
+        let mut impulse_at = None;
+        for i in 0..2000 {
+            let x = if i == 0 { 1.0 } else { 0.0 };
+            let out = harmonizer.process(&mut ctx, &mut flags, [x].into())[0];
+            if out.abs() > 0.5 {
+                impulse_at = Some(i);
+            }
+        }
+        assert_eq!(impulse_at, Some(480));
+    }
+}
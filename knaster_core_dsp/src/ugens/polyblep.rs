@@ -2,6 +2,9 @@
 //!
 //! PolyBlep UGen for anti-aliased waveforms using the polyblep method
 //!
+//! - [`PolyBlep`]: oscillator controlled using parameters
+//! - [`PolyBlepSync`]: oscillator with audio rate frequency, pulse width and hard sync inputs
+//!
 //! Ported from Martin Finke's C++ port of the PolyBLEP Waveform
 //! generator from the Jesusonic code by Tale
 //! (https://github.com/martinfinke/PolyBLEP)
@@ -72,6 +75,56 @@ fn bitwise_or_zero<F: Float>(t: F) -> F {
 }
 use knaster_core::num_traits;
 
+/// Anti-aliased sawtooth at phase `t` with phase increment `dt`
+#[inline]
+pub(crate) fn sawtooth<F: Float>(t: F, dt: F) -> F {
+    let mut t = t + F::new(0.5);
+    t -= bitwise_or_zero(t);
+
+    let mut y = F::new(2.0) * t - F::ONE;
+    y -= blep(t, dt);
+
+    y
+}
+
+/// Anti-aliased rectangle wave at phase `t` with phase increment `dt`
+#[inline]
+pub(crate) fn rectangle<F: Float>(t: F, dt: F, pulse_width: F) -> F {
+    let mut t2 = t + F::ONE - pulse_width;
+    t2 -= bitwise_or_zero(t2);
+
+    let mut y = -F::new(2.0) * pulse_width;
+    if t < pulse_width {
+        y += F::new(2.0);
+    }
+
+    y += blep(t, dt) - blep(t2, dt);
+
+    y
+}
+
+/// Anti-aliased triangle wave at phase `t` with phase increment `dt`
+#[inline]
+pub(crate) fn triangle<F: Float>(t: F, dt: F) -> F {
+    let mut t1 = t + F::new(0.25);
+    t1 -= bitwise_or_zero(t1);
+
+    let mut t2 = t + F::new(0.75);
+    t2 -= bitwise_or_zero(t2);
+
+    let mut y = t * F::new(4.);
+
+    if y >= F::new(3.) {
+        y -= F::new(4.);
+    } else if y > F::ONE {
+        y = F::new(2.0) - y;
+    }
+
+    y += F::new(4.) * dt * (blamp(t1, dt) - blamp(t2, dt));
+
+    y
+}
+
 #[derive(
     Default,
     Debug,
@@ -276,26 +329,7 @@ impl<F: Float> PolyBlep<F> {
     }
 
     fn tri(&mut self) -> F {
-        let mut t1 = self.t + F::new(0.25);
-        t1 -= bitwise_or_zero(t1);
-
-        let mut t2 = self.t + F::new(0.75);
-        t2 -= bitwise_or_zero(t2);
-
-        let mut y = self.t * F::new(4.);
-
-        if y >= F::new(3.) {
-            y -= F::new(4.);
-        } else if y > F::ONE {
-            y = F::new(2.0) - y;
-        }
-
-        y += F::new(4.)
-            * self.freq_in_seconds_per_sample
-            * (blamp(t1, self.freq_in_seconds_per_sample)
-                - blamp(t2, self.freq_in_seconds_per_sample));
-
-        y
+        triangle(self.t, self.freq_in_seconds_per_sample)
     }
 
     fn tri2(&mut self) -> F {
@@ -473,28 +507,11 @@ impl<F: Float> PolyBlep<F> {
     }
 
     fn rect(&mut self) -> F {
-        let mut t2 = self.t + F::ONE - self.pulse_width;
-        t2 -= bitwise_or_zero(t2);
-
-        let mut y = -F::new(2.0) * self.pulse_width;
-        if self.t < self.pulse_width {
-            y += F::new(2.0);
-        }
-
-        y += blep(self.t, self.freq_in_seconds_per_sample)
-            - blep(t2, self.freq_in_seconds_per_sample);
-
-        y
+        rectangle(self.t, self.freq_in_seconds_per_sample, self.pulse_width)
     }
 
     fn saw(&mut self) -> F {
-        let mut _t = self.t + F::new(0.5);
-        _t -= bitwise_or_zero(_t);
-
-        let mut y = F::new(2.0) * _t - F::ONE;
-        y -= blep(_t, self.freq_in_seconds_per_sample);
-
-        y
+        sawtooth(self.t, self.freq_in_seconds_per_sample)
     }
 
     fn ramp(&mut self) -> F {
@@ -507,3 +524,194 @@ impl<F: Float> PolyBlep<F> {
         y
     }
 }
+
+impl<F: Float> PolyBlep<F> {
+    /// The waveform at phase `t` without anti-aliasing
+    fn naive_sample(&mut self, t: F) -> F {
+        let (phase, dt) = (self.t, self.freq_in_seconds_per_sample);
+        let sine_fallback = self.get_freq_in_hz() >= self.sample_rate / F::new(4.);
+        // All blep and blamp corrections are zero when the phase increment is zero
+        self.t = t;
+        self.freq_in_seconds_per_sample = F::ZERO;
+        let sample = if sine_fallback {
+            self.sin()
+        } else {
+            self.next_sample()
+        };
+        self.t = phase;
+        self.freq_in_seconds_per_sample = dt;
+        sample
+    }
+}
+
+fn wrap_phase<F: Float>(t: F) -> F {
+    let t = t - t.floor();
+    if t >= F::ONE { F::ZERO } else { t }
+}
+
+/// [`PolyBlep`] with audio rate inputs for frequency, pulse width and hard sync.
+///
+/// The inputs are the frequency in Hz, the pulse width and the sync signal. The phase is reset
+/// every time the sync signal goes from zero or below to above zero, see the
+/// [trigger convention](crate::trigger#trigger-convention). The time of the sync within the
+/// sample is estimated from the two sync samples around the crossing, so using the output of the
+/// master oscillator as the sync signal gives sub-sample accurate sync, while a trigger syncs at
+/// the sample boundary. The discontinuity at the sync is corrected using PolyBLEP.
+///
+/// The correction of the sample before the sync means that the output is delayed by one sample.
+#[derive(Debug)]
+pub struct PolyBlepSync<F: Copy = f32> {
+    osc: PolyBlep<F>,
+    last_sync: F,
+    /// The output sample delayed by one sample
+    delayed: F,
+}
+#[impl_ugen]
+impl<F: Float> PolyBlepSync<F> {
+    #[allow(missing_docs)]
+    pub fn new(waveform: Waveform) -> Self {
+        Self {
+            osc: PolyBlep::new(waveform, F::ZERO),
+            last_sync: F::ZERO,
+            delayed: F::ZERO,
+        }
+    }
+    #[allow(missing_docs)]
+    pub fn init(&mut self, sample_rate: u32, block_size: usize) {
+        self.osc.init(sample_rate, block_size);
+    }
+    /// Set the waveform
+    #[param]
+    pub fn waveform(&mut self, waveform: PInteger) {
+        self.osc.waveform = Waveform::from(waveform);
+    }
+    #[allow(missing_docs)]
+    pub fn process(&mut self, input: [F; 3]) -> [F; 1] {
+        let [freq, pulse_width, sync] = input;
+        self.osc.set_freq(freq.max(F::ZERO));
+        self.osc.pulse_width = pulse_width.clamp(F::ZERO, F::ONE);
+        let dt = self.osc.freq_in_seconds_per_sample;
+        let mut correction = F::ZERO;
+        let mut sample = if self.last_sync <= F::ZERO && sync > F::ZERO {
+            // How long before this sample the sync happened, in samples
+            let d = (sync / (sync - self.last_sync)).min(F::ONE);
+            let before = self.osc.naive_sample(wrap_phase(self.osc.t - d * dt));
+            let start = self.osc.naive_sample(F::ZERO);
+            // The waveform's own correction of a discontinuity at phase 0 is applied after the
+            // sync, so only the rest of the step is corrected here
+            let end = self.osc.naive_sample(F::ONE - F::new(1e-6));
+            self.osc.t = wrap_phase(d * dt);
+            let one_minus_d = F::ONE - d;
+            correction = (start - before) * F::new(0.5) * d * d;
+            self.osc.next_sample() - (end - before) * F::new(0.5) * one_minus_d * one_minus_d
+        } else {
+            self.osc.next_sample()
+        };
+        self.last_sync = sync;
+        self.osc.inc();
+        core::mem::swap(&mut sample, &mut self.delayed);
+        [sample + correction]
+    }
+}
+
+#[cfg(all(test, any(feature = "std", feature = "alloc")))]
+mod tests {
+    use super::*;
+    use knaster_core::{AudioCtx, UGen, UGenFlags, log::ArLogSender};
+
+    const SR: u32 = 48000;
+
+    /// Energy of the frequency `freq` in `signal`
+    fn energy_at(signal: &[f64], freq: f64) -> f64 {
+        let (mut re, mut im) = (0.0, 0.0);
+        for (i, x) in signal.iter().enumerate() {
+            let phase = core::f64::consts::TAU * freq * i as f64 / SR as f64;
+            re += x * phase.cos();
+            im += x * phase.sin();
+        }
+        re * re + im * im
+    }
+
+    #[test]
+    fn sync_matches_polyblep_without_sync() {
+        let mut ctx = AudioCtx::new(SR, 64, ArLogSender::non_rt());
+        let mut flags = UGenFlags::new();
+        for waveform in [Waveform::Sawtooth, Waveform::Rectangle, Waveform::Triangle] {
+            let mut osc = PolyBlep::<f64>::new(waveform, 440.);
+            osc.init(SR, 64);
+            osc.pulse_width(0.3);
+            let mut sync_osc = PolyBlepSync::<f64>::new(waveform);
+            sync_osc.init(SR, 64);
+            let expected: Vec<f64> = (0..1000)
+                .map(|_| UGen::process(&mut osc, &mut ctx, &mut flags, [].into())[0])
+                .collect();
+            let output: Vec<f64> = (0..1000)
+                .map(|_| {
+                    UGen::process(&mut sync_osc, &mut ctx, &mut flags, [440., 0.3, 0.].into())[0]
+                })
+                .collect();
+            assert_eq!(output[0], 0.0);
+            for (a, b) in expected.iter().zip(&output[1..]) {
+                assert!((a - b).abs() < 1e-12, "{waveform:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn hard_sync_reduces_aliasing() {
+        let mut ctx = AudioCtx::new(SR, 64, ArLogSender::non_rt());
+        let mut flags = UGenFlags::new();
+        let master_freq = 110.;
+        let slave_freq = master_freq * 2.7;
+        // A sine has no discontinuities of its own, so all aliasing comes from the sync
+        let sync: Vec<f64> = (0..9600)
+            .map(|i| (core::f64::consts::TAU * master_freq * i as f64 / SR as f64).sin())
+            .collect();
+
+        let mut sync_osc = PolyBlepSync::<f64>::new(Waveform::Sine);
+        sync_osc.init(SR, 64);
+        let output: Vec<f64> = sync
+            .iter()
+            .map(|&s| {
+                UGen::process(
+                    &mut sync_osc,
+                    &mut ctx,
+                    &mut flags,
+                    [slave_freq, 0.5, s].into(),
+                )[0]
+            })
+            .collect();
+
+        // The same sub-sample accurate sync without correcting the discontinuity
+        let mut osc = PolyBlep::<f64>::new(Waveform::Sine, slave_freq);
+        osc.init(SR, 64);
+        let dt = slave_freq / SR as f64;
+        let mut last_sync = 0.0;
+        let naive: Vec<f64> = sync
+            .iter()
+            .map(|&s| {
+                if last_sync <= 0.0 && s > 0.0 {
+                    osc.sync(s / (s - last_sync) * dt);
+                }
+                last_sync = s;
+                UGen::process(&mut osc, &mut ctx, &mut flags, [].into())[0]
+            })
+            .collect();
+
+        // 4800 samples are exactly 11 periods of the master, so the harmonics of the master fall
+        // on every 11th bin of a DFT of 10 Hz bins and everything else is aliasing
+        let aliasing = |signal: &[f64]| -> f64 {
+            (1..2400)
+                .filter(|bin| bin % 11 != 0)
+                .map(|bin| energy_at(&signal[4800..9600], bin as f64 * 10.))
+                .sum()
+        };
+        let (aliasing, naive_aliasing) = (aliasing(&output), aliasing(&naive));
+        assert!(
+            aliasing * 10.0 < naive_aliasing,
+            "{aliasing} {naive_aliasing}"
+        );
+        // The phase is reset on every sync
+        assert!(output.iter().all(|x| x.abs() < 1.5));
+    }
+}
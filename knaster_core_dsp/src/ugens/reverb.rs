@@ -0,0 +1,396 @@
+//! # Reverb
+//!
+//! [`FdnReverb`] is a stereo feedback delay network (FDN) reverb. The input passes through a
+//! pre-delay and a chain of allpass diffusers before being fed into a set of delay lines whose
+//! outputs are mixed by an orthogonal matrix and fed back. Each line has a damping lowpass and
+//! a slowly modulated length which reduces metallic ringing.
+//!
+//! Delay lengths are specified in seconds so the reverb sounds the same at any sample rate.
+
+use crate::delay::InterpolatingDelay;
+use knaster_core::{
+    Float, KnasterIntegerParameter, PFloat, PInteger, impl_ugen,
+    num_derive::{FromPrimitive, ToPrimitive},
+    num_traits,
+};
+use std::prelude::v1::*;
+
+/// Base lengths of the feedback delay lines in seconds, at `size` 1.0. Chosen so that the
+/// lengths have no common factors when converted to frames at common sample rates.
+const LINE_LENGTHS: [f64; 16] = [
+    0.0297, 0.0371, 0.0411, 0.0437, 0.0479, 0.0533, 0.0599, 0.0617, 0.0671, 0.0713, 0.0739, 0.0797,
+    0.0833, 0.0891, 0.0973, 0.1019,
+];
+/// Lengths of the input diffusers in seconds for the left and right channels
+const DIFFUSER_LENGTHS: [[f64; 4]; 2] = [
+    [0.004771, 0.003595, 0.012734, 0.009307],
+    [0.004919, 0.003769, 0.013103, 0.009613],
+];
+/// Largest `size`
+const MAX_SIZE: f64 = 2.0;
+/// Longest pre-delay in seconds
+const MAX_PRE_DELAY: f64 = 0.5;
+/// Modulation depth of the delay lines at `modulation` 1.0 in seconds
+const MAX_MODULATION_DEPTH: f64 = 0.0005;
+/// Time constant for smoothing changes to `size` and `pre_delay` in seconds
+const SMOOTHING_TIME: f64 = 0.05;
+
+/// The feedback matrix of an [`FdnReverb`]
+#[derive(
+    Clone, Copy, Debug, FromPrimitive, ToPrimitive, PartialEq, Default, KnasterIntegerParameter,
+)]
+#[num_traits = "num_traits"]
+#[repr(u8)]
+pub enum FdnMatrix {
+    /// Householder reflection. Every line feeds back mostly to itself, which gives a slower
+    /// build-up of echo density.
+    Householder = 0,
+    /// Normalised Hadamard matrix. Every line feeds back equally to every other line, which
+    /// gives the fastest build-up of echo density.
+    #[default]
+    Hadamard,
+}
+
+/// Schroeder allpass diffuser with a fixed length
+struct Diffuser<F> {
+    buffer: Vec<F>,
+    index: usize,
+}
+impl<F: Float> Diffuser<F> {
+    fn new(length: usize) -> Self {
+        Self {
+            buffer: vec![F::ZERO; length.max(1)],
+            index: 0,
+        }
+    }
+    #[inline]
+    fn process(&mut self, input: F, gain: F) -> F {
+        let delayed = self.buffer[self.index];
+        let w = input + gain * delayed;
+        self.buffer[self.index] = w;
+        self.index = (self.index + 1) % self.buffer.len();
+        delayed - gain * w
+    }
+}
+
+/// One delay line of the feedback network
+struct FdnLine<F: Copy> {
+    delay: InterpolatingDelay<F>,
+    /// Length in frames at `size` 1.0
+    base_frames: F,
+    /// Feedback gain giving the set decay time
+    gain: F,
+    damping_state: F,
+    lfo_phase: F,
+    lfo_increment: F,
+}
+
+/// Stereo feedback delay network reverb with 4, 8 or 16 delay lines.
+///
+/// More lines give a denser reverb at a higher CPU cost.
+///
+/// - `pre_delay`: delay before the reverb in seconds
+/// - `size`: scales the delay line lengths, from 0.1 to 2.0
+/// - `decay`: time in seconds for the reverb to decay by 60 dB (RT60)
+/// - `diffusion`: amount of input diffusion, from 0.0 to 1.0. Higher values smear the early
+///   echoes.
+/// - `damping`: high frequency damping, from 0.0 to 1.0. High frequencies decay faster with
+///   more damping.
+/// - `modulation`: amount of delay line modulation, from 0.0 to 1.0
+/// - `width`: stereo width of the reverb, 0.0 is mono
+/// - `mix`: dry/wet mix, 0.0 is only dry and 1.0 is only wet
+/// - `matrix`: the [`FdnMatrix`] used for feedback
+pub struct FdnReverb<F: Float = f32> {
+    pre_delay: F,
+    size: F,
+    decay: F,
+    diffusion: F,
+    damping: F,
+    modulation: F,
+    width: F,
+    mix: F,
+    matrix: FdnMatrix,
+    sample_rate: F,
+    smoothing_coeff: F,
+    damping_coeff: F,
+    current_size: F,
+    current_pre_delay: F,
+    pre_delays: [InterpolatingDelay<F>; 2],
+    diffusers: [Vec<Diffuser<F>>; 2],
+    lines: Vec<FdnLine<F>>,
+    feedback: Vec<F>,
+}
+#[impl_ugen]
+impl<F: Float> FdnReverb<F> {
+    /// New reverb with `lines` feedback delay lines
+    ///
+    /// # Panics
+    /// Panics if `lines` is not 4, 8 or 16
+    pub fn new(lines: usize) -> Self {
+        assert!(
+            matches!(lines, 4 | 8 | 16),
+            "FdnReverb needs 4, 8 or 16 lines"
+        );
+        let mut s = Self {
+            pre_delay: F::new(0.02),
+            size: F::ONE,
+            decay: F::new(2.),
+            diffusion: F::new(0.7),
+            damping: F::new(0.3),
+            modulation: F::new(0.3),
+            width: F::ONE,
+            mix: F::new(0.3),
+            matrix: FdnMatrix::Hadamard,
+            sample_rate: F::new(48000.),
+            smoothing_coeff: F::ONE,
+            damping_coeff: F::ONE,
+            current_size: F::ONE,
+            current_pre_delay: F::ZERO,
+            pre_delays: [InterpolatingDelay::new(0), InterpolatingDelay::new(0)],
+            diffusers: [Vec::new(), Vec::new()],
+            lines: Vec::with_capacity(lines),
+            feedback: vec![F::ZERO; lines],
+        };
+        s.allocate(lines);
+        s
+    }
+    /// Pre-delay in seconds
+    #[param(kind = Seconds, default = 0.02, range = 0.0..=0.5)]
+    pub fn pre_delay(&mut self, pre_delay: PFloat) {
+        self.pre_delay = F::new(pre_delay.clamp(0.0, MAX_PRE_DELAY));
+    }
+    /// Scale of the delay line lengths
+    #[param(default = 1.0, range = 0.1..=2.0)]
+    pub fn size(&mut self, size: PFloat) {
+        self.size = F::new(size.clamp(0.1, MAX_SIZE));
+    }
+    /// Time in seconds to decay by 60 dB
+    #[param(kind = Seconds, default = 2.0, range = 0.1..=30.0, logarithmic = true)]
+    pub fn decay(&mut self, decay: PFloat) {
+        self.decay = F::new(decay.max(0.01));
+        self.update_gains();
+    }
+    /// Amount of input diffusion
+    #[param(default = 0.7, range = 0.0..=1.0)]
+    pub fn diffusion(&mut self, diffusion: PFloat) {
+        self.diffusion = F::new(diffusion.clamp(0.0, 1.0));
+    }
+    /// Amount of high frequency damping
+    #[param(default = 0.3, range = 0.0..=1.0)]
+    pub fn damping(&mut self, damping: PFloat) {
+        self.damping = F::new(damping.clamp(0.0, 1.0));
+        self.update_damping();
+    }
+    /// Amount of delay line modulation
+    #[param(default = 0.3, range = 0.0..=1.0)]
+    pub fn modulation(&mut self, modulation: PFloat) {
+        self.modulation = F::new(modulation.clamp(0.0, 1.0));
+    }
+    /// Stereo width
+    #[param(default = 1.0, range = 0.0..=1.0)]
+    pub fn width(&mut self, width: PFloat) {
+        self.width = F::new(width);
+    }
+    /// Dry/wet mix
+    #[param(default = 0.3, range = 0.0..=1.0)]
+    pub fn mix(&mut self, mix: PFloat) {
+        self.mix = F::new(mix);
+    }
+    /// Set the feedback matrix
+    #[param(from = FdnMatrix)]
+    pub fn matrix(&mut self, matrix: PInteger) {
+        self.matrix = FdnMatrix::from(matrix);
+    }
+    fn init(&mut self, sample_rate: u32, _block_size: usize) {
+        self.sample_rate = F::new(sample_rate as f32);
+        self.allocate(self.feedback.len());
+    }
+    /// Allocate the delay lines for the current sample rate
+    fn allocate(&mut self, lines: usize) {
+        let sample_rate = self.sample_rate.to_f64();
+        let frames = |seconds: f64| (seconds * sample_rate).round() as usize;
+        self.smoothing_coeff = F::new(1.0 - (-1.0 / (SMOOTHING_TIME * sample_rate)).exp());
+        self.current_size = self.size;
+        self.current_pre_delay = self.pre_delay * self.sample_rate;
+        self.pre_delays = [
+            InterpolatingDelay::new(frames(MAX_PRE_DELAY)),
+            InterpolatingDelay::new(frames(MAX_PRE_DELAY)),
+        ];
+        self.diffusers = DIFFUSER_LENGTHS.map(|lengths| {
+            lengths
+                .iter()
+                .map(|&length| Diffuser::new(frames(length)))
+                .collect()
+        });
+        let max_frames = frames(LINE_LENGTHS[15] * MAX_SIZE + MAX_MODULATION_DEPTH) + 2;
+        self.lines.clear();
+        for i in 0..lines {
+            let base_seconds = LINE_LENGTHS[i * LINE_LENGTHS.len() / lines];
+            // Spread out the modulation rates and phases between 0.1 and 0.9 Hz
+            let lfo_rate = 0.1 + 0.8 * i as f64 / lines as f64;
+            self.lines.push(FdnLine {
+                delay: InterpolatingDelay::new(max_frames),
+                base_frames: F::from_usize(frames(base_seconds)),
+                gain: F::ZERO,
+                damping_state: F::ZERO,
+                lfo_phase: F::new(i as f64 / lines as f64),
+                lfo_increment: F::new(lfo_rate / sample_rate),
+            });
+        }
+        self.feedback.fill(F::ZERO);
+        self.update_gains();
+        self.update_damping();
+    }
+    fn update_gains(&mut self) {
+        let decay_frames = self.decay * self.sample_rate;
+        for line in &mut self.lines {
+            // Follows the delay line length as `size` glides
+            let length = line.base_frames * self.current_size;
+            // -60 dB after `decay` seconds
+            line.gain = F::new(10.).powf(F::new(-3.) * length / decay_frames);
+        }
+    }
+    fn update_damping(&mut self) {
+        let cutoff =
+            (F::new(18000.) * F::new(0.01).powf(self.damping)).min(self.sample_rate * F::new(0.45));
+        self.damping_coeff = F::ONE - (-F::TAU * cutoff / self.sample_rate).exp();
+    }
+    fn process(&mut self, input: [F; 2]) -> [F; 2] {
+        if self.current_size != self.size {
+            self.current_size += (self.size - self.current_size) * self.smoothing_coeff;
+            if (self.size - self.current_size).abs() < F::new(1e-6) {
+                self.current_size = self.size;
+            }
+            self.update_gains();
+        }
+        self.current_pre_delay +=
+            (self.pre_delay * self.sample_rate - self.current_pre_delay) * self.smoothing_coeff;
+        let diffusion_gain = self.diffusion * F::new(0.75);
+        let mut diffused = [F::ZERO; 2];
+        for channel in 0..2 {
+            let pre_delay = &mut self.pre_delays[channel];
+            pre_delay.write_and_advance(input[channel]);
+            let mut sig = pre_delay.read(self.current_pre_delay);
+            for diffuser in &mut self.diffusers[channel] {
+                sig = diffuser.process(sig, diffusion_gain);
+            }
+            diffused[channel] = sig;
+        }
+
+        let modulation_depth = self.modulation * F::new(MAX_MODULATION_DEPTH) * self.sample_rate;
+        let mut wet = [F::ZERO; 2];
+        for (i, (line, feedback)) in self.lines.iter_mut().zip(&mut self.feedback).enumerate() {
+            // Triangle LFO shaped to be smooth at the peaks
+            let triangle = F::new(4.) * (line.lfo_phase - F::new(0.5)).abs() - F::ONE;
+            let lfo = triangle * (F::new(1.5) - F::new(0.5) * triangle * triangle);
+            line.lfo_phase += line.lfo_increment;
+            line.lfo_phase = line.lfo_phase - line.lfo_phase.floor();
+            let length = line.base_frames * self.current_size + modulation_depth * (F::ONE + lfo);
+            let out = line.delay.read(length);
+            wet[i % 2] += out;
+            line.damping_state += (out - line.damping_state) * self.damping_coeff;
+            *feedback = line.damping_state * line.gain;
+        }
+        match self.matrix {
+            FdnMatrix::Householder => householder(&mut self.feedback),
+            FdnMatrix::Hadamard => hadamard(&mut self.feedback),
+        }
+        for (i, (line, feedback)) in self.lines.iter_mut().zip(&self.feedback).enumerate() {
+            line.delay.write_and_advance(*feedback + diffused[i % 2]);
+        }
+
+        let output_gain = (F::new(2.) / F::from_usize(self.lines.len())).sqrt();
+        let mid = (wet[0] + wet[1]) * F::new(0.5) * output_gain;
+        let side = (wet[0] - wet[1]) * F::new(0.5) * output_gain * self.width;
+        let wet = [mid + side, mid - side];
+        [
+            input[0] + (wet[0] - input[0]) * self.mix,
+            input[1] + (wet[1] - input[1]) * self.mix,
+        ]
+    }
+}
+
+/// Multiply by the Householder matrix `I - 2/N * 1 1^T` in place
+#[inline]
+fn householder<F: Float>(values: &mut [F]) {
+    let sum = values.iter().fold(F::ZERO, |acc, &v| acc + v);
+    let correction = sum * F::new(2.) / F::from_usize(values.len());
+    for v in values {
+        *v -= correction;
+    }
+}
+/// Multiply by the normalised Hadamard matrix in place. The length must be a power of two.
+#[inline]
+fn hadamard<F: Float>(values: &mut [F]) {
+    let len = values.len();
+    let mut half = 1;
+    while half < len {
+        for start in (0..len).step_by(half * 2) {
+            for i in start..start + half {
+                let a = values[i];
+                let b = values[i + half];
+                values[i] = a + b;
+                values[i + half] = a - b;
+            }
+        }
+        half *= 2;
+    }
+    let norm = F::ONE / F::from_usize(len).sqrt();
+    for v in values {
+        *v *= norm;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use knaster_core::{AudioCtx, UGen, UGenFlags, log::ArLogSender};
+
+    #[test]
+    fn matrices_are_orthogonal() {
+        for matrix in [householder::<f64> as fn(&mut [f64]), hadamard::<f64>] {
+            for len in [4, 8, 16] {
+                let mut values: Vec<f64> = (0..len).map(|i| (i as f64 * 1.3).sin()).collect();
+                let energy: f64 = values.iter().map(|v| v * v).sum();
+                matrix(&mut values);
+                let mixed_energy: f64 = values.iter().map(|v| v * v).sum();
+                assert!((energy - mixed_energy).abs() < 1e-12);
+            }
+        }
+    }
+
+    #[test]
+    fn decay_time() {
+        const SR: u32 = 44100;
+        let mut ctx = AudioCtx::new(SR, 64, ArLogSender::non_rt());
+        let mut flags = UGenFlags::new();
+        for lines in [4, 8, 16] {
+            for matrix in [FdnMatrix::Householder, FdnMatrix::Hadamard] {
+                let mut reverb = FdnReverb::<f64>::new(lines);
+                reverb.init(SR, 64);
+                reverb.param(&mut ctx, "decay", 1.0).unwrap();
+                reverb.param(&mut ctx, "damping", 0.0).unwrap();
+                reverb.param(&mut ctx, "mix", 1.0).unwrap();
+                reverb.param(&mut ctx, "matrix", matrix).unwrap();
+                let mut energy = [0.0; 2];
+                for i in 0..SR as usize {
+                    let x = if i == 0 { 1.0 } else { 0.0 };
+                    let out = UGen::process(&mut reverb, &mut ctx, &mut flags, [x, x].into());
+                    let window = i * 10 / SR as usize;
+                    let e = out[0] * out[0] + out[1] * out[1];
+                    match window {
+                        2 => energy[0] += e,
+                        7 => energy[1] += e,
+                        _ => (),
+                    }
+                }
+                // 0.5 seconds apart should be 30 dB down
+                let drop_db = 10. * (energy[1] / energy[0]).log10();
+                assert!(
+                    (drop_db + 30.).abs() < 6.,
+                    "{lines} lines, {matrix:?}: {drop_db} dB"
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,536 @@
+//! # Sequencer
+//!
+//! Sample accurate sequencing inside the graph.
+//!
+//! - [`StepSequencer`]: a step sequencer with a value, a gate and a probability per step
+//!
+//! Triggers follow the [trigger convention](crate::trigger#trigger-convention).
+
+#[allow(unused)]
+use crate::noise::next_randomness_seed;
+use crate::noise::{SeededRng, random_unipolar};
+use crate::trigger::TriggerDetector;
+use crate::ugens::param_names::collect_param_names;
+use knaster_core::{
+    AudioCtx, Float, FloatParameterKind, Frame, KnasterIntegerParameter, PFloat, PInteger,
+    ParameterHint, ParameterValue, Size, UGen, UGenFlags,
+    num_derive::{FromPrimitive, ToPrimitive},
+    num_traits,
+    numeric_array::NumericArray,
+    typenum::{Prod, Sum, U2, U3, U7},
+};
+
+/// Where a [`StepSequencer`] gets its clock from
+#[derive(
+    Clone, Copy, Debug, FromPrimitive, ToPrimitive, PartialEq, Default, KnasterIntegerParameter,
+)]
+#[num_traits = "num_traits"]
+#[repr(u8)]
+pub enum SequencerClock {
+    /// Advance at the `rate` parameter
+    #[default]
+    Internal = 0,
+    /// Advance on every trigger in the clock input
+    External,
+}
+
+/// The order in which a [`StepSequencer`] plays its steps
+#[derive(
+    Clone, Copy, Debug, FromPrimitive, ToPrimitive, PartialEq, Default, KnasterIntegerParameter,
+)]
+#[num_traits = "num_traits"]
+#[repr(u8)]
+pub enum SequencerDirection {
+    /// First to last step
+    #[default]
+    Forward = 0,
+    /// Last to first step
+    Backward,
+    /// First to last step and back again, without repeating the first and last steps
+    PingPong,
+    /// A random step every time
+    Random,
+}
+
+/// The maximum number of steps supported by [`StepSequencer`]
+pub const MAX_SEQUENCER_STEPS: usize = 32;
+/// The number of parameters for each step of a [`StepSequencer`]
+const PARAMETERS_PER_STEP: usize = 3;
+/// The number of parameters before the step parameters of a [`StepSequencer`]
+const GLOBAL_PARAMETERS: usize = 7;
+const GLOBAL_PARAM_NAMES: [&str; GLOBAL_PARAMETERS] = [
+    "rate",
+    "clock",
+    "direction",
+    "length",
+    "swing",
+    "gate_length",
+    "t_reset",
+];
+const STEP_PARAM_NAMES: [[&str; PARAMETERS_PER_STEP]; MAX_SEQUENCER_STEPS] = indexed_param_names!(
+    ["value", "gate", "probability"];
+    0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20, 21, 22, 23, 24, 25,
+    26, 27, 28, 29, 30, 31
+);
+
+/// One step of a [`StepSequencer`]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SequencerStep {
+    /// The value output while the step is playing
+    pub value: PFloat,
+    /// Whether the step opens the gate
+    pub gate: bool,
+    /// The probability of the gate opening if `gate` is true, between 0 and 1
+    pub probability: PFloat,
+}
+impl Default for SequencerStep {
+    fn default() -> Self {
+        Self {
+            value: 0.,
+            gate: true,
+            probability: 1.,
+        }
+    }
+}
+
+/// Step sequencer with `Steps` steps, each with a value, a gate and a probability.
+///
+/// The inputs are a clock and a reset trigger, and the outputs are the value of the current step,
+/// the gate and an end-of-cycle trigger. The value changes when a step starts, even if its gate
+/// is off. The end-of-cycle trigger fires when the sequence starts over, on the same sample as the
+/// first step of the new cycle.
+///
+/// The clock is either internal at `rate` steps per second, or the clock input, depending on
+/// `clock`. `length` sets how many of the steps are played. `swing` delays every second step by a
+/// fraction of the step duration, e.g. 1/3 for a triplet feel. `gate_length` is the fraction of
+/// the step duration the gate stays open. The gate always closes for at least one sample between
+/// two steps, and closes when a step with its gate off starts. With the external clock, the step
+/// duration is the time between the last two clock triggers, or based on `rate` until two clock
+/// triggers have been received.
+///
+/// A reset, from the reset input or the `t_reset` parameter, makes the next clock start the
+/// sequence from the beginning. With the internal clock, the clock is also restarted so that the
+/// first step plays immediately.
+///
+/// The parameters are `rate`, `clock`, `direction`, `length`, `swing`, `gate_length` and
+/// `t_reset`, followed by `value{n}`, `gate{n}` and `probability{n}` for each step, e.g. `value0`.
+///
+/// `Steps` can be at most [`MAX_SEQUENCER_STEPS`].
+///
+/// ```
+/// use knaster_core_dsp::sequencer::StepSequencer;
+/// use knaster_core::typenum::U4;
+/// // A bass line in semitones where the last step plays half of the time
+/// let mut sequencer = StepSequencer::<f32, U4>::new();
+/// sequencer.set_step(0, 0., true, 1.);
+/// sequencer.set_step(1, 12., true, 1.);
+/// sequencer.set_step(2, 0., false, 1.);
+/// sequencer.set_step(3, 7., true, 0.5);
+/// ```
+pub struct StepSequencer<F: Copy, Steps: Size> {
+    rng: SeededRng,
+    steps: NumericArray<SequencerStep, Steps>,
+    rate: PFloat,
+    clock: SequencerClock,
+    direction: SequencerDirection,
+    length: usize,
+    swing: PFloat,
+    gate_length: PFloat,
+    sample_rate: u32,
+    clock_trigger: TriggerDetector<F>,
+    reset_trigger: TriggerDetector<F>,
+    /// The phase of the internal clock. A step starts when it reaches 1.
+    phase: f64,
+    /// Frames since the last external clock trigger
+    frames_since_clock: u64,
+    /// Frames between the two latest external clock triggers, 0 until measured
+    clock_period: u64,
+    /// The number of clock ticks since the last reset, used for swing
+    ticks: u64,
+    /// Frames until a step delayed by swing starts
+    pending_step: Option<u64>,
+    /// The position within the current cycle, or `None` if the sequence has been reset
+    position: Option<usize>,
+    value: F,
+    gate_frames_left: u64,
+    /// The gate was open when a new gated step started
+    gate_retriggered: bool,
+}
+impl<F: Float, Steps: Size> StepSequencer<F, Steps> {
+    /// New [`StepSequencer`] where all steps have the value 0 and an open gate
+    ///
+    /// # Panics
+    /// Panics if `Steps` is larger than [`MAX_SEQUENCER_STEPS`]
+    pub fn new() -> Self {
+        assert!(
+            Steps::USIZE <= MAX_SEQUENCER_STEPS,
+            "StepSequencer supports at most {MAX_SEQUENCER_STEPS} steps"
+        );
+        Self {
+            rng: SeededRng::new(),
+            steps: NumericArray::default(),
+            rate: 4.,
+            clock: SequencerClock::Internal,
+            direction: SequencerDirection::Forward,
+            length: Steps::USIZE,
+            swing: 0.,
+            gate_length: 0.5,
+            sample_rate: 0,
+            clock_trigger: TriggerDetector::new(),
+            reset_trigger: TriggerDetector::new(),
+            phase: 1.,
+            frames_since_clock: 0,
+            clock_period: 0,
+            ticks: 0,
+            pending_step: None,
+            position: None,
+            value: F::ZERO,
+            gate_frames_left: 0,
+            gate_retriggered: false,
+        }
+    }
+    /// Use `seed` instead of a seed from the graph or [`next_randomness_seed`]
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.rng.set_seed(seed);
+        self
+    }
+    /// Set the value, gate and probability of a step. The probability is clamped to 0..=1.
+    pub fn set_step(&mut self, step: usize, value: PFloat, gate: bool, probability: PFloat) {
+        self.steps[step] = SequencerStep {
+            value,
+            gate,
+            probability: probability.clamp(0., 1.),
+        };
+    }
+    /// The current settings of a step
+    pub fn step(&self, step: usize) -> SequencerStep {
+        self.steps[step]
+    }
+    /// Start from the first step on the next clock
+    pub fn reset(&mut self) {
+        self.position = None;
+        self.pending_step = None;
+        self.ticks = 0;
+        if self.clock == SequencerClock::Internal {
+            self.phase = 1.;
+        }
+    }
+    /// The duration of a step in frames
+    fn step_frames(&self) -> f64 {
+        if self.clock == SequencerClock::External && self.clock_period > 0 {
+            self.clock_period as f64
+        } else if self.rate > 0. {
+            self.sample_rate as f64 / self.rate
+        } else {
+            0.
+        }
+    }
+    /// Returns true if the clock ticks on this frame
+    fn tick(&mut self, clock: F) -> bool {
+        match self.clock {
+            SequencerClock::Internal => {
+                let tick = self.phase >= 1.;
+                if tick {
+                    self.phase -= 1.;
+                    // Don't accumulate ticks if the rate is higher than the sample rate
+                    if self.phase >= 1. {
+                        self.phase = 0.;
+                    }
+                }
+                if self.sample_rate > 0 {
+                    self.phase += self.rate / self.sample_rate as f64;
+                }
+                tick
+            }
+            SequencerClock::External => {
+                self.frames_since_clock += 1;
+                let tick = self.clock_trigger.detect(clock);
+                if tick {
+                    if self.ticks > 0 {
+                        self.clock_period = self.frames_since_clock;
+                    }
+                    self.frames_since_clock = 0;
+                }
+                tick
+            }
+        }
+    }
+    /// Start the next step. Returns true if a new cycle started.
+    fn start_step(&mut self) -> bool {
+        let length = self.length.clamp(1, Steps::USIZE.max(1));
+        let cycle = match self.direction {
+            SequencerDirection::PingPong if length > 1 => 2 * (length - 1),
+            _ => length,
+        };
+        let (position, end_of_cycle) = match self.position {
+            None => (0, false),
+            Some(position) if position + 1 >= cycle => (0, true),
+            Some(position) => (position + 1, false),
+        };
+        self.position = Some(position);
+        let index = match self.direction {
+            SequencerDirection::Forward => position,
+            SequencerDirection::Backward => length - 1 - position,
+            SequencerDirection::PingPong if position < length => position,
+            SequencerDirection::PingPong => cycle - position,
+            SequencerDirection::Random => self.rng.usize(0..length),
+        };
+        let Some(step) = self.steps.get(index).copied() else {
+            return end_of_cycle;
+        };
+        self.value = F::new(step.value);
+        if step.gate
+            && (step.probability >= 1.
+                || random_unipolar::<F>(&mut self.rng) < F::new(step.probability))
+        {
+            let step_frames = self.step_frames();
+            let gate_frames = (step_frames * self.gate_length).round() as u64;
+            let gate_frames = gate_frames
+                .min((step_frames as u64).saturating_sub(1))
+                .max(1);
+            // Close the gate for this sample if it is open so that the new step is a new gate
+            self.gate_retriggered = self.gate_frames_left > 0;
+            self.gate_frames_left = gate_frames;
+        } else {
+            self.gate_frames_left = 0;
+        }
+        end_of_cycle
+    }
+}
+impl<F: Float, Steps: Size> Default for StepSequencer<F, Steps> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<F: Float, Steps: Size> UGen for StepSequencer<F, Steps>
+where
+    Steps: core::ops::Mul<U3> + Send,
+    Prod<Steps, U3>: core::ops::Add<U7>,
+    Sum<Prod<Steps, U3>, U7>: Size,
+{
+    type Sample = F;
+    type Inputs = U2;
+    type Outputs = U3;
+    type Parameters = Sum<Prod<Steps, U3>, U7>;
+
+    fn init(&mut self, sample_rate: u32, _block_size: usize) {
+        self.sample_rate = sample_rate;
+    }
+    fn reseed(&mut self, seed: u64) {
+        self.rng.reseed(seed);
+    }
+
+    fn process(
+        &mut self,
+        _ctx: &mut AudioCtx,
+        _flags: &mut UGenFlags,
+        input: Frame<Self::Sample, Self::Inputs>,
+    ) -> Frame<Self::Sample, Self::Outputs> {
+        if self.reset_trigger.detect(input[1]) {
+            self.reset();
+        }
+        let mut end_of_cycle = false;
+        if self.tick(input[0]) {
+            // A step delayed by swing starts before the next one
+            if self.pending_step.take().is_some() {
+                end_of_cycle |= self.start_step();
+            }
+            let off_beat = self.ticks % 2 == 1;
+            self.ticks += 1;
+            let delay = if off_beat {
+                (self.step_frames() * self.swing).round() as u64
+            } else {
+                0
+            };
+            if delay == 0 {
+                end_of_cycle |= self.start_step();
+            } else {
+                self.pending_step = Some(delay);
+            }
+        } else if let Some(frames) = self.pending_step {
+            if frames <= 1 {
+                self.pending_step = None;
+                end_of_cycle |= self.start_step();
+            } else {
+                self.pending_step = Some(frames - 1);
+            }
+        }
+        let gate = if self.gate_retriggered {
+            self.gate_retriggered = false;
+            false
+        } else if self.gate_frames_left > 0 {
+            self.gate_frames_left -= 1;
+            true
+        } else {
+            false
+        };
+        let gate = if gate { F::ONE } else { F::ZERO };
+        let end_of_cycle = if end_of_cycle { F::ONE } else { F::ZERO };
+        [self.value, gate, end_of_cycle].into()
+    }
+
+    fn param_descriptions() -> NumericArray<&'static str, Self::Parameters> {
+        collect_param_names(
+            GLOBAL_PARAM_NAMES
+                .into_iter()
+                .chain(STEP_PARAM_NAMES.as_flattened().iter().copied()),
+        )
+    }
+
+    fn param_hints() -> NumericArray<ParameterHint, Self::Parameters> {
+        let mut hints: NumericArray<ParameterHint, Self::Parameters> = NumericArray::default();
+        let defaults = SequencerStep::default();
+        hints[0] = ParameterHint::new_float(|h| {
+            h.kind(FloatParameterKind::Frequency)
+                .minmax(0., 1000.)
+                .default(4.)
+        });
+        hints[1] = ParameterHint::from_pinteger_enum::<SequencerClock>();
+        hints[2] = ParameterHint::from_pinteger_enum::<SequencerDirection>();
+        hints[3] = ParameterHint::new_integer((PInteger(1), PInteger(Steps::USIZE)), |mut h| {
+            h.default = Some(PInteger(Steps::USIZE));
+            h
+        });
+        hints[4] = ParameterHint::new_float(|h| h.minmax(0., 0.9).default(0.));
+        hints[5] = ParameterHint::new_float(|h| h.minmax(0., 1.).default(0.5));
+        hints[6] = ParameterHint::Trigger;
+        for step_hints in hints[GLOBAL_PARAMETERS..].chunks_mut(PARAMETERS_PER_STEP) {
+            step_hints[0] = ParameterHint::new_float(|h| h.default(defaults.value));
+            step_hints[1] = ParameterHint::boolean();
+            step_hints[2] =
+                ParameterHint::new_float(|h| h.minmax(0., 1.).default(defaults.probability));
+        }
+        hints
+    }
+
+    fn param_apply(&mut self, _ctx: &mut AudioCtx, index: usize, value: ParameterValue) {
+        match (index, value) {
+            (0, ParameterValue::Float(rate)) => self.rate = rate.max(0.),
+            (1, ParameterValue::Integer(clock)) => self.clock = SequencerClock::from(clock),
+            (2, ParameterValue::Integer(direction)) => {
+                self.direction = SequencerDirection::from(direction)
+            }
+            (3, ParameterValue::Integer(length)) => self.length = length.0,
+            (4, ParameterValue::Float(swing)) => self.swing = swing.clamp(0., 0.9),
+            (5, ParameterValue::Float(gate_length)) => self.gate_length = gate_length.clamp(0., 1.),
+            (6, ParameterValue::Trigger) => self.reset(),
+            (index, value) if index >= GLOBAL_PARAMETERS => {
+                let step = (index - GLOBAL_PARAMETERS) / PARAMETERS_PER_STEP;
+                if step >= Steps::USIZE {
+                    return;
+                }
+                let settings = &mut self.steps[step];
+                match ((index - GLOBAL_PARAMETERS) % PARAMETERS_PER_STEP, value) {
+                    (0, ParameterValue::Float(value)) => settings.value = value,
+                    (1, ParameterValue::Bool(gate)) => settings.gate = gate,
+                    (2, ParameterValue::Float(probability)) => {
+                        settings.probability = probability.clamp(0., 1.)
+                    }
+                    _ => (),
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+#[cfg(all(test, any(feature = "std", feature = "alloc")))]
+mod tests {
+    use super::*;
+    use knaster_core::{log::ArLogSender, typenum::U4};
+    use std::prelude::v1::*;
+
+    const SR: u32 = 1000;
+
+    /// Run the sequencer and return the value, gate and end of cycle outputs for every frame
+    fn run(
+        sequencer: &mut StepSequencer<f32, U4>,
+        ctx: &mut AudioCtx,
+        frames: usize,
+        clock: &[usize],
+    ) -> Vec<[f32; 3]> {
+        let mut flags = UGenFlags::new();
+        (0..frames)
+            .map(|i| {
+                let c = if clock.contains(&i) { 1.0 } else { 0.0 };
+                let out = UGen::process(sequencer, ctx, &mut flags, [c, 0.0].into());
+                [out[0], out[1], out[2]]
+            })
+            .collect()
+    }
+
+    fn new_sequencer() -> StepSequencer<f32, U4> {
+        let mut sequencer = StepSequencer::<f32, U4>::new().seed(1);
+        for step in 0..4 {
+            sequencer.set_step(step, step as PFloat, true, 1.);
+        }
+        sequencer.init(SR, 64);
+        sequencer
+    }
+
+    #[test]
+    fn internal_clock_and_directions() {
+        let mut ctx = AudioCtx::new(SR, 64, ArLogSender::non_rt());
+        // 125 steps per second at 1000 Hz is 8 frames per step
+        let mut sequencer = new_sequencer();
+        sequencer.param(&mut ctx, "rate", 125.).unwrap();
+        let output = run(&mut sequencer, &mut ctx, 72, &[]);
+        let values: Vec<f32> = output.iter().step_by(8).map(|f| f[0]).collect();
+        assert_eq!(values, vec![0., 1., 2., 3., 0., 1., 2., 3., 0.]);
+        let end_of_cycle: Vec<usize> = (0..72).filter(|&i| output[i][2] == 1.0).collect();
+        assert_eq!(end_of_cycle, vec![32, 64]);
+        // Gates are half a step long
+        let gate: Vec<f32> = output[..16].iter().map(|f| f[1]).collect();
+        assert_eq!(
+            gate,
+            vec![
+                1., 1., 1., 1., 0., 0., 0., 0., 1., 1., 1., 1., 0., 0., 0., 0.
+            ]
+        );
+
+        for (direction, expected) in [
+            (
+                SequencerDirection::Backward,
+                vec![3., 2., 1., 0., 3., 2., 1.],
+            ),
+            (
+                SequencerDirection::PingPong,
+                vec![0., 1., 2., 3., 2., 1., 0.],
+            ),
+        ] {
+            let mut sequencer = new_sequencer();
+            sequencer.param(&mut ctx, "rate", 125.).unwrap();
+            sequencer.param(&mut ctx, "direction", direction).unwrap();
+            let output = run(&mut sequencer, &mut ctx, 56, &[]);
+            let values: Vec<f32> = output.iter().step_by(8).map(|f| f[0]).collect();
+            assert_eq!(values, expected, "{direction:?}");
+        }
+    }
+
+    #[test]
+    fn external_clock_swing_and_probability() {
+        let mut ctx = AudioCtx::new(SR, 64, ArLogSender::non_rt());
+        let mut sequencer = new_sequencer();
+        sequencer
+            .param(&mut ctx, "clock", SequencerClock::External)
+            .unwrap();
+        sequencer.param(&mut ctx, "swing", 0.5).unwrap();
+        sequencer.param(&mut ctx, "gate_length", 1.0).unwrap();
+        sequencer.param(&mut ctx, "probability2", 0.0).unwrap();
+        let output = run(&mut sequencer, &mut ctx, 80, &[0, 20, 40, 60]);
+        let starts: Vec<usize> = (1..80)
+            .filter(|&i| output[i][0] != output[i - 1][0])
+            .collect();
+        // The first step has the same value as before it started. Every second step is delayed
+        // by half a step.
+        assert_eq!(starts, vec![30, 40, 70]);
+        // The gate of the first step is still open, so it closes for one sample before the
+        // second step. It stays closed for the third step.
+        assert_eq!(output[29][1], 1.0);
+        assert_eq!(output[30][1], 0.0);
+        assert_eq!(output[31][1], 1.0);
+        assert!(output[40..70].iter().all(|f| f[1] == 0.0));
+        assert_eq!(output[70][1], 1.0);
+    }
+}
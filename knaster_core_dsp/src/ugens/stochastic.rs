@@ -0,0 +1,558 @@
+//! # Stochastic
+//!
+//! Random control sources in the style of SuperCollider:
+//! - [`Dust`] and [`Dust2`]: random impulses at an average density
+//! - [`SampleAndHold`] and [`TrackAndHold`]: hold a signal using a trigger or gate
+//! - [`RandomWalk`]: a bounded Brownian random walk
+//! - [`RandomCubic`]: random values with cubic interpolation
+//! - [`WeightedChoice`]: chooses one of N values with different probabilities on every trigger
+//!
+//! All UGens producing randomness are seeded by their graph or using [`next_randomness_seed`]
+//! unless a seed is given using their `seed` method, see [`noise`](crate::noise) for more
+//! information.
+//!
+//! Triggers follow the [trigger convention](crate::trigger#trigger-convention).
+
+#[allow(unused)]
+use crate::noise::next_randomness_seed;
+use crate::noise::{SeededRng, random_bipolar, random_unipolar};
+use crate::trigger::TriggerDetector;
+use crate::ugens::param_names::collect_param_names;
+use knaster_core::{
+    AudioCtx, Float, Frame, PFloat, ParameterHint, ParameterValue, Size, UGen, UGenFlags,
+    impl_ugen,
+    numeric_array::NumericArray,
+    typenum::{Prod, U1, U2},
+};
+
+/// Random impulses between 0 and 1 at an average `density` of impulses per second
+pub struct Dust<F: Copy = f32> {
+    rng: SeededRng,
+    density: F,
+    /// The probability of an impulse in each sample
+    threshold: F,
+    sample_duration: F,
+}
+#[impl_ugen]
+impl<F: Float> Dust<F> {
+    #[allow(missing_docs)]
+    pub fn new(density: F) -> Self {
+        Self {
+            rng: SeededRng::new(),
+            density,
+            threshold: F::ZERO,
+            sample_duration: F::ZERO,
+        }
+    }
+    /// Use `seed` instead of a seed from the graph or [`next_randomness_seed`]
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.rng.set_seed(seed);
+        self
+    }
+    fn reseed(&mut self, seed: u64) {
+        self.rng.reseed(seed);
+    }
+    fn init(&mut self, sample_rate: u32, _block_size: usize) {
+        self.sample_duration = F::ONE / F::from(sample_rate).unwrap();
+        self.threshold = self.density * self.sample_duration;
+    }
+    /// The average number of impulses per second
+    #[param(kind = Frequency, default = 1.0, range = 0.0..=20000.0)]
+    pub fn density(&mut self, density: PFloat) {
+        self.density = F::new(density);
+        self.threshold = self.density * self.sample_duration;
+    }
+    #[allow(missing_docs)]
+    pub fn process(&mut self) -> [F; 1] {
+        let r = random_unipolar::<F>(&mut self.rng);
+        // Scaling the random number which was below the threshold gives a uniformly distributed
+        // amplitude without drawing another random number
+        if r < self.threshold {
+            [r / self.threshold]
+        } else {
+            [F::ZERO]
+        }
+    }
+}
+
+/// Random impulses between -1 and 1 at an average `density` of impulses per second
+pub struct Dust2<F: Copy = f32> {
+    dust: Dust<F>,
+}
+#[impl_ugen]
+impl<F: Float> Dust2<F> {
+    #[allow(missing_docs)]
+    pub fn new(density: F) -> Self {
+        Self {
+            dust: Dust::new(density),
+        }
+    }
+    /// Use `seed` instead of a seed from the graph or [`next_randomness_seed`]
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.dust = self.dust.seed(seed);
+        self
+    }
+    fn reseed(&mut self, seed: u64) {
+        self.dust.reseed(seed);
+    }
+    fn init(&mut self, sample_rate: u32, block_size: usize) {
+        self.dust.init(sample_rate, block_size);
+    }
+    /// The average number of impulses per second
+    #[param(kind = Frequency, default = 1.0, range = 0.0..=20000.0)]
+    pub fn density(&mut self, density: PFloat) {
+        self.dust.density(density);
+    }
+    #[allow(missing_docs)]
+    pub fn process(&mut self) -> [F; 1] {
+        let r = random_unipolar::<F>(&mut self.dust.rng);
+        if r < self.dust.threshold {
+            [r / self.dust.threshold * F::new(2.0) - F::ONE]
+        } else {
+            [F::ZERO]
+        }
+    }
+}
+
+/// Samples the first input when the second input is triggered and outputs it until the next
+/// trigger. Outputs 0 until the first trigger.
+pub struct SampleAndHold<F: Copy = f32> {
+    value: F,
+    trigger: TriggerDetector<F>,
+}
+#[impl_ugen]
+impl<F: Float> SampleAndHold<F> {
+    #[allow(clippy::new_without_default)]
+    #[allow(missing_docs)]
+    pub fn new() -> Self {
+        Self {
+            value: F::ZERO,
+            trigger: TriggerDetector::new(),
+        }
+    }
+    #[allow(missing_docs)]
+    pub fn process(&mut self, input: [F; 2]) -> [F; 1] {
+        let [signal, trigger] = input;
+        if self.trigger.detect(trigger) {
+            self.value = signal;
+        }
+        [self.value]
+    }
+}
+
+/// Outputs the first input while the second input is above zero, and holds the last value while
+/// it is zero or below. Outputs 0 until the gate first opens.
+pub struct TrackAndHold<F: Copy = f32> {
+    value: F,
+}
+#[impl_ugen]
+impl<F: Float> TrackAndHold<F> {
+    #[allow(clippy::new_without_default)]
+    #[allow(missing_docs)]
+    pub fn new() -> Self {
+        Self { value: F::ZERO }
+    }
+    #[allow(missing_docs)]
+    pub fn process(&mut self, input: [F; 2]) -> [F; 1] {
+        let [signal, gate] = input;
+        if gate > F::ZERO {
+            self.value = signal;
+        }
+        [self.value]
+    }
+}
+
+/// A bounded random walk. New values are chosen `freq` times per second by taking a random step
+/// of at most `step` from the previous value, reflecting off the `min` and `max` bounds. The
+/// output is linearly interpolated between the values.
+pub struct RandomWalk<F: Copy = f32> {
+    rng: SeededRng,
+    min: F,
+    max: F,
+    step: F,
+    from: F,
+    to: F,
+    // when phase reaches 1 we choose a new value
+    phase: F,
+    freq: F,
+    sample_duration: F,
+}
+#[impl_ugen]
+impl<F: Float> RandomWalk<F> {
+    /// Create a new [`RandomWalk`] between -1 and 1, starting at a random value
+    pub fn new(freq: F, step: F) -> Self {
+        let mut rng = SeededRng::new();
+        let start = random_bipolar(&mut rng);
+        Self {
+            rng,
+            min: -F::ONE,
+            max: F::ONE,
+            step,
+            from: start,
+            to: start,
+            phase: F::ZERO,
+            freq,
+            sample_duration: F::ZERO,
+        }
+    }
+    /// Use `seed` instead of a seed from the graph or [`next_randomness_seed`]
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.rng.set_seed(seed);
+        self.start_at_random_value();
+        self
+    }
+    fn reseed(&mut self, seed: u64) {
+        if self.rng.reseed(seed) {
+            self.start_at_random_value();
+        }
+    }
+    fn start_at_random_value(&mut self) {
+        let start = self.min + random_unipolar::<F>(&mut self.rng) * (self.max - self.min);
+        self.from = start;
+        self.to = start;
+    }
+    fn init(&mut self, sample_rate: u32, _block_size: usize) {
+        self.sample_duration = F::ONE / F::from(sample_rate).unwrap();
+        self.new_value();
+    }
+    /// The number of new values per second
+    #[param(kind = Frequency, default = 10.0, range = 0.0..=20000.0)]
+    pub fn freq(&mut self, freq: PFloat) {
+        self.freq = F::new(freq);
+    }
+    /// The largest step between two values
+    #[param(default = 0.1, range = 0.0..=1.0)]
+    pub fn step(&mut self, step: PFloat) {
+        self.step = F::new(step);
+    }
+    /// The lower bound
+    #[param(default = -1.0)]
+    pub fn min(&mut self, min: PFloat) {
+        self.min = F::new(min);
+    }
+    /// The upper bound
+    #[param(default = 1.0)]
+    pub fn max(&mut self, max: PFloat) {
+        self.max = F::new(max);
+    }
+    fn new_value(&mut self) {
+        self.from = self.to;
+        let mut to = self.from + random_bipolar::<F>(&mut self.rng) * self.step;
+        if to > self.max {
+            to = self.max * F::new(2.0) - to;
+        }
+        if to < self.min {
+            to = self.min * F::new(2.0) - to;
+        }
+        self.to = to.max(self.min).min(self.max);
+        self.phase = F::ZERO;
+    }
+    #[allow(missing_docs)]
+    pub fn process(&mut self) -> [F; 1] {
+        let out = self.from + (self.to - self.from) * self.phase;
+        self.phase += self.freq * self.sample_duration;
+        if self.phase >= F::ONE {
+            self.new_value();
+        }
+        [out]
+    }
+}
+
+/// Random numbers 0..1 with cubic interpolation with new values at some frequency. Smoother than
+/// [`RandomLin`](crate::noise::RandomLin).
+pub struct RandomCubic<F: Copy = f32> {
+    rng: SeededRng,
+    /// The values before, at the start of, at the end of and after the current segment
+    values: [F; 4],
+    // when phase reaches 1 we choose a new value
+    phase: F,
+    freq: F,
+    sample_duration: F,
+}
+#[impl_ugen]
+impl<F: Float> RandomCubic<F> {
+    #[allow(missing_docs)]
+    pub fn new(freq: F) -> Self {
+        let mut s = Self {
+            rng: SeededRng::new(),
+            values: [F::ZERO; 4],
+            phase: F::ZERO,
+            freq,
+            sample_duration: F::ZERO,
+        };
+        s.fill_values();
+        s
+    }
+    /// Use `seed` instead of a seed from the graph or [`next_randomness_seed`]
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.rng.set_seed(seed);
+        self.fill_values();
+        self
+    }
+    fn reseed(&mut self, seed: u64) {
+        if self.rng.reseed(seed) {
+            self.fill_values();
+        }
+    }
+    fn fill_values(&mut self) {
+        for value in &mut self.values {
+            *value = random_unipolar(&mut self.rng);
+        }
+    }
+    fn init(&mut self, sample_rate: u32, _block_size: usize) {
+        self.sample_duration = F::ONE / F::from(sample_rate).unwrap();
+    }
+    /// Set the frequency of producing new random numbers
+    #[param(kind = Frequency, default = 1.0, range = 0.0..=20000.0)]
+    pub fn freq(&mut self, freq: PFloat) {
+        self.freq = F::new(freq);
+    }
+    #[allow(missing_docs)]
+    pub fn process(&mut self) -> [F; 1] {
+        let [y0, y1, y2, y3] = self.values;
+        let t = self.phase;
+        let half = F::new(0.5);
+        // Catmull-Rom spline through the four values
+        let c1 = half * (y2 - y0);
+        let c2 = y0 - F::new(2.5) * y1 + F::new(2.0) * y2 - half * y3;
+        let c3 = half * (y3 - y0) + F::new(1.5) * (y1 - y2);
+        let out = ((c3 * t + c2) * t + c1) * t + y1;
+
+        self.phase += self.freq * self.sample_duration;
+        if self.phase >= F::ONE {
+            self.phase -= F::ONE;
+            self.values = [y1, y2, y3, random_unipolar(&mut self.rng)];
+        }
+        // The spline can overshoot slightly
+        [out.clamp(F::ZERO, F::ONE)]
+    }
+}
+
+/// The maximum number of choices supported by [`WeightedChoice`]
+pub const MAX_WEIGHTED_CHOICES: usize = 16;
+/// The number of parameters for each choice of a [`WeightedChoice`]
+const PARAMETERS_PER_CHOICE: usize = 2;
+const CHOICE_PARAM_NAMES: [[&str; PARAMETERS_PER_CHOICE]; MAX_WEIGHTED_CHOICES] = indexed_param_names!(
+    ["value", "weight"];
+    0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15
+);
+
+/// Chooses one of `Choices` values every time the input is triggered and outputs it until the
+/// next trigger. The probability of each value is its weight divided by the sum of all weights.
+///
+/// The parameters are `value{n}` and `weight{n}` for each choice, e.g. `value0` and `weight0`.
+/// By default, the values are the index of the choice and all weights are 1.
+///
+/// `Choices` can be at most [`MAX_WEIGHTED_CHOICES`].
+///
+/// ```
+/// use knaster_core_dsp::stochastic::WeightedChoice;
+/// use knaster_core::typenum::U3;
+/// // Choose a root, a fifth or an octave, mostly the root
+/// let mut choice = WeightedChoice::<f32, U3>::new();
+/// choice.set_choice(0, 0., 4.);
+/// choice.set_choice(1, 7., 1.);
+/// choice.set_choice(2, 12., 1.);
+/// ```
+pub struct WeightedChoice<F: Copy, Choices: Size> {
+    rng: SeededRng,
+    values: NumericArray<F, Choices>,
+    weights: NumericArray<F, Choices>,
+    value: F,
+    trigger: TriggerDetector<F>,
+}
+impl<F: Float, Choices: Size> WeightedChoice<F, Choices> {
+    /// New [`WeightedChoice`] choosing between the choice indices with equal weights
+    ///
+    /// # Panics
+    /// Panics if `Choices` is larger than [`MAX_WEIGHTED_CHOICES`]
+    pub fn new() -> Self {
+        assert!(
+            Choices::USIZE <= MAX_WEIGHTED_CHOICES,
+            "WeightedChoice supports at most {MAX_WEIGHTED_CHOICES} choices"
+        );
+        let mut s = Self {
+            rng: SeededRng::new(),
+            values: NumericArray::from_iter((0..Choices::USIZE).map(F::from_usize)),
+            weights: NumericArray::from_iter((0..Choices::USIZE).map(|_| F::ONE)),
+            value: F::ZERO,
+            trigger: TriggerDetector::new(),
+        };
+        s.choose();
+        s
+    }
+    /// Use `seed` instead of a seed from the graph or [`next_randomness_seed`]
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.rng.set_seed(seed);
+        self.choose();
+        self
+    }
+    /// Set the value and weight of a choice. Negative weights are treated as 0.
+    pub fn set_choice(&mut self, choice: usize, value: PFloat, weight: PFloat) {
+        self.values[choice] = F::new(value);
+        self.weights[choice] = F::new(weight.max(0.));
+    }
+    fn choose(&mut self) {
+        let total: F = self.weights.iter().copied().sum();
+        if total <= F::ZERO {
+            return;
+        }
+        let r = random_unipolar::<F>(&mut self.rng) * total;
+        let mut sum = F::ZERO;
+        for (&value, &weight) in self.values.iter().zip(self.weights.iter()) {
+            // Rounding errors can leave r slightly above the sum of all weights, so the last
+            // choice with a non zero weight is kept in that case
+            if weight > F::ZERO {
+                self.value = value;
+            }
+            sum += weight;
+            if r < sum {
+                break;
+            }
+        }
+    }
+}
+impl<F: Float, Choices: Size> Default for WeightedChoice<F, Choices> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl<F: Float, Choices: Size> UGen for WeightedChoice<F, Choices>
+where
+    Choices: core::ops::Mul<U2> + Send,
+    Prod<Choices, U2>: Size,
+{
+    type Sample = F;
+    type Inputs = U1;
+    type Outputs = U1;
+    type Parameters = Prod<Choices, U2>;
+
+    fn reseed(&mut self, seed: u64) {
+        if self.rng.reseed(seed) {
+            self.choose();
+        }
+    }
+    fn process(
+        &mut self,
+        _ctx: &mut AudioCtx,
+        _flags: &mut UGenFlags,
+        input: Frame<Self::Sample, Self::Inputs>,
+    ) -> Frame<Self::Sample, Self::Outputs> {
+        if self.trigger.detect(input[0]) {
+            self.choose();
+        }
+        [self.value].into()
+    }
+
+    fn param_descriptions() -> NumericArray<&'static str, Self::Parameters> {
+        collect_param_names(CHOICE_PARAM_NAMES.as_flattened().iter().copied())
+    }
+
+    fn param_hints() -> NumericArray<ParameterHint, Self::Parameters> {
+        let mut hints: NumericArray<ParameterHint, Self::Parameters> = NumericArray::default();
+        for (i, choice_hints) in hints.chunks_mut(PARAMETERS_PER_CHOICE).enumerate() {
+            choice_hints[0] = ParameterHint::new_float(|h| h.default(i as PFloat));
+            choice_hints[1] = ParameterHint::new_float(|h| h.minmax(0., 1000.).default(1.));
+        }
+        hints
+    }
+
+    fn param_apply(&mut self, _ctx: &mut AudioCtx, index: usize, value: ParameterValue) {
+        let ParameterValue::Float(value) = value else {
+            return;
+        };
+        let choice = index / PARAMETERS_PER_CHOICE;
+        if choice >= Choices::USIZE {
+            return;
+        }
+        if index.is_multiple_of(PARAMETERS_PER_CHOICE) {
+            self.values[choice] = F::new(value);
+        } else {
+            self.weights[choice] = F::new(value.max(0.));
+        }
+    }
+}
+
+#[cfg(all(test, any(feature = "std", feature = "alloc")))]
+mod tests {
+    use super::*;
+    use knaster_core::log::ArLogSender;
+    use std::prelude::v1::*;
+
+    const SR: u32 = 48000;
+
+    #[test]
+    fn dust_density() {
+        let mut ctx = AudioCtx::new(SR, 64, ArLogSender::non_rt());
+        let mut flags = UGenFlags::new();
+        let mut dust = Dust::<f64>::new(100.).seed(1);
+        dust.init(SR, 64);
+        let impulses: Vec<f64> = (0..SR * 10)
+            .map(|_| UGen::process(&mut dust, &mut ctx, &mut flags, [].into())[0])
+            .filter(|x| *x != 0.0)
+            .collect();
+        assert!((900..1100).contains(&impulses.len()), "{}", impulses.len());
+        assert!(impulses.iter().all(|x| *x > 0.0 && *x <= 1.0));
+        let mean = impulses.iter().sum::<f64>() / impulses.len() as f64;
+        assert!((mean - 0.5).abs() < 0.05, "{mean}");
+    }
+
+    #[test]
+    fn sample_and_hold() {
+        let mut ctx = AudioCtx::new(SR, 64, ArLogSender::non_rt());
+        let mut flags = UGenFlags::new();
+        let mut sh = SampleAndHold::<f32>::new();
+        let mut th = TrackAndHold::<f32>::new();
+        let input = [(1., 0.), (2., 1.), (3., 1.), (4., 0.), (5., 1.), (6., 0.)];
+        let sampled: Vec<f32> = input
+            .iter()
+            .map(|&(x, t)| UGen::process(&mut sh, &mut ctx, &mut flags, [x, t].into())[0])
+            .collect();
+        let tracked: Vec<f32> = input
+            .iter()
+            .map(|&(x, t)| UGen::process(&mut th, &mut ctx, &mut flags, [x, t].into())[0])
+            .collect();
+        assert_eq!(sampled, vec![0., 2., 2., 2., 5., 5.]);
+        assert_eq!(tracked, vec![0., 2., 3., 3., 5., 5.]);
+    }
+
+    #[test]
+    fn random_walk_stays_within_bounds() {
+        let mut ctx = AudioCtx::new(SR, 64, ArLogSender::non_rt());
+        let mut flags = UGenFlags::new();
+        let mut walk = RandomWalk::<f32>::new(1000., 0.5);
+        walk.param(&mut ctx, "min", 0.0).unwrap();
+        walk.param(&mut ctx, "max", 0.5).unwrap();
+        walk.init(SR, 64);
+        let output: Vec<f32> = (0..SR)
+            .map(|_| UGen::process(&mut walk, &mut ctx, &mut flags, [].into())[0])
+            .collect();
+        // The walk starts at a random value between -1 and 1 and reaches the bounds after the
+        // first step, 48 frames at 1000 Hz
+        assert!(output[48..].iter().all(|x| (0.0..=0.5).contains(x)));
+        assert!(output.windows(2).any(|w| w[0] != w[1]));
+    }
+
+    #[test]
+    fn weighted_choice() {
+        let mut ctx = AudioCtx::new(SR, 64, ArLogSender::non_rt());
+        let mut flags = UGenFlags::new();
+        let mut choice = WeightedChoice::<f64, U2>::new().seed(3);
+        choice.param(&mut ctx, "value0", 10.).unwrap();
+        choice.param(&mut ctx, "weight0", 3.).unwrap();
+        choice.set_choice(1, 20., 1.);
+        let mut counts = [0; 2];
+        for _ in 0..10000 {
+            UGen::process(&mut choice, &mut ctx, &mut flags, [0.].into());
+            let value = UGen::process(&mut choice, &mut ctx, &mut flags, [1.].into())[0];
+            counts[if value == 10. { 0 } else { 1 }] += 1;
+        }
+        assert!((7200..7800).contains(&counts[0]), "{counts:?}");
+        choice.set_choice(0, 10., 0.);
+        UGen::process(&mut choice, &mut ctx, &mut flags, [0.].into());
+        assert_eq!(
+            UGen::process(&mut choice, &mut ctx, &mut flags, [1.].into())[0],
+            20.
+        );
+    }
+}
@@ -10,6 +10,8 @@ use knaster_core::{
 };
 use std::prelude::v1::*;
 
+use crate::dsp::response::{Complex, FrequencyResponse, prewarped_s};
+
 /// Different supported filter types
 #[derive(
     Clone, Copy, Debug, FromPrimitive, ToPrimitive, PartialEq, Default, KnasterIntegerParameter,
@@ -46,16 +48,186 @@ pub struct SvfFilter<F: Copy> {
     cutoff_freq: F,
     q: F,
     gain_db: F,
-    // state
+    state: SvfState<F>,
+    coefficients: SvfCoefficients<F>,
+    /// Coefficients derived from `coefficients`
+    a: [F; 3],
+}
+
+/// The parameters of the SVF which determine the filter response: the prewarped cutoff `g`,
+/// damping `k` and the output mix `m0`, `m1`, `m2`.
+///
+/// Interpolating between two sets of coefficients always results in a stable filter, which
+/// makes them suitable for smoothing parameter changes.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct SvfCoefficients<T> {
+    #[allow(missing_docs)]
+    pub g: T,
+    #[allow(missing_docs)]
+    pub k: T,
+    #[allow(missing_docs)]
+    pub m0: T,
+    #[allow(missing_docs)]
+    pub m1: T,
+    #[allow(missing_docs)]
+    pub m2: T,
+}
+impl<T: Float> SvfCoefficients<T> {
+    /// Calculate the coefficients for a filter type. `gain_db` is only used for Bell, HighShelf
+    /// and LowShelf.
+    pub fn new(ty: SvfFilterType, cutoff: T, q: T, gain_db: T, sample_rate: T) -> Self {
+        let g = ((T::PI * cutoff) / sample_rate).tan();
+        let k = T::ONE / q;
+        match ty {
+            SvfFilterType::Low => Self {
+                g,
+                k,
+                m0: T::ZERO,
+                m1: T::ZERO,
+                m2: T::ONE,
+            },
+            SvfFilterType::Band => Self {
+                g,
+                k,
+                m0: T::ZERO,
+                m1: T::ONE,
+                m2: T::ZERO,
+            },
+            SvfFilterType::High => Self {
+                g,
+                k,
+                m0: T::ONE,
+                m1: -k,
+                m2: -T::ONE,
+            },
+            SvfFilterType::Notch => Self {
+                g,
+                k,
+                m0: T::ONE,
+                m1: -k,
+                m2: T::ZERO,
+            },
+            SvfFilterType::Peak => Self {
+                g,
+                k,
+                m0: T::ONE,
+                m1: -k,
+                m2: -T::new(2.),
+            },
+            SvfFilterType::All => Self {
+                g,
+                k,
+                m0: T::ONE,
+                m1: -T::new(2.) * k,
+                m2: T::ZERO,
+            },
+            SvfFilterType::Bell => {
+                let amp = T::new(10.0).powf(gain_db / T::new(40.));
+                let k = T::ONE / (q * amp);
+                Self {
+                    g,
+                    k,
+                    m0: T::ONE,
+                    m1: k * (amp * amp - T::ONE),
+                    m2: T::ZERO,
+                }
+            }
+            SvfFilterType::LowShelf => {
+                let amp = T::new(10.0).powf(gain_db / T::new(40.));
+                Self {
+                    g: g / amp.sqrt(),
+                    k,
+                    m0: T::ONE,
+                    m1: k * (amp - T::ONE),
+                    m2: amp * amp - T::ONE,
+                }
+            }
+            SvfFilterType::HighShelf => {
+                let amp = T::new(10.0).powf(gain_db / T::new(40.));
+                Self {
+                    g: g * amp.sqrt(),
+                    k,
+                    m0: amp * amp,
+                    m1: k * (T::ONE - amp) * amp,
+                    m2: T::ONE - amp * amp,
+                }
+            }
+        }
+    }
+    /// The same filter with the output mix set to pass the input through unchanged
+    pub fn bypassed(self) -> Self {
+        Self {
+            m0: T::ONE,
+            m1: T::ZERO,
+            m2: T::ZERO,
+            ..self
+        }
+    }
+    /// The coefficients `a1`, `a2`, `a3` used when processing
+    #[inline]
+    pub fn a(&self) -> [T; 3] {
+        let a1 = T::ONE / (T::ONE + self.g * (self.g + self.k));
+        let a2 = self.g * a1;
+        [a1, a2, self.g * a2]
+    }
+    /// The response of the SVF at the prewarped analog frequency `s`
+    fn response(&self, s: Complex<T>) -> Complex<T> {
+        // v1 = s / d and v2 = 1 / d are the band and low outputs of the SVF
+        let d = s * s + s * self.k + T::ONE;
+        (d * self.m0 + s * self.m1 + self.m2) / d
+    }
+}
+
+/// The state of one channel of an SVF. The same coefficients can be used to process several
+/// channels, each with their own state.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SvfState<F> {
     ic1eq: F,
     ic2eq: F,
-    // coefficients
-    a1: F,
-    a2: F,
-    a3: F,
-    m0: F,
-    m1: F,
-    m2: F,
+}
+impl<F: Float> SvfState<F> {
+    #[allow(missing_docs)]
+    pub fn new() -> Self {
+        Self {
+            ic1eq: F::ZERO,
+            ic2eq: F::ZERO,
+        }
+    }
+    /// Clear the state
+    pub fn reset(&mut self) {
+        self.ic1eq = F::ZERO;
+        self.ic2eq = F::ZERO;
+    }
+    /// Process one sample where `a` is from [`SvfCoefficients::a`]
+    #[inline]
+    pub fn process_sample(&mut self, a: [F; 3], coefficients: &SvfCoefficients<F>, v0: F) -> F {
+        let SvfState { ic1eq, ic2eq } = self;
+        let [a1, a2, a3] = a;
+        let SvfCoefficients { m0, m1, m2, .. } = *coefficients;
+
+        #[cfg(feature = "no_denormals")]
+        unsafe {
+            no_denormals::no_denormals(|| {
+                let v3 = v0 - *ic2eq;
+                let v1 = a1 * *ic1eq + a2 * v3;
+                let v2 = *ic2eq + a2 * *ic1eq + a3 * v3;
+                *ic1eq = F::new(2.) * v1 - *ic1eq;
+                *ic2eq = F::new(2.) * v2 - *ic2eq;
+
+                m0 * v0 + m1 * v1 + m2 * v2
+            })
+        }
+        #[cfg(not(feature = "no_denormals"))]
+        {
+            let v3 = v0 - *ic2eq;
+            let v1 = a1 * *ic1eq + a2 * v3;
+            let v2 = *ic2eq + a2 * *ic1eq + a3 * v3;
+            *ic1eq = F::new(2.) * v1 - *ic1eq;
+            *ic2eq = F::new(2.) * v2 - *ic2eq;
+
+            m0 * v0 + m1 * v1 + m2 * v2
+        }
+    }
 }
 
 #[impl_ugen]
@@ -63,14 +235,9 @@ impl<F: Float> SvfFilter<F> {
     #[allow(missing_docs)]
     pub fn new(ty: SvfFilterType, cutoff_freq: F, q: F, gain_db: F) -> Self {
         Self {
-            ic1eq: F::ZERO,
-            ic2eq: F::ZERO,
-            a1: F::ZERO,
-            a2: F::ZERO,
-            a3: F::ZERO,
-            m0: F::ZERO,
-            m1: F::ZERO,
-            m2: F::ZERO,
+            state: SvfState::new(),
+            coefficients: SvfCoefficients::default(),
+            a: [F::ZERO; 3],
             ty,
             cutoff_freq,
             q,
@@ -144,138 +311,28 @@ impl<F: Float> SvfFilter<F> {
     }
     /// Set the coefficients for the currently set filter type. `gain_db` is only used for Bell, HighShelf and LowShelf.
     pub fn set_coeffs(&mut self, cutoff: F, q: F, gain_db: F, sample_rate: F) {
-        match self.ty {
-            SvfFilterType::Low => {
-                let g = ((F::PI * cutoff) / sample_rate).tan();
-                let k = F::ONE / q;
-                self.a1 = F::ONE / (F::ONE + g * (g + k));
-                self.a2 = g * self.a1;
-                self.a3 = g * self.a2;
-                self.m0 = F::ZERO;
-                self.m1 = F::ZERO;
-                self.m2 = F::ONE;
-            }
-            SvfFilterType::Band => {
-                let g = ((F::PI * cutoff) / sample_rate).tan();
-                let k = F::ONE / q;
-                self.a1 = F::ONE / (F::ONE + g * (g + k));
-                self.a2 = g * self.a1;
-                self.a3 = g * self.a2;
-                self.m0 = F::ZERO;
-                self.m1 = F::ONE;
-                self.m2 = F::ZERO;
-            }
-            SvfFilterType::High => {
-                let g = ((F::PI * cutoff) / sample_rate).tan();
-                let k = F::ONE / q;
-                self.a1 = F::ONE / (F::ONE + g * (g + k));
-                self.a2 = g * self.a1;
-                self.a3 = g * self.a2;
-                self.m0 = F::ONE;
-                self.m1 = -k;
-                self.m2 = -F::ONE;
-            }
-            SvfFilterType::Notch => {
-                let g = ((F::PI * cutoff) / sample_rate).tan();
-                let k = F::ONE / q;
-                self.a1 = F::ONE / (F::ONE + g * (g + k));
-                self.a2 = g * self.a1;
-                self.a3 = g * self.a2;
-                self.m0 = F::ONE;
-                self.m1 = -k;
-                self.m2 = F::ZERO;
-            }
-            SvfFilterType::Peak => {
-                let g = ((F::PI * cutoff) / sample_rate).tan();
-                let k = F::ONE / q;
-                self.a1 = F::ONE / (F::ONE + g * (g + k));
-                self.a2 = g * self.a1;
-                self.a3 = g * self.a2;
-                self.m0 = F::ONE;
-                self.m1 = -k;
-                self.m2 = -F::new(2.);
-            }
-            SvfFilterType::All => {
-                let g = ((F::PI * cutoff) / sample_rate).tan();
-                let k = F::ONE / q;
-                self.a1 = F::ONE / (F::ONE + g * (g + k));
-                self.a2 = g * self.a1;
-                self.a3 = g * self.a2;
-                self.m0 = F::ONE;
-                self.m1 = -F::new(2.) * k;
-                self.m2 = F::ZERO;
-            }
-            SvfFilterType::Bell => {
-                let amp = F::new(10.0).powf(gain_db / F::new(40.));
-                let g = ((F::PI * cutoff) / sample_rate).tan() / amp.sqrt();
-                let k = F::ONE / (q * amp);
-                self.a1 = F::ONE / (F::ONE + g * (g + k));
-                self.a2 = g * self.a1;
-                self.a3 = g * self.a2;
-                self.m0 = F::ONE;
-                self.m1 = k * (amp * amp - F::ONE);
-                self.m2 = F::ZERO;
-            }
-            SvfFilterType::LowShelf => {
-                let amp = F::new(10.0).powf(gain_db / F::new(40.));
-                let g = ((F::PI * cutoff) / sample_rate).tan() / amp.sqrt();
-                let k = F::ONE / q;
-                self.a1 = F::ONE / (F::ONE + g * (g + k));
-                self.a2 = g * self.a1;
-                self.a3 = g * self.a2;
-                self.m0 = F::ONE;
-                self.m1 = k * (amp - F::ONE);
-                self.m2 = amp * amp - F::ONE;
-            }
-            SvfFilterType::HighShelf => {
-                let amp = F::new(10.0).powf(gain_db / F::new(40.));
-                let g = ((F::PI * cutoff) / sample_rate).tan() * amp.sqrt();
-                let k = F::ONE / q;
-                self.a1 = F::ONE / (F::ONE + g * (g + k));
-                self.a2 = g * self.a1;
-                self.a3 = g * self.a2;
-                self.m0 = amp * amp;
-                self.m1 = k * (F::ONE - amp) * amp;
-                self.m2 = F::ONE - amp * amp;
-            }
-        }
+        self.coefficients = SvfCoefficients::new(self.ty, cutoff, q, gain_db, sample_rate);
+        self.a = self.coefficients.a();
+    }
+    /// Clear the internal state of the filter
+    pub fn reset(&mut self) {
+        self.state.reset();
     }
-    // TODO: This is vectorisable such that multiple filters can be run at once, e.g. multiple channels with the same coefficients
     #[allow(missing_docs)]
     pub fn process_sample(&mut self, v0: F) -> F {
-        let SvfFilter {
-            ic1eq,
-            ic2eq,
-            a1,
-            a2,
-            a3,
-            m0,
-            m1,
-            m2,
-            ..
-        } = self;
-
-        #[cfg(feature = "no_denormals")]
-        unsafe {
-            no_denormals::no_denormals(|| {
-                let v3 = v0 - *ic2eq;
-                let v1 = *a1 * *ic1eq + *a2 * v3;
-                let v2 = *ic2eq + *a2 * *ic1eq + *a3 * v3;
-                *ic1eq = F::new(2.) * v1 - *ic1eq;
-                *ic2eq = F::new(2.) * v2 - *ic2eq;
-
-                *m0 * v0 + *m1 * v1 + *m2 * v2
-            })
-        }
-        #[cfg(not(feature = "no_denormals"))]
-        {
-            let v3 = v0 - *ic2eq;
-            let v1 = *a1 * *ic1eq + *a2 * v3;
-            let v2 = *ic2eq + *a2 * *ic1eq + *a3 * v3;
-            *ic1eq = F::new(2.) * v1 - *ic1eq;
-            *ic2eq = F::new(2.) * v2 - *ic2eq;
+        self.state.process_sample(self.a, &self.coefficients, v0)
+    }
+}
 
-            *m0 * v0 + *m1 * v1 + *m2 * v2
-        }
+impl<F: Float> FrequencyResponse for SvfFilter<F> {
+    fn frequency_response(&self, freq: f64, sample_rate: f64) -> Complex<f64> {
+        let coefficients = SvfCoefficients::new(
+            self.ty,
+            self.cutoff_freq.to_f64(),
+            self.q.to_f64(),
+            self.gain_db.to_f64(),
+            sample_rate,
+        );
+        coefficients.response(prewarped_s(freq, coefficients.g, sample_rate))
     }
 }
@@ -0,0 +1,411 @@
+//! # Tempo
+//!
+//! UGens locked to a musical tempo:
+//! - [`TempoClock`]: beat and bar phases and triggers at a tempo
+//! - [`TempoLfo`]: an LFO with its rate set as a [`NoteDivision`] of a tempo
+//!
+//! Graphs have no tempo of their own, so the tempo is set using the `bpm` parameter of each UGen.
+//! Set the same `bpm` on all tempo synced UGens, or use the triggers from a [`TempoClock`] to
+//! retrigger [`TempoLfo`]s so that they stay in phase.
+//!
+//! A beat is a quarter note. Triggers follow the
+//! [trigger convention](crate::trigger#trigger-convention).
+
+#[allow(unused)]
+use crate::noise::next_randomness_seed;
+use crate::noise::{SeededRng, random_bipolar};
+use crate::trigger::TriggerDetector;
+use knaster_core::{
+    Float, KnasterIntegerParameter, PFloat, PInteger, impl_ugen,
+    num_derive::{FromPrimitive, ToPrimitive},
+    num_traits,
+};
+
+// Works without std, unlike the inherent f64 methods
+fn floor(x: f64) -> f64 {
+    num_traits::Float::floor(x)
+}
+fn fract(x: f64) -> f64 {
+    x - floor(x)
+}
+
+/// A note value as a duration relative to a tempo
+#[derive(
+    Clone, Copy, Debug, FromPrimitive, ToPrimitive, PartialEq, Default, KnasterIntegerParameter,
+)]
+#[num_traits = "num_traits"]
+#[repr(u8)]
+pub enum NoteDivision {
+    /// Two whole notes, 8 beats
+    DoubleWhole = 0,
+    /// Dotted whole note, 6 beats
+    WholeDotted,
+    /// Whole note, 4 beats
+    Whole,
+    /// Whole note triplet, 8/3 beats
+    WholeTriplet,
+    /// Dotted half note, 3 beats
+    HalfDotted,
+    /// Half note, 2 beats
+    Half,
+    /// Half note triplet, 4/3 beats
+    HalfTriplet,
+    /// Dotted quarter note, 3/2 beats
+    QuarterDotted,
+    /// Quarter note, 1 beat
+    #[default]
+    Quarter,
+    /// Quarter note triplet, 2/3 beats
+    QuarterTriplet,
+    /// Dotted eighth note, 3/4 beats
+    EighthDotted,
+    /// Eighth note, 1/2 beat
+    Eighth,
+    /// Eighth note triplet, 1/3 beat
+    EighthTriplet,
+    /// Dotted sixteenth note, 3/8 beats
+    SixteenthDotted,
+    /// Sixteenth note, 1/4 beat
+    Sixteenth,
+    /// Sixteenth note triplet, 1/6 beat
+    SixteenthTriplet,
+    /// Thirty-second note, 1/8 beat
+    ThirtySecond,
+    /// Thirty-second note triplet, 1/12 beat
+    ThirtySecondTriplet,
+}
+impl NoteDivision {
+    /// The duration in beats, where a beat is a quarter note
+    pub fn beats(self) -> f64 {
+        match self {
+            NoteDivision::DoubleWhole => 8.,
+            NoteDivision::WholeDotted => 6.,
+            NoteDivision::Whole => 4.,
+            NoteDivision::WholeTriplet => 8. / 3.,
+            NoteDivision::HalfDotted => 3.,
+            NoteDivision::Half => 2.,
+            NoteDivision::HalfTriplet => 4. / 3.,
+            NoteDivision::QuarterDotted => 1.5,
+            NoteDivision::Quarter => 1.,
+            NoteDivision::QuarterTriplet => 2. / 3.,
+            NoteDivision::EighthDotted => 0.75,
+            NoteDivision::Eighth => 0.5,
+            NoteDivision::EighthTriplet => 1. / 3.,
+            NoteDivision::SixteenthDotted => 0.375,
+            NoteDivision::Sixteenth => 0.25,
+            NoteDivision::SixteenthTriplet => 1. / 6.,
+            NoteDivision::ThirtySecond => 0.125,
+            NoteDivision::ThirtySecondTriplet => 1. / 12.,
+        }
+    }
+    /// The duration in seconds at `bpm` beats per minute
+    pub fn seconds(self, bpm: f64) -> f64 {
+        self.beats() * 60. / bpm
+    }
+}
+
+/// Outputs the beat phase, the bar phase, a beat trigger and a bar trigger at `bpm` beats per
+/// minute.
+///
+/// The phases ramp from 0 to 1 over each beat and bar. The triggers fire on the first sample of
+/// every beat and bar, starting with the first sample processed.
+pub struct TempoClock<F: Copy = f32> {
+    /// The position in beats since the start of the current bar
+    position: f64,
+    bpm: f64,
+    beats_per_bar: u32,
+    sample_rate: u32,
+    /// Output a beat trigger on the next sample
+    beat_trigger: bool,
+    /// Output a bar trigger on the next sample
+    bar_trigger: bool,
+    _phantom: crate::core::marker::PhantomData<F>,
+}
+#[impl_ugen]
+impl<F: Float> TempoClock<F> {
+    #[allow(missing_docs)]
+    pub fn new(bpm: f64) -> Self {
+        Self {
+            position: 0.,
+            bpm,
+            beats_per_bar: 4,
+            sample_rate: 0,
+            beat_trigger: true,
+            bar_trigger: true,
+            _phantom: crate::core::marker::PhantomData,
+        }
+    }
+    fn init(&mut self, sample_rate: u32, _block_size: usize) {
+        self.sample_rate = sample_rate;
+    }
+    /// The tempo in beats per minute
+    #[param(default = 120.0, range = 1.0..=999.0)]
+    pub fn bpm(&mut self, bpm: PFloat) {
+        self.bpm = bpm.max(0.);
+    }
+    /// The number of beats in a bar
+    #[param(default = 4)]
+    pub fn beats_per_bar(&mut self, beats_per_bar: PInteger) {
+        self.beats_per_bar = (beats_per_bar.0 as u32).max(1);
+        if self.position >= self.beats_per_bar as f64 {
+            self.position = fract(self.position);
+        }
+    }
+    /// Restart from the beginning of a bar on the next sample
+    #[param]
+    pub fn t_reset(&mut self) {
+        self.position = 0.;
+        self.beat_trigger = true;
+        self.bar_trigger = true;
+    }
+    #[allow(missing_docs)]
+    pub fn process(&mut self) -> [F; 4] {
+        let beats_per_bar = self.beats_per_bar as f64;
+        let out = [
+            F::new(fract(self.position)),
+            F::new(self.position / beats_per_bar),
+            if self.beat_trigger { F::ONE } else { F::ZERO },
+            if self.bar_trigger { F::ONE } else { F::ZERO },
+        ];
+        self.beat_trigger = false;
+        self.bar_trigger = false;
+        if self.sample_rate == 0 {
+            return out;
+        }
+        let beat = floor(self.position);
+        self.position += self.bpm / 60. / self.sample_rate as f64;
+        if self.position >= beats_per_bar {
+            self.position -= beats_per_bar;
+            // Don't accumulate bars if the tempo is extremely fast
+            if self.position >= beats_per_bar {
+                self.position = 0.;
+            }
+            self.bar_trigger = true;
+            self.beat_trigger = true;
+        } else if floor(self.position) != beat {
+            self.beat_trigger = true;
+        }
+        out
+    }
+}
+
+/// The waveform of a [`TempoLfo`]
+#[derive(
+    Clone, Copy, Debug, FromPrimitive, ToPrimitive, PartialEq, Default, KnasterIntegerParameter,
+)]
+#[num_traits = "num_traits"]
+#[repr(u8)]
+pub enum LfoShape {
+    /// Sine wave starting at 0 going up
+    #[default]
+    Sine = 0,
+    /// Triangle wave starting at 0 going up
+    Triangle,
+    /// Rising saw wave from -1 to 1
+    Saw,
+    /// Square wave, 1 for the first half of the period and -1 for the second
+    Square,
+    /// A new random value between -1 and 1 every period
+    SampleAndHold,
+}
+
+/// A bipolar LFO with its period set as a [`NoteDivision`] at `bpm` beats per minute.
+///
+/// A trigger in the input or the `t_retrigger` parameter restarts the period. `phase_offset`
+/// shifts the waveform by a fraction of the period.
+pub struct TempoLfo<F: Copy = f32> {
+    rng: SeededRng,
+    phase: f64,
+    phase_offset: f64,
+    bpm: f64,
+    division: NoteDivision,
+    shape: LfoShape,
+    sample_rate: u32,
+    /// The phase increment per sample
+    step: f64,
+    retrigger: TriggerDetector<F>,
+    /// The current value of the [`LfoShape::SampleAndHold`] shape
+    held: F,
+}
+#[impl_ugen]
+impl<F: Float> TempoLfo<F> {
+    #[allow(missing_docs)]
+    pub fn new(bpm: f64, division: NoteDivision, shape: LfoShape) -> Self {
+        let mut rng = SeededRng::new();
+        let held = random_bipolar(&mut rng);
+        Self {
+            rng,
+            phase: 0.,
+            phase_offset: 0.,
+            bpm,
+            division,
+            shape,
+            sample_rate: 0,
+            step: 0.,
+            retrigger: TriggerDetector::new(),
+            held,
+        }
+    }
+    /// Use `seed` instead of a seed from the graph or [`next_randomness_seed`]
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.rng.set_seed(seed);
+        self.held = random_bipolar(&mut self.rng);
+        self
+    }
+    fn reseed(&mut self, seed: u64) {
+        if self.rng.reseed(seed) {
+            self.held = random_bipolar(&mut self.rng);
+        }
+    }
+    fn init(&mut self, sample_rate: u32, _block_size: usize) {
+        self.sample_rate = sample_rate;
+        self.update_step();
+    }
+    fn update_step(&mut self) {
+        self.step = if self.sample_rate > 0 {
+            1. / (self.division.seconds(self.bpm) * self.sample_rate as f64)
+        } else {
+            0.
+        };
+    }
+    /// The tempo in beats per minute
+    #[param(default = 120.0, range = 1.0..=999.0)]
+    pub fn bpm(&mut self, bpm: PFloat) {
+        self.bpm = bpm.max(1e-3);
+        self.update_step();
+    }
+    /// The period of the LFO
+    #[param(from = NoteDivision)]
+    pub fn division(&mut self, division: PInteger) {
+        self.division = NoteDivision::from(division);
+        self.update_step();
+    }
+    /// The waveform
+    #[param(from = LfoShape)]
+    pub fn shape(&mut self, shape: PInteger) {
+        self.shape = LfoShape::from(shape);
+    }
+    /// Shift the waveform by a fraction of the period
+    #[param(default = 0.0, range = 0.0..=1.0)]
+    pub fn phase_offset(&mut self, phase_offset: PFloat) {
+        self.phase_offset = fract(phase_offset);
+    }
+    /// Restart the period on the next sample
+    #[param]
+    pub fn t_retrigger(&mut self) {
+        self.restart();
+    }
+    fn restart(&mut self) {
+        self.phase = 0.;
+        self.held = random_bipolar(&mut self.rng);
+    }
+    #[allow(missing_docs)]
+    pub fn process(&mut self, input: [F; 1]) -> [F; 1] {
+        if self.retrigger.detect(input[0]) {
+            self.restart();
+        }
+        let p = fract(self.phase + self.phase_offset);
+        let out = match self.shape {
+            LfoShape::Sine => F::new(num_traits::Float::sin(core::f64::consts::TAU * p)),
+            LfoShape::Triangle => F::new(if p < 0.25 {
+                4. * p
+            } else if p < 0.75 {
+                2. - 4. * p
+            } else {
+                4. * p - 4.
+            }),
+            LfoShape::Saw => F::new(2. * p - 1.),
+            LfoShape::Square => {
+                if p < 0.5 {
+                    F::ONE
+                } else {
+                    -F::ONE
+                }
+            }
+            LfoShape::SampleAndHold => self.held,
+        };
+        self.phase += self.step;
+        if self.phase >= 1. {
+            self.phase = fract(self.phase);
+            self.held = random_bipolar(&mut self.rng);
+        }
+        [out]
+    }
+}
+
+#[cfg(all(test, any(feature = "std", feature = "alloc")))]
+mod tests {
+    use super::*;
+    use knaster_core::{AudioCtx, UGen, UGenFlags, log::ArLogSender};
+    use std::prelude::v1::*;
+
+    // At 60 bpm a beat is 1024 frames, which keeps the phase increments exact
+    const SR: u32 = 1024;
+
+    #[test]
+    fn clock() {
+        let mut ctx = AudioCtx::new(SR, 64, ArLogSender::non_rt());
+        let mut flags = UGenFlags::new();
+        let mut clock = TempoClock::<f64>::new(60.);
+        clock.init(SR, 64);
+        let output: Vec<[f64; 4]> = (0..SR * 5)
+            .map(|_| {
+                let out = UGen::process(&mut clock, &mut ctx, &mut flags, [].into());
+                [out[0], out[1], out[2], out[3]]
+            })
+            .collect();
+        let triggers = |channel: usize| -> Vec<usize> {
+            (0..output.len())
+                .filter(|&i| output[i][channel] == 1.0)
+                .collect()
+        };
+        assert_eq!(triggers(2), vec![0, 1024, 2048, 3072, 4096]);
+        assert_eq!(triggers(3), vec![0, 4096]);
+        assert_eq!(output[512][0], 0.5);
+        assert_eq!(output[512][1], 0.125);
+        assert_eq!(output[3072 + 512][1], 0.875);
+    }
+
+    fn run(lfo: &mut TempoLfo<f64>, ctx: &mut AudioCtx, retrigger_at: Option<usize>) -> Vec<f64> {
+        let mut flags = UGenFlags::new();
+        (0..SR as usize)
+            .map(|i| {
+                let trigger = if Some(i) == retrigger_at { 1.0 } else { 0.0 };
+                UGen::process(lfo, ctx, &mut flags, [trigger].into())[0]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn lfo() {
+        let mut ctx = AudioCtx::new(SR, 64, ArLogSender::non_rt());
+        assert_eq!(NoteDivision::EighthTriplet.beats() * 3., 1.);
+        assert_eq!(NoteDivision::SixteenthDotted.seconds(120.), 0.1875);
+
+        let mut lfo = TempoLfo::<f64>::new(60., NoteDivision::Eighth, LfoShape::Saw);
+        lfo.init(SR, 64);
+        let output = run(&mut lfo, &mut ctx, None);
+        assert_eq!(output[0], -1.0);
+        assert_eq!(output[256], 0.0);
+        assert_eq!(output[512], -1.0);
+        let output = run(&mut lfo, &mut ctx, Some(100));
+        assert_eq!(output[100], -1.0);
+        assert_eq!(output[356], 0.0);
+
+        let mut lfo = TempoLfo::<f64>::new(60., NoteDivision::QuarterDotted, LfoShape::Square);
+        lfo.init(SR, 64);
+        lfo.param(&mut ctx, "phase_offset", 0.5).unwrap();
+        let output = run(&mut lfo, &mut ctx, None);
+        assert_eq!(output[0], -1.0);
+        assert_eq!(output[767], -1.0);
+        assert_eq!(output[768], 1.0);
+
+        let mut lfo =
+            TempoLfo::<f64>::new(60., NoteDivision::Sixteenth, LfoShape::SampleAndHold).seed(1);
+        lfo.init(SR, 64);
+        let output = run(&mut lfo, &mut ctx, None);
+        assert!(output[..256].iter().all(|&x| x == output[0]));
+        assert!(output[256..512].iter().all(|&x| x == output[256]));
+        assert_ne!(output[0], output[256]);
+    }
+}
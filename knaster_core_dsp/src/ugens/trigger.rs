@@ -0,0 +1,632 @@
+//! # Trigger
+//!
+//! UGens for working with triggers and gates, e.g. for rhythmic patching.
+//!
+//! ## Trigger convention
+//!
+//! A trigger is detected when a signal crosses from zero or below to above zero, i.e. when the
+//! previous sample was `<= 0.0` and the current sample is `> 0.0`. A signal staying above zero
+//! does not trigger again; it has to return to zero or below first. Use [`TriggerDetector`] to
+//! follow the same convention in your own [`UGen`](knaster_core::UGen)s.
+//!
+//! A gate is open while the signal is above zero.
+//!
+//! Triggers produced by the UGens in this module are a single sample of `1.0` followed by `0.0`.
+//!
+//! - [`Impulse`]: an impulse train at a frequency
+//! - [`PulseCounter`]: counts triggers, with a reset input
+//! - [`ClockDivider`] and [`ClockMultiplier`]: divide or multiply the rate of a trigger clock
+//! - [`TrigToGate`]: opens a gate for a duration on every trigger
+//! - [`EdgeDetector`]: triggers on rising or falling edges or changes of a signal
+//! - [`Latch`]: a set/reset latch
+//! - [`Toggle`]: a flip-flop which toggles between 0 and 1 on every trigger
+//! - [`TriggerDelay`]: delays triggers by a time
+
+use knaster_core::{
+    Float, KnasterIntegerParameter, PFloat, PInteger, impl_ugen,
+    num_derive::{FromPrimitive, ToPrimitive},
+    num_traits,
+};
+
+/// Detects triggers according to the [trigger convention](self#trigger-convention)
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TriggerDetector<F> {
+    last: F,
+}
+impl<F: Float> TriggerDetector<F> {
+    #[allow(missing_docs)]
+    pub fn new() -> Self {
+        Self { last: F::ZERO }
+    }
+    /// Returns true if `sample` is a trigger given the previous sample passed to this function
+    #[inline]
+    pub fn detect(&mut self, sample: F) -> bool {
+        let trigger = self.last <= F::ZERO && sample > F::ZERO;
+        self.last = sample;
+        trigger
+    }
+}
+
+/// Returns the value of a trigger output
+#[inline]
+fn trigger_value<F: Float>(trigger: bool) -> F {
+    if trigger { F::ONE } else { F::ZERO }
+}
+
+/// Outputs a train of single sample impulses at `freq` Hz, starting with an impulse.
+pub struct Impulse<F: Copy = f32> {
+    phase: F,
+    freq: F,
+    sample_duration: F,
+}
+#[impl_ugen]
+impl<F: Float> Impulse<F> {
+    #[allow(missing_docs)]
+    pub fn new(freq: F) -> Self {
+        Self {
+            phase: F::ONE,
+            freq,
+            sample_duration: F::ZERO,
+        }
+    }
+    fn init(&mut self, sample_rate: u32, _block_size: usize) {
+        self.sample_duration = F::ONE / F::from(sample_rate).unwrap();
+    }
+    /// The number of impulses per second
+    #[param(kind = Frequency, default = 1.0, range = 0.0..=20000.0)]
+    pub fn freq(&mut self, freq: PFloat) {
+        self.freq = F::new(freq);
+    }
+    /// Output an impulse on the next sample and restart the period from there
+    #[param]
+    pub fn t_reset(&mut self) {
+        self.phase = F::ONE;
+    }
+    #[allow(missing_docs)]
+    pub fn process(&mut self) -> [F; 1] {
+        let trigger = self.phase >= F::ONE;
+        if trigger {
+            self.phase -= F::ONE;
+            // Don't accumulate impulses if the frequency is higher than the sample rate
+            if self.phase >= F::ONE {
+                self.phase = F::ZERO;
+            }
+        }
+        self.phase += self.freq * self.sample_duration;
+        [trigger_value(trigger)]
+    }
+}
+
+/// Counts the triggers in the first input and outputs the count. A trigger in the second input
+/// resets the count to 0 before counting a trigger in the first input on the same sample.
+///
+/// If `wrap` is above 0, the count wraps around to 0 when reaching `wrap`, e.g. counting 0, 1, 2,
+/// 3, 0, 1 with `wrap` at 4.
+pub struct PulseCounter<F: Copy = f32> {
+    trigger: TriggerDetector<F>,
+    reset: TriggerDetector<F>,
+    count: u64,
+    wrap: u64,
+}
+#[impl_ugen]
+impl<F: Float> PulseCounter<F> {
+    #[allow(clippy::new_without_default)]
+    #[allow(missing_docs)]
+    pub fn new() -> Self {
+        Self {
+            trigger: TriggerDetector::new(),
+            reset: TriggerDetector::new(),
+            count: 0,
+            wrap: 0,
+        }
+    }
+    /// The count wraps around to 0 at this value. 0 means no wrapping.
+    #[param]
+    pub fn wrap(&mut self, wrap: PInteger) {
+        self.wrap = wrap.0 as u64;
+        if self.wrap > 0 {
+            self.count %= self.wrap;
+        }
+    }
+    #[allow(missing_docs)]
+    pub fn process(&mut self, input: [F; 2]) -> [F; 1] {
+        let [trigger, reset] = input;
+        if self.reset.detect(reset) {
+            self.count = 0;
+        }
+        if self.trigger.detect(trigger) {
+            self.count += 1;
+            if self.wrap > 0 && self.count >= self.wrap {
+                self.count = 0;
+            }
+        }
+        [F::new(self.count as f64)]
+    }
+}
+
+/// Outputs a trigger for every `divisor` triggers in the first input, starting with the first
+/// trigger. A trigger in the second input resets the division so that the next trigger is let
+/// through.
+pub struct ClockDivider<F: Copy = f32> {
+    trigger: TriggerDetector<F>,
+    reset: TriggerDetector<F>,
+    divisor: u32,
+    /// The number of triggers since the last output trigger, or `divisor` after a reset
+    count: u32,
+}
+#[impl_ugen]
+impl<F: Float> ClockDivider<F> {
+    #[allow(missing_docs)]
+    pub fn new(divisor: u32) -> Self {
+        let divisor = divisor.max(1);
+        Self {
+            trigger: TriggerDetector::new(),
+            reset: TriggerDetector::new(),
+            divisor,
+            count: divisor,
+        }
+    }
+    /// Let through every `divisor`th trigger
+    #[param(default = 2)]
+    pub fn divisor(&mut self, divisor: PInteger) {
+        self.divisor = (divisor.0 as u32).max(1);
+    }
+    #[allow(missing_docs)]
+    pub fn process(&mut self, input: [F; 2]) -> [F; 1] {
+        let [trigger, reset] = input;
+        if self.reset.detect(reset) {
+            self.count = self.divisor;
+        }
+        let mut out = false;
+        if self.trigger.detect(trigger) {
+            if self.count >= self.divisor {
+                out = true;
+                self.count = 0;
+            }
+            self.count += 1;
+        }
+        [trigger_value(out)]
+    }
+}
+
+/// Outputs `factor` evenly spaced triggers for every trigger in the input. The spacing is
+/// based on the time between the two latest input triggers, so the output follows the input
+/// after one period of the input clock. Every input trigger is let through directly, which
+/// keeps the output in phase with the input.
+pub struct ClockMultiplier<F: Copy = f32> {
+    trigger: TriggerDetector<F>,
+    factor: u32,
+    /// Frames since the last input trigger
+    frames_since_trigger: u64,
+    /// Frames between the two latest input triggers, 0 until measured
+    period: u64,
+    /// The number of output triggers since the last input trigger
+    outputs_since_trigger: u32,
+}
+#[impl_ugen]
+impl<F: Float> ClockMultiplier<F> {
+    #[allow(missing_docs)]
+    pub fn new(factor: u32) -> Self {
+        Self {
+            trigger: TriggerDetector::new(),
+            factor: factor.max(1),
+            frames_since_trigger: 0,
+            period: 0,
+            outputs_since_trigger: 0,
+        }
+    }
+    /// The number of output triggers per input trigger
+    #[param(default = 2)]
+    pub fn factor(&mut self, factor: PInteger) {
+        self.factor = (factor.0 as u32).max(1);
+    }
+    #[allow(missing_docs)]
+    pub fn process(&mut self, input: [F; 1]) -> [F; 1] {
+        if self.trigger.detect(input[0]) {
+            if self.frames_since_trigger > 0 && self.outputs_since_trigger > 0 {
+                self.period = self.frames_since_trigger;
+            }
+            self.frames_since_trigger = 1;
+            self.outputs_since_trigger = 1;
+            return [F::ONE];
+        }
+        let mut out = false;
+        if self.period > 0
+            && self.outputs_since_trigger > 0
+            && self.outputs_since_trigger < self.factor
+        {
+            let next_output = self.period * self.outputs_since_trigger as u64 / self.factor as u64;
+            if self.frames_since_trigger >= next_output {
+                out = true;
+                self.outputs_since_trigger += 1;
+            }
+        }
+        self.frames_since_trigger += 1;
+        [trigger_value(out)]
+    }
+}
+
+/// Opens a gate (outputs 1) for `duration` seconds after every trigger. A trigger while the gate
+/// is open restarts the duration.
+///
+/// The gate closes for one sample before it is retriggered so that a following trigger detector
+/// sees the new trigger.
+pub struct TrigToGate<F: Copy = f32> {
+    trigger: TriggerDetector<F>,
+    duration: PFloat,
+    duration_frames: u64,
+    frames_left: u64,
+    sample_rate: u32,
+}
+#[impl_ugen]
+impl<F: Float> TrigToGate<F> {
+    #[allow(missing_docs)]
+    pub fn new(duration: PFloat) -> Self {
+        Self {
+            trigger: TriggerDetector::new(),
+            duration,
+            duration_frames: 0,
+            frames_left: 0,
+            sample_rate: 0,
+        }
+    }
+    fn init(&mut self, sample_rate: u32, _block_size: usize) {
+        self.sample_rate = sample_rate;
+        self.duration(self.duration);
+    }
+    /// How long the gate is open after a trigger
+    #[param(kind = Seconds, default = 0.1, range = 0.0..=60.0)]
+    pub fn duration(&mut self, duration: PFloat) {
+        self.duration = duration.max(0.);
+        self.duration_frames = (self.duration * self.sample_rate as f64).round() as u64;
+    }
+    #[allow(missing_docs)]
+    pub fn process(&mut self, input: [F; 1]) -> [F; 1] {
+        if self.trigger.detect(input[0]) {
+            if self.frames_left > 0 {
+                // Close the gate for this sample and open it again on the next
+                self.frames_left = self.duration_frames + 1;
+                return [F::ZERO];
+            }
+            self.frames_left = self.duration_frames;
+        }
+        if self.frames_left > 0 {
+            self.frames_left -= 1;
+            [F::ONE]
+        } else {
+            [F::ZERO]
+        }
+    }
+}
+
+/// What an [`EdgeDetector`] detects
+#[derive(
+    Clone, Copy, Debug, FromPrimitive, ToPrimitive, PartialEq, Default, KnasterIntegerParameter,
+)]
+#[num_traits = "num_traits"]
+#[repr(u8)]
+pub enum EdgeMode {
+    /// The signal goes from zero or below to above zero, i.e. a trigger or a gate opening
+    #[default]
+    Rising = 0,
+    /// The signal goes from above zero to zero or below, i.e. a gate closing
+    Falling,
+    /// The signal changes by more than the threshold from one sample to the next
+    Changed,
+}
+
+/// Outputs a trigger when the input has a rising or falling edge, or when it changes value,
+/// depending on the [`EdgeMode`].
+pub struct EdgeDetector<F: Copy = f32> {
+    mode: EdgeMode,
+    threshold: F,
+    last: F,
+}
+#[impl_ugen]
+impl<F: Float> EdgeDetector<F> {
+    #[allow(missing_docs)]
+    pub fn new(mode: EdgeMode) -> Self {
+        Self {
+            mode,
+            threshold: F::ZERO,
+            last: F::ZERO,
+        }
+    }
+    /// What kind of edge to detect
+    #[param(from = EdgeMode)]
+    pub fn mode(&mut self, mode: PInteger) {
+        self.mode = EdgeMode::from(mode);
+    }
+    /// How much the signal has to change to count as changed in [`EdgeMode::Changed`]
+    #[param(default = 0.0)]
+    pub fn threshold(&mut self, threshold: PFloat) {
+        self.threshold = F::new(threshold.abs());
+    }
+    #[allow(missing_docs)]
+    pub fn process(&mut self, input: [F; 1]) -> [F; 1] {
+        let x = input[0];
+        let edge = match self.mode {
+            EdgeMode::Rising => self.last <= F::ZERO && x > F::ZERO,
+            EdgeMode::Falling => self.last > F::ZERO && x <= F::ZERO,
+            EdgeMode::Changed => (x - self.last).abs() > self.threshold,
+        };
+        self.last = x;
+        [trigger_value(edge)]
+    }
+}
+
+/// A set/reset latch. A trigger in the first input opens the gate (outputs 1) and a trigger in
+/// the second input closes it. If both are triggered on the same sample, reset wins.
+pub struct Latch<F: Copy = f32> {
+    set: TriggerDetector<F>,
+    reset: TriggerDetector<F>,
+    open: bool,
+}
+#[impl_ugen]
+impl<F: Float> Latch<F> {
+    #[allow(clippy::new_without_default)]
+    #[allow(missing_docs)]
+    pub fn new() -> Self {
+        Self {
+            set: TriggerDetector::new(),
+            reset: TriggerDetector::new(),
+            open: false,
+        }
+    }
+    #[allow(missing_docs)]
+    pub fn process(&mut self, input: [F; 2]) -> [F; 1] {
+        let [set, reset] = input;
+        if self.set.detect(set) {
+            self.open = true;
+        }
+        if self.reset.detect(reset) {
+            self.open = false;
+        }
+        [trigger_value(self.open)]
+    }
+}
+
+/// A flip-flop, switching between outputting 0 and 1 on every trigger. Starts at 0.
+pub struct Toggle<F: Copy = f32> {
+    trigger: TriggerDetector<F>,
+    state: bool,
+}
+#[impl_ugen]
+impl<F: Float> Toggle<F> {
+    #[allow(clippy::new_without_default)]
+    #[allow(missing_docs)]
+    pub fn new() -> Self {
+        Self {
+            trigger: TriggerDetector::new(),
+            state: false,
+        }
+    }
+    #[allow(missing_docs)]
+    pub fn process(&mut self, input: [F; 1]) -> [F; 1] {
+        if self.trigger.detect(input[0]) {
+            self.state = !self.state;
+        }
+        [trigger_value(self.state)]
+    }
+}
+
+/// The maximum number of triggers a [`TriggerDelay`] can hold at the same time
+pub const MAX_PENDING_TRIGGERS: usize = 64;
+
+/// Delays every trigger by `delay` seconds. Up to [`MAX_PENDING_TRIGGERS`] triggers can be
+/// waiting at the same time; more triggers than that are ignored.
+pub struct TriggerDelay<F: Copy = f32> {
+    trigger: TriggerDetector<F>,
+    delay: PFloat,
+    delay_frames: u64,
+    sample_rate: u32,
+    /// The frame counter value at which each pending trigger is output, in no particular order
+    /// since the delay can change while triggers are pending
+    pending: [u64; MAX_PENDING_TRIGGERS],
+    num_pending: usize,
+    frame: u64,
+}
+#[impl_ugen]
+impl<F: Float> TriggerDelay<F> {
+    #[allow(missing_docs)]
+    pub fn new(delay: PFloat) -> Self {
+        Self {
+            trigger: TriggerDetector::new(),
+            delay,
+            delay_frames: 0,
+            sample_rate: 0,
+            pending: [0; MAX_PENDING_TRIGGERS],
+            num_pending: 0,
+            frame: 0,
+        }
+    }
+    fn init(&mut self, sample_rate: u32, _block_size: usize) {
+        self.sample_rate = sample_rate;
+        self.delay(self.delay);
+    }
+    /// The delay time. Changing it only affects new triggers.
+    #[param(kind = Seconds, default = 0.1, range = 0.0..=60.0)]
+    pub fn delay(&mut self, delay: PFloat) {
+        self.delay = delay.max(0.);
+        self.delay_frames = (self.delay * self.sample_rate as f64).round() as u64;
+    }
+    #[allow(missing_docs)]
+    pub fn process(&mut self, input: [F; 1]) -> [F; 1] {
+        if self.trigger.detect(input[0]) && self.num_pending < MAX_PENDING_TRIGGERS {
+            self.pending[self.num_pending] = self.frame + self.delay_frames;
+            self.num_pending += 1;
+        }
+        let mut out = false;
+        // Triggers due on the same frame are merged into one
+        let mut i = 0;
+        while i < self.num_pending {
+            if self.pending[i] <= self.frame {
+                out = true;
+                self.num_pending -= 1;
+                self.pending[i] = self.pending[self.num_pending];
+            } else {
+                i += 1;
+            }
+        }
+        self.frame += 1;
+        [trigger_value(out)]
+    }
+}
+
+#[cfg(all(test, any(feature = "std", feature = "alloc")))]
+mod tests {
+    use super::*;
+    use knaster_core::{AudioCtx, UGen, UGenFlags, log::ArLogSender};
+    use std::prelude::v1::*;
+
+    const SR: u32 = 1000;
+
+    /// Indices of the non zero samples
+    fn triggers(output: &[f32]) -> Vec<usize> {
+        output
+            .iter()
+            .enumerate()
+            .filter(|(_, x)| **x != 0.0)
+            .map(|(i, _)| i)
+            .collect()
+    }
+    /// A trigger signal with triggers at the given frames
+    fn trigger_signal(frames: usize, at: &[usize]) -> Vec<f32> {
+        (0..frames)
+            .map(|i| if at.contains(&i) { 1.0 } else { 0.0 })
+            .collect()
+    }
+
+    #[test]
+    fn impulse() {
+        let mut ctx = AudioCtx::new(SR, 64, ArLogSender::non_rt());
+        let mut flags = UGenFlags::new();
+        let mut impulse = Impulse::<f32>::new(125.);
+        impulse.init(SR, 64);
+        let output: Vec<f32> = (0..35)
+            .map(|_| UGen::process(&mut impulse, &mut ctx, &mut flags, [].into())[0])
+            .collect();
+        assert_eq!(triggers(&output), vec![0, 8, 16, 24, 32]);
+    }
+
+    #[test]
+    fn counters_and_dividers() {
+        let mut ctx = AudioCtx::new(SR, 64, ArLogSender::non_rt());
+        let mut flags = UGenFlags::new();
+        let clock = trigger_signal(40, &[0, 4, 8, 12, 16, 20, 24, 28, 32, 36]);
+        let reset = trigger_signal(40, &[20]);
+
+        let mut counter = PulseCounter::<f32>::new();
+        let counts: Vec<f32> = clock
+            .iter()
+            .zip(&reset)
+            .map(|(&c, &r)| UGen::process(&mut counter, &mut ctx, &mut flags, [c, r].into())[0])
+            .collect();
+        assert_eq!(counts[0], 1.0);
+        assert_eq!(counts[19], 5.0);
+        assert_eq!(counts[20], 1.0);
+        assert_eq!(counts[39], 5.0);
+
+        let mut divider = ClockDivider::<f32>::new(3);
+        let divided: Vec<f32> = clock
+            .iter()
+            .zip(&reset)
+            .map(|(&c, &r)| UGen::process(&mut divider, &mut ctx, &mut flags, [c, r].into())[0])
+            .collect();
+        assert_eq!(triggers(&divided), vec![0, 12, 20, 32]);
+
+        let mut multiplier = ClockMultiplier::<f32>::new(4);
+        let multiplied: Vec<f32> = clock
+            .iter()
+            .map(|&c| UGen::process(&mut multiplier, &mut ctx, &mut flags, [c].into())[0])
+            .collect();
+        // The period is known after the second input trigger
+        assert_eq!(
+            triggers(&multiplied[..12]),
+            vec![0, 4, 5, 6, 7, 8, 9, 10, 11]
+        );
+    }
+
+    #[test]
+    fn gates() {
+        let mut ctx = AudioCtx::new(SR, 64, ArLogSender::non_rt());
+        let mut flags = UGenFlags::new();
+        let input = trigger_signal(20, &[2, 12, 14]);
+
+        let mut gate = TrigToGate::<f32>::new(0.005);
+        gate.init(SR, 64);
+        let output: Vec<f32> = input
+            .iter()
+            .map(|&x| UGen::process(&mut gate, &mut ctx, &mut flags, [x].into())[0])
+            .collect();
+        assert_eq!(
+            triggers(&output),
+            vec![2, 3, 4, 5, 6, 12, 13, 15, 16, 17, 18, 19]
+        );
+
+        let mut toggle = Toggle::<f32>::new();
+        let output: Vec<f32> = input
+            .iter()
+            .map(|&x| UGen::process(&mut toggle, &mut ctx, &mut flags, [x].into())[0])
+            .collect();
+        assert_eq!(triggers(&output), (2..12).chain(14..20).collect::<Vec<_>>());
+
+        let mut latch = Latch::<f32>::new();
+        let reset = trigger_signal(20, &[6]);
+        let output: Vec<f32> = input
+            .iter()
+            .zip(&reset)
+            .map(|(&s, &r)| UGen::process(&mut latch, &mut ctx, &mut flags, [s, r].into())[0])
+            .collect();
+        assert_eq!(triggers(&output), (2..6).chain(12..20).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn edges_and_delay() {
+        let mut ctx = AudioCtx::new(SR, 64, ArLogSender::non_rt());
+        let mut flags = UGenFlags::new();
+        let input = [0.0, 1.0, 1.0, 0.5, 0.0, -1.0, 0.5];
+        for (mode, expected) in [
+            (EdgeMode::Rising, vec![1, 6]),
+            (EdgeMode::Falling, vec![4]),
+            (EdgeMode::Changed, vec![1, 3, 4, 5, 6]),
+        ] {
+            let mut edge = EdgeDetector::<f32>::new(mode);
+            let output: Vec<f32> = input
+                .iter()
+                .map(|&x| UGen::process(&mut edge, &mut ctx, &mut flags, [x].into())[0])
+                .collect();
+            assert_eq!(triggers(&output), expected, "{mode:?}");
+        }
+
+        let mut delay = TriggerDelay::<f32>::new(0.003);
+        delay.init(SR, 64);
+        let input = trigger_signal(20, &[0, 2, 10]);
+        let output: Vec<f32> = input
+            .iter()
+            .map(|&x| UGen::process(&mut delay, &mut ctx, &mut flags, [x].into())[0])
+            .collect();
+        assert_eq!(triggers(&output), vec![3, 5, 13]);
+    }
+
+    #[test]
+    fn trigger_delay_shortened_while_pending() {
+        let mut ctx = AudioCtx::new(SR, 64, ArLogSender::non_rt());
+        let mut flags = UGenFlags::new();
+        let mut delay = TriggerDelay::<f32>::new(0.010);
+        delay.init(SR, 64);
+        let input = trigger_signal(20, &[0, 2]);
+        let output: Vec<f32> = input
+            .iter()
+            .enumerate()
+            .map(|(i, &x)| {
+                if i == 1 {
+                    delay.param(&mut ctx, "delay", 0.002).unwrap();
+                }
+                UGen::process(&mut delay, &mut ctx, &mut flags, [x].into())[0]
+            })
+            .collect();
+        // The second trigger is due before the first one, which keeps its delay
+        assert_eq!(triggers(&output), vec![4, 10]);
+    }
+}
@@ -0,0 +1,313 @@
+//! # Unison
+//!
+//! [`Unison`]: many detuned oscillator voices in one UGen, e.g. for a supersaw.
+//!
+//! The voices use the same anti-aliased waveforms as [`PolyBlep`](crate::polyblep::PolyBlep).
+//! The state of the voices is kept in arrays and all voices are processed in the same loop,
+//! which lets the compiler vectorize it.
+
+#[allow(unused)]
+use crate::noise::next_randomness_seed;
+use crate::noise::{SeededRng, random_unipolar};
+use crate::polyblep::{rectangle, sawtooth, triangle};
+use knaster_core::{
+    Float, KnasterIntegerParameter, PFloat, PInteger, impl_ugen,
+    num_derive::{FromPrimitive, ToPrimitive},
+    num_traits,
+};
+
+/// The maximum number of voices of a [`Unison`]
+pub const MAX_UNISON_VOICES: usize = 16;
+
+/// The waveform of the voices of a [`Unison`]
+#[derive(
+    Clone, Copy, Debug, FromPrimitive, ToPrimitive, PartialEq, Default, KnasterIntegerParameter,
+)]
+#[num_traits = "num_traits"]
+#[repr(u8)]
+pub enum UnisonWaveform {
+    /// Sawtooth, for a supersaw
+    #[default]
+    Sawtooth = 0,
+    /// Rectangle wave with a variable pulse width
+    Rectangle,
+    #[allow(missing_docs)]
+    Triangle,
+    #[allow(missing_docs)]
+    Sine,
+}
+
+/// Unison oscillator with up to [`MAX_UNISON_VOICES`] detuned voices and stereo output.
+///
+/// Voice 0 is the center voice, which plays `freq` in the middle of the stereo field. The other
+/// voices are detuned evenly from `-detune` to `+detune` cents and panned from left to right by
+/// `spread`; with two voices, the one detuned voice plays at `+detune` on the right.
+/// `detune_curve` moves the detuned voices towards the center pitch: at 0 they are spaced
+/// linearly and at 1 they follow a cubic curve, with more voices close to the center.
+///
+/// `mix` is the balance between the center voice and the detuned voices, from only the center
+/// voice at 0 to only the detuned voices at 1. The detuned voices are scaled down with the
+/// number of voices to keep a similar loudness.
+///
+/// The start phase of every voice is random, scaled by `phase_randomness`. `t_reset` restarts
+/// all voices from new start phases, e.g. on a new note.
+pub struct Unison<F: Copy = f32> {
+    rng: SeededRng,
+    waveform: UnisonWaveform,
+    freq: F,
+    voices: usize,
+    detune: F,
+    detune_curve: F,
+    spread: F,
+    mix: F,
+    pulse_width: F,
+    phase_randomness: F,
+    sample_duration: F,
+    phases: [F; MAX_UNISON_VOICES],
+    /// The phase increment of each voice per sample
+    steps: [F; MAX_UNISON_VOICES],
+    gains_left: [F; MAX_UNISON_VOICES],
+    gains_right: [F; MAX_UNISON_VOICES],
+}
+#[impl_ugen]
+impl<F: Float> Unison<F> {
+    #[allow(missing_docs)]
+    pub fn new(waveform: UnisonWaveform, freq: F, voices: usize) -> Self {
+        let mut s = Self {
+            rng: SeededRng::new(),
+            waveform,
+            freq,
+            voices: voices.clamp(1, MAX_UNISON_VOICES),
+            detune: F::new(25.),
+            detune_curve: F::ZERO,
+            spread: F::new(0.8),
+            mix: F::new(0.5),
+            pulse_width: F::new(0.5),
+            phase_randomness: F::ONE,
+            sample_duration: F::ZERO,
+            phases: [F::ZERO; MAX_UNISON_VOICES],
+            steps: [F::ZERO; MAX_UNISON_VOICES],
+            gains_left: [F::ZERO; MAX_UNISON_VOICES],
+            gains_right: [F::ZERO; MAX_UNISON_VOICES],
+        };
+        s.randomize_phases();
+        s.update_gains();
+        s
+    }
+    /// Use `seed` instead of a seed from the graph or [`next_randomness_seed`]
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.rng.set_seed(seed);
+        self.randomize_phases();
+        self
+    }
+    fn reseed(&mut self, seed: u64) {
+        if self.rng.reseed(seed) {
+            self.randomize_phases();
+        }
+    }
+    fn init(&mut self, sample_rate: u32, _block_size: usize) {
+        self.sample_duration = F::ONE / F::from(sample_rate).unwrap();
+        self.update_steps();
+    }
+    /// The frequency of the center voice
+    #[param(kind = Frequency, default = 220.0)]
+    pub fn freq(&mut self, freq: PFloat) {
+        self.freq = F::new(freq);
+        self.update_steps();
+    }
+    /// The number of voices, including the center voice
+    #[param(default = 7)]
+    pub fn voices(&mut self, voices: PInteger) {
+        self.voices = voices.0.clamp(1, MAX_UNISON_VOICES);
+        self.update_steps();
+        self.update_gains();
+    }
+    /// The detuning of the outermost voices in cents
+    #[param(default = 25.0, range = 0.0..=100.0)]
+    pub fn detune(&mut self, detune: PFloat) {
+        self.detune = F::new(detune.max(0.));
+        self.update_steps();
+    }
+    /// How much the detuned voices are moved towards the center pitch
+    #[param(default = 0.0, range = 0.0..=1.0)]
+    pub fn detune_curve(&mut self, detune_curve: PFloat) {
+        self.detune_curve = F::new(detune_curve.clamp(0., 1.));
+        self.update_steps();
+    }
+    /// The stereo width of the detuned voices
+    #[param(default = 0.8, range = 0.0..=1.0)]
+    pub fn spread(&mut self, spread: PFloat) {
+        self.spread = F::new(spread.clamp(0., 1.));
+        self.update_gains();
+    }
+    /// The balance between the center voice and the detuned voices
+    #[param(default = 0.5, range = 0.0..=1.0)]
+    pub fn mix(&mut self, mix: PFloat) {
+        self.mix = F::new(mix.clamp(0., 1.));
+        self.update_gains();
+    }
+    /// The pulse width of [`UnisonWaveform::Rectangle`]
+    #[param(default = 0.5, range = 0.0..=1.0)]
+    pub fn pulse_width(&mut self, pulse_width: PFloat) {
+        self.pulse_width = F::new(pulse_width.clamp(0., 1.));
+    }
+    /// How random the start phases of the voices are, from all starting at 0 to fully random
+    #[param(default = 1.0, range = 0.0..=1.0)]
+    pub fn phase_randomness(&mut self, phase_randomness: PFloat) {
+        self.phase_randomness = F::new(phase_randomness.clamp(0., 1.));
+    }
+    /// The waveform of all voices
+    #[param(from = UnisonWaveform)]
+    pub fn waveform(&mut self, waveform: PInteger) {
+        self.waveform = UnisonWaveform::from(waveform);
+    }
+    /// Restart all voices from new start phases
+    #[param]
+    pub fn t_reset(&mut self) {
+        self.randomize_phases();
+    }
+    fn randomize_phases(&mut self) {
+        for phase in &mut self.phases {
+            *phase = random_unipolar::<F>(&mut self.rng) * self.phase_randomness;
+        }
+    }
+    /// The position of a detuned voice from -1 to 1. A single detuned voice is at 1.
+    fn voice_position(&self, voice: usize) -> F {
+        let detuned_voices = self.voices - 1;
+        if detuned_voices < 2 {
+            return F::ONE;
+        }
+        F::new(-1.0 + 2.0 * (voice - 1) as f64 / (detuned_voices - 1) as f64)
+    }
+    fn update_steps(&mut self) {
+        let base_step = self.freq * self.sample_duration;
+        self.steps[0] = base_step;
+        // Cubic at curve 1
+        let exponent = F::ONE + F::new(2.) * self.detune_curve;
+        for voice in 1..self.voices {
+            let position = self.voice_position(voice);
+            let curved = position.abs().powf(exponent).copysign(position);
+            let cents = curved * self.detune;
+            self.steps[voice] = base_step * F::new(2.).powf(cents / F::new(1200.));
+        }
+    }
+    fn update_gains(&mut self) {
+        let detuned_voices = self.voices - 1;
+        let (center_gain, detuned_gain) = if detuned_voices > 0 {
+            (
+                F::ONE - self.mix,
+                self.mix / F::new(detuned_voices as f64).sqrt(),
+            )
+        } else {
+            (F::ONE, F::ZERO)
+        };
+        // Equal power panning
+        let quarter_pi = F::PI / F::new(4.);
+        self.gains_left[0] = center_gain * quarter_pi.cos();
+        self.gains_right[0] = center_gain * quarter_pi.sin();
+        for voice in 1..MAX_UNISON_VOICES {
+            if voice < self.voices {
+                let angle = (self.voice_position(voice) * self.spread + F::ONE) * quarter_pi;
+                self.gains_left[voice] = detuned_gain * angle.cos();
+                self.gains_right[voice] = detuned_gain * angle.sin();
+            } else {
+                self.gains_left[voice] = F::ZERO;
+                self.gains_right[voice] = F::ZERO;
+            }
+        }
+    }
+    #[allow(missing_docs)]
+    pub fn process(&mut self) -> [F; 2] {
+        let voices = self.voices;
+        let phases = &mut self.phases[..voices];
+        let steps = &self.steps[..voices];
+        let gains_left = &self.gains_left[..voices];
+        let gains_right = &self.gains_right[..voices];
+        let mut left = F::ZERO;
+        let mut right = F::ZERO;
+        macro_rules! voice_loop {
+            ($waveform:expr) => {
+                for (((phase, &step), &gain_left), &gain_right) in phases
+                    .iter_mut()
+                    .zip(steps)
+                    .zip(gains_left)
+                    .zip(gains_right)
+                {
+                    let sample = $waveform(*phase, step);
+                    left += sample * gain_left;
+                    right += sample * gain_right;
+                    *phase += step;
+                    *phase -= phase.floor();
+                }
+            };
+        }
+        let pulse_width = self.pulse_width;
+        // Dispatch on the waveform outside of the voice loop
+        match self.waveform {
+            UnisonWaveform::Sawtooth => voice_loop!(sawtooth),
+            UnisonWaveform::Rectangle => voice_loop!(|t, dt| rectangle(t, dt, pulse_width)),
+            UnisonWaveform::Triangle => voice_loop!(triangle),
+            UnisonWaveform::Sine => voice_loop!(|t: F, _dt| (t * F::TAU).sin()),
+        }
+        [left, right]
+    }
+}
+
+#[cfg(all(test, any(feature = "std", feature = "alloc")))]
+mod tests {
+    use super::*;
+    use knaster_core::{AudioCtx, PTrigger, UGen, UGenFlags, log::ArLogSender};
+    use std::prelude::v1::*;
+
+    const SR: u32 = 48000;
+
+    #[test]
+    fn detuned_voices() {
+        let mut ctx = AudioCtx::new(SR, 64, ArLogSender::non_rt());
+        let mut flags = UGenFlags::new();
+        let mut unison = Unison::<f64>::new(UnisonWaveform::Sawtooth, 220., 7).seed(1);
+        unison.init(SR, 64);
+        unison.param(&mut ctx, "detune", 50.).unwrap();
+        let base = 220. / SR as f64;
+        assert_eq!(unison.steps[0], base);
+        assert!((unison.steps[1] / base - 2f64.powf(-50. / 1200.)).abs() < 1e-12);
+        assert!((unison.steps[6] / base - 2f64.powf(50. / 1200.)).abs() < 1e-12);
+        assert!((unison.steps[4] / base - 2f64.powf(10. / 1200.)).abs() < 1e-12);
+        unison.param(&mut ctx, "detune_curve", 1.).unwrap();
+        assert!((unison.steps[4] / base - 2f64.powf(0.4 / 1200.)).abs() < 1e-12);
+        // A single detuned voice is detuned all the way up
+        let mut two_voices = Unison::<f64>::new(UnisonWaveform::Sawtooth, 220., 2);
+        two_voices.init(SR, 64);
+        two_voices.param(&mut ctx, "detune", 50.).unwrap();
+        assert!((two_voices.steps[1] / base - 2f64.powf(50. / 1200.)).abs() < 1e-12);
+
+        // Voices panned to opposite sides make the channels different
+        let output: Vec<[f64; 2]> = (0..1000)
+            .map(|_| {
+                let out = UGen::process(&mut unison, &mut ctx, &mut flags, [].into());
+                [out[0], out[1]]
+            })
+            .collect();
+        assert!(output.iter().any(|[l, r]| (l - r).abs() > 0.1));
+        assert!(output.iter().all(|[l, r]| l.abs() < 2.0 && r.abs() < 2.0));
+
+        // Without spread and with only the center voice, the output is a mono sawtooth
+        unison.param(&mut ctx, "spread", 0.).unwrap();
+        unison.param(&mut ctx, "mix", 0.).unwrap();
+        for _ in 0..1000 {
+            let out = UGen::process(&mut unison, &mut ctx, &mut flags, [].into());
+            assert!((out[0] - out[1]).abs() < 1e-12);
+            assert!(out[0].abs() <= core::f64::consts::FRAC_1_SQRT_2 + 1e-9);
+        }
+    }
+
+    #[test]
+    fn phase_randomness() {
+        let mut ctx = AudioCtx::new(SR, 64, ArLogSender::non_rt());
+        let mut unison = Unison::<f32>::new(UnisonWaveform::Sine, 220., 5).seed(2);
+        assert!(unison.phases[..5].windows(2).all(|w| w[0] != w[1]));
+        unison.param(&mut ctx, "phase_randomness", 0.).unwrap();
+        unison.param(&mut ctx, "t_reset", PTrigger).unwrap();
+        assert!(unison.phases.iter().all(|&p| p == 0.));
+    }
+}
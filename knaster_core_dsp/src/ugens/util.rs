@@ -2,7 +2,15 @@
 //!
 //! Utility UGens
 use crate::core::marker::PhantomData;
-use knaster_core::{AudioCtx, Float, PFloat, UGenFlags, impl_ugen, rt_log};
+use crate::trigger::TriggerDetector;
+use knaster_core::events::MAX_EVENT_VALUES;
+use knaster_core::{
+    AudioCtx, Block, BlockRead, Float, Frame, PFloat, ParameterHint, ParameterValue, Size, UGen,
+    UGenFlags, impl_ugen,
+    numeric_array::NumericArray,
+    rt_log,
+    typenum::{Add1, B1, U0},
+};
 
 /// Sets the done flag when it receives a trigger. Use in combination with `Graph::push_with_done_action` or [`WrDone`] and a [`Done`] which frees more than the current node.
 pub struct DoneOnTrig<F> {
@@ -99,3 +107,79 @@ impl<F: Float> LogProbe<F> {
         []
     }
 }
+
+/// Sends an event to the main thread every time the trigger input (the first input) is triggered,
+/// see the [trigger convention](crate::trigger#trigger-convention). The event carries the values of the other inputs at the time of the
+/// trigger, up to [`MAX_EVENT_VALUES`]. See [`AudioCtx::send_event`].
+///
+/// If the event channel is full, the event is dropped.
+pub struct SendEvent<F, Values> {
+    id: u32,
+    trigger: TriggerDetector<F>,
+    _phantom: PhantomData<Values>,
+}
+impl<F: Float, Values: Size> SendEvent<F, Values> {
+    /// Create a new [`SendEvent`] sending events with the given `id`
+    pub fn new(id: u32) -> Self {
+        Self {
+            id,
+            trigger: TriggerDetector::new(),
+            _phantom: PhantomData,
+        }
+    }
+}
+impl<F: Float, Values: Size> UGen for SendEvent<F, Values>
+where
+    Values: core::ops::Add<B1>,
+    Add1<Values>: Size,
+{
+    type Sample = F;
+    type Inputs = Add1<Values>;
+    type Outputs = U0;
+    type Parameters = U0;
+
+    fn process(
+        &mut self,
+        ctx: &mut AudioCtx,
+        _flags: &mut UGenFlags,
+        input: Frame<Self::Sample, Self::Inputs>,
+    ) -> Frame<Self::Sample, Self::Outputs> {
+        if self.trigger.detect(input[0]) {
+            let mut values = [0.0; MAX_EVENT_VALUES];
+            let num_values = Values::USIZE.min(MAX_EVENT_VALUES);
+            for (value, x) in values.iter_mut().zip(&input[1..=num_values]) {
+                *value = Float::to_f64(*x);
+            }
+            ctx.send_event(self.id, &values[..num_values], 0);
+        }
+        Frame::default()
+    }
+
+    fn process_block<InBlock, OutBlock>(
+        &mut self,
+        ctx: &mut AudioCtx,
+        _flags: &mut UGenFlags,
+        input: &InBlock,
+        _output: &mut OutBlock,
+    ) where
+        InBlock: BlockRead<Sample = Self::Sample> + ?Sized,
+        OutBlock: Block<Sample = Self::Sample> + ?Sized,
+    {
+        let num_values = Values::USIZE.min(MAX_EVENT_VALUES);
+        for frame in 0..ctx.frames_to_process() {
+            if self.trigger.detect(input.read(0, frame)) {
+                let mut values = [0.0; MAX_EVENT_VALUES];
+                for (i, value) in values[..num_values].iter_mut().enumerate() {
+                    *value = input.read(i + 1, frame).to_f64();
+                }
+                ctx.send_event(self.id, &values[..num_values], frame);
+            }
+        }
+    }
+
+    fn param_hints() -> NumericArray<ParameterHint, Self::Parameters> {
+        NumericArray::default()
+    }
+
+    fn param_apply(&mut self, _ctx: &mut AudioCtx, _index: usize, _value: ParameterValue) {}
+}
@@ -0,0 +1,672 @@
+//! # Virtual analog filters
+//!
+//! Nonlinear resonant filters modelled on classic analog designs:
+//! - [`LadderFilter`]: 4-pole transistor ladder with mode mixing
+//! - [`SallenKeyFilter`]: 2-pole Sallen-Key lowpass in the style of the Korg 35 (MS-20)
+//! - [`DiodeLadderFilter`]: 4-pole diode ladder
+//!
+//! All filters are zero-delay-feedback (ZDF) filters built from trapezoidal (TPT) one-pole
+//! integrators, which keeps them stable under fast, even audio rate, cutoff modulation. Use
+//! [`WrArParams`](crate::wrappers_core::WrArParams) or `connect_to_parameter` in a graph to
+//! modulate `cutoff_freq` at audio rate.
+//!
+//! Nonlinearities are applied at the input of the feedback loop, which lets the filters
+//! self-oscillate with a bounded amplitude at high resonance.
+//!
+//! Implementations based on Vadim Zavalishin, "The Art of VA Filter Design", and Will Pirkle's
+//! application notes AN-5 (Korg35) and AN-6 (diode ladder).
+
+use knaster_core::{
+    AudioCtx, Float, KnasterIntegerParameter, PFloat, PInteger, impl_ugen,
+    num_derive::{FromPrimitive, ToPrimitive},
+    num_traits,
+};
+
+use crate::dsp::response::{Complex, FrequencyResponse, bilinear_s};
+
+/// The highest cutoff frequency as a ratio of the sample rate. `tan` goes to infinity at the
+/// Nyquist frequency.
+const MAX_CUTOFF_RATIO: f64 = 0.49;
+
+/// Computes the TPT integrator gain `g = tan(PI * fc / fs)` with the cutoff clamped to a sane
+/// range.
+#[inline]
+fn prewarp<F: Float>(cutoff_freq: F, sample_rate: F) -> F {
+    let ratio = (cutoff_freq / sample_rate).clamp(F::ZERO, F::new(MAX_CUTOFF_RATIO));
+    (F::PI * ratio).tan()
+}
+
+/// The normalised analog frequency `s` at `freq`, taking the cutoff clamping into account
+fn analog_s(cutoff_freq: f64, freq: f64, sample_rate: f64) -> Complex<f64> {
+    let cutoff_freq = cutoff_freq.clamp(0.0, sample_rate * MAX_CUTOFF_RATIO);
+    bilinear_s(freq, cutoff_freq, sample_rate)
+}
+
+/// Trapezoidal (TPT) one-pole integrator used as a building block for ZDF filters
+#[derive(Clone, Copy, Debug)]
+struct TptOnePole<F> {
+    s: F,
+}
+impl<F: Float> TptOnePole<F> {
+    fn new() -> Self {
+        Self { s: F::ZERO }
+    }
+    /// Process a sample through the lowpass, where `big_g` is `g / (1 + g)`
+    #[inline]
+    fn lowpass(&mut self, x: F, big_g: F) -> F {
+        let v = (x - self.s) * big_g;
+        let y = v + self.s;
+        self.s = y + v;
+        y
+    }
+}
+
+/// Output modes of the [`LadderFilter`]. Modes other than `LowPass24` are made by mixing the
+/// outputs of the ladder stages as in the Oberheim Xpander.
+#[derive(
+    Clone, Copy, Debug, FromPrimitive, ToPrimitive, PartialEq, Default, KnasterIntegerParameter,
+)]
+#[num_traits = "num_traits"]
+#[repr(u8)]
+pub enum LadderMode {
+    /// 24 dB/oct lowpass
+    #[default]
+    LowPass24 = 0,
+    /// 12 dB/oct lowpass
+    LowPass12,
+    /// 4-pole bandpass
+    BandPass24,
+    /// 2-pole bandpass
+    BandPass12,
+    /// 24 dB/oct highpass
+    HighPass24,
+    /// 12 dB/oct highpass
+    HighPass12,
+}
+
+/// Zero-delay-feedback 4-pole ladder filter with a saturating input stage.
+///
+/// - `resonance` is 0.0 to 1.0, where the filter starts to self-oscillate close to 1.0.
+/// - `drive` is the gain into the saturating input stage.
+///
+/// As with the analog ladder, the passband level drops as resonance increases.
+#[derive(Clone, Debug)]
+pub struct LadderFilter<F: Float = f32> {
+    mode: LadderMode,
+    cutoff_freq: F,
+    resonance: F,
+    drive: F,
+    sample_rate: F,
+    /// g / (1 + g)
+    big_g: F,
+    /// Feedback gain, 0.0 - 4.4
+    k: F,
+    stages: [TptOnePole<F>; 4],
+}
+#[impl_ugen]
+impl<F: Float> LadderFilter<F> {
+    #[allow(missing_docs)]
+    pub fn new(mode: LadderMode, cutoff_freq: F, resonance: F) -> Self {
+        let mut s = Self {
+            mode,
+            cutoff_freq,
+            resonance,
+            drive: F::ONE,
+            sample_rate: F::new(48000.),
+            big_g: F::ZERO,
+            k: F::ZERO,
+            stages: [TptOnePole::new(); 4],
+        };
+        s.set_resonance(resonance);
+        s
+    }
+    /// Cutoff frequency in Hz
+    #[param(kind = Frequency, default = 1000.0, range = 20.0..=20000.0, logarithmic = true)]
+    pub fn cutoff_freq(&mut self, cutoff_freq: PFloat, ctx: &AudioCtx) {
+        self.sample_rate = F::new(ctx.sample_rate() as f32);
+        self.set_cutoff_freq(F::new(cutoff_freq));
+    }
+    /// Resonance, self-oscillates close to 1.0
+    #[param(default = 0.0, range = 0.0..=1.0)]
+    pub fn resonance(&mut self, resonance: PFloat) {
+        self.set_resonance(F::new(resonance));
+    }
+    /// Gain into the saturating input stage
+    #[param(default = 1.0, range = 0.1..=20.0, logarithmic = true)]
+    pub fn drive(&mut self, drive: PFloat) {
+        self.drive = F::new(drive);
+    }
+    /// Set the filter mode
+    #[param(from = LadderMode)]
+    pub fn mode(&mut self, mode: PInteger) {
+        self.mode = LadderMode::from(mode);
+    }
+    fn init(&mut self, sample_rate: u32, _block_size: usize) {
+        self.sample_rate = F::new(sample_rate as f32);
+        self.set_cutoff_freq(self.cutoff_freq);
+        self.stages = [TptOnePole::new(); 4];
+    }
+    fn process(&mut self, input: [F; 1]) -> [F; 1] {
+        [self.process_sample(input[0])]
+    }
+    /// Set the cutoff frequency in Hz using the last known sample rate
+    #[inline]
+    pub fn set_cutoff_freq(&mut self, cutoff_freq: F) {
+        self.cutoff_freq = cutoff_freq;
+        let g = prewarp(cutoff_freq, self.sample_rate);
+        self.big_g = g / (F::ONE + g);
+    }
+    /// Set the resonance in the range 0.0 - 1.0
+    pub fn set_resonance(&mut self, resonance: F) {
+        self.resonance = resonance.clamp(F::ZERO, F::ONE);
+        // The linear ladder self-oscillates at K = 4.0. Going slightly above lets the saturation
+        // settle the oscillation at a stable amplitude.
+        self.k = self.resonance * F::new(4.4);
+    }
+    /// Process one sample
+    #[inline]
+    pub fn process_sample(&mut self, input: F) -> F {
+        let g = self.big_g;
+        let one_minus_g = F::ONE - g;
+        let [s1, s2, s3, s4] = [
+            self.stages[0].s,
+            self.stages[1].s,
+            self.stages[2].s,
+            self.stages[3].s,
+        ];
+        // The output of the last stage is `G^4 * u + sigma`
+        let sigma = one_minus_g * (((s1 * g + s2) * g + s3) * g + s4);
+        let g4 = g * g * g * g;
+        let x = input * self.drive + F::ANTI_DENORMAL;
+        let u = ((x - self.k * sigma) / (F::ONE + self.k * g4)).tanh();
+        let y1 = self.stages[0].lowpass(u, g);
+        let y2 = self.stages[1].lowpass(y1, g);
+        let y3 = self.stages[2].lowpass(y2, g);
+        let y4 = self.stages[3].lowpass(y3, g);
+        let two = F::new(2.);
+        let four = F::new(4.);
+        match self.mode {
+            LadderMode::LowPass24 => y4,
+            LadderMode::LowPass12 => y2,
+            LadderMode::BandPass24 => four * (y2 - two * y3 + y4),
+            LadderMode::BandPass12 => two * (y1 - y2),
+            LadderMode::HighPass24 => u - four * (y1 + y3) + F::new(6.) * y2 + y4,
+            LadderMode::HighPass12 => u - two * y1 + y2,
+        }
+    }
+}
+
+/// 2-pole Sallen-Key lowpass filter modelled on the Korg 35 filter found in the MS-20.
+///
+/// - `resonance` is 0.0 to 1.0, where the filter starts to self-oscillate close to 1.0.
+/// - `drive` is the gain into the saturator in the feedback loop. Higher values give the
+///   characteristic screaming MS-20 resonance.
+#[derive(Clone, Debug)]
+pub struct SallenKeyFilter<F: Float = f32> {
+    cutoff_freq: F,
+    resonance: F,
+    drive: F,
+    sample_rate: F,
+    big_g: F,
+    /// Feedback gain, 0.01 - 2.2
+    k: F,
+    lpf1: TptOnePole<F>,
+    lpf2: TptOnePole<F>,
+    hpf: TptOnePole<F>,
+    lpf2_beta: F,
+    hpf_beta: F,
+    alpha0: F,
+}
+#[impl_ugen]
+impl<F: Float> SallenKeyFilter<F> {
+    #[allow(missing_docs)]
+    pub fn new(cutoff_freq: F, resonance: F) -> Self {
+        let mut s = Self {
+            cutoff_freq,
+            resonance,
+            drive: F::ONE,
+            sample_rate: F::new(48000.),
+            big_g: F::ZERO,
+            k: F::ZERO,
+            lpf1: TptOnePole::new(),
+            lpf2: TptOnePole::new(),
+            hpf: TptOnePole::new(),
+            lpf2_beta: F::ZERO,
+            hpf_beta: F::ZERO,
+            alpha0: F::ONE,
+        };
+        s.set_resonance(resonance);
+        s
+    }
+    /// Cutoff frequency in Hz
+    #[param(kind = Frequency, default = 1000.0, range = 20.0..=20000.0, logarithmic = true)]
+    pub fn cutoff_freq(&mut self, cutoff_freq: PFloat, ctx: &AudioCtx) {
+        self.sample_rate = F::new(ctx.sample_rate() as f32);
+        self.set_cutoff_freq(F::new(cutoff_freq));
+    }
+    /// Resonance, self-oscillates close to 1.0
+    #[param(default = 0.0, range = 0.0..=1.0)]
+    pub fn resonance(&mut self, resonance: PFloat) {
+        self.set_resonance(F::new(resonance));
+    }
+    /// Gain into the saturator in the feedback loop
+    #[param(default = 1.0, range = 0.1..=20.0, logarithmic = true)]
+    pub fn drive(&mut self, drive: PFloat) {
+        self.drive = F::new(drive);
+    }
+    fn init(&mut self, sample_rate: u32, _block_size: usize) {
+        self.sample_rate = F::new(sample_rate as f32);
+        self.set_cutoff_freq(self.cutoff_freq);
+        self.lpf1 = TptOnePole::new();
+        self.lpf2 = TptOnePole::new();
+        self.hpf = TptOnePole::new();
+    }
+    fn process(&mut self, input: [F; 1]) -> [F; 1] {
+        [self.process_sample(input[0])]
+    }
+    /// Set the cutoff frequency in Hz using the last known sample rate
+    #[inline]
+    pub fn set_cutoff_freq(&mut self, cutoff_freq: F) {
+        self.cutoff_freq = cutoff_freq;
+        let g = prewarp(cutoff_freq, self.sample_rate);
+        self.big_g = g / (F::ONE + g);
+        self.update_feedback_coefficients(g);
+    }
+    /// Set the resonance in the range 0.0 - 1.0
+    pub fn set_resonance(&mut self, resonance: F) {
+        self.resonance = resonance.clamp(F::ZERO, F::ONE);
+        // The linear filter self-oscillates at K = 2.0. Going slightly above lets the saturation
+        // settle the oscillation. Keep K above 0.0 for the output normalisation.
+        self.k = F::new(0.01) + self.resonance * F::new(2.19);
+        let g = prewarp(self.cutoff_freq, self.sample_rate);
+        self.update_feedback_coefficients(g);
+    }
+    fn update_feedback_coefficients(&mut self, g: F) {
+        let big_g = self.big_g;
+        let k = self.k;
+        self.lpf2_beta = (k - k * big_g) / (F::ONE + g);
+        self.hpf_beta = -F::ONE / (F::ONE + g);
+        self.alpha0 = F::ONE / (F::ONE - k * big_g + k * big_g * big_g);
+    }
+    /// Process one sample
+    #[inline]
+    pub fn process_sample(&mut self, input: F) -> F {
+        let g = self.big_g;
+        let y1 = self.lpf1.lowpass(input + F::ANTI_DENORMAL, g);
+        let sigma = self.lpf2_beta * self.lpf2.s + self.hpf_beta * self.hpf.s;
+        let u = (self.drive * self.alpha0 * (y1 + sigma)).tanh() / self.drive;
+        let y = self.k * self.lpf2.lowpass(u, g);
+        // Only the state of the highpass is used
+        self.hpf.lowpass(y, g);
+        y / self.k
+    }
+}
+
+/// One pole stage of the diode ladder. The stages are coupled so they need some additional
+/// coefficients compared to [`TptOnePole`].
+#[derive(Clone, Copy, Debug)]
+struct DiodeStage<F> {
+    s: F,
+    a0: F,
+    beta: F,
+    gamma: F,
+    delta: F,
+    epsilon: F,
+    feedback: F,
+}
+impl<F: Float> DiodeStage<F> {
+    fn new(a0: F) -> Self {
+        Self {
+            s: F::ZERO,
+            a0,
+            beta: F::ZERO,
+            gamma: F::ONE,
+            delta: F::ZERO,
+            epsilon: F::ZERO,
+            feedback: F::ZERO,
+        }
+    }
+    #[inline]
+    fn feedback_output(&self) -> F {
+        self.beta * (self.s + self.feedback * self.delta)
+    }
+    #[inline]
+    fn process(&mut self, x: F, alpha: F) -> F {
+        let x = x * self.gamma + self.feedback + self.epsilon * self.feedback_output();
+        let v = (self.a0 * x - self.s) * alpha;
+        let y = v + self.s;
+        self.s = y + v;
+        y
+    }
+}
+
+/// 4-pole diode ladder lowpass filter, in the style of the EMS VCS3 and Roland TB-303.
+///
+/// Unlike the transistor ladder, the stages of a diode ladder load each other, giving a less
+/// steep, more aggressive slope and an uneven resonance.
+///
+/// - `resonance` is 0.0 to 1.0, where the filter starts to self-oscillate close to 1.0.
+/// - `drive` is the gain into the saturating input stage.
+///
+/// The diode ladder has a lower output level than the transistor ladder, especially when
+/// self-oscillating.
+#[derive(Clone, Debug)]
+pub struct DiodeLadderFilter<F: Float = f32> {
+    cutoff_freq: F,
+    resonance: F,
+    drive: F,
+    sample_rate: F,
+    alpha: F,
+    /// Feedback gain, 0.0 - 18.0
+    k: F,
+    /// Total forward gain through the ladder
+    gamma: F,
+    /// Gains from each stage state to the output
+    sg: [F; 4],
+    stages: [DiodeStage<F>; 4],
+}
+#[impl_ugen]
+impl<F: Float> DiodeLadderFilter<F> {
+    #[allow(missing_docs)]
+    pub fn new(cutoff_freq: F, resonance: F) -> Self {
+        let half = F::new(0.5);
+        let mut s = Self {
+            cutoff_freq,
+            resonance,
+            drive: F::ONE,
+            sample_rate: F::new(48000.),
+            alpha: F::ZERO,
+            k: F::ZERO,
+            gamma: F::ZERO,
+            sg: [F::ZERO; 4],
+            stages: [
+                DiodeStage::new(F::ONE),
+                DiodeStage::new(half),
+                DiodeStage::new(half),
+                DiodeStage::new(half),
+            ],
+        };
+        s.set_resonance(resonance);
+        s
+    }
+    /// Cutoff frequency in Hz
+    #[param(kind = Frequency, default = 1000.0, range = 20.0..=20000.0, logarithmic = true)]
+    pub fn cutoff_freq(&mut self, cutoff_freq: PFloat, ctx: &AudioCtx) {
+        self.sample_rate = F::new(ctx.sample_rate() as f32);
+        self.set_cutoff_freq(F::new(cutoff_freq));
+    }
+    /// Resonance, self-oscillates close to 1.0
+    #[param(default = 0.0, range = 0.0..=1.0)]
+    pub fn resonance(&mut self, resonance: PFloat) {
+        self.set_resonance(F::new(resonance));
+    }
+    /// Gain into the saturating input stage
+    #[param(default = 1.0, range = 0.1..=20.0, logarithmic = true)]
+    pub fn drive(&mut self, drive: PFloat) {
+        self.drive = F::new(drive);
+    }
+    fn init(&mut self, sample_rate: u32, _block_size: usize) {
+        self.sample_rate = F::new(sample_rate as f32);
+        self.set_cutoff_freq(self.cutoff_freq);
+        for stage in &mut self.stages {
+            stage.s = F::ZERO;
+        }
+    }
+    fn process(&mut self, input: [F; 1]) -> [F; 1] {
+        [self.process_sample(input[0])]
+    }
+    /// Set the resonance in the range 0.0 - 1.0
+    pub fn set_resonance(&mut self, resonance: F) {
+        self.resonance = resonance.clamp(F::ZERO, F::ONE);
+        // The linear filter self-oscillates at K = 17.0. Going slightly above lets the saturation
+        // settle the oscillation at a stable amplitude.
+        self.k = self.resonance * F::new(18.);
+    }
+    /// Set the cutoff frequency in Hz using the last known sample rate
+    #[inline]
+    pub fn set_cutoff_freq(&mut self, cutoff_freq: F) {
+        self.cutoff_freq = cutoff_freq;
+        let g = prewarp(cutoff_freq, self.sample_rate);
+        let half = F::new(0.5);
+        let one = F::ONE;
+        let g4 = half * g / (one + g);
+        let g3 = half * g / (one + g - half * g * g4);
+        let g2 = half * g / (one + g - half * g * g3);
+        let g1 = g / (one + g - g * g2);
+        self.gamma = g4 * g3 * g2 * g1;
+        self.sg = [g4 * g3 * g2, g4 * g3, g4, one];
+        self.alpha = g / (one + g);
+
+        let [s1, s2, s3, s4] = &mut self.stages;
+        s1.beta = one / (one + g - g * g2);
+        s2.beta = one / (one + g - half * g * g3);
+        s3.beta = one / (one + g - half * g * g4);
+        s4.beta = one / (one + g);
+        s1.gamma = one + g1 * g2;
+        s2.gamma = one + g2 * g3;
+        s3.gamma = one + g3 * g4;
+        s1.delta = g;
+        s2.delta = half * g;
+        s3.delta = half * g;
+        s1.epsilon = g2;
+        s2.epsilon = g3;
+        s3.epsilon = g4;
+    }
+    /// Process one sample
+    #[inline]
+    pub fn process_sample(&mut self, input: F) -> F {
+        self.stages[3].feedback = F::ZERO;
+        self.stages[2].feedback = self.stages[3].feedback_output();
+        self.stages[1].feedback = self.stages[2].feedback_output();
+        self.stages[0].feedback = self.stages[1].feedback_output();
+        let mut sigma = F::ZERO;
+        for (stage, sg) in self.stages.iter().zip(self.sg) {
+            sigma += sg * stage.feedback_output();
+        }
+        let x = input * self.drive + F::ANTI_DENORMAL;
+        let u = ((x - self.k * sigma) / (F::ONE + self.k * self.gamma)).tanh();
+        let mut y = u;
+        for stage in &mut self.stages {
+            y = stage.process(y, self.alpha);
+        }
+        y
+    }
+}
+
+impl<F: Float> FrequencyResponse for LadderFilter<F> {
+    fn frequency_response(&self, freq: f64, sample_rate: f64) -> Complex<f64> {
+        let s = analog_s(self.cutoff_freq.to_f64(), freq, sample_rate);
+        // Response of each one-pole stage
+        let h = Complex::real(1.0) / (s + 1.0);
+        let h2 = h * h;
+        let h4 = h2 * h2;
+        let k = self.k.to_f64();
+        let u = Complex::real(self.drive.to_f64()) / (h4 * k + 1.0);
+        let mix = match self.mode {
+            LadderMode::LowPass24 => h4,
+            LadderMode::LowPass12 => h2,
+            LadderMode::BandPass24 => (h2 - h2 * h * 2.0 + h4) * 4.0,
+            LadderMode::BandPass12 => (h - h2) * 2.0,
+            LadderMode::HighPass24 => {
+                let hp = -h + 1.0;
+                hp * hp * hp * hp
+            }
+            LadderMode::HighPass12 => {
+                let hp = -h + 1.0;
+                hp * hp
+            }
+        };
+        u * mix
+    }
+}
+impl<F: Float> FrequencyResponse for SallenKeyFilter<F> {
+    fn frequency_response(&self, freq: f64, sample_rate: f64) -> Complex<f64> {
+        let s = analog_s(self.cutoff_freq.to_f64(), freq, sample_rate);
+        let k = self.k.to_f64();
+        Complex::real(1.0) / (s * s + s * (2.0 - k) + 1.0)
+    }
+}
+impl<F: Float> FrequencyResponse for DiodeLadderFilter<F> {
+    fn frequency_response(&self, freq: f64, sample_rate: f64) -> Complex<f64> {
+        let s = analog_s(self.cutoff_freq.to_f64(), freq, sample_rate);
+        let p = s + 1.0;
+        let p2 = p * p;
+        let k = self.k.to_f64();
+        Complex::real(self.drive.to_f64()) / (p2 * p2 * 8.0 - p2 * 8.0 + 1.0 + k)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SR: f32 = 48000.;
+
+    /// Measures the peak output level of a sine at `freq` after the filter has settled
+    fn sine_gain(mut filter: impl FnMut(f32) -> f32, freq: f32) -> f32 {
+        let mut peak = 0.0f32;
+        for i in 0..(SR as usize) {
+            let x = 0.01 * (core::f32::consts::TAU * freq * i as f32 / SR).sin();
+            let y = filter(x);
+            if i > SR as usize / 2 {
+                peak = peak.max(y.abs());
+            }
+        }
+        peak / 0.01
+    }
+
+    #[test]
+    fn lowpass_responses() {
+        let mut ladder = LadderFilter::new(LadderMode::LowPass24, 1000.0, 0.0);
+        ladder.sample_rate = SR;
+        ladder.set_cutoff_freq(1000.);
+        let mut sk = SallenKeyFilter::new(1000.0, 0.0);
+        sk.sample_rate = SR;
+        sk.set_cutoff_freq(1000.);
+        let mut diode = DiodeLadderFilter::new(1000.0, 0.0);
+        diode.sample_rate = SR;
+        diode.set_cutoff_freq(1000.);
+
+        for (name, filter) in [
+            (
+                "ladder",
+                &mut (|x| ladder.process_sample(x)) as &mut dyn FnMut(f32) -> f32,
+            ),
+            ("sallen-key", &mut |x| sk.process_sample(x)),
+            ("diode", &mut |x| diode.process_sample(x)),
+        ] {
+            let pass = sine_gain(&mut *filter, 100.);
+            let stop = sine_gain(&mut *filter, 10000.);
+            assert!(pass > 0.5, "{name}: passband gain {pass}");
+            assert!(stop < 0.05, "{name}: stopband gain {stop}");
+        }
+    }
+
+    /// Sweeping the cutoff at audio rate at full resonance must stay bounded
+    #[test]
+    fn audio_rate_modulation_is_stable() {
+        let mut ladder = LadderFilter::new(LadderMode::LowPass24, 1000.0, 1.0);
+        let mut sk = SallenKeyFilter::new(1000.0, 1.0);
+        let mut diode = DiodeLadderFilter::new(1000.0, 1.0);
+        ladder.sample_rate = SR;
+        sk.sample_rate = SR;
+        diode.sample_rate = SR;
+        for i in 0..(SR as usize) {
+            let t = i as f32 / SR;
+            let cutoff =
+                20. * 1000.0f32.powf(0.5 + 0.5 * (core::f32::consts::TAU * 440. * t).sin());
+            let x = if i % 100 == 0 { 1.0 } else { 0.0 };
+            ladder.set_cutoff_freq(cutoff);
+            sk.set_cutoff_freq(cutoff);
+            diode.set_cutoff_freq(cutoff);
+            for y in [
+                ladder.process_sample(x),
+                sk.process_sample(x),
+                diode.process_sample(x),
+            ] {
+                assert!(y.is_finite() && y.abs() < 10.0, "unstable output {y}");
+            }
+        }
+    }
+
+    #[test]
+    fn self_oscillation() {
+        let mut ladder = LadderFilter::new(LadderMode::LowPass24, 1000.0, 1.0);
+        let mut sk = SallenKeyFilter::new(1000.0, 1.0);
+        let mut diode = DiodeLadderFilter::new(1000.0, 1.0);
+        ladder.sample_rate = SR;
+        ladder.set_cutoff_freq(1000.);
+        sk.sample_rate = SR;
+        sk.set_cutoff_freq(1000.);
+        diode.sample_rate = SR;
+        diode.set_cutoff_freq(1000.);
+        for (name, filter) in [
+            (
+                "ladder",
+                &mut (|x| ladder.process_sample(x)) as &mut dyn FnMut(f32) -> f32,
+            ),
+            ("sallen-key", &mut |x| sk.process_sample(x)),
+            ("diode", &mut |x| diode.process_sample(x)),
+        ] {
+            filter(0.1);
+            let mut peak = 0.0f32;
+            for i in 0..(SR as usize) {
+                let y = filter(0.0);
+                if i > SR as usize / 2 {
+                    peak = peak.max(y.abs());
+                }
+            }
+            assert!(peak > 0.01 && peak < 10.0, "{name}: peak {peak}");
+        }
+    }
+
+    /// The response from `FrequencyResponse` must match the small signal response of the filter
+    #[test]
+    fn frequency_response_matches() {
+        let mut ladder = LadderFilter::<f64>::new(LadderMode::BandPass12, 2000.0, 0.5);
+        let mut sk = SallenKeyFilter::<f64>::new(2000.0, 0.5);
+        let mut diode = DiodeLadderFilter::<f64>::new(2000.0, 0.5);
+        ladder.init(SR as u32, 64);
+        sk.init(SR as u32, 64);
+        diode.init(SR as u32, 64);
+        let sr = SR as f64;
+        for freq in [200., 2000., 8000.] {
+            for filter in [&mut ladder as &mut dyn TestFilter, &mut sk, &mut diode] {
+                let expected = filter.response(freq, sr).magnitude();
+                let amp = 1e-4;
+                let mut sum = 0.0;
+                for i in 0..SR as usize {
+                    let x = amp * (core::f64::consts::TAU * freq * i as f64 / sr).sin();
+                    let y = filter.process(x) / amp;
+                    if i >= SR as usize / 2 {
+                        sum += y * y;
+                    }
+                }
+                let measured = (sum / (SR as f64 / 2.)).sqrt() * core::f64::consts::SQRT_2;
+                assert!(
+                    (measured - expected).abs() < 0.01 * expected.max(0.01),
+                    "{freq} Hz: measured {measured}, expected {expected}"
+                );
+            }
+        }
+    }
+    trait TestFilter {
+        fn process(&mut self, x: f64) -> f64;
+        fn response(&self, freq: f64, sample_rate: f64) -> Complex<f64>;
+    }
+    macro_rules! test_filter {
+        ($t:ty) => {
+            impl TestFilter for $t {
+                fn process(&mut self, x: f64) -> f64 {
+                    self.process_sample(x)
+                }
+                fn response(&self, freq: f64, sample_rate: f64) -> Complex<f64> {
+                    self.frequency_response(freq, sample_rate)
+                }
+            }
+        };
+    }
+    test_filter!(LadderFilter<f64>);
+    test_filter!(SallenKeyFilter<f64>);
+    test_filter!(DiodeLadderFilter<f64>);
+}
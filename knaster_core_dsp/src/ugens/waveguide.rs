@@ -0,0 +1,570 @@
+//! # Waveguide models
+//!
+//! Physical models of strings and tubes built from delay lines.
+//!
+//! - [`PluckedString`]: an extended Karplus-Strong plucked string
+//! - [`Waveguide`]: a bowed string or a reed instrument, driven by an excitation signal
+//!
+//! The delay lines are tuned with an [`AllpassInterpolator`] whose coefficient is solved for the
+//! exact phase delay at the fundamental, so the pitch stays accurate even for short delay lines
+//! at high frequencies.
+
+use crate::delay::AllpassInterpolator;
+#[allow(unused)]
+use crate::noise::next_randomness_seed;
+use crate::noise::{SeededRng, random_bipolar};
+use crate::onepole::OnePole;
+use crate::trigger::TriggerDetector;
+use knaster_core::{
+    Float, KnasterIntegerParameter, PFloat, PInteger, impl_ugen,
+    num_derive::{FromPrimitive, ToPrimitive},
+    num_traits,
+};
+use std::prelude::v1::*;
+
+/// The lowest frequency of the waveguide models, which sets the length of the delay lines
+pub const MIN_WAVEGUIDE_FREQ: PFloat = 20.0;
+/// The highest loop gain of a [`PluckedString`], which keeps it stable at DC
+const MAX_LOOP_GAIN: f64 = 0.9999;
+/// Reflection gain at the ends of a [`Waveguide`]
+const REFLECTION_GAIN: f64 = 0.95;
+/// Cutoff of the DC blocker on the output of a [`Waveguide`]
+const DC_BLOCKER_FREQ: f64 = 10.0;
+
+/// Delay line with a fractional length, tuned at a given frequency
+#[derive(Clone, Debug)]
+struct FractionalDelay<F: Copy> {
+    buffer: Vec<F>,
+    write_frame: usize,
+    frames: usize,
+    allpass: AllpassInterpolator<F>,
+}
+impl<F: Float> FractionalDelay<F> {
+    fn new() -> Self {
+        Self {
+            buffer: Vec::new(),
+            write_frame: 0,
+            frames: 1,
+            allpass: AllpassInterpolator::new(),
+        }
+    }
+    fn allocate(&mut self, max_frames: usize) {
+        self.buffer = vec![F::ZERO; max_frames.max(2)];
+        self.write_frame = 0;
+        self.allpass.clear();
+    }
+    fn clear(&mut self) {
+        self.buffer.fill(F::ZERO);
+        self.allpass.clear();
+    }
+    /// Set the delay in frames, at least 1.5. The allpass has exactly the right phase delay at
+    /// `freq`, given as a fraction of the sample rate.
+    fn set_delay(&mut self, frames: f64, freq: f64) {
+        let frames = frames.clamp(1.5, (self.buffer.len() - 1) as f64);
+        // Keep the fractional part in 0.5..1.5 where the allpass behaves well
+        let integer = num_traits::Float::floor(frames - 0.5).max(1.);
+        let fraction = frames - integer;
+        self.frames = integer as usize;
+        // A first order allpass with coefficient (1 - k) / (1 + k) has the phase delay d at the
+        // angular frequency w where tan(w * d / 2) = k * tan(w / 2)
+        let w = core::f64::consts::TAU * freq;
+        let k = if w > 1e-6 && w < core::f64::consts::PI {
+            num_traits::Float::tan(w * fraction * 0.5) / num_traits::Float::tan(w * 0.5)
+        } else {
+            fraction
+        };
+        self.allpass.set_delta(F::new(k));
+    }
+    /// Read the output of the delay line. Call before [`Self::write`].
+    #[inline]
+    fn read(&mut self) -> F {
+        let len = self.buffer.len();
+        let read_frame = (self.write_frame + len - self.frames) % len;
+        self.allpass.process_sample(self.buffer[read_frame])
+    }
+    #[inline]
+    fn write(&mut self, input: F) {
+        self.buffer[self.write_frame] = input;
+        self.write_frame = (self.write_frame + 1) % self.buffer.len();
+    }
+}
+
+/// Symmetric three tap lowpass with a delay of exactly one sample at all frequencies. The gain is
+/// 1 at DC and `1 - damping` at Nyquist.
+#[derive(Clone, Copy, Debug)]
+struct DampingFilter<F> {
+    side: F,
+    center: F,
+    previous: [F; 2],
+}
+impl<F: Float> DampingFilter<F> {
+    fn new() -> Self {
+        Self {
+            side: F::ZERO,
+            center: F::ONE,
+            previous: [F::ZERO; 2],
+        }
+    }
+    fn set_damping(&mut self, damping: PFloat) {
+        let side = damping.clamp(0., 1.) * 0.25;
+        self.side = F::new(side);
+        self.center = F::new(1. - 2. * side);
+    }
+    /// The gain at `freq`, given as a fraction of the sample rate
+    fn gain(&self, freq: f64) -> f64 {
+        let side = self.side.to_f64();
+        1. - 2. * side * (1. - num_traits::Float::cos(core::f64::consts::TAU * freq))
+    }
+    fn clear(&mut self) {
+        self.previous = [F::ZERO; 2];
+    }
+    #[inline]
+    fn process(&mut self, input: F) -> F {
+        let output = self.side * (input + self.previous[1]) + self.center * self.previous[0];
+        self.previous = [input, self.previous[0]];
+        output
+    }
+}
+
+/// Plucked string based on the extended Karplus-Strong algorithm.
+///
+/// A pluck, from a trigger in the input or the `t_pluck` parameter, excites the string with a
+/// burst of noise one period long. `pick_position` is where along the string it is plucked,
+/// from the end at 0 to the middle at 0.5, which removes the harmonics with a node at that
+/// point. Plucking close to the end gives a thin sound.
+///
+/// `decay` is the time in seconds for the fundamental to fall by 60 dB and `damping` makes the
+/// higher harmonics decay faster, from no extra damping at 0 to a dull string at 1. At high
+/// frequencies with a long decay, the damping can make the decay shorter than `decay`. The loop is
+/// tuned with a fractional delay, so the pitch is accurate to a fraction of a cent.
+///
+/// Triggers follow the [trigger convention](crate::trigger#trigger-convention).
+pub struct PluckedString<F: Copy = f32> {
+    rng: SeededRng,
+    trigger: TriggerDetector<F>,
+    delay: FractionalDelay<F>,
+    filter: DampingFilter<F>,
+    /// Preallocated noise burst that is fed into the string after a pluck
+    excitation: Vec<F>,
+    excitation_frame: usize,
+    excitation_frames: usize,
+    freq: PFloat,
+    decay: PFloat,
+    damping: PFloat,
+    pick_position: PFloat,
+    sample_rate: u32,
+    loop_gain: F,
+}
+#[impl_ugen]
+impl<F: Float> PluckedString<F> {
+    #[allow(missing_docs)]
+    pub fn new(freq: PFloat) -> Self {
+        let mut filter = DampingFilter::new();
+        filter.set_damping(0.5);
+        Self {
+            rng: SeededRng::new(),
+            trigger: TriggerDetector::new(),
+            delay: FractionalDelay::new(),
+            filter,
+            excitation: Vec::new(),
+            excitation_frame: 0,
+            excitation_frames: 0,
+            freq,
+            decay: 3.0,
+            damping: 0.5,
+            pick_position: 0.2,
+            sample_rate: 0,
+            loop_gain: F::ZERO,
+        }
+    }
+    /// Use `seed` instead of a seed from the graph or [`next_randomness_seed`]
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.rng.set_seed(seed);
+        self
+    }
+    fn reseed(&mut self, seed: u64) {
+        self.rng.reseed(seed);
+    }
+    fn init(&mut self, sample_rate: u32, _block_size: usize) {
+        self.sample_rate = sample_rate;
+        let max_frames = (sample_rate as PFloat / MIN_WAVEGUIDE_FREQ) as usize + 2;
+        self.delay.allocate(max_frames);
+        self.excitation = vec![F::ZERO; max_frames];
+        self.excitation_frames = 0;
+        self.update();
+    }
+    /// The frequency of the string
+    #[param(kind = Frequency, default = 220.0)]
+    pub fn freq(&mut self, freq: PFloat) {
+        self.freq = freq;
+        self.update();
+    }
+    /// The time for the fundamental to fall by 60 dB
+    #[param(kind = Seconds, default = 3.0, range = 0.01..=60.0)]
+    pub fn decay(&mut self, decay: PFloat) {
+        self.decay = decay;
+        self.update();
+    }
+    /// Extra damping of the higher harmonics
+    #[param(default = 0.5, range = 0.0..=1.0)]
+    pub fn damping(&mut self, damping: PFloat) {
+        self.damping = damping;
+        self.filter.set_damping(damping);
+        self.update();
+    }
+    /// Where the string is plucked, from the end at 0 to the middle at 0.5
+    #[param(default = 0.2, range = 0.0..=0.5)]
+    pub fn pick_position(&mut self, pick_position: PFloat) {
+        self.pick_position = pick_position.clamp(0., 0.5);
+    }
+    /// Pluck the string
+    #[param]
+    pub fn t_pluck(&mut self) {
+        self.pluck();
+    }
+    /// The period in frames for the current frequency
+    fn period(&self) -> PFloat {
+        let max_freq = self.sample_rate as PFloat / 2.5;
+        self.sample_rate as PFloat / self.freq.clamp(MIN_WAVEGUIDE_FREQ, max_freq)
+    }
+    fn update(&mut self) {
+        if self.sample_rate == 0 {
+            return;
+        }
+        let period = self.period();
+        let freq = 1. / period;
+        // The damping filter delays by one frame
+        self.delay.set_delay(period - 1., freq);
+        let decay_per_period = num_traits::Float::powf(
+            0.001,
+            period / (self.decay.max(0.001) * self.sample_rate as PFloat),
+        );
+        // Compensate for the damping filter so that the fundamental decays in `decay` seconds,
+        // but keep the gain at DC, where the filter doesn't damp, below 1
+        self.loop_gain = F::new((decay_per_period / self.filter.gain(freq)).min(MAX_LOOP_GAIN));
+    }
+    fn pluck(&mut self) {
+        if self.excitation.is_empty() {
+            return;
+        }
+        let period = self.period();
+        let frames = (period.round() as usize).clamp(1, self.excitation.len());
+        let burst = &mut self.excitation[..frames];
+        let mut sum = F::ZERO;
+        for sample in burst.iter_mut() {
+            *sample = random_bipolar::<F>(&mut self.rng) * F::new(0.5);
+            sum += *sample;
+        }
+        // Remove DC so that the string doesn't start with an offset
+        let mean = sum / F::from(frames).unwrap();
+        for sample in burst.iter_mut() {
+            *sample -= mean;
+        }
+        // Comb filter for the pick position, backwards so that the original values are used
+        let pick_frames = (period * self.pick_position).round() as usize;
+        if pick_frames > 0 {
+            for frame in (pick_frames..frames).rev() {
+                let delayed = burst[frame - pick_frames];
+                burst[frame] -= delayed;
+            }
+        }
+        self.excitation_frame = 0;
+        self.excitation_frames = frames;
+    }
+    #[allow(missing_docs)]
+    pub fn process(&mut self, input: [F; 1]) -> [F; 1] {
+        if self.trigger.detect(input[0]) {
+            self.pluck();
+        }
+        let excitation = if self.excitation_frame < self.excitation_frames {
+            self.excitation_frame += 1;
+            self.excitation[self.excitation_frame - 1]
+        } else {
+            F::ZERO
+        };
+        let delayed = self.delay.read();
+        let out = self.filter.process(delayed) * self.loop_gain + excitation;
+        self.delay.write(out);
+        [out]
+    }
+}
+
+/// How a [`Waveguide`] is excited
+#[derive(
+    Clone, Copy, Debug, FromPrimitive, ToPrimitive, PartialEq, Default, KnasterIntegerParameter,
+)]
+#[num_traits = "num_traits"]
+#[repr(u8)]
+pub enum WaveguideExciter {
+    /// A bowed string, where the input is the bow velocity
+    #[default]
+    Bow = 0,
+    /// A reed instrument like a clarinet, where the input is the breath pressure
+    Reed,
+}
+
+/// Bowed string or reed instrument as a digital waveguide with a nonlinear exciter.
+///
+/// The input is the excitation: the bow velocity for [`WaveguideExciter::Bow`], or the breath
+/// pressure for [`WaveguideExciter::Reed`]. Typical values are around 0.1-0.5 for the bow and
+/// 0.5-1.0 for the reed, shaped with an envelope, and a little noise in the breath makes the
+/// reed sound more natural. Too little excitation doesn't start the oscillation.
+///
+/// `pressure` is the bow pressure, or the stiffness of the reed, and shapes the nonlinearity.
+/// `position` is where the string is bowed, from the bridge at 0 to the nut at 1, and is not
+/// used by the reed. `damping` makes the higher harmonics decay faster.
+///
+/// The bowed string is two delay lines on either side of the bow, with a friction curve where the
+/// string sticks to the bow at low relative velocities. The reed is a tube which is closed at the
+/// reed, so that it mostly has odd harmonics, with a reed that opens and closes with the
+/// pressure difference. Both are tuned to `freq`, but the nonlinearity may pull the pitch
+/// slightly at extreme settings.
+pub struct Waveguide<F: Float = f32> {
+    exciter: WaveguideExciter,
+    /// The delay between the bow and the bridge, or the whole tube
+    bridge_delay: FractionalDelay<F>,
+    /// The delay between the bow and the nut
+    neck_delay: FractionalDelay<F>,
+    filter: DampingFilter<F>,
+    dc_blocker: OnePole<F>,
+    freq: PFloat,
+    pressure: PFloat,
+    position: PFloat,
+    sample_rate: u32,
+    /// Slope of the bow friction curve or the reed
+    slope: F,
+}
+#[impl_ugen]
+impl<F: Float> Waveguide<F> {
+    #[allow(missing_docs)]
+    pub fn new(exciter: WaveguideExciter, freq: PFloat) -> Self {
+        let mut filter = DampingFilter::new();
+        filter.set_damping(0.3);
+        let mut s = Self {
+            exciter,
+            bridge_delay: FractionalDelay::new(),
+            neck_delay: FractionalDelay::new(),
+            filter,
+            dc_blocker: OnePole::new(),
+            freq,
+            pressure: 0.5,
+            position: 0.13,
+            sample_rate: 0,
+            slope: F::ZERO,
+        };
+        s.update_slope();
+        s
+    }
+    fn init(&mut self, sample_rate: u32, _block_size: usize) {
+        self.sample_rate = sample_rate;
+        let max_frames = (sample_rate as PFloat / MIN_WAVEGUIDE_FREQ) as usize + 2;
+        self.bridge_delay.allocate(max_frames);
+        self.neck_delay.allocate(max_frames);
+        self.dc_blocker
+            .set_freq_lowpass(F::new(DC_BLOCKER_FREQ), F::from(sample_rate).unwrap());
+        self.update_delays();
+    }
+    /// The frequency of the instrument
+    #[param(kind = Frequency, default = 220.0)]
+    pub fn freq(&mut self, freq: PFloat) {
+        self.freq = freq;
+        self.update_delays();
+    }
+    /// Bow pressure, or reed stiffness
+    #[param(default = 0.5, range = 0.0..=1.0)]
+    pub fn pressure(&mut self, pressure: PFloat) {
+        self.pressure = pressure.clamp(0., 1.);
+        self.update_slope();
+    }
+    /// Where the string is bowed, from the bridge at 0 to the nut at 1
+    #[param(default = 0.13, range = 0.0..=1.0)]
+    pub fn position(&mut self, position: PFloat) {
+        self.position = position.clamp(0., 1.);
+        self.update_delays();
+    }
+    /// Extra damping of the higher harmonics
+    #[param(default = 0.3, range = 0.0..=1.0)]
+    pub fn damping(&mut self, damping: PFloat) {
+        self.filter.set_damping(damping);
+    }
+    /// The exciter of the model
+    #[param(from = WaveguideExciter)]
+    pub fn exciter(&mut self, exciter: PInteger) {
+        self.exciter = WaveguideExciter::from(exciter);
+        self.update_slope();
+        self.update_delays();
+        self.bridge_delay.clear();
+        self.neck_delay.clear();
+        self.filter.clear();
+    }
+    fn update_slope(&mut self) {
+        self.slope = F::new(match self.exciter {
+            WaveguideExciter::Bow => 5. - 4. * self.pressure,
+            WaveguideExciter::Reed => -0.44 + 0.26 * self.pressure,
+        });
+    }
+    fn update_delays(&mut self) {
+        if self.sample_rate == 0 {
+            return;
+        }
+        let sample_rate = self.sample_rate as PFloat;
+        match self.exciter {
+            WaveguideExciter::Bow => {
+                let freq = self.freq.clamp(MIN_WAVEGUIDE_FREQ, sample_rate / 5.) / sample_rate;
+                // The round trip through both delays and the damping filter is one period
+                let frames = 1. / freq - 1.;
+                let bridge = frames * self.position.clamp(0.05, 0.95);
+                self.bridge_delay.set_delay(bridge, freq);
+                self.neck_delay.set_delay(frames - bridge, freq);
+            }
+            WaveguideExciter::Reed => {
+                let freq = self.freq.clamp(MIN_WAVEGUIDE_FREQ, sample_rate / 6.) / sample_rate;
+                // The inverting reflection at the reed makes the period two round trips
+                self.bridge_delay.set_delay(0.5 / freq - 1., freq);
+            }
+        }
+    }
+    #[allow(missing_docs)]
+    pub fn process(&mut self, input: [F; 1]) -> [F; 1] {
+        let excitation = input[0];
+        let reflection_gain = F::new(REFLECTION_GAIN);
+        let out = match self.exciter {
+            WaveguideExciter::Bow => {
+                let bridge_out = self.bridge_delay.read();
+                let bridge_reflection = -self.filter.process(bridge_out) * reflection_gain;
+                let nut_reflection = -self.neck_delay.read();
+                let string_velocity = bridge_reflection + nut_reflection;
+                let relative_velocity = excitation - string_velocity;
+                // Friction: the string sticks to the bow at low relative velocities
+                let friction =
+                    ((relative_velocity + F::new(0.001)) * self.slope).abs() + F::new(0.75);
+                let friction = friction.powi(-4).min(F::ONE);
+                let new_velocity = relative_velocity * friction;
+                self.neck_delay.write(bridge_reflection + new_velocity);
+                self.bridge_delay.write(nut_reflection + new_velocity);
+                bridge_out
+            }
+            WaveguideExciter::Reed => {
+                let reflection = -self.filter.process(self.bridge_delay.read()) * reflection_gain;
+                let pressure_difference = reflection - excitation;
+                // The reed lets more air through as it opens with the pressure difference
+                let reed = (F::new(0.7) + self.slope * pressure_difference).clamp(-F::ONE, F::ONE);
+                let out = excitation + pressure_difference * reed;
+                self.bridge_delay.write(out);
+                out
+            }
+        };
+        [self.dc_blocker.process_hp(out)]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use knaster_core::{AudioCtx, PTrigger, UGen, UGenFlags, log::ArLogSender};
+    use std::f64::consts::TAU;
+
+    const SR: u32 = 48000;
+
+    /// The frequency of the strongest component close to `freq`, from the phase difference between
+    /// two windows
+    fn measure_freq(signal: &[f64], freq: f64) -> f64 {
+        let window = 16384;
+        let hop = 1024;
+        let phase = |start: usize| {
+            let (mut re, mut im) = (0., 0.);
+            for (i, &x) in signal[start..start + window].iter().enumerate() {
+                let w = 0.5 - 0.5 * (TAU * i as f64 / window as f64).cos();
+                let angle = TAU * freq * (start + i) as f64 / SR as f64;
+                re += x * w * angle.cos();
+                im -= x * w * angle.sin();
+            }
+            im.atan2(re)
+        };
+        let start = signal.len() - window - hop;
+        let mut difference = phase(start + hop) - phase(start);
+        difference -= TAU * (difference / TAU).round();
+        freq + difference * SR as f64 / (TAU * hop as f64)
+    }
+
+    fn cents(freq: f64, reference: f64) -> f64 {
+        1200. * (freq / reference).log2()
+    }
+
+    #[test]
+    fn plucked_string_pitch() {
+        let mut ctx = AudioCtx::new(SR, 64, ArLogSender::non_rt());
+        let mut flags = UGenFlags::new();
+        for freq in [41.2, 110., 440., 1318.5, 3520.] {
+            let mut string = PluckedString::<f64>::new(freq).seed(1);
+            string.init(SR, 64);
+            string.param(&mut ctx, "decay", 10.).unwrap();
+            string.param(&mut ctx, "t_pluck", PTrigger).unwrap();
+            let output: Vec<f64> = (0..SR as usize / 2)
+                .map(|_| UGen::process(&mut string, &mut ctx, &mut flags, [0.].into())[0])
+                .collect();
+            let measured = measure_freq(&output, freq);
+            assert!(cents(measured, freq).abs() < 1., "{freq}: {measured}");
+        }
+    }
+
+    #[test]
+    fn plucked_string_decay() {
+        let mut ctx = AudioCtx::new(SR, 64, ArLogSender::non_rt());
+        let mut flags = UGenFlags::new();
+        let mut string = PluckedString::<f64>::new(220.).seed(2);
+        string.init(SR, 64);
+        string.param(&mut ctx, "decay", 0.5).unwrap();
+        let mut output: Vec<f64> = [1., 0.]
+            .iter()
+            .chain([0.; SR as usize].iter())
+            .map(|&trig| UGen::process(&mut string, &mut ctx, &mut flags, [trig].into())[0])
+            .collect();
+        let peak = |s: &[f64]| s.iter().fold(0., |p: f64, x| p.max(x.abs()));
+        let start = peak(&output[..2000]);
+        assert!(start > 0.1);
+        // 60 dB down after 0.5 s, with some margin for the window
+        assert!(peak(&output[24000..26000]) < start * 0.003);
+        // Nothing is left after a second
+        assert!(peak(&output[SR as usize - 1000..]) < 1e-4);
+        // Retriggering plucks again
+        output.clear();
+        for trig in [0., 1.] {
+            output.push(UGen::process(&mut string, &mut ctx, &mut flags, [trig].into())[0]);
+        }
+        output.extend(
+            (0..1000).map(|_| UGen::process(&mut string, &mut ctx, &mut flags, [0.].into())[0]),
+        );
+        assert!(peak(&output) > 0.1);
+    }
+
+    #[test]
+    fn waveguide_oscillates_in_tune() {
+        let mut ctx = AudioCtx::new(SR, 64, ArLogSender::non_rt());
+        let mut flags = UGenFlags::new();
+        // A bowed string takes a while to settle into a stable motion
+        for (exciter, excitation, freqs) in [
+            (WaveguideExciter::Bow, 0.2, &[220.][..]),
+            (WaveguideExciter::Reed, 0.8, &[110., 220., 440.]),
+        ] {
+            for &freq in freqs {
+                let mut waveguide = Waveguide::<f64>::new(exciter, freq);
+                waveguide.init(SR, 64);
+                let output: Vec<f64> = (0..SR as usize)
+                    .map(|_| {
+                        UGen::process(&mut waveguide, &mut ctx, &mut flags, [excitation].into())[0]
+                    })
+                    .collect();
+                let tail = &output[output.len() / 2..];
+                let rms = (tail.iter().map(|x| x * x).sum::<f64>() / tail.len() as f64).sqrt();
+                assert!(rms > 0.01, "{exciter:?} {freq}: {rms}");
+                assert!(tail.iter().all(|x| x.is_finite() && x.abs() < 10.));
+                let measured = measure_freq(&output, freq);
+                assert!(
+                    cents(measured, freq).abs() < 5.,
+                    "{exciter:?} {freq}: {measured}"
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,451 @@
+//! # Waveshaper
+//!
+//! Distortion and saturation [`UGen`]s:
+//! - [`Waveshaper`]: a selection of static curves ([`WaveshaperCurve`]): tanh, soft clip, hard
+//!   clip, an asymmetric tube style curve and a sine wavefolder.
+//! - [`TableWaveshaper`]: a curve from a lookup table built from a closure or a [`Buffer`]
+//!   (requires `std` or `alloc`)
+//!
+//! All curves are anti-aliased using first order antiderivative anti-aliasing (ADAA). Instead of
+//! applying the nonlinearity `f(x)` directly, the difference quotient of its antiderivative `F1`
+//! is used: `y[n] = (F1(x[n]) - F1(x[n-1])) / (x[n] - x[n-1])`. This suppresses aliasing
+//! considerably at the cost of half a sample of delay and a gentle high frequency rolloff.
+//!
+//! Based on Parker, Zavalishin and Le Bivic, "Reducing the aliasing of nonlinear waveshaping
+//! using continuous-time convolution", DAFx 2016.
+//!
+//! [`Buffer`]: crate::dsp::buffer::Buffer
+
+#[allow(unused)]
+use knaster_core::UGen;
+use knaster_core::{
+    Float, KnasterIntegerParameter, PFloat, PInteger, impl_ugen,
+    num_derive::{FromPrimitive, ToPrimitive},
+    num_traits,
+};
+
+use crate::onepole::OnePole;
+
+#[cfg(any(feature = "alloc", feature = "std"))]
+pub use waveshaper_table::*;
+
+/// Below this difference between two consecutive inputs, the ADAA difference quotient is
+/// ill-conditioned and the curve is evaluated at the midpoint instead.
+const ADAA_EPSILON: f64 = 1e-6;
+/// Cutoff frequency of the DC blocker applied to the shaped signal.
+const DC_BLOCKER_FREQ: f64 = 5.0;
+
+/// First order antiderivative anti-aliasing state.
+///
+/// Computation happens in f64 regardless of the sample type, because the difference quotient
+/// suffers from catastrophic cancellation in f32.
+#[derive(Clone, Copy, Debug)]
+pub struct Adaa1 {
+    x1: f64,
+    ad1: f64,
+}
+impl Adaa1 {
+    /// Create a new ADAA state with a previous input of 0.0
+    pub fn new() -> Self {
+        Self { x1: 0.0, ad1: 0.0 }
+    }
+    /// Set the previous input value. Call this if the antiderivative function changes.
+    pub fn reset(&mut self, x: f64, antiderivative: impl Fn(f64) -> f64) {
+        self.x1 = x;
+        self.ad1 = antiderivative(x);
+    }
+    /// Apply the curve `shape` to `x` in the anti-aliased domain. `antiderivative` must be the
+    /// first antiderivative of `shape`.
+    #[inline]
+    pub fn process(
+        &mut self,
+        x: f64,
+        shape: impl Fn(f64) -> f64,
+        antiderivative: impl Fn(f64) -> f64,
+    ) -> f64 {
+        let ad = antiderivative(x);
+        let dx = x - self.x1;
+        let y = if dx.abs() < ADAA_EPSILON {
+            shape((x + self.x1) * 0.5)
+        } else {
+            (ad - self.ad1) / dx
+        };
+        self.x1 = x;
+        self.ad1 = ad;
+        y
+    }
+}
+impl Default for Adaa1 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Curves supported by [`Waveshaper`]
+#[derive(
+    Clone, Copy, Debug, FromPrimitive, ToPrimitive, PartialEq, Default, KnasterIntegerParameter,
+)]
+#[num_traits = "num_traits"]
+#[repr(u8)]
+pub enum WaveshaperCurve {
+    /// Hyperbolic tangent
+    #[default]
+    Tanh = 0,
+    /// Cubic soft clipper, `1.5x - 0.5x^3` within +-1.0
+    SoftClip,
+    /// Clamp to +-1.0
+    HardClip,
+    /// Asymmetric exponential curve which saturates at 1.0 for positive and -0.5 for negative
+    /// values, producing even harmonics.
+    Tube,
+    /// Sine wavefolder, `sin(x * PI/2)`. Inputs above 1.0 fold back down.
+    Fold,
+}
+impl WaveshaperCurve {
+    /// Evaluate the curve
+    pub fn shape<T: Float>(self, x: T) -> T {
+        let half = T::new(0.5);
+        match self {
+            WaveshaperCurve::Tanh => x.tanh(),
+            WaveshaperCurve::SoftClip => {
+                if x.abs() <= T::ONE {
+                    T::new(1.5) * x - half * x * x * x
+                } else {
+                    x.signum()
+                }
+            }
+            WaveshaperCurve::HardClip => x.clamp(-T::ONE, T::ONE),
+            WaveshaperCurve::Tube => {
+                if x >= T::ZERO {
+                    T::ONE - (-x).exp()
+                } else {
+                    (T::new(TUBE_NEGATIVE_HARDNESS) * x).exp_m1() / T::new(TUBE_NEGATIVE_HARDNESS)
+                }
+            }
+            WaveshaperCurve::Fold => (x * T::PI * half).sin(),
+        }
+    }
+    /// Evaluate the first antiderivative of the curve, chosen such that it is 0.0 at x = 0.0
+    pub fn antiderivative<T: Float>(self, x: T) -> T {
+        let half = T::new(0.5);
+        match self {
+            WaveshaperCurve::Tanh => {
+                // ln(cosh(x)) computed in a way that doesn't overflow for large x
+                let ax = x.abs();
+                ax + (T::new(-2.) * ax).exp().ln_1p() - T::new(2.).ln()
+            }
+            WaveshaperCurve::SoftClip => {
+                let ax = x.abs();
+                if ax <= T::ONE {
+                    T::new(0.75) * x * x - T::new(0.125) * x * x * x * x
+                } else {
+                    ax - T::new(0.375)
+                }
+            }
+            WaveshaperCurve::HardClip => {
+                let ax = x.abs();
+                if ax <= T::ONE {
+                    half * x * x
+                } else {
+                    ax - half
+                }
+            }
+            WaveshaperCurve::Tube => {
+                if x >= T::ZERO {
+                    x + (-x).exp_m1()
+                } else {
+                    let k = T::new(TUBE_NEGATIVE_HARDNESS);
+                    (k * x).exp_m1() / (k * k) - x / k
+                }
+            }
+            WaveshaperCurve::Fold => (T::ONE - (x * T::PI * half).cos()) * T::new(2.) / T::PI,
+        }
+    }
+}
+/// How quickly the negative half of [`WaveshaperCurve::Tube`] saturates. The negative half
+/// saturates at `-1.0 / TUBE_NEGATIVE_HARDNESS`.
+const TUBE_NEGATIVE_HARDNESS: f64 = 2.0;
+
+/// Anti-aliased waveshaper with a selection of curves.
+///
+/// The input is multiplied by `drive` and offset by `bias` before the curve is applied. The shaped
+/// signal is DC blocked and mixed with the dry input according to `mix`. Because of ADAA, the
+/// shaped signal is delayed half a sample relative to the dry signal.
+#[derive(Clone, Debug)]
+pub struct Waveshaper<F: Float = f32> {
+    curve: WaveshaperCurve,
+    drive: F,
+    bias: F,
+    mix: F,
+    adaa: Adaa1,
+    dc_blocker: OnePole<F>,
+}
+#[impl_ugen]
+impl<F: Float> Waveshaper<F> {
+    #[allow(missing_docs)]
+    pub fn new(curve: WaveshaperCurve, drive: F) -> Self {
+        Self {
+            curve,
+            drive,
+            bias: F::ZERO,
+            mix: F::ONE,
+            adaa: Adaa1::new(),
+            dc_blocker: OnePole::new(),
+        }
+    }
+    /// Linear gain applied to the input before shaping
+    #[param(default = 1.0, range = 0.0..=100.0, logarithmic = true)]
+    pub fn drive(&mut self, drive: PFloat) {
+        self.drive = F::new(drive);
+    }
+    /// DC offset added to the input after `drive`. Moves the operating point of the curve,
+    /// introducing even harmonics.
+    #[param(default = 0.0, range = -1.0..=1.0)]
+    pub fn bias(&mut self, bias: PFloat) {
+        self.bias = F::new(bias);
+    }
+    /// Dry/wet balance where 0.0 is only the dry signal and 1.0 is only the shaped signal
+    #[param(default = 1.0, range = 0.0..=1.0)]
+    pub fn mix(&mut self, mix: PFloat) {
+        self.mix = F::new(mix);
+    }
+    /// Set the curve
+    #[param(from = WaveshaperCurve)]
+    pub fn curve(&mut self, curve: PInteger) {
+        self.curve = WaveshaperCurve::from(curve);
+        let curve = self.curve;
+        let x1 = self.adaa.x1;
+        self.adaa.reset(x1, |x| curve.antiderivative(x));
+    }
+    fn init(&mut self, sample_rate: u32, _block_size: usize) {
+        self.dc_blocker
+            .set_freq_highpass(F::new(DC_BLOCKER_FREQ), F::new(sample_rate as f32));
+        let curve = self.curve;
+        self.adaa.reset(0.0, |x| curve.antiderivative(x));
+    }
+    fn process(&mut self, input: [F; 1]) -> [F; 1] {
+        [self.process_sample(input[0])]
+    }
+    /// Process one sample
+    #[inline]
+    pub fn process_sample(&mut self, input: F) -> F {
+        let curve = self.curve;
+        let x = (input * self.drive + self.bias).to_f64();
+        let shaped = self
+            .adaa
+            .process(x, |x| curve.shape(x), |x| curve.antiderivative(x));
+        let wet = self.dc_blocker.process_hp(F::new(shaped));
+        input + (wet - input) * self.mix
+    }
+}
+
+#[cfg(any(feature = "alloc", feature = "std"))]
+mod waveshaper_table {
+    use crate::dsp::buffer::Buffer;
+    use crate::onepole::OnePole;
+    use knaster_core::{Float, PFloat, impl_ugen};
+    use std::prelude::v1::*;
+
+    use super::{Adaa1, DC_BLOCKER_FREQ};
+
+    /// A waveshaping curve stored as a lookup table covering the input range -1.0 to 1.0.
+    ///
+    /// Values are linearly interpolated. Inputs outside of the table range continue with the slope
+    /// of the end points, i.e. the curve is flat beyond its ends.
+    #[derive(Clone, Debug)]
+    pub struct ShaperTable {
+        values: Vec<f64>,
+        /// The antiderivative at every table point, integrated exactly from the interpolated
+        /// curve.
+        antiderivative: Vec<f64>,
+        step: f64,
+    }
+    impl ShaperTable {
+        /// Create a table of `size` points by evaluating `c` at evenly spaced inputs from -1.0 to 1.0.
+        ///
+        /// # Panics
+        /// If `size` is less than 2
+        pub fn from_closure(size: usize, c: impl Fn(f64) -> f64) -> Self {
+            assert!(size >= 2, "a shaper table needs at least 2 points");
+            let values = (0..size)
+                .map(|i| c(-1.0 + 2.0 * i as f64 / (size - 1) as f64))
+                .collect();
+            Self::from_values(values)
+        }
+        /// Create a table from the given channel of a [`Buffer`]. The first frame of the buffer
+        /// corresponds to the input -1.0 and the last frame to 1.0.
+        ///
+        /// # Panics
+        /// If the buffer has less than 2 frames or `channel` is out of bounds
+        pub fn from_buffer<F: Float>(buffer: &Buffer<F>, channel: usize) -> Self {
+            assert!(channel < buffer.num_channels());
+            let num_frames = buffer.num_frames() as usize;
+            assert!(num_frames >= 2, "a shaper table needs at least 2 points");
+            let values = (0..num_frames)
+                .map(|i| buffer.get_interleaved(i)[channel].to_f64())
+                .collect();
+            Self::from_values(values)
+        }
+        fn from_values(values: Vec<f64>) -> Self {
+            let step = 2.0 / (values.len() - 1) as f64;
+            let mut antiderivative = Vec::with_capacity(values.len());
+            let mut acc = 0.0;
+            antiderivative.push(acc);
+            for pair in values.windows(2) {
+                acc += (pair[0] + pair[1]) * 0.5 * step;
+                antiderivative.push(acc);
+            }
+            let mut table = Self {
+                values,
+                antiderivative,
+                step,
+            };
+            // Make the antiderivative 0.0 at x = 0.0 to keep values small around the origin
+            let offset = table.antiderivative(0.0);
+            for v in &mut table.antiderivative {
+                *v -= offset;
+            }
+            table
+        }
+        /// Returns the segment index and the position within that segment (0.0..=1.0) for `x`
+        #[inline]
+        fn segment(&self, x: f64) -> (usize, f64) {
+            let pos = ((x + 1.0) / self.step).clamp(0.0, (self.values.len() - 1) as f64);
+            let index = (pos as usize).min(self.values.len() - 2);
+            (index, pos - index as f64)
+        }
+        /// Evaluate the curve with linear interpolation
+        #[inline]
+        pub fn shape(&self, x: f64) -> f64 {
+            let (i, frac) = self.segment(x);
+            self.values[i] + (self.values[i + 1] - self.values[i]) * frac
+        }
+        /// Evaluate the exact antiderivative of the linearly interpolated curve
+        #[inline]
+        pub fn antiderivative(&self, x: f64) -> f64 {
+            let last = self.values.len() - 1;
+            if x < -1.0 {
+                self.antiderivative[0] + (x + 1.0) * self.values[0]
+            } else if x > 1.0 {
+                self.antiderivative[last] + (x - 1.0) * self.values[last]
+            } else {
+                let (i, frac) = self.segment(x);
+                let y0 = self.values[i];
+                let y1 = self.values[i + 1];
+                self.antiderivative[i] + frac * self.step * (y0 + 0.5 * frac * (y1 - y0))
+            }
+        }
+    }
+
+    /// Anti-aliased waveshaper using a [`ShaperTable`] as its curve.
+    ///
+    /// Has the same `drive`, `bias` and `mix` parameters as [`Waveshaper`](super::Waveshaper).
+    #[derive(Clone, Debug)]
+    pub struct TableWaveshaper<F: Float = f32> {
+        table: ShaperTable,
+        drive: F,
+        bias: F,
+        mix: F,
+        adaa: Adaa1,
+        dc_blocker: OnePole<F>,
+    }
+    #[impl_ugen]
+    impl<F: Float> TableWaveshaper<F> {
+        #[allow(missing_docs)]
+        pub fn new(table: ShaperTable, drive: F) -> Self {
+            Self {
+                table,
+                drive,
+                bias: F::ZERO,
+                mix: F::ONE,
+                adaa: Adaa1::new(),
+                dc_blocker: OnePole::new(),
+            }
+        }
+        /// Create a new TableWaveshaper from a closure evaluated at `size` points in the range
+        /// -1.0 to 1.0
+        pub fn from_closure(size: usize, c: impl Fn(f64) -> f64) -> Self {
+            Self::new(ShaperTable::from_closure(size, c), F::ONE)
+        }
+        /// Linear gain applied to the input before shaping
+        #[param(default = 1.0, range = 0.0..=100.0, logarithmic = true)]
+        pub fn drive(&mut self, drive: PFloat) {
+            self.drive = F::new(drive);
+        }
+        /// DC offset added to the input after `drive`
+        #[param(default = 0.0, range = -1.0..=1.0)]
+        pub fn bias(&mut self, bias: PFloat) {
+            self.bias = F::new(bias);
+        }
+        /// Dry/wet balance where 0.0 is only the dry signal and 1.0 is only the shaped signal
+        #[param(default = 1.0, range = 0.0..=1.0)]
+        pub fn mix(&mut self, mix: PFloat) {
+            self.mix = F::new(mix);
+        }
+        fn init(&mut self, sample_rate: u32, _block_size: usize) {
+            self.dc_blocker
+                .set_freq_highpass(F::new(DC_BLOCKER_FREQ), F::new(sample_rate as f32));
+            let table = &self.table;
+            self.adaa.reset(0.0, |x| table.antiderivative(x));
+        }
+        fn process(&mut self, input: [F; 1]) -> [F; 1] {
+            [self.process_sample(input[0])]
+        }
+        /// Process one sample
+        #[inline]
+        pub fn process_sample(&mut self, input: F) -> F {
+            let table = &self.table;
+            let x = (input * self.drive + self.bias).to_f64();
+            let shaped = self
+                .adaa
+                .process(x, |x| table.shape(x), |x| table.antiderivative(x));
+            let wet = self.dc_blocker.process_hp(F::new(shaped));
+            input + (wet - input) * self.mix
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The antiderivatives must match the curves, otherwise ADAA produces garbage
+    #[test]
+    fn antiderivatives_match_curves() {
+        let curves = [
+            WaveshaperCurve::Tanh,
+            WaveshaperCurve::SoftClip,
+            WaveshaperCurve::HardClip,
+            WaveshaperCurve::Tube,
+            WaveshaperCurve::Fold,
+        ];
+        let h = 1e-5;
+        for curve in curves {
+            assert!(curve.antiderivative(0.0_f64).abs() < 1e-12);
+            for i in -400..400 {
+                let x = i as f64 * 0.01 + 0.005;
+                let derivative =
+                    (curve.antiderivative(x + h) - curve.antiderivative(x - h)) / (2.0 * h);
+                assert!(
+                    (derivative - curve.shape(x)).abs() < 1e-5,
+                    "{curve:?} at {x}: {derivative} != {}",
+                    curve.shape(x)
+                );
+            }
+        }
+    }
+    #[cfg(any(feature = "alloc", feature = "std"))]
+    #[test]
+    fn table_matches_closure() {
+        let table = ShaperTable::from_closure(1025, |x| x.tanh());
+        for i in -300..300 {
+            let x = i as f64 * 0.01;
+            let expected = WaveshaperCurve::Tanh.shape(x.clamp(-1.0, 1.0));
+            assert!((table.shape(x) - expected).abs() < 1e-5);
+            let h = 1e-5;
+            let derivative =
+                (table.antiderivative(x + h) - table.antiderivative(x - h)) / (2.0 * h);
+            assert!((derivative - table.shape(x)).abs() < 1e-4);
+        }
+    }
+}
@@ -38,6 +38,9 @@ impl<T: UGen> UGen for WrArParams<T> {
     fn init(&mut self, sample_rate: u32, block_size: usize) {
         self.ugen.init(sample_rate, block_size);
     }
+    fn reseed(&mut self, seed: u64) {
+        self.ugen.reseed(seed);
+    }
 
     fn process(
         &mut self,
@@ -112,6 +115,9 @@ where
         // TODO: check that this parameter is a float parameter
         self.ugen.init(sample_rate, block_size)
     }
+    fn reseed(&mut self, seed: u64) {
+        self.ugen.reseed(seed);
+    }
 
     fn process(
         &mut self,
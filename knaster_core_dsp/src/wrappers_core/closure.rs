@@ -22,6 +22,9 @@ impl<T: UGen, C: FnMut(T::Sample) -> T::Sample + 'static> UGen for WrClosure<T,
     fn init(&mut self, sample_rate: u32, block_size: usize) {
         self.ugen.init(sample_rate, block_size);
     }
+    fn reseed(&mut self, seed: u64) {
+        self.ugen.reseed(seed);
+    }
 
     fn process(
         &mut self,
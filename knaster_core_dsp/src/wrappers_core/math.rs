@@ -36,6 +36,9 @@ where
     fn init(&mut self, sample_rate: u32, block_size: usize) {
         self.ugen.init(sample_rate, block_size);
     }
+    fn reseed(&mut self, seed: u64) {
+        self.ugen.reseed(seed);
+    }
 
     fn process(
         &mut self,
@@ -131,6 +134,9 @@ impl<T: UGen> UGen for WrAdd<T> {
     fn init(&mut self, sample_rate: u32, block_size: usize) {
         self.ugen.init(sample_rate, block_size);
     }
+    fn reseed(&mut self, seed: u64) {
+        self.ugen.reseed(seed);
+    }
     fn process(
         &mut self,
         ctx: &mut AudioCtx,
@@ -209,6 +215,9 @@ impl<T: UGen> UGen for WrSub<T> {
     fn init(&mut self, sample_rate: u32, block_size: usize) {
         self.ugen.init(sample_rate, block_size);
     }
+    fn reseed(&mut self, seed: u64) {
+        self.ugen.reseed(seed);
+    }
     fn process(
         &mut self,
         ctx: &mut AudioCtx,
@@ -287,6 +296,9 @@ impl<T: UGen> UGen for WrVSub<T> {
     fn init(&mut self, sample_rate: u32, block_size: usize) {
         self.ugen.init(sample_rate, block_size);
     }
+    fn reseed(&mut self, seed: u64) {
+        self.ugen.reseed(seed);
+    }
     fn process(
         &mut self,
         ctx: &mut AudioCtx,
@@ -366,6 +378,9 @@ impl<T: UGen> UGen for WrDiv<T> {
     fn init(&mut self, sample_rate: u32, block_size: usize) {
         self.ugen.init(sample_rate, block_size);
     }
+    fn reseed(&mut self, seed: u64) {
+        self.ugen.reseed(seed);
+    }
     fn process(
         &mut self,
         ctx: &mut AudioCtx,
@@ -444,6 +459,9 @@ impl<T: UGen> UGen for WrVDiv<T> {
     fn init(&mut self, sample_rate: u32, block_size: usize) {
         self.ugen.init(sample_rate, block_size);
     }
+    fn reseed(&mut self, seed: u64) {
+        self.ugen.reseed(seed);
+    }
     fn process(
         &mut self,
         ctx: &mut AudioCtx,
@@ -523,6 +541,9 @@ impl<T: UGen> UGen for WrPowf<T> {
     fn init(&mut self, sample_rate: u32, block_size: usize) {
         self.ugen.init(sample_rate, block_size);
     }
+    fn reseed(&mut self, seed: u64) {
+        self.ugen.reseed(seed);
+    }
     fn process(
         &mut self,
         ctx: &mut AudioCtx,
@@ -602,6 +623,9 @@ impl<T: UGen> UGen for WrPowi<T> {
     fn init(&mut self, sample_rate: u32, block_size: usize) {
         self.ugen.init(sample_rate, block_size);
     }
+    fn reseed(&mut self, seed: u64) {
+        self.ugen.reseed(seed);
+    }
     fn process(
         &mut self,
         ctx: &mut AudioCtx,
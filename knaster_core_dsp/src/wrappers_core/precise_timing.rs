@@ -47,6 +47,9 @@ impl<T: UGen, const DELAYED_CHANGES_PER_BLOCK: usize> UGen
     fn init(&mut self, sample_rate: u32, block_size: usize) {
         self.ugen.init(sample_rate, block_size);
     }
+    fn reseed(&mut self, seed: u64) {
+        self.ugen.reseed(seed);
+    }
 
     fn process(
         &mut self,
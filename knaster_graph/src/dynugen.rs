@@ -24,6 +24,8 @@ pub trait DynUGen<F: Float> {
     /// Initalize the UGen before sending it to the audio thread. This function may allocate and
     /// perform other potentially blocking operations without impacting the audio thread.
     fn init(&mut self, sample_rate: u32, block_size: usize);
+    /// Replace the seed of any randomness the UGen produces, see [`UGen::reseed`]
+    fn reseed(&mut self, seed: u64);
     /// Process one block of audio
     fn process_block(
         &mut self,
@@ -65,6 +67,9 @@ impl<F: Float, T: UGen<Sample = F> + 'static> DynUGen<F> for T {
     fn init(&mut self, sample_rate: u32, block_size: usize) {
         self.init(sample_rate, block_size)
     }
+    fn reseed(&mut self, seed: u64) {
+        self.reseed(seed)
+    }
 
     fn process_block(
         &mut self,
@@ -192,6 +197,11 @@ impl<F: Float> DynUGen<F> for UGenEnum<F> {
             UGenEnum::Dyn(ugen) => DynUGen::init(&mut (**ugen), sample_rate, block_size),
         }
     }
+    fn reseed(&mut self, seed: u64) {
+        if let UGenEnum::Dyn(ugen) = self {
+            DynUGen::reseed(&mut (**ugen), seed);
+        }
+    }
 
     fn process_block(
         &mut self,
@@ -20,7 +20,8 @@ use crate::{
     graph_edit::GraphEdit,
     graph_gen::GraphGen,
     handle::{Handle, RawHandle, SchedulingChannelSender},
-    node::{Node, NodeData},
+    node::{FreeFlag, Node, NodeData},
+    notifications::{FreeReason, NotificationReceiver, Notifier},
     task::{ArParameterChange, BlockOrGraphInput, OutputTask, Task, TaskData},
 };
 use core::cell::Cell;
@@ -29,15 +30,20 @@ use knaster_core::numeric_array::NumericArray;
 /// no_std_compat prelude import, supporting both std and no_std
 use std::prelude::v1::*;
 
-use crate::inspection::{EdgeInspection, EdgeSource, GraphInspection, NodeInspection};
+use crate::inspection::{
+    EdgeInspection, EdgeSource, GraphInspection, NodeInspection, ProbeInspection,
+};
+use crate::probe::{ProbeReader, ProbeUGen};
 use crate::wrappers_graph::done::WrDone;
+use knaster_core::events::{EventReceiver, EventSource};
 use knaster_core::{
     AudioCtx, Done, Float, Param, ParameterError, ParameterValue, Size, UGen, log::ArLogSender,
     typenum::*,
 };
 use knaster_core_dsp::math::{Add, MathUGen};
+use knaster_core_dsp::noise::RandomnessSeeds;
 use rtrb::RingBuffer;
-use slotmap::{SecondaryMap, SlotMap, new_key_type};
+use slotmap::{KeyData, SecondaryMap, SlotMap, new_key_type};
 
 /// Unique id identifying a [`Graph`]. Is set from an atomic any time a [`Graph`] is created.
 ///
@@ -76,6 +82,14 @@ impl NodeId {
     pub fn key(&self) -> NodeKey {
         self.key
     }
+    /// Returns the NodeId of the node which sent an event. Events sent from outside of any
+    /// [`Graph`] don't have a valid NodeId.
+    pub fn from_event_source(source: EventSource) -> Self {
+        Self {
+            key: NodeKey::from(KeyData::from_ffi(source.node)),
+            graph: source.graph,
+        }
+    }
 }
 
 /// Options for a new [`Graph`]
@@ -179,6 +193,8 @@ pub struct Graph<F: Float> {
     node_mortality: SecondaryMap<NodeKey, bool>,
     node_order: Vec<NodeKey>,
     disconnected_nodes: Vec<NodeKey>,
+    /// Probes attached to node outputs in this graph
+    probes: Vec<ProbeInspection>,
     /// The outputs of the Graph
     output_edges: Box<[Option<Edge>]>,
     /// If changes have been made that require recalculating the graph this will be set to true.
@@ -196,16 +212,27 @@ pub struct Graph<F: Float> {
     /// The nodeId of the Graph node in the parent. Only the top level Graph has an invalid NodeId
     /// which will not allow any action.
     self_node_id: NodeId,
+    /// Receives events sent by nodes on the audio thread. Only set in the top level Graph until it
+    /// is taken.
+    pub(crate) event_receiver: Option<EventReceiver>,
+    /// Sends notifications about added and freed nodes. Shared by all graphs in the hierarchy.
+    notifier: Notifier,
+    /// Seeds for the randomness of pushed nodes if the hierarchy has a master seed. Shared by all
+    /// graphs in the hierarchy.
+    randomness_seeds: Option<Arc<RandomnessSeeds>>,
 }
 
 impl<F: Float> Graph<F> {
     /// Create a new empty [`Graph`] with a unique atomically generated [`GraphId`]
+    #[allow(clippy::too_many_arguments)]
     pub(crate) fn new<Inputs: Size, Outputs: Size>(
         options: GraphOptions,
         node_id: NodeId,
         shared_frame_clock: SharedFrameClock,
         block_size: usize,
         sample_rate: u32,
+        notifier: Notifier,
+        randomness_seeds: Option<Arc<RandomnessSeeds>>,
         init_callback: impl FnOnce(GraphEdit<F>),
     ) -> (Self, Node<F>) {
         let GraphOptions {
@@ -238,7 +265,8 @@ impl<F: Float> Graph<F> {
             next_change_flag: Arc::new(AtomicBool::new(false)),
             shared_frame_clock,
         };
-        let remove_me = Arc::new(AtomicBool::new(false));
+        let remove_me = Arc::new(FreeFlag::new());
+        notifier.graph_added(graph_id);
         let mut graph = Self {
             graph_id,
             name,
@@ -248,6 +276,7 @@ impl<F: Float> Graph<F> {
             node_mortality: SecondaryMap::with_capacity(DEFAULT_NUM_NODES),
             node_order: Vec::with_capacity(DEFAULT_NUM_NODES),
             disconnected_nodes: vec![],
+            probes: vec![],
             node_keys_to_free_when_safe: vec![],
             output_edges: vec![None; Outputs::USIZE].into(),
             num_inputs: Inputs::U16,
@@ -259,6 +288,9 @@ impl<F: Float> Graph<F> {
             buffers_to_free_when_safe: vec![],
             buffer_allocator,
             self_node_id: node_id,
+            event_receiver: None,
+            notifier,
+            randomness_seeds,
         };
         (init_callback)(GraphEdit::new(&mut graph));
         // graph_gen
@@ -311,6 +343,7 @@ impl<F: Float> Graph<F> {
         let mut graph_gen = Node::new(
             graph.name.clone(),
             GraphGen::<F, Inputs, Outputs> {
+                graph_id: graph.graph_id,
                 sample_rate: graph.sample_rate,
                 current_task_data: task_data,
                 block_size: graph.block_size,
@@ -399,7 +432,7 @@ impl<F: Float> Graph<F> {
         <T as UGen>::Parameters: crate::core::ops::Add<B1>,
         <<T as UGen>::Parameters as crate::core::ops::Add<B1>>::Output: Size,
     {
-        let free_self_flag = Arc::new(AtomicBool::new(false));
+        let free_self_flag = Arc::new(FreeFlag::new());
         let ugen = WrDone {
             ugen,
             free_self_flag: free_self_flag.clone(),
@@ -458,13 +491,27 @@ impl<F: Float> Graph<F> {
     }
 
     /// Add a node to this Graph. The Node will be (re)initialised with the
-    /// correct block size for this Graph.
+    /// correct block size for this Graph, and reseeded if the graph has a master seed.
     fn push_node(&mut self, mut node: Node<F>) -> NodeKey {
         self.recalculation_required = true;
 
+        if let Some(seeds) = &self.randomness_seeds {
+            node.reseed(seeds.next_seed());
+        }
         node.init(self.sample_rate, self.block_size);
         let node_inputs = node.data.inputs;
+        let is_graph = node.is_graph;
+        let free_flag = node.remove_me.clone();
         let key = self.get_nodes_mut().insert(node);
+        self.notifier.node_added(
+            NodeId {
+                key,
+                graph: self.graph_id,
+            },
+            is_graph,
+            free_flag,
+            self.current_frame(),
+        );
         self.node_input_edges
             .insert(key, vec![None; node_inputs as usize].into_boxed_slice());
         // self.node_feedback_edges.insert(key, vec![]);
@@ -1124,11 +1171,13 @@ impl<F: Float> Graph<F> {
                                 }
                             }
                         }
-                        self.free_node_from_key(key).ok();
+                        self.free_node_from_key(key, FreeReason::Dependency, self.current_frame())
+                            .ok();
                     }
                     (None, None) => {
                         // No inputs, simply remove
-                        self.free_node_from_key(key).ok();
+                        self.free_node_from_key(key, FreeReason::Dependency, self.current_frame())
+                            .ok();
                     }
                 }
             }
@@ -1155,7 +1204,8 @@ impl<F: Float> Graph<F> {
                 }
             }
             if unconnected {
-                self.free_node_from_key(key).ok();
+                self.free_node_from_key(key, FreeReason::Dependency, self.current_frame())
+                    .ok();
             }
         }
     }
@@ -1421,10 +1471,13 @@ impl<F: Float> Graph<F> {
             self.graph_gen_communicator.shared_frame_clock.clone(),
             self.block_size,
             self.sample_rate,
+            self.notifier.clone(),
+            self.randomness_seeds.clone(),
             |_| {},
         );
+        let mut graph_gen = graph_gen;
+        graph_gen.is_graph = Some(subgraph.graph_id);
         let node_key = self.push_node(graph_gen);
-        self.get_nodes_mut()[node_key].is_graph = Some(subgraph.graph_id);
         // Set the real NodeId of the Graph
         subgraph.self_node_id = NodeId {
             key: node_key,
@@ -1445,10 +1498,13 @@ impl<F: Float> Graph<F> {
             self.graph_gen_communicator.shared_frame_clock.clone(),
             self.block_size,
             self.sample_rate,
+            self.notifier.clone(),
+            self.randomness_seeds.clone(),
             init_callback,
         );
+        let mut graph_gen = graph_gen;
+        graph_gen.is_graph = Some(subgraph.graph_id);
         let node_key = self.push_node(graph_gen);
-        self.get_nodes_mut()[node_key].is_graph = Some(subgraph.graph_id);
         // Set the real NodeId of the Graph
         subgraph.self_node_id = NodeId {
             key: node_key,
@@ -1707,6 +1763,7 @@ impl<F: Float> Graph<F> {
     pub(crate) fn commit_changes(&mut self) -> Result<(), GraphError> {
         // We need to run free_old to know if there are nodes to free and hence a recalculation required.
         self.free_old();
+        self.notifier.notify_self_freed_nodes();
         self.graph_gen_communicator.free_old_task_data();
         if self.recalculation_required {
             self.calculate_node_order();
@@ -1724,12 +1781,53 @@ impl<F: Float> Graph<F> {
         }
         Ok(())
     }
+    /// Take the receiver of the events sent from nodes on the audio thread using
+    /// [`AudioCtx::send_event`](knaster_core::AudioCtx::send_event). Convert the source of an
+    /// event to a [`NodeId`] using [`NodeId::from_event_source`].
+    ///
+    /// Only the top level [`Graph`] has an event receiver and it can only be taken once. Returns
+    /// `None` otherwise.
+    pub fn take_event_receiver(&mut self) -> Option<EventReceiver> {
+        self.event_receiver.take()
+    }
+    /// Create a [`NotificationReceiver`] which receives notifications about nodes being added to and
+    /// freed from this graph and all other graphs in the same hierarchy. See
+    /// [`notifications`](crate::notifications) for more information.
+    pub fn notification_receiver(&self) -> NotificationReceiver {
+        self.notifier.receiver()
+    }
+    /// Free nodes in this graph which have freed themselves on the audio thread, and send
+    /// notifications about such nodes anywhere in the graph hierarchy, including subgraphs. This
+    /// also happens every time changes are committed, e.g. at the end of [`Graph::edit`].
+    ///
+    /// A parent graph doesn't own the [`Graph`] of a subgraph, so the memory of a node which freed
+    /// itself inside a subgraph is only reclaimed when changes to that subgraph are committed.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the updated graph could not be sent to the audio thread.
+    pub fn update(&mut self) -> Result<(), GraphError> {
+        self.commit_changes()
+    }
+    /// The current frame time on the audio thread, used for notifications
+    pub(crate) fn current_frame(&self) -> u64 {
+        self.graph_gen_communicator
+            .shared_frame_clock
+            .get()
+            .to_samples(self.sample_rate as u64)
+    }
     /// Returns the [`GraphId`] of this graph.
     pub fn graph_id(&self) -> GraphId {
         self.graph_id
     }
 
-    pub(crate) fn free_node_from_key(&mut self, node_key: NodeKey) -> Result<(), FreeError> {
+    /// Free a node, sending a notification with `reason` and the frame time `frame`
+    pub(crate) fn free_node_from_key(
+        &mut self,
+        node_key: NodeKey,
+        reason: FreeReason,
+        frame: u64,
+    ) -> Result<(), FreeError> {
         // Does the Node exist?
         if !self.get_nodes_mut().contains_key(node_key) {
             return Err(FreeError::NodeNotFound);
@@ -1746,6 +1844,29 @@ impl<F: Float> Graph<F> {
         }
         //
         self.recalculation_required = true;
+        self.notifier.node_freed(
+            NodeId {
+                key: node_key,
+                graph: self.graph_id,
+            },
+            reason,
+            frame,
+        );
+
+        // A probe is removed with the node it is attached to
+        let mut i = 0;
+        while i < self.probes.len() {
+            let probe = self.probes[i];
+            if probe.probe == node_key {
+                self.probes.remove(i);
+            } else if probe.source == node_key {
+                self.probes.remove(i);
+                self.free_node_from_key(probe.probe, FreeReason::Dependency, frame)
+                    .ok();
+            } else {
+                i += 1;
+            }
+        }
 
         // Remove all edges leading to the node
         if let Some(_edges) = self.node_input_edges.remove(node_key) {}
@@ -1792,12 +1913,80 @@ impl<F: Float> Graph<F> {
         self.node_keys_to_free_when_safe
             .push((node_key, ggc.next_change_flag.clone()));
         if let Some(dep) = self.get_nodes()[node_key].strong_dependent {
-            self.free_node_from_key(dep).ok();
+            self.free_node_from_key(dep, FreeReason::Dependency, frame)
+                .ok();
         }
         // self.node_keys_pending_removal.insert(node_key);
         Ok(())
     }
 
+    /// Attach a probe to output channel `channel` of `source`, copying the signal to the returned
+    /// [`ProbeReader`]. `capacity` is the number of samples kept by the reader and the size of the
+    /// ring buffer between the threads.
+    ///
+    /// The probe is attached when the changes are committed, see [`Graph::edit`] and
+    /// [`GraphEdit::probe`].
+    pub(crate) fn probe_internal(
+        &mut self,
+        source: impl Into<NodeId>,
+        channel: u16,
+        capacity: usize,
+    ) -> Result<ProbeReader<F>, GraphError> {
+        let source = source.into();
+        if source.graph != self.graph_id {
+            return Err(GraphError::WrongSourceNodeGraph {
+                expected_graph: self.graph_id,
+                found_graph: source.graph,
+            });
+        }
+        let nodes = self.get_nodes();
+        let Some(source_node) = nodes.get(source.key()) else {
+            return Err(GraphError::NodeNotFound);
+        };
+        if channel >= source_node.data.outputs {
+            return Err(GraphError::OutputOutOfBounds(channel));
+        }
+        let capacity = if capacity == 0 { 1 } else { capacity };
+        let (producer, consumer) = RingBuffer::new(capacity);
+        let probe = self.push_node(Node::new(
+            EcoString::from("Probe"),
+            ProbeUGen::new(producer),
+        ));
+        let probe_id = NodeId {
+            key: probe,
+            graph: self.graph_id,
+        };
+        self.connect_nodes_internal(source, probe_id, channel, 0, false, false)?;
+        self.probes.push(ProbeInspection {
+            probe,
+            source: source.key(),
+            channel,
+        });
+        Ok(ProbeReader::new(probe_id, consumer, capacity))
+    }
+    /// Remove a probe attached with [`Graph::probe_internal`]
+    pub(crate) fn remove_probe_internal(&mut self, probe: NodeId) -> Result<(), GraphError> {
+        if probe.graph != self.graph_id || !self.probes.iter().any(|p| p.probe == probe.key()) {
+            return Err(GraphError::ProbeNotFound);
+        }
+        self.free_node_from_key(probe.key(), FreeReason::Explicit, self.current_frame())?;
+        Ok(())
+    }
+    /// Attach a probe to output channel `channel` of `source` and commit the change. See
+    /// [`GraphEdit::probe`].
+    pub fn probe(
+        &mut self,
+        source: impl Into<NodeId>,
+        channel: u16,
+        capacity: usize,
+    ) -> Result<ProbeReader<F>, GraphError> {
+        self.edit(|graph| graph.probe(source, channel, capacity))
+    }
+    /// Remove a probe and commit the change. See [`GraphEdit::remove_probe`].
+    pub fn remove_probe(&mut self, probe: impl Into<NodeId>) -> Result<(), GraphError> {
+        self.edit(|graph| graph.remove_probe(probe))
+    }
+
     /// Generate inspection metadata for this graph. Intended for
     /// generating static or dynamic inspection and graph manipulation tools.
     pub fn inspection(&self) -> GraphInspection {
@@ -1806,6 +1995,10 @@ impl<F: Float> Graph<F> {
         let mut node_key_processed = Vec::with_capacity(real_nodes.len());
         let mut nodes = Vec::with_capacity(real_nodes.len());
         for &node_key in &self.node_order {
+            // Probes are listed separately
+            if self.probes.iter().any(|p| p.probe == node_key) {
+                continue;
+            }
             let node = &real_nodes[node_key];
             let mut input_edges = Vec::new();
             if let Some(edges) = self.node_input_edges.get(node_key) {
@@ -1860,6 +2053,7 @@ impl<F: Float> Graph<F> {
             num_outputs: self.num_outputs,
             graph_id: self.graph_id,
             graph_output_edges,
+            probes: self.probes.clone(),
             graph_name: self.name.clone(),
             param_sender: self
                 .graph_gen_communicator
@@ -1881,13 +2075,13 @@ impl<F: Float> Graph<F> {
         let mut free_queue = Vec::new();
         for (key, node) in self.get_nodes_mut() {
             if let Some(to_free) = &mut node.remove_me {
-                if to_free.load(Ordering::SeqCst) {
-                    free_queue.push(key);
+                if let Some(frame) = to_free.freed_at() {
+                    free_queue.push((key, frame));
                 }
             }
         }
-        for key in free_queue {
-            self.free_node_from_key(key).ok();
+        for (key, frame) in free_queue {
+            self.free_node_from_key(key, FreeReason::Done, frame).ok();
         }
         // Remove orphaned internal math nodes.
         // Math nodes should be removed when one of its inputs was removed
@@ -2271,6 +2465,9 @@ pub enum GraphError {
     #[error(transparent)]
     #[allow(missing_docs)]
     FreeError(#[from] FreeError),
+    #[error("The probe cannot be found in the current Graph. It may have been removed already.")]
+    #[allow(missing_docs)]
+    ProbeNotFound,
 }
 
 #[allow(missing_docs)]
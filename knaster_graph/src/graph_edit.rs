@@ -29,6 +29,8 @@ use crate::graph::{Channels, GraphError, GraphOptions, NodeOrGraph};
 use crate::graph_gen::GraphGen;
 use crate::handle::SchedulingChannelSender;
 use crate::node::NodeData;
+use crate::notifications::FreeReason;
+use crate::probe::ProbeReader;
 use crate::wrappers_graph::done::WrDone;
 
 use ecow::EcoString;
@@ -166,9 +168,27 @@ impl<'b, F: Float> GraphEdit<'b, F> {
                 found_graph: node.graph,
             });
         }
-        self.graph.write().free_node_from_key(node.key())?;
+        let mut g = self.graph.write();
+        let frame = g.current_frame();
+        g.free_node_from_key(node.key(), FreeReason::Explicit, frame)?;
         Ok(())
     }
+    /// Attach a probe to output channel `channel` of `source`, copying the signal to the returned
+    /// [`ProbeReader`] on the main thread without changing the graph's output. `capacity` is the
+    /// number of samples kept by the reader. See the [`probe`](crate::probe) module.
+    pub fn probe(
+        &self,
+        source: impl Into<NodeId>,
+        channel: u16,
+        capacity: usize,
+    ) -> Result<ProbeReader<F>, GraphError> {
+        self.graph.write().probe_internal(source, channel, capacity)
+    }
+    /// Remove a probe attached with [`GraphEdit::probe`], using the id from
+    /// [`ProbeReader::id`]. Probes are also removed when the node they are attached to is freed.
+    pub fn remove_probe(&self, probe: impl Into<NodeId>) -> Result<(), GraphError> {
+        self.graph.write().remove_probe_internal(probe.into())
+    }
     /// Create a new handle to the graph input(s).
     ///
     /// # Example
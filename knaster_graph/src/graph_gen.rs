@@ -3,24 +3,20 @@ use crate::core::sync::Arc;
 use crate::task::Task;
 use crate::{
     SchedulingEvent,
-    core::{
-        cell::UnsafeCell,
-        marker::PhantomData,
-        slice,
-        sync::atomic::{AtomicBool, Ordering},
-    },
+    core::{cell::UnsafeCell, marker::PhantomData, slice},
     dynugen::DynUGen,
 };
 
 use knaster_core::{
-    AudioCtx, Float, Size, UGen, UGenFlags, numeric_array::NumericArray, rt_log, typenum::U0,
+    AudioCtx, Float, Size, UGen, UGenFlags, events::EventSource, numeric_array::NumericArray,
+    rt_log, typenum::U0,
 };
-use slotmap::SlotMap;
+use slotmap::{Key, SlotMap};
 
 use crate::{
     SchedulingChannelConsumer,
-    graph::{NodeKey, OwnedRawBuffer},
-    node::Node,
+    graph::{GraphId, NodeKey, OwnedRawBuffer},
+    node::{FreeFlag, Node},
     task::TaskData,
 };
 
@@ -35,6 +31,8 @@ use crate::{
 /// mustn't use the _arc_nodes field; it is only there to make sure the nodes
 /// don't get dropped.
 pub struct GraphGen<F: Float, Inputs: Size, Outputs: Size> {
+    // The id of the Graph, used to identify the source of events sent by nodes
+    pub(super) graph_id: GraphId,
     // block_size with oversampling applied
     pub(super) block_size: usize,
     // sample_rate with oversampling applied
@@ -60,7 +58,7 @@ pub struct GraphGen<F: Float, Inputs: Size, Outputs: Size> {
     pub(super) scheduling_event_receiver: SchedulingChannelConsumer,
     pub(super) task_data_to_be_dropped_producer: rtrb::Producer<TaskData<F>>,
     pub(super) new_task_data_consumer: rtrb::Consumer<TaskData<F>>,
-    pub(super) remove_me_flag: Arc<AtomicBool>,
+    pub(super) remove_me_flag: Arc<FreeFlag>,
     pub(super) _channels: PhantomData<(NumericArray<(), Inputs>, NumericArray<(), Outputs>)>,
     pub(super) blocks_to_keep_scheduled_changes: u32,
 }
@@ -176,7 +174,7 @@ impl<F: Float, Inputs: Size, Outputs: Size> UGen for GraphGen<F, Inputs, Outputs
             graph_input_channels_to_nodes,
             applied: _,
             ar_parameter_changes: _,
-            node_task_order: _,
+            node_task_order,
         } = task_data;
 
         if let Some(buffer_allocation) = new_buffer_allocation.take() {
@@ -195,9 +193,15 @@ impl<F: Float, Inputs: Size, Outputs: Size> UGen for GraphGen<F, Inputs, Outputs
 
         let mut new_flags = UGenFlags::default();
         // Run the tasks
-        for task in tasks.iter_mut() {
+        let parent_event_source = ctx.event_source();
+        for (task, key) in tasks.iter_mut().zip(node_task_order.iter()) {
+            ctx.set_event_source(EventSource {
+                graph: self.graph_id,
+                node: key.data().as_ffi(),
+            });
             task.run(ctx, &mut new_flags);
         }
+        ctx.set_event_source(parent_event_source);
 
         // Set the output of the graph
         // Zero the output buffer.
@@ -234,7 +238,8 @@ impl<F: Float, Inputs: Size, Outputs: Size> UGen for GraphGen<F, Inputs, Outputs
                 }
             }
             self.freed = true;
-            self.remove_me_flag.store(true, Ordering::SeqCst);
+            let frame_in_block = (frame_num as usize).min(self.block_size) as u64;
+            self.remove_me_flag.set(ctx.frame_clock() + frame_in_block);
         }
     }
 
@@ -20,6 +20,8 @@ pub struct GraphInspection {
     pub nodes: Vec<NodeInspection>,
     /// The indices of nodes connected to the graph output(s)
     pub graph_output_edges: Vec<EdgeInspection>,
+    /// The active probes in the graph. The probe nodes are not included in `nodes`.
+    pub probes: Vec<ProbeInspection>,
     /// Number of inputs to the graph
     pub num_inputs: u16,
     /// Number of outputs from the graph
@@ -241,6 +243,17 @@ pub struct NodeInspection {
     pub is_graph: Option<GraphId>,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+/// Metadata about a probe attached with [`GraphEdit::probe`](crate::graph_edit::GraphEdit::probe)
+pub struct ProbeInspection {
+    /// The probe node, which is also the id of the probe
+    pub probe: NodeKey,
+    /// The node whose output is probed
+    pub source: NodeKey,
+    /// The probed output channel of the source
+    pub channel: u16,
+}
+
 #[derive(Debug, Clone, Copy)]
 /// Metadata for an edge.
 #[allow(missing_docs)]
@@ -71,6 +71,10 @@ pub mod inspection;
 #[cfg(any(feature = "std", feature = "alloc"))]
 mod node;
 #[cfg(any(feature = "std", feature = "alloc"))]
+pub mod notifications;
+#[cfg(any(feature = "std", feature = "alloc"))]
+pub mod probe;
+#[cfg(any(feature = "std", feature = "alloc"))]
 pub mod processor;
 #[cfg(any(feature = "std", feature = "alloc"))]
 mod scheduling;
@@ -1,5 +1,5 @@
 use crate::core::sync::Arc;
-use crate::core::sync::atomic::AtomicBool;
+use crate::core::sync::atomic::{AtomicU64, Ordering};
 use crate::dynugen::UGenEnum;
 /// no_std_compat prelude import, supporting both std and no_std
 use std::prelude::v1::*;
@@ -10,6 +10,33 @@ use knaster_core::{Float, ParameterHint};
 use crate::graph::{GraphId, NodeKey};
 use crate::{buffer_allocator::BufferAllocator, dynugen::DynUGen, task::Task};
 
+/// Flag set on the audio thread when a node should be freed, together with the frame time at
+/// which it happened.
+pub(crate) struct FreeFlag {
+    // 0 if not set, otherwise the frame time + 1
+    frame: AtomicU64,
+}
+impl FreeFlag {
+    pub(crate) fn new() -> Self {
+        Self {
+            frame: AtomicU64::new(0),
+        }
+    }
+    /// Mark the node to be freed at frame time `frame`. Only the first call has an effect.
+    pub(crate) fn set(&self, frame: u64) {
+        self.frame
+            .compare_exchange(0, frame + 1, Ordering::SeqCst, Ordering::SeqCst)
+            .ok();
+    }
+    /// Returns the frame time the node was marked to be freed at, if it has been marked
+    pub(crate) fn freed_at(&self) -> Option<u64> {
+        match self.frame.load(Ordering::SeqCst) {
+            0 => None,
+            frame => Some(frame - 1),
+        }
+    }
+}
+
 #[derive(Clone, Copy, Debug)]
 pub struct NodeData {
     pub(crate) parameter_descriptions_fn: fn(usize) -> Option<&'static str>,
@@ -79,8 +106,8 @@ pub(crate) struct Node<F: Float> {
     /// allocating buffers.
     pub(crate) num_output_dependents: usize,
     /// If this node can signal its own removal from the audio thread, it will
-    /// do so by setting this AtomicBool to true.
-    pub(crate) remove_me: Option<Arc<AtomicBool>>,
+    /// do so by setting this flag.
+    pub(crate) remove_me: Option<Arc<FreeFlag>>,
 }
 impl<F: Float> Node<F> {
     pub fn new<T: DynUGen<F> + 'static>(name: EcoString, ugen: T) -> Self {
@@ -118,6 +145,11 @@ impl<F: Float> Node<F> {
             ugen.init(sample_rate, block_size);
         }
     }
+    pub fn reseed(&mut self, seed: u64) {
+        if let NodeUGen::Local(ugen) = &mut self.ugen {
+            ugen.reseed(seed);
+        }
+    }
     pub fn ugen(&mut self) -> Option<&mut UGenEnum<F>> {
         match &mut self.ugen {
             NodeUGen::Local(ugen) => Some(ugen),
@@ -0,0 +1,276 @@
+//! # Notifications
+//!
+//! Notifications tell the main thread when nodes are added to or freed from a [`Graph`], including
+//! nodes freeing themselves from the audio thread using [`Done`]. This is useful for keeping
+//! application state such as voice bookkeeping or a GUI in sync with the graph.
+//!
+//! Get a [`NotificationReceiver`] using [`Graph::notification_receiver`]. Notifications from all
+//! graphs in the same graph hierarchy are sent to the same receiver. Nodes freeing themselves
+//! anywhere in the hierarchy are detected the next time changes to any graph in it are committed,
+//! e.g. at the end of [`Graph::edit`], or when calling [`Graph::update`], so regularly updating
+//! the top level graph is enough to also hear about nodes in subgraphs, such as voices.
+//!
+//! ```rust
+//! # use knaster_graph::{processor::AudioProcessor, processor::AudioProcessorOptions, typenum::*};
+//! # use knaster_graph::{Done, envelopes::EnvAsr, notifications::{FreeReason, GraphNotification}};
+//! # let (mut graph, mut audio_processor, _log_receiver) = AudioProcessor::<f32>::new::<U0, U1>(AudioProcessorOptions{
+//! #     block_size: 16,
+//! #     sample_rate: 48000,
+//! #     ring_buffer_size: 50,
+//! #     ..Default::default()
+//! # });
+//! let mut notifications = graph.notification_receiver();
+//! let env = graph.edit(|graph| {
+//!     let env = graph.push_with_done_action(EnvAsr::new(0.0, 0.0), Done::FreeSelf);
+//!     env.to_graph_out();
+//!     env.id()
+//! });
+//! assert!(matches!(
+//!     notifications.try_recv(),
+//!     Some(GraphNotification::NodeAdded { node, .. }) if node == env
+//! ));
+//! ```
+
+use crate::core::collections::{HashMap, VecDeque};
+use crate::core::sync::{Arc, Mutex, MutexGuard};
+#[allow(unused)]
+use crate::graph::Graph;
+use crate::graph::{GraphId, NodeId, NodeKey};
+use crate::node::FreeFlag;
+#[allow(unused)]
+use knaster_core::Done;
+/// no_std_compat prelude import, supporting both std and no_std
+use std::prelude::v1::*;
+
+/// Why a node was freed
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FreeReason {
+    /// The node marked itself as done and its [`Done`] action freed it, or it freed itself in some
+    /// other way from the audio thread.
+    Done,
+    /// The node was freed from the main thread, e.g. using `GraphEdit::free_node`.
+    Explicit,
+    /// The node was in a graph which was freed.
+    ParentFreed,
+    /// The node was freed because a node it depends on was freed, e.g. an automatically inserted
+    /// math node which lost one of its inputs or a probe on a node.
+    Dependency,
+}
+
+/// A change to the nodes in a [`Graph`].
+///
+/// The frame time is the number of frames since the audio processing started. For nodes freed
+/// from the audio thread it is the exact time they were marked as done. For changes made on the
+/// main thread it is the frame time on the audio thread when the change was made, i.e. the change
+/// takes effect on the audio thread a short time after.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GraphNotification {
+    /// A node was added to a graph
+    NodeAdded {
+        /// The new node
+        node: NodeId,
+        /// The frame time when the node was added
+        frame: u64,
+    },
+    /// A node was freed
+    NodeFreed {
+        /// The freed node
+        node: NodeId,
+        /// Why the node was freed
+        reason: FreeReason,
+        /// The frame time when the node was freed
+        frame: u64,
+    },
+    /// A graph was freed. All nodes in the graph are freed with [`FreeReason::ParentFreed`] before
+    /// this notification.
+    GraphFreed {
+        /// The freed graph
+        graph: GraphId,
+        /// The node of the graph in its parent graph
+        node: NodeId,
+        /// Why the graph was freed
+        reason: FreeReason,
+        /// The frame time when the graph was freed
+        frame: u64,
+    },
+}
+
+#[derive(Default)]
+struct NotifierState {
+    /// The number of receivers. Notifications are only queued if there is a receiver.
+    receivers: usize,
+    queue: VecDeque<GraphNotification>,
+    /// The nodes of every live graph
+    graphs: HashMap<GraphId, Vec<NotifierNode>>,
+}
+struct NotifierNode {
+    key: NodeKey,
+    /// The id of the graph if the node is a graph
+    subgraph: Option<GraphId>,
+    /// Set on the audio thread if the node frees itself
+    free_flag: Option<Arc<FreeFlag>>,
+}
+impl NotifierState {
+    fn push(&mut self, notification: GraphNotification) {
+        if self.receivers > 0 {
+            self.queue.push_back(notification);
+        }
+    }
+    /// Remove `graph` and send notifications for all nodes in it
+    fn free_graph_contents(&mut self, graph: GraphId, frame: u64) {
+        let Some(nodes) = self.graphs.remove(&graph) else {
+            return;
+        };
+        for NotifierNode { key, subgraph, .. } in nodes {
+            let node = NodeId { key, graph };
+            match subgraph {
+                Some(subgraph) => {
+                    self.free_graph_contents(subgraph, frame);
+                    self.push(GraphNotification::GraphFreed {
+                        graph: subgraph,
+                        node,
+                        reason: FreeReason::ParentFreed,
+                        frame,
+                    });
+                }
+                None => self.push(GraphNotification::NodeFreed {
+                    node,
+                    reason: FreeReason::ParentFreed,
+                    frame,
+                }),
+            }
+        }
+    }
+}
+
+fn lock(state: &Mutex<NotifierState>) -> MutexGuard<'_, NotifierState> {
+    // no_std_compat uses `spin` replacements for Mutex, which has a different API.
+    #[cfg(feature = "std")]
+    {
+        // Lock should never be poisoned, but if it is we don't care.
+        match state.lock() {
+            Ok(s) => s,
+            Err(s) => s.into_inner(),
+        }
+    }
+    #[cfg(not(feature = "std"))]
+    {
+        state.lock()
+    }
+}
+
+/// Keeps track of the nodes in all graphs in a graph hierarchy and sends notifications when they
+/// change. Shared by all graphs in the hierarchy.
+#[derive(Clone, Default)]
+pub(crate) struct Notifier(Arc<Mutex<NotifierState>>);
+impl Notifier {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+    pub(crate) fn graph_added(&self, graph: GraphId) {
+        lock(&self.0).graphs.entry(graph).or_default();
+    }
+    pub(crate) fn node_added(
+        &self,
+        node: NodeId,
+        subgraph: Option<GraphId>,
+        free_flag: Option<Arc<FreeFlag>>,
+        frame: u64,
+    ) {
+        let mut state = lock(&self.0);
+        let Some(nodes) = state.graphs.get_mut(&node.graph) else {
+            return;
+        };
+        nodes.push(NotifierNode {
+            key: node.key,
+            subgraph,
+            free_flag,
+        });
+        state.push(GraphNotification::NodeAdded { node, frame });
+    }
+    /// Send notifications for all nodes in the hierarchy which have freed themselves on the audio
+    /// thread, in the order they were freed. The nodes are removed from their graphs later, when
+    /// the graph they are in commits its changes.
+    pub(crate) fn notify_self_freed_nodes(&self) {
+        let mut freed: Vec<(u64, NodeId)> = {
+            let state = lock(&self.0);
+            state
+                .graphs
+                .iter()
+                .flat_map(|(&graph, nodes)| {
+                    nodes.iter().filter_map(move |n| {
+                        let frame = n.free_flag.as_ref()?.freed_at()?;
+                        Some((frame, NodeId { key: n.key, graph }))
+                    })
+                })
+                .collect()
+        };
+        freed.sort_by_key(|(frame, _)| *frame);
+        for (frame, node) in freed {
+            self.node_freed(node, FreeReason::Done, frame);
+        }
+    }
+    /// Send a notification that `node` was freed, unless that has already happened
+    pub(crate) fn node_freed(&self, node: NodeId, reason: FreeReason, frame: u64) {
+        let mut state = lock(&self.0);
+        let Some(nodes) = state.graphs.get_mut(&node.graph) else {
+            return;
+        };
+        let Some(i) = nodes.iter().position(|n| n.key == node.key) else {
+            return;
+        };
+        let subgraph = nodes.remove(i).subgraph;
+        match subgraph {
+            Some(subgraph) => {
+                state.free_graph_contents(subgraph, frame);
+                state.push(GraphNotification::GraphFreed {
+                    graph: subgraph,
+                    node,
+                    reason,
+                    frame,
+                });
+            }
+            None => state.push(GraphNotification::NodeFreed {
+                node,
+                reason,
+                frame,
+            }),
+        }
+    }
+    pub(crate) fn receiver(&self) -> NotificationReceiver {
+        lock(&self.0).receivers += 1;
+        NotificationReceiver {
+            notifier: self.clone(),
+        }
+    }
+}
+
+/// Receives [`GraphNotification`]s from all graphs in a graph hierarchy. See the
+/// [module documentation](self) for more information.
+///
+/// Notifications are only queued while there is a receiver. If there are several receivers, each
+/// notification is only received by one of them.
+pub struct NotificationReceiver {
+    notifier: Notifier,
+}
+impl NotificationReceiver {
+    /// Receive the next notification, if there is one
+    pub fn try_recv(&mut self) -> Option<GraphNotification> {
+        lock(&self.notifier.0).queue.pop_front()
+    }
+    /// Call `notification_handler` for every notification received since the last call
+    pub fn recv(&mut self, mut notification_handler: impl FnMut(GraphNotification)) {
+        while let Some(notification) = self.try_recv() {
+            notification_handler(notification);
+        }
+    }
+}
+impl Drop for NotificationReceiver {
+    fn drop(&mut self) {
+        let mut state = lock(&self.notifier.0);
+        state.receivers -= 1;
+        if state.receivers == 0 {
+            state.queue.clear();
+        }
+    }
+}
@@ -0,0 +1,145 @@
+//! # Probe
+//!
+//! Probes copy the signal on any node output channel to the main thread, e.g. for showing it in
+//! an oscilloscope while debugging. Attach a probe with [`GraphEdit::probe`] or [`Graph::probe`]
+//! and read the signal from the returned [`ProbeReader`]. Probes don't change the signal or the
+//! connections of the graph and can be removed at any time with [`GraphEdit::remove_probe`].
+//!
+//! ```rust
+//! # use knaster_graph::{processor::AudioProcessor, osc::SinWt, processor::AudioProcessorOptions, typenum::*};
+//! # use knaster_graph::probe::ProbeTrigger;
+//! # let (mut graph, mut audio_processor, _log_receiver) = AudioProcessor::new::<U0, U1>(AudioProcessorOptions{
+//! #     block_size: 16,
+//! #     sample_rate: 48000,
+//! #     ring_buffer_size: 50,
+//! #     ..Default::default()
+//! # });
+//! let mut probe = graph.edit(|graph| {
+//!     let sine = graph.push(SinWt::new(200.));
+//!     sine.to_graph_out();
+//!     graph.probe(sine.id(), 0, 4096).unwrap()
+//! });
+//! for _ in 0..100 {
+//!     audio_processor.run_without_inputs();
+//! }
+//! // One cycle of the sine, starting at an upwards zero crossing
+//! let window = probe.capture(240, ProbeTrigger::Rising(0.0));
+//! # assert!(window.is_some());
+//! ```
+
+use crate::core::collections::VecDeque;
+use crate::graph::NodeId;
+#[allow(unused)]
+use crate::{graph::Graph, graph_edit::GraphEdit};
+use knaster_core::{Float, impl_ugen};
+use rtrb::{Consumer, Producer};
+/// no_std_compat prelude import, supporting both std and no_std
+use std::prelude::v1::*;
+
+/// Copies its input to a ring buffer which is read by a [`ProbeReader`]. If the reader doesn't
+/// keep up, new samples are dropped.
+pub(crate) struct ProbeUGen<F: Copy> {
+    producer: Producer<F>,
+}
+#[impl_ugen]
+impl<F: Float> ProbeUGen<F> {
+    pub(crate) fn new(producer: Producer<F>) -> Self {
+        Self { producer }
+    }
+    fn process(&mut self, input: [F; 1]) -> [F; 0] {
+        self.producer.push(input[0]).ok();
+        []
+    }
+    fn process_block(&mut self, input: [&[F]; 1], _output: [&mut [F]; 0]) {
+        // Copy as much of the block as fits and drop the rest
+        let n = input[0].len().min(self.producer.slots());
+        if let Ok(chunk) = self.producer.write_chunk_uninit(n) {
+            chunk.fill_from_iter(input[0].iter().copied());
+        }
+    }
+}
+
+/// Condition for the start of a window captured by [`ProbeReader::capture`], like the trigger
+/// of an oscilloscope
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ProbeTrigger<F> {
+    /// No trigger, capture the latest samples
+    Free,
+    /// Start the window where the signal rises above the level
+    Rising(F),
+    /// Start the window where the signal falls below the level
+    Falling(F),
+}
+
+/// Reads the signal from a probe on the main thread. Reading never blocks the audio thread.
+///
+/// The reader keeps a history of the latest samples which windows are captured from. The history
+/// is as long as the capacity given when attaching the probe.
+pub struct ProbeReader<F: Copy> {
+    id: NodeId,
+    consumer: Consumer<F>,
+    history: VecDeque<F>,
+    capacity: usize,
+}
+impl<F: Float> ProbeReader<F> {
+    pub(crate) fn new(id: NodeId, consumer: Consumer<F>, capacity: usize) -> Self {
+        Self {
+            id,
+            consumer,
+            history: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+    /// The id of the probe, used to remove it
+    pub fn id(&self) -> NodeId {
+        self.id
+    }
+    /// Move new samples from the audio thread into the history. Called by the other methods
+    /// reading samples, but needs to be called regularly to not drop samples if the reader is not
+    /// read often.
+    pub fn update(&mut self) {
+        let available = self.consumer.slots();
+        if available == 0 {
+            return;
+        }
+        if let Ok(chunk) = self.consumer.read_chunk(available) {
+            for sample in chunk {
+                if self.history.len() == self.capacity {
+                    self.history.pop_front();
+                }
+                self.history.push_back(sample);
+            }
+        }
+    }
+    /// The latest samples in the history, oldest first, up to `len` samples
+    pub fn latest(&mut self, len: usize) -> Vec<F> {
+        self.update();
+        let start = self.history.len().saturating_sub(len);
+        self.history.range(start..).copied().collect()
+    }
+    /// Capture a window of `len` samples starting at the latest `trigger` for which there is a
+    /// whole window in the history. Returns `None` if there are not enough samples or the trigger
+    /// condition was not found.
+    pub fn capture(&mut self, len: usize, trigger: ProbeTrigger<F>) -> Option<Vec<F>> {
+        self.update();
+        if len == 0 || self.history.len() < len {
+            return None;
+        }
+        let last_start = self.history.len() - len;
+        let start = match trigger {
+            ProbeTrigger::Free => Some(last_start),
+            ProbeTrigger::Rising(level) => (1..=last_start)
+                .rev()
+                .find(|&i| self.history[i - 1] <= level && self.history[i] > level),
+            ProbeTrigger::Falling(level) => (1..=last_start)
+                .rev()
+                .find(|&i| self.history[i - 1] >= level && self.history[i] < level),
+        }?;
+        Some(self.history.range(start..start + len).copied().collect())
+    }
+    /// Remove all samples from the history
+    pub fn clear(&mut self) {
+        self.update();
+        self.history.clear();
+    }
+}
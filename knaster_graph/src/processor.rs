@@ -5,13 +5,17 @@
 //! context for non-realtime processing.
 
 use knaster_core::Seconds;
+use knaster_core::events::event_channel;
 use knaster_core::log::ArLogReceiver;
 use knaster_core::typenum::U1;
 use knaster_core::{AudioCtx, Float, Size, UGenFlags, typenum::NonZero};
+use knaster_core_dsp::noise::RandomnessSeeds;
 
 use crate::SharedFrameClock;
+use crate::core::sync::Arc;
 use crate::dynugen::DynUGen;
 use crate::graph::NodeId;
+use crate::notifications::Notifier;
 use crate::{
     block::{RawAggregateBlockRead, RawContiguousBlock},
     graph::{Graph, GraphOptions, OwnedRawBuffer},
@@ -32,6 +36,15 @@ pub struct AudioProcessorOptions {
     /// Log channel capacity for `ArLogMessage`s, i.e. those sent using the `rt_log` macro from the
     /// audio thread.
     pub log_channel_capacity: usize,
+    /// Event channel capacity for `UGenEvent`s, i.e. those sent using `AudioCtx::send_event`
+    /// from the audio thread. The receiver is taken using [`Graph::take_event_receiver`].
+    pub event_channel_capacity: usize,
+    /// If set, the master seed for randomness in the graph hierarchy. Every node pushed to the
+    /// [`Graph`] or one of its subgraphs is reseeded with the next seed derived from it, see
+    /// [`UGen::reseed`](knaster_core::UGen::reseed). Pushing the same nodes in the same order
+    /// then gives the same randomness every time, making renders reproducible. Other graphs and
+    /// UGens created outside a graph are not affected.
+    pub seed: Option<u64>,
 }
 impl Default for AudioProcessorOptions {
     fn default() -> Self {
@@ -40,6 +53,8 @@ impl Default for AudioProcessorOptions {
             sample_rate: 48000,
             ring_buffer_size: 1000,
             log_channel_capacity: 100,
+            event_channel_capacity: 1000,
+            seed: None,
         }
     }
 }
@@ -79,17 +94,24 @@ impl<F: Float> AudioProcessor<F> {
             name: "OuterGraph".into(),
             ring_buffer_size: options.ring_buffer_size,
         };
-        let (graph, node) = Graph::new::<Inputs, Outputs>(
+        let (mut graph, node) = Graph::new::<Inputs, Outputs>(
             graph_options,
             invalid_node_id,
             shared_frame_clock.clone(),
             block_size,
             sample_rate,
+            Notifier::new(),
+            options
+                .seed
+                .map(|seed| Arc::new(RandomnessSeeds::new(seed))),
             |_| {},
         );
         let log_receiver = ArLogReceiver::new();
         let (log_sender, log_receiver) = log_receiver.sender(options.log_channel_capacity);
-        let ctx = AudioCtx::new(sample_rate, block_size, log_sender);
+        let mut ctx = AudioCtx::new(sample_rate, block_size, log_sender);
+        let (event_sender, event_receiver) = event_channel(options.event_channel_capacity);
+        ctx.set_event_sender(event_sender);
+        graph.event_receiver = Some(event_receiver);
 
         let mut input_pointers = crate::core::vec::Vec::with_capacity(Inputs::USIZE);
         for _ in 0..Inputs::USIZE {
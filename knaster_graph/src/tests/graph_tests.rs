@@ -1,5 +1,5 @@
 use crate::Time;
-use crate::graph::NodeOrGraph;
+use crate::graph::{GraphError, NodeOrGraph};
 use crate::processor::AudioProcessorOptions;
 use crate::tests::utils::TestNumUGen;
 use crate::{processor::AudioProcessor, tests::utils::TestInPlusParamUGen};
@@ -295,3 +295,260 @@ fn disconnect() {
     let output = audio_processor.output_block();
     assert_eq!(output.read(0, 0), 0.125);
 }
+#[test]
+fn probe() {
+    let block_size = 16;
+    let (mut g, mut audio_processor, _log_receiver) =
+        AudioProcessor::<f32>::new::<U0, U1>(AudioProcessorOptions {
+            block_size,
+            sample_rate: 48000,
+            ring_buffer_size: 50,
+            ..Default::default()
+        });
+
+    let (n1, mut probe) = g.edit(|graph| {
+        let n1 = graph.push(TestInPlusParamUGen::new());
+        n1.param(0).set(0.5).unwrap();
+        n1.to_graph_out();
+        let probe = graph.probe(n1.id(), 0, 64).unwrap();
+        (n1.id(), probe)
+    });
+    assert!(matches!(
+        g.probe(n1, 1, 64),
+        Err(GraphError::OutputOutOfBounds(1))
+    ));
+    let inspection = g.inspection();
+    assert_eq!(inspection.nodes.len(), 1);
+    assert_eq!(inspection.probes.len(), 1);
+    assert_eq!(inspection.probes[0].source, n1.key());
+    assert_eq!(inspection.probes[0].probe, probe.id().key());
+
+    audio_processor.run_without_inputs();
+    audio_processor.run_without_inputs();
+    assert_eq!(audio_processor.output_block().read(0, 0), 0.5);
+    assert_eq!(probe.latest(64), vec![0.5; block_size * 2]);
+
+    g.remove_probe(probe.id()).unwrap();
+    assert!(g.inspection().probes.is_empty());
+    assert!(matches!(
+        g.remove_probe(probe.id()),
+        Err(GraphError::ProbeNotFound)
+    ));
+    audio_processor.run_without_inputs();
+    assert_eq!(audio_processor.output_block().read(0, 0), 0.5);
+    assert_eq!(probe.latest(64).len(), block_size * 2);
+
+    // Freeing the probed node removes the probe
+    let _probe = g.probe(n1, 0, 64).unwrap();
+    g.edit(|graph| graph.free_node(n1).unwrap());
+    assert!(g.inspection().probes.is_empty());
+}
+
+#[test]
+fn send_event() {
+    use crate::graph::NodeId;
+    use knaster_core_dsp::util::SendEvent;
+    let block_size = 16;
+    let (mut g, mut audio_processor, _log_receiver) =
+        AudioProcessor::<f32>::new::<U0, U1>(AudioProcessorOptions {
+            block_size,
+            sample_rate: 48000,
+            ring_buffer_size: 50,
+            ..Default::default()
+        });
+    let mut events = g.take_event_receiver().unwrap();
+    assert!(g.take_event_receiver().is_none());
+
+    let (trigger, send) = g.edit(|graph| {
+        let trigger = graph.push(TestInPlusParamUGen::new());
+        let value = graph.push(TestInPlusParamUGen::new());
+        value.param(0).set(0.25).unwrap();
+        let send = graph.push(SendEvent::<f32, U1>::new(7));
+        (trigger | value).to(send);
+        (trigger.id(), send.id())
+    });
+    audio_processor.run_without_inputs();
+    assert!(events.try_recv().is_none());
+
+    g.set(trigger, 0, 1.0, Time::asap()).unwrap();
+    for _ in 0..3 {
+        audio_processor.run_without_inputs();
+    }
+    let event = events.try_recv().unwrap();
+    assert_eq!(NodeId::from_event_source(event.source), send);
+    assert_eq!(event.id, 7);
+    assert_eq!(event.values(), &[0.25]);
+    assert_eq!(event.frame, block_size as u64);
+    assert!(events.try_recv().is_none());
+}
+
+#[test]
+fn notifications() {
+    use crate::graph::GraphOptions;
+    use crate::notifications::{FreeReason, GraphNotification};
+    use knaster_core::{Done, PTrigger};
+    use knaster_core_dsp::envelopes::EnvAsr;
+    let block_size = 16;
+    let (mut g, mut audio_processor, _log_receiver) =
+        AudioProcessor::<f32>::new::<U0, U1>(AudioProcessorOptions {
+            block_size,
+            sample_rate: 48000,
+            ring_buffer_size: 50,
+            ..Default::default()
+        });
+    let mut notifications = g.notification_receiver();
+
+    let (env, sub, inner, mut subgraph) = g.edit(|graph| {
+        let env = graph.push_with_done_action(EnvAsr::new(0.0, 0.0), Done::FreeSelf);
+        env.param("t_restart").set(PTrigger).unwrap();
+        env.param("t_release").set(PTrigger).unwrap();
+        let mut inner = None;
+        let (sub, subgraph) = graph.subgraph::<U0, U1>(GraphOptions::default(), |graph| {
+            let n = graph.push(TestInPlusParamUGen::new());
+            n.to_graph_out();
+            inner = Some(n.id());
+        });
+        sub.to_graph_out();
+        (env.id(), sub.id(), inner.unwrap(), subgraph)
+    });
+    let mut added = vec![];
+    notifications.recv(|n| {
+        if let GraphNotification::NodeAdded { node, frame: 0 } = n {
+            added.push(node);
+        }
+    });
+    assert_eq!(added.len(), 3);
+    for node in [env, sub, inner] {
+        assert!(added.contains(&node));
+    }
+
+    // The envelope frees itself on the audio thread
+    for _ in 0..10 {
+        audio_processor.run_without_inputs();
+    }
+    assert!(notifications.try_recv().is_none());
+    g.update().unwrap();
+    let freed = notifications.try_recv();
+    assert!(matches!(
+        freed,
+        Some(GraphNotification::NodeFreed {
+            node,
+            reason: FreeReason::Done,
+            frame,
+        }) if node == env && frame < (block_size * 10) as u64
+    ));
+    assert!(notifications.try_recv().is_none());
+
+    // Freeing the subgraph frees the nodes in it
+    g.edit(|graph| graph.free_node(sub).unwrap());
+    assert_eq!(
+        notifications.try_recv(),
+        Some(GraphNotification::NodeFreed {
+            node: inner,
+            reason: FreeReason::ParentFreed,
+            frame: (block_size * 10) as u64,
+        })
+    );
+    assert_eq!(
+        notifications.try_recv(),
+        Some(GraphNotification::GraphFreed {
+            graph: subgraph.graph_id(),
+            node: sub,
+            reason: FreeReason::Explicit,
+            frame: (block_size * 10) as u64,
+        })
+    );
+    // Nodes in a freed graph are only reported once
+    subgraph.edit(|graph| graph.free_node(inner).unwrap());
+    assert!(notifications.try_recv().is_none());
+}
+
+#[test]
+fn notifications_from_subgraphs() {
+    use crate::graph::GraphOptions;
+    use crate::notifications::{FreeReason, GraphNotification};
+    use knaster_core::{Done, PTrigger};
+    use knaster_core_dsp::envelopes::EnvAsr;
+    let block_size = 16;
+    let (mut g, mut audio_processor, _log_receiver) =
+        AudioProcessor::<f32>::new::<U0, U1>(AudioProcessorOptions {
+            block_size,
+            sample_rate: 48000,
+            ring_buffer_size: 50,
+            ..Default::default()
+        });
+    let mut notifications = g.notification_receiver();
+    let (env, mut subgraph) = g.edit(|graph| {
+        let mut env = None;
+        let (sub, subgraph) = graph.subgraph::<U0, U1>(GraphOptions::default(), |graph| {
+            let e = graph.push_with_done_action(EnvAsr::new(0.0, 0.0), Done::FreeSelf);
+            e.param("t_restart").set(PTrigger).unwrap();
+            e.param("t_release").set(PTrigger).unwrap();
+            e.to_graph_out();
+            env = Some(e.id());
+        });
+        sub.to_graph_out();
+        (env.unwrap(), subgraph)
+    });
+    notifications.recv(|_| {});
+
+    // Only the top level graph is updated
+    for _ in 0..10 {
+        audio_processor.run_without_inputs();
+    }
+    g.update().unwrap();
+    assert!(matches!(
+        notifications.try_recv(),
+        Some(GraphNotification::NodeFreed {
+            node,
+            reason: FreeReason::Done,
+            frame,
+        }) if node == env && frame < (block_size * 10) as u64
+    ));
+    assert!(notifications.try_recv().is_none());
+    // Removing the node from the subgraph later doesn't report it again
+    subgraph.update().unwrap();
+    assert!(notifications.try_recv().is_none());
+}
+
+#[test]
+fn seeded_graphs_are_reproducible() {
+    use knaster_core_dsp::noise::{WhiteNoise, next_randomness_seed};
+    let render = |seed: Option<u64>, explicit_seed: Option<u64>| {
+        let (mut g, mut audio_processor, _log_receiver) =
+            AudioProcessor::<f32>::new::<U0, U2>(AudioProcessorOptions {
+                block_size: 16,
+                sample_rate: 48000,
+                ring_buffer_size: 50,
+                seed,
+                ..Default::default()
+            });
+        g.edit(|graph| {
+            let a = graph.push(WhiteNoise::new());
+            let b = match explicit_seed {
+                Some(explicit_seed) => graph.push(WhiteNoise::new().seed(explicit_seed)),
+                None => graph.push(WhiteNoise::new()),
+            };
+            a.to_graph_out_channels(0);
+            b.to_graph_out_channels(1);
+        });
+        // Using the global seeds in between doesn't change the seeds from the graph
+        next_randomness_seed();
+        audio_processor.run_without_inputs();
+        let output = audio_processor.output_block();
+        (0..16)
+            .map(|i| (output.read(0, i), output.read(1, i)))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(render(Some(1), None), render(Some(1), None));
+    assert_ne!(render(Some(1), None), render(Some(2), None));
+    assert_ne!(render(None, None), render(None, None));
+    // An explicit seed takes precedence over the seed from the graph
+    let explicit = |seed| {
+        render(Some(seed), Some(9))
+            .iter()
+            .map(|x| x.1)
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(explicit(1), explicit(2));
+}
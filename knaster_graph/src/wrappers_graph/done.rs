@@ -7,9 +7,9 @@
 //! which can be used to change what action is taken when the node is marked as done.
 use crate::core::ops::Add;
 use crate::core::sync::Arc;
-use crate::core::sync::atomic::AtomicBool;
 #[allow(unused)]
 use crate::graph_edit::GraphEdit;
+use crate::node::FreeFlag;
 use knaster_core::numeric_array::NumericArray;
 use knaster_core::typenum::{Add1, B1, Unsigned};
 use knaster_core::{
@@ -26,7 +26,7 @@ use knaster_core::{
 /// information.
 pub struct WrDone<T> {
     pub(crate) ugen: T,
-    pub(crate) free_self_flag: Arc<AtomicBool>,
+    pub(crate) free_self_flag: Arc<FreeFlag>,
     pub(crate) done_action: Done,
 }
 impl<T: UGen> WrDone<T> {
@@ -39,8 +39,8 @@ impl<T: UGen> WrDone<T> {
             }
         }
         if flags.remove_self() {
-            self.free_self_flag
-                .store(true, crate::core::sync::atomic::Ordering::Relaxed);
+            let frame_in_block = flags.done().unwrap_or(0) as u64;
+            self.free_self_flag.set(ctx.frame_clock() + frame_in_block);
         }
     }
 }
@@ -60,6 +60,9 @@ where
     fn init(&mut self, sample_rate: u32, block_size: usize) {
         self.ugen.init(sample_rate, block_size)
     }
+    fn reseed(&mut self, seed: u64) {
+        self.ugen.reseed(seed);
+    }
 
     fn process(
         &mut self,
@@ -88,6 +88,8 @@ fn parse_ugen_impl(mut input: ItemImpl) -> syn::Result<proc_macro2::TokenStream>
     let struct_name = &input.self_ty;
     // optional
     let mut init_fn = None;
+    // optional
+    let mut reseed_fn = None;
     // required
     let mut process_fn = None;
     // optional
@@ -108,6 +110,9 @@ fn parse_ugen_impl(mut input: ItemImpl) -> syn::Result<proc_macro2::TokenStream>
                     "init" => {
                         init_fn = Some(method);
                     }
+                    "reseed" => {
+                        reseed_fn = Some(method);
+                    }
                     "process" => {
                         process_fn = Some(method);
                     }
@@ -153,6 +158,17 @@ fn parse_ugen_impl(mut input: ItemImpl) -> syn::Result<proc_macro2::TokenStream>
         }
         None => quote! {},
     };
+    let reseed_impl = match reseed_fn {
+        Some(reseed_fn) => {
+            let reseed_fn_name = &reseed_fn.sig.ident;
+            quote! {
+                fn reseed(&mut self, seed: u64) {
+                    self.#reseed_fn_name ( seed );
+                }
+            }
+        }
+        None => quote! {},
+    };
 
     let mut num_input_channels = None;
     let mut num_output_channels = None;
@@ -734,6 +750,8 @@ fn parse_ugen_impl(mut input: ItemImpl) -> syn::Result<proc_macro2::TokenStream>
 
             #init_impl
 
+            #reseed_impl
+
             #process_impl
 
             #process_block_impl